@@ -0,0 +1,83 @@
+//! Estimating the offset between a node's clock and the server's, NTP-style,
+//! so nodes can run effects on shared time.
+//!
+//! A node sends its local time in a [`crate::net::NodeMessage::TimeRequest`],
+//! and the server answers with it and the server's time in a
+//! [`crate::net::ServerMessage::TimeResponse`]. Assuming the trip each way
+//! took as long, the server's time was read halfway through the round trip.
+//! Round trips that took longest were most likely delayed one way, so the
+//! estimate comes from the quickest of the recent ones.
+
+/// How many recent round trips the estimate is picked from.
+pub const SAMPLES: usize = 8;
+
+/// One round trip's view of the clock offset.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Sample {
+    /// Server time minus local time, in milliseconds.
+    pub offset: i64,
+    /// How long the round trip took, in milliseconds.
+    pub rtt: u64,
+}
+
+impl Sample {
+    /// Measure a round trip: a request sent at local time `sent`, stamped
+    /// `server` by the server, and answered at local time `received`.
+    pub fn new(sent: u64, server: u64, received: u64) -> Self {
+        let rtt = received.saturating_sub(sent);
+        let midpoint = sent + rtt / 2;
+        Self {
+            offset: server as i64 - midpoint as i64,
+            rtt,
+        }
+    }
+}
+
+/// `ClockSync` keeps a node's estimate of the server's clock.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ClockSync {
+    samples: [Option<Sample>; SAMPLES],
+    next: usize,
+}
+
+impl ClockSync {
+    pub const fn new() -> Self {
+        Self {
+            samples: [None; SAMPLES],
+            next: 0,
+        }
+    }
+
+    /// Record a round trip, replacing the oldest one once the window is full.
+    pub fn add_sample(&mut self, sent: u64, server: u64, received: u64) {
+        self.samples[self.next] = Some(Sample::new(sent, server, received));
+        self.next = (self.next + 1) % SAMPLES;
+    }
+
+    /// The quickest recent round trip, which the estimate comes from.
+    pub fn best(&self) -> Option<Sample> {
+        self.samples.iter().flatten().copied().min_by_key(|s| s.rtt)
+    }
+
+    /// Server time minus local time, in milliseconds, once any round trip is in.
+    pub fn offset(&self) -> Option<i64> {
+        self.best().map(|s| s.offset)
+    }
+
+    pub fn is_synced(&self) -> bool {
+        self.samples.iter().any(Option::is_some)
+    }
+
+    /// The server's time at local time `local`, or `local` until synced.
+    pub fn server_time(&self, local: u64) -> u64 {
+        match self.offset() {
+            Some(offset) => local.saturating_add_signed(offset),
+            None => local,
+        }
+    }
+
+    /// Forget every round trip, e.g. when the server changes.
+    pub fn reset(&mut self) {
+        *self = Self::new();
+    }
+}
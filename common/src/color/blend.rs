@@ -0,0 +1,77 @@
+use core::str::FromStr;
+
+use serde::{
+    Deserialize, Serialize,
+    de::{IntoDeserializer, value},
+};
+
+use crate::color::RgbaF32;
+
+/// How a layer's colors combine with the colors beneath it.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BlendMode {
+    /// Paint over, letting the background show through transparent areas.
+    #[default]
+    Over,
+    /// Sum the colors, clamped to full brightness.
+    Add,
+    /// Darken by multiplying the colors together.
+    Multiply,
+    /// Lighten by inverting, multiplying and inverting again.
+    Screen,
+    /// Take the brighter of the two colors per channel.
+    Max,
+}
+
+/// Parses the mode's lowercase name.
+impl FromStr for BlendMode {
+    type Err = value::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::deserialize(s.into_deserializer())
+    }
+}
+
+impl RgbaF32 {
+    /// Blend this RGBA onto another with a blend mode.
+    ///
+    /// Apart from [`BlendMode::Add`], modes follow the W3C compositing spec:
+    /// where only one layer is opaque its color shows unchanged, and where both
+    /// are the mode's function decides.
+    pub fn blend(self, bg: Self, mode: BlendMode) -> Self {
+        let (s, d) = (self, bg);
+        let union = s.a + d.a - s.a * d.a;
+        let separable = |f: fn(f32, f32, f32, f32) -> f32| Self {
+            r: s.r * (1.0 - d.a) + d.r * (1.0 - s.a) + f(s.r, s.a, d.r, d.a),
+            g: s.g * (1.0 - d.a) + d.g * (1.0 - s.a) + f(s.g, s.a, d.g, d.a),
+            b: s.b * (1.0 - d.a) + d.b * (1.0 - s.a) + f(s.b, s.a, d.b, d.a),
+            a: union,
+        };
+
+        match mode {
+            BlendMode::Over => self.blend_over(bg),
+            BlendMode::Add => Self {
+                r: (s.r + d.r).min(1.0),
+                g: (s.g + d.g).min(1.0),
+                b: (s.b + d.b).min(1.0),
+                a: (s.a + d.a).min(1.0),
+            },
+            // each `f` is `sa * da * B(cs, cd)` in premultiplied terms
+            BlendMode::Multiply => separable(|s, _, d, _| s * d),
+            BlendMode::Screen => separable(|s, sa, d, da| s * da + d * sa - s * d),
+            BlendMode::Max => separable(|s, sa, d, da| (s * da).max(d * sa)),
+        }
+    }
+
+    /// Scale this RGBA's opacity.
+    pub fn fade(self, opacity: f32) -> Self {
+        let opacity = opacity.clamp(0.0, 1.0);
+        Self {
+            r: self.r * opacity,
+            g: self.g * opacity,
+            b: self.b * opacity,
+            a: self.a * opacity,
+        }
+    }
+}
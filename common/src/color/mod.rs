@@ -1,10 +1,16 @@
+pub mod blend;
 pub mod hsvf32;
+pub mod order;
 pub mod rgb8;
+pub mod rgba8;
 pub mod rgbaf32;
 pub mod rgbf32;
 
+pub use blend::BlendMode;
 pub use hsvf32::HsvF32;
+pub use order::ColorOrder;
 pub use rgb8::Rgb8;
+pub use rgba8::Rgba8;
 pub use rgbaf32::RgbaF32;
 pub use rgbf32::RgbF32;
 
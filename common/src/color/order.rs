@@ -0,0 +1,56 @@
+use core::str::FromStr;
+
+use serde::{
+    Deserialize, Serialize,
+    de::{IntoDeserializer, value},
+};
+
+use crate::color::Rgb8;
+
+/// The order a fixture or LED chip takes the three channels of each pixel in.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ColorOrder {
+    #[default]
+    Rgb,
+    Rbg,
+    Grb,
+    Gbr,
+    Brg,
+    Bgr,
+}
+
+impl ColorOrder {
+    /// Read a pixel from its three channels.
+    pub fn read(self, [a, b, c]: [u8; 3]) -> Rgb8 {
+        match self {
+            Self::Rgb => Rgb8::new(a, b, c),
+            Self::Rbg => Rgb8::new(a, c, b),
+            Self::Grb => Rgb8::new(b, a, c),
+            Self::Gbr => Rgb8::new(c, a, b),
+            Self::Brg => Rgb8::new(b, c, a),
+            Self::Bgr => Rgb8::new(c, b, a),
+        }
+    }
+
+    /// Split a pixel into its three channels, in order.
+    pub fn write(self, Rgb8 { r, g, b }: Rgb8) -> [u8; 3] {
+        match self {
+            Self::Rgb => [r, g, b],
+            Self::Rbg => [r, b, g],
+            Self::Grb => [g, r, b],
+            Self::Gbr => [g, b, r],
+            Self::Brg => [b, r, g],
+            Self::Bgr => [b, g, r],
+        }
+    }
+}
+
+/// Parses the order's lowercase name, as in configs.
+impl FromStr for ColorOrder {
+    type Err = value::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::deserialize(s.into_deserializer())
+    }
+}
@@ -1,5 +1,7 @@
 use core::ops::Mul;
 
+use serde::{Deserialize, Serialize};
+
 use crate::{
     color::{MapColor, RgbF32, RgbaF32},
     math::f32_to_u8,
@@ -23,7 +25,7 @@ pub const GAMMA: [u8; 256] = [
 ];
 
 /// 8-bit sRGB.
-#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct Rgb8 {
     pub r: u8,
     pub g: u8,
@@ -0,0 +1,44 @@
+use crate::color::{Rgb8, RgbaF32};
+
+/// 8-bit pre-multiplied RGBA, as streamed over the network.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct Rgba8 {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+    pub a: u8,
+}
+
+impl Rgba8 {
+    /// Create an RGBA from pre-multiplied components.
+    pub const fn new(r: u8, g: u8, b: u8, a: u8) -> Self {
+        Self { r, g, b, a }
+    }
+
+    /// All-components-zero RGBA.
+    pub const fn zero() -> Self {
+        Self::new(0, 0, 0, 0)
+    }
+
+    /// Convert a RGB to an opaque RGBA.
+    pub const fn opaque(rgb: Rgb8) -> Self {
+        Self::new(rgb.r, rgb.g, rgb.b, 255)
+    }
+}
+
+impl From<Rgb8> for Rgba8 {
+    fn from(value: Rgb8) -> Self {
+        Self::opaque(value)
+    }
+}
+
+impl From<Rgba8> for RgbaF32 {
+    fn from(value: Rgba8) -> Self {
+        Self::new_premultiplied(
+            value.r as f32 / 255.0,
+            value.g as f32 / 255.0,
+            value.b as f32 / 255.0,
+            value.a as f32 / 255.0,
+        )
+    }
+}
@@ -0,0 +1,196 @@
+//! A node's settings, kept in flash so they survive a reboot.
+//!
+//! The config is stored as one record: a header naming the layout version,
+//! the payload's length and its CRC-32, followed by the postcard-encoded
+//! [`NodeConfig`]. Anything unreadable, whether blank flash, a torn write or
+//! a layout from newer firmware, falls back to the defaults.
+
+use alloc::{string::String, vec::Vec};
+use core::net::Ipv4Addr;
+
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+
+use crate::{
+    effect::param::ParamValue,
+    net::{StripConfig, StripMode, auth::Key, failsafe::StreamTimeout},
+};
+
+pub mod storage;
+
+pub use storage::{ConfigStorage, RamStorage};
+
+/// Marks the start of a config record, so blank or foreign flash isn't read as one.
+pub const MAGIC: [u8; 4] = *b"LSNC";
+
+/// The layout [`NodeConfig`] is written in. When it changes, bump this and
+/// keep the old layout around to migrate records from, so an update keeps a
+/// node's settings.
+pub const VERSION: u8 = 1;
+
+/// Magic, version, payload length and CRC-32.
+pub const HEADER_LEN: usize = 11;
+
+/// Why a stored config couldn't be loaded or saved.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConfigError<E> {
+    /// The storage itself failed.
+    Storage(E),
+    /// Nothing has been saved yet.
+    Missing,
+    /// The record was written by firmware newer than this.
+    UnsupportedVersion(u8),
+    /// The payload doesn't match its CRC, e.g. after a torn write.
+    Corrupt,
+    /// The payload passed its CRC but doesn't decode as its version's layout.
+    Malformed,
+    /// The record doesn't fit in the storage.
+    TooLarge,
+}
+
+/// The network a node joins, and who it is on it.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NetworkConfig {
+    pub ssid: String,
+    pub password: String,
+    /// What the node calls itself, e.g. in its setup network's SSID.
+    pub name: String,
+    /// The only server allowed to open a control session, or `None` for any.
+    pub server: Option<Ipv4Addr>,
+    /// The installation's key, which the server's traffic must be
+    /// authenticated with, or `None` to take it from anyone (see
+    /// [`crate::net::auth`]).
+    pub key: Option<Key>,
+}
+
+/// How a strip is wired, and what it does after booting.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StoredStrip {
+    pub config: StripConfig,
+    pub mode: StripMode,
+    /// How long the strip waits for streamed colors before falling back, or
+    /// `None` to hold the last frame forever.
+    pub stream_timeout: Option<StreamTimeout>,
+}
+
+/// `NodeConfig` is everything a node remembers across reboots.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NodeConfig {
+    pub network: NetworkConfig,
+    pub strips: Vec<StoredStrip>,
+    /// The registry index of the effect running.
+    pub effect: u8,
+    /// `(effect, param, value)` for every parameter changed from its default.
+    pub params: Vec<(u8, u8, ParamValue)>,
+    /// Global brightness, applied once after compositing.
+    pub brightness: f32,
+}
+
+impl NodeConfig {
+    /// Record a parameter change, replacing any earlier value for it.
+    pub fn set_param(&mut self, effect: u8, param: u8, value: ParamValue) {
+        match self
+            .params
+            .iter_mut()
+            .find(|(e, p, _)| (*e, *p) == (effect, param))
+        {
+            Some(entry) => entry.2 = value,
+            None => self.params.push((effect, param, value)),
+        }
+    }
+
+    /// Encode the config as a record in the current layout.
+    pub fn encode(&self) -> Vec<u8> {
+        encode_record(VERSION, self)
+    }
+
+    /// Decode a record.
+    fn decode<E>(record: &[u8]) -> Result<Self, ConfigError<E>> {
+        let header = record.get(..HEADER_LEN).ok_or(ConfigError::Missing)?;
+        if header[..4] != MAGIC {
+            return Err(ConfigError::Missing);
+        }
+        let version = header[4];
+        let len = u16::from_le_bytes([header[5], header[6]]) as usize;
+        let crc = u32::from_le_bytes([header[7], header[8], header[9], header[10]]);
+
+        let payload = record
+            .get(HEADER_LEN..HEADER_LEN + len)
+            .ok_or(ConfigError::Corrupt)?;
+        if crc32(payload) != crc {
+            return Err(ConfigError::Corrupt);
+        }
+
+        match version {
+            VERSION => decode_payload(payload),
+            version => Err(ConfigError::UnsupportedVersion(version)),
+        }
+    }
+
+    /// Read the config from storage.
+    pub fn load<S: ConfigStorage>(storage: &mut S) -> Result<Self, ConfigError<S::Error>> {
+        let mut header = [0u8; HEADER_LEN];
+        storage.read(&mut header).map_err(ConfigError::Storage)?;
+        if header[..4] != MAGIC {
+            return Err(ConfigError::Missing);
+        }
+
+        let len = u16::from_le_bytes([header[5], header[6]]) as usize;
+        if HEADER_LEN + len > storage.capacity() {
+            return Err(ConfigError::Corrupt);
+        }
+        let mut record = alloc::vec![0u8; HEADER_LEN + len];
+        storage.read(&mut record).map_err(ConfigError::Storage)?;
+        Self::decode(&record)
+    }
+
+    /// Read the config from storage, or fall back to `default` if there's
+    /// none or it can't be read, along with why.
+    pub fn load_or<S: ConfigStorage>(
+        storage: &mut S,
+        default: impl FnOnce() -> Self,
+    ) -> (Self, Option<ConfigError<S::Error>>) {
+        match Self::load(storage) {
+            Ok(config) => (config, None),
+            Err(e) => (default(), Some(e)),
+        }
+    }
+
+    /// Write the config to storage, replacing whatever was there.
+    pub fn save<S: ConfigStorage>(&self, storage: &mut S) -> Result<(), ConfigError<S::Error>> {
+        let record = self.encode();
+        if record.len() > storage.capacity() {
+            return Err(ConfigError::TooLarge);
+        }
+        storage.write(&record).map_err(ConfigError::Storage)
+    }
+}
+
+fn encode_record(version: u8, config: &impl Serialize) -> Vec<u8> {
+    // every field is plain data, so encoding into a `Vec` can't fail
+    let payload = postcard::to_extend(config, Vec::new()).unwrap_or_default();
+
+    let mut record = Vec::with_capacity(HEADER_LEN + payload.len());
+    record.extend(MAGIC);
+    record.push(version);
+    record.extend((payload.len() as u16).to_le_bytes());
+    record.extend(crc32(&payload).to_le_bytes());
+    record.extend(payload);
+    record
+}
+
+fn decode_payload<T: DeserializeOwned, E>(payload: &[u8]) -> Result<T, ConfigError<E>> {
+    postcard::from_bytes(payload).map_err(|_| ConfigError::Malformed)
+}
+
+/// CRC-32 (IEEE), as used by zip and Ethernet.
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xEDB8_8320 & mask);
+        }
+    }
+    !crc
+}
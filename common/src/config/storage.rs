@@ -0,0 +1,70 @@
+//! Where a [`super::NodeConfig`] record is kept.
+
+use alloc::{vec, vec::Vec};
+
+/// `ConfigStorage` is a region that holds one config record, starting at its
+/// first byte.
+pub trait ConfigStorage {
+    type Error;
+
+    /// The most bytes the region holds.
+    fn capacity(&self) -> usize;
+
+    /// Fill `buf` from the start of the region.
+    fn read(&mut self, buf: &mut [u8]) -> Result<(), Self::Error>;
+
+    /// Replace the start of the region with `data`, erasing first if the
+    /// storage needs it.
+    fn write(&mut self, data: &[u8]) -> Result<(), Self::Error>;
+}
+
+/// The region was asked for more bytes than it holds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OutOfRange;
+
+/// `RamStorage` keeps the region in memory, for hosts and tests. It starts
+/// out erased, like blank flash.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RamStorage {
+    bytes: Vec<u8>,
+}
+
+impl RamStorage {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            bytes: vec![0xff; capacity],
+        }
+    }
+
+    pub fn bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    /// The raw region, e.g. to corrupt it.
+    pub fn bytes_mut(&mut self) -> &mut [u8] {
+        &mut self.bytes
+    }
+}
+
+impl ConfigStorage for RamStorage {
+    type Error = OutOfRange;
+
+    fn capacity(&self) -> usize {
+        self.bytes.len()
+    }
+
+    fn read(&mut self, buf: &mut [u8]) -> Result<(), OutOfRange> {
+        let src = self.bytes.get(..buf.len()).ok_or(OutOfRange)?;
+        buf.copy_from_slice(src);
+        Ok(())
+    }
+
+    fn write(&mut self, data: &[u8]) -> Result<(), OutOfRange> {
+        if data.len() > self.bytes.len() {
+            return Err(OutOfRange);
+        }
+        self.bytes.fill(0xff);
+        self.bytes[..data.len()].copy_from_slice(data);
+        Ok(())
+    }
+}
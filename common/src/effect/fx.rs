@@ -0,0 +1,218 @@
+//! A node's effects, shared by the firmware and the host simulator.
+
+use alloc::{boxed::Box, vec::Vec};
+
+use serde::{Deserialize, Serialize};
+
+use crate::color::{BlendMode, RgbaF32};
+
+use super::{
+    EffectMode, StripInfo,
+    layer::blend_layer,
+    param::{ParamError, ParamValue},
+    registry,
+    transition::Transition,
+};
+
+/// The most overlays a strip can have, since each is rendered every frame.
+pub const MAX_OVERLAYS: usize = 4;
+
+/// A registered effect for a node to layer over a strip's base effect.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct OverlayConfig {
+    /// The effect's id in [`registry`].
+    pub effect: u8,
+    pub mode: BlendMode,
+    /// From 0 to 1.
+    pub opacity: f32,
+}
+
+/// An effect composited over a strip's base effect.
+struct Overlay {
+    effect: Box<dyn EffectMode>,
+    mode: BlendMode,
+    opacity: f32,
+}
+
+/// A transition away from a previous effect, in progress.
+struct ActiveTransition {
+    from: usize,
+    transition: Transition,
+    /// When the transition started, set on the first frame rendered with it.
+    start: Option<u64>,
+    /// The frame the transition finished on, which the rest of the strips
+    /// still render it for.
+    end: Option<u64>,
+}
+
+/// `Effects` stores configured `EffectMode`s and allows shifting between them.
+///
+/// Every strip gets its own instance of each effect, so stateful effects keep
+/// separate state per strip. All strips show the same effect index, with any
+/// overlays a strip has layered on top.
+pub struct Effects<const N: usize> {
+    strips: Vec<[Box<dyn EffectMode>; N]>,
+    overlays: Vec<Vec<Overlay>>,
+    index: usize,
+    /// Used when switching effects without an explicit transition.
+    transition: Option<Transition>,
+    active: Option<ActiveTransition>,
+}
+
+impl<const N: usize> Effects<N> {
+    /// Create the effect list for each of `strips` strips by calling `make` with
+    /// the strip's index.
+    pub fn new<F>(strips: usize, make: F) -> Self
+    where
+        F: FnMut(usize) -> [Box<dyn EffectMode>; N],
+    {
+        Self {
+            strips: (0..strips).map(make).collect(),
+            overlays: (0..strips).map(|_| Vec::new()).collect(),
+            index: 0,
+            transition: None,
+            active: None,
+        }
+    }
+
+    /// Set the transition used when switching effects, or `None` to switch instantly.
+    pub fn set_transition(&mut self, transition: Option<Transition>) {
+        self.transition = transition;
+    }
+
+    /// Switch to an effect by index, with a transition or the default one.
+    pub fn set_effect(&mut self, index: usize, transition: Option<Transition>) {
+        self.switch_to(index % N, transition.or(self.transition));
+    }
+
+    pub fn next_effect(&mut self) {
+        self.switch_to((self.index + 1) % N, self.transition);
+    }
+
+    pub fn prev_effect(&mut self) {
+        self.switch_to(self.index.checked_sub(1).unwrap_or(N - 1), self.transition);
+    }
+
+    /// Shift the current effect by a delta, wrapping around in either direction,
+    /// with a transition or the default one.
+    pub fn shift_effect(&mut self, delta: i8, transition: Option<Transition>) {
+        let index = (self.index as isize + delta as isize).rem_euclid(N as isize) as usize;
+        self.switch_to(index, transition.or(self.transition));
+    }
+
+    fn switch_to(&mut self, index: usize, transition: Option<Transition>) {
+        if index == self.index {
+            return;
+        }
+
+        self.active = transition
+            .filter(|transition| transition.duration_ms > 0)
+            .map(|transition| ActiveTransition {
+                from: self.index,
+                transition,
+                start: None,
+                end: None,
+            });
+        self.index = index;
+    }
+
+    pub fn current_effect(&mut self, strip: usize) -> &mut dyn EffectMode {
+        &mut *self.strips[strip][self.index]
+    }
+
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    /// Set a parameter of an effect on every strip.
+    pub fn set_param(&mut self, index: usize, id: u8, value: ParamValue) -> Result<(), ParamError> {
+        for effects in &mut self.strips {
+            effects[index].set_param(id, value)?;
+        }
+        Ok(())
+    }
+
+    /// Layer an effect over a strip's base effect, above any existing overlays.
+    /// Nodes keep to [`MAX_OVERLAYS`] per strip.
+    pub fn add_overlay(
+        &mut self,
+        strip: usize,
+        effect: Box<dyn EffectMode>,
+        mode: BlendMode,
+        opacity: f32,
+    ) {
+        self.overlays[strip].push(Overlay {
+            effect,
+            mode,
+            opacity,
+        });
+    }
+
+    /// The number of overlays on a strip.
+    pub fn overlays(&self, strip: usize) -> usize {
+        self.overlays[strip].len()
+    }
+
+    pub fn clear_overlays(&mut self, strip: usize) {
+        self.overlays[strip].clear();
+    }
+
+    /// Render the current effect and a strip's overlays into a transparent
+    /// buffer, using `scratch` to render the effect being transitioned away
+    /// from and each overlay before blending them in.
+    pub fn update(
+        &mut self,
+        strip: usize,
+        strip_info: &StripInfo,
+        buf: &mut [RgbaF32],
+        scratch: &mut [RgbaF32],
+        now: u64,
+    ) {
+        self.current_effect(strip).update(strip_info, buf, now);
+
+        // every strip renders a frame at the same time, so any other time
+        // means they've all had the transition's last
+        if self
+            .active
+            .as_ref()
+            .is_some_and(|active| active.end.is_some_and(|end| now != end))
+        {
+            self.active = None;
+        }
+        if let Some(active) = &mut self.active {
+            // time jumps back when a node syncs to a server that started more
+            // recently, so carry on from there rather than stall
+            let start = active.start.filter(|&start| start <= now).unwrap_or(now);
+            active.start = Some(start);
+            let progress = active.transition.progress(now.saturating_sub(start));
+
+            scratch.fill(RgbaF32::zero());
+            self.strips[strip][active.from].update(strip_info, scratch, now);
+            active.transition.blend(scratch, buf, progress, strip_info);
+
+            if progress >= 1.0 {
+                active.end.get_or_insert(now);
+            }
+        }
+        for overlay in &mut self.overlays[strip] {
+            scratch.fill(RgbaF32::zero());
+            overlay.effect.update(strip_info, scratch, now);
+            blend_layer(buf, scratch, overlay.mode, overlay.opacity);
+        }
+    }
+}
+
+impl Effects<{ registry::COUNT }> {
+    /// Build every registered effect for each of `strips` strips of up to `LEN`
+    /// LEDs, in id order.
+    pub fn from_registry<const LEN: usize, F>(strips: usize, mut seed: F) -> Self
+    where
+        F: FnMut() -> u32,
+    {
+        Self::new(strips, |_| {
+            core::array::from_fn(|id| {
+                registry::build::<LEN>(id as u8, seed()).expect("ids below COUNT are registered")
+            })
+        })
+    }
+}
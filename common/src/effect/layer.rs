@@ -0,0 +1,24 @@
+//! Compositing effect layers into a strip's final colors.
+//!
+//! A strip is rendered bottom-up into a premultiplied [`RgbaF32`] buffer: the
+//! base effect, then any overlays, then the layer streamed from the server.
+//! Gamma and brightness are applied once, by [`flatten`], on the way out.
+
+use crate::color::{BlendMode, Rgb8, RgbF32, RgbaF32};
+
+/// Blend a layer onto the layers beneath it, at an opacity.
+pub fn blend_layer(dst: &mut [RgbaF32], src: &[RgbaF32], mode: BlendMode, opacity: f32) {
+    for (bg, &fg) in dst.iter_mut().zip(src) {
+        *bg = fg.fade(opacity).blend(*bg, mode);
+    }
+}
+
+/// Flatten composited colors over black into gamma-corrected output colors.
+pub fn flatten(buf: &[RgbaF32], brightness: f32) -> impl Iterator<Item = Rgb8> + '_ {
+    let black = RgbaF32::from(RgbF32::zero());
+    buf.iter().map(move |px| {
+        Rgb8::from(px.blend_over(black))
+            .gamma_correct()
+            .brightness(brightness)
+    })
+}
@@ -1,13 +1,28 @@
-use crate::color::{HsvF32, Rgb8, RgbF32};
+use serde::{Deserialize, Serialize};
+
+use crate::color::{HsvF32, Rgb8, RgbF32, RgbaF32};
+
+use param::{ParamError, ParamInfo, ParamValue, check};
 
 #[cfg(feature = "firmware")]
 use num_traits::{Euclid, Float};
 
-#[derive(Debug, Default, Clone, Copy)]
+pub mod fx;
+pub mod layer;
+pub mod param;
+pub mod registry;
+pub mod spatial;
+pub mod stateful;
+pub mod transition;
+
+pub use stateful::{Fire, Meteor, Twinkle};
+
+/// What an effect needs to know about a strip. The geometry behind it lives in
+/// `layout::StripLayout` on the host side.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub struct StripInfo {
     pub leds: usize,
     pub rev: bool,
-    // TODO: spatial data
 }
 
 impl StripInfo {
@@ -19,8 +34,30 @@ impl StripInfo {
     }
 }
 
-pub trait EffectMode {
-    fn update(&self, info: &StripInfo, buf: &mut [Rgb8], time: u64);
+/// An effect rendered over a strip's pixel indices.
+///
+/// Effects may keep state between frames, so each strip needs its own instance.
+/// They write pre-multiplied colors, leaving any pixel they don't cover
+/// transparent, and are composited by [`layer`] before gamma correction.
+///
+/// Effects must be `Send` so the host simulator can render them from any task.
+pub trait EffectMode: Send {
+    fn update(&mut self, info: &StripInfo, buf: &mut [RgbaF32], time: u64);
+
+    /// The effect's tunable parameters, indexed by id.
+    fn params(&self) -> &'static [ParamInfo<'static>] {
+        &[]
+    }
+
+    /// The current value of a parameter.
+    fn param(&self, _id: u8) -> Option<ParamValue> {
+        None
+    }
+
+    /// Set a parameter, after checking it against [`EffectMode::params`].
+    fn set_param(&mut self, id: u8, _value: ParamValue) -> Result<(), ParamError> {
+        Err(ParamError::UnknownParam(id))
+    }
 }
 
 fn rem_euclid(a: f32, b: f32) -> f32 {
@@ -45,8 +82,17 @@ pub struct ColorWheel {
     pub deg_per_sec: f32,
 }
 
+impl ColorWheel {
+    pub const PARAMS: &'static [ParamInfo<'static>] = &[
+        ParamInfo::f32("saturation", 0.0, 1.0, 1.0),
+        ParamInfo::f32("value", 0.0, 1.0, 1.0),
+        ParamInfo::f32("deg_per_px", -1.0, 1.0, 1.0 / 500.0),
+        ParamInfo::f32("deg_per_sec", -720.0, 720.0, 30.0),
+    ];
+}
+
 impl EffectMode for ColorWheel {
-    fn update(&self, _: &StripInfo, buf: &mut [Rgb8], time: u64) {
+    fn update(&mut self, _: &StripInfo, buf: &mut [RgbaF32], time: u64) {
         for (i, px) in buf.iter_mut().enumerate() {
             let hsv = HsvF32::new(
                 rem_euclid(
@@ -58,8 +104,34 @@ impl EffectMode for ColorWheel {
                 self.value,
             );
 
-            *px = Rgb8::from(RgbF32::from(hsv)).gamma_correct();
+            *px = RgbF32::from(hsv).into();
+        }
+    }
+
+    fn params(&self) -> &'static [ParamInfo<'static>] {
+        Self::PARAMS
+    }
+
+    fn param(&self, id: u8) -> Option<ParamValue> {
+        Some(match id {
+            0 => self.saturation.into(),
+            1 => self.value.into(),
+            2 => self.deg_per_px.into(),
+            3 => self.deg_per_sec.into(),
+            _ => return None,
+        })
+    }
+
+    fn set_param(&mut self, id: u8, value: ParamValue) -> Result<(), ParamError> {
+        check(Self::PARAMS, id, value)?;
+        match (id, value) {
+            (0, ParamValue::F32(x)) => self.saturation = x,
+            (1, ParamValue::F32(x)) => self.value = x,
+            (2, ParamValue::F32(x)) => self.deg_per_px = x,
+            (3, ParamValue::F32(x)) => self.deg_per_sec = x,
+            _ => return Err(ParamError::UnknownParam(id)),
         }
+        Ok(())
     }
 }
 
@@ -68,12 +140,48 @@ pub struct ColorPattern<const N: usize> {
     pub speed: f32,
 }
 
+/// `speed`, then one parameter per color for up to 4 colors.
+const PATTERN_PARAMS: &[ParamInfo<'static>] = &[
+    ParamInfo::f32("speed", -10.0, 10.0, 1.0),
+    ParamInfo::color("color0", Rgb8::new(255, 0, 0)),
+    ParamInfo::color("color1", Rgb8::new(0, 255, 0)),
+    ParamInfo::color("color2", Rgb8::new(0, 0, 255)),
+    ParamInfo::color("color3", Rgb8::new(255, 255, 255)),
+];
+
+impl<const N: usize> ColorPattern<N> {
+    pub const PARAMS: &'static [ParamInfo<'static>] =
+        PATTERN_PARAMS.split_at(1 + if N < 4 { N } else { 4 }).0;
+}
+
 impl<const N: usize> EffectMode for ColorPattern<N> {
-    fn update(&self, _: &StripInfo, buf: &mut [Rgb8], time: u64) {
-        let time_shift = (time as f32 / 1000.0 * self.speed * N as f32).floor() as usize;
+    fn update(&mut self, _: &StripInfo, buf: &mut [RgbaF32], time: u64) {
+        let time_shift = (time as f32 / 1000.0 * self.speed * N as f32).floor() as isize;
         for (i, px) in buf.iter_mut().enumerate() {
-            *px = self.colors[(i - time_shift).rem_euclid(N)].gamma_correct();
+            *px = self.colors[(i as isize - time_shift).rem_euclid(N as isize) as usize].into();
+        }
+    }
+
+    fn params(&self) -> &'static [ParamInfo<'static>] {
+        Self::PARAMS
+    }
+
+    fn param(&self, id: u8) -> Option<ParamValue> {
+        match id {
+            0 => Some(self.speed.into()),
+            _ if (id as usize) < Self::PARAMS.len() => Some(self.colors[id as usize - 1].into()),
+            _ => None,
+        }
+    }
+
+    fn set_param(&mut self, id: u8, value: ParamValue) -> Result<(), ParamError> {
+        check(Self::PARAMS, id, value)?;
+        match (id, value) {
+            (0, ParamValue::F32(x)) => self.speed = x,
+            (_, ParamValue::Color(c)) => self.colors[id as usize - 1] = c,
+            _ => return Err(ParamError::UnknownParam(id)),
         }
+        Ok(())
     }
 }
 
@@ -82,18 +190,47 @@ pub struct Bounce {
     pub speed: f32,
 }
 
+impl Bounce {
+    pub const PARAMS: &'static [ParamInfo<'static>] = &[
+        ParamInfo::color("color", Rgb8::new(255, 255, 255)),
+        ParamInfo::f32("speed", -10.0, 10.0, 0.5),
+    ];
+}
+
 impl EffectMode for Bounce {
-    fn update(&self, info: &StripInfo, buf: &mut [Rgb8], time: u64) {
+    fn update(&mut self, info: &StripInfo, buf: &mut [RgbaF32], time: u64) {
         let cur = (((time as f32 / 1000.0 * core::f32::consts::PI * 2.0 * self.speed).sin())
             * (info.leds as f32 / 2.0)
             + (info.leds as f32 / 2.0))
             .floor() as usize;
         for (i, px) in buf.iter_mut().enumerate() {
             if i == cur {
-                *px = self.color;
+                *px = self.color.into();
             } else {
-                *px = Rgb8::zero();
+                *px = RgbaF32::zero();
             }
         }
     }
+
+    fn params(&self) -> &'static [ParamInfo<'static>] {
+        Self::PARAMS
+    }
+
+    fn param(&self, id: u8) -> Option<ParamValue> {
+        Some(match id {
+            0 => self.color.into(),
+            1 => self.speed.into(),
+            _ => return None,
+        })
+    }
+
+    fn set_param(&mut self, id: u8, value: ParamValue) -> Result<(), ParamError> {
+        check(Self::PARAMS, id, value)?;
+        match (id, value) {
+            (0, ParamValue::Color(c)) => self.color = c,
+            (1, ParamValue::F32(x)) => self.speed = x,
+            _ => return Err(ParamError::UnknownParam(id)),
+        }
+        Ok(())
+    }
 }
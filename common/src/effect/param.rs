@@ -0,0 +1,139 @@
+//! Named, typed effect parameters that can be described and set over the network.
+
+use core::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::color::Rgb8;
+
+/// The type of a parameter, and the range of values it accepts.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum ParamKind {
+    F32 { min: f32, max: f32 },
+    U8 { min: u8, max: u8 },
+    Bool,
+    Color,
+}
+
+/// A parameter's value.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum ParamValue {
+    F32(f32),
+    U8(u8),
+    Bool(bool),
+    Color(Rgb8),
+}
+
+/// A description of one of an effect's parameters.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ParamInfo<'a> {
+    pub name: &'a str,
+    pub kind: ParamKind,
+    pub default: ParamValue,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ParamError {
+    /// The effect has no parameter with this id.
+    UnknownParam(u8),
+    /// The value is a different type than the parameter.
+    WrongType,
+    /// The value is outside the parameter's range.
+    OutOfRange,
+}
+
+impl fmt::Display for ParamError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnknownParam(id) => write!(f, "unknown parameter {id}"),
+            Self::WrongType => write!(f, "wrong parameter type"),
+            Self::OutOfRange => write!(f, "parameter out of range"),
+        }
+    }
+}
+
+impl core::error::Error for ParamError {}
+
+impl<'a> ParamInfo<'a> {
+    pub const fn f32(name: &'a str, min: f32, max: f32, default: f32) -> Self {
+        Self {
+            name,
+            kind: ParamKind::F32 { min, max },
+            default: ParamValue::F32(default),
+        }
+    }
+
+    pub const fn u8(name: &'a str, min: u8, max: u8, default: u8) -> Self {
+        Self {
+            name,
+            kind: ParamKind::U8 { min, max },
+            default: ParamValue::U8(default),
+        }
+    }
+
+    pub const fn bool(name: &'a str, default: bool) -> Self {
+        Self {
+            name,
+            kind: ParamKind::Bool,
+            default: ParamValue::Bool(default),
+        }
+    }
+
+    pub const fn color(name: &'a str, default: Rgb8) -> Self {
+        Self {
+            name,
+            kind: ParamKind::Color,
+            default: ParamValue::Color(default),
+        }
+    }
+
+    /// Check that a value is the right type and within range for this parameter.
+    pub fn check(&self, value: ParamValue) -> Result<(), ParamError> {
+        let in_range = match (self.kind, value) {
+            (ParamKind::F32 { min, max }, ParamValue::F32(x)) => (min..=max).contains(&x),
+            (ParamKind::U8 { min, max }, ParamValue::U8(x)) => (min..=max).contains(&x),
+            (ParamKind::Bool, ParamValue::Bool(_)) | (ParamKind::Color, ParamValue::Color(_)) => {
+                true
+            }
+            _ => return Err(ParamError::WrongType),
+        };
+
+        if in_range {
+            Ok(())
+        } else {
+            Err(ParamError::OutOfRange)
+        }
+    }
+}
+
+/// Check a value against the parameter `id` in a list of parameters.
+pub fn check(params: &[ParamInfo], id: u8, value: ParamValue) -> Result<(), ParamError> {
+    params
+        .get(id as usize)
+        .ok_or(ParamError::UnknownParam(id))?
+        .check(value)
+}
+
+impl From<f32> for ParamValue {
+    fn from(value: f32) -> Self {
+        Self::F32(value)
+    }
+}
+
+impl From<u8> for ParamValue {
+    fn from(value: u8) -> Self {
+        Self::U8(value)
+    }
+}
+
+impl From<bool> for ParamValue {
+    fn from(value: bool) -> Self {
+        Self::Bool(value)
+    }
+}
+
+impl From<Rgb8> for ParamValue {
+    fn from(value: Rgb8) -> Self {
+        Self::Color(value)
+    }
+}
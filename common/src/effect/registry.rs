@@ -0,0 +1,83 @@
+//! Every effect a node can run, looked up by id or name.
+//!
+//! Ids are indices into [`EFFECTS`], so new effects go at the end.
+
+use alloc::boxed::Box;
+use serde::Serialize;
+
+use crate::color::Rgb8;
+
+use super::{
+    Bounce, ColorPattern, ColorWheel, EffectMode, Fire, Meteor, Twinkle, param::ParamInfo,
+};
+
+/// A registered effect's name and parameters.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct EffectInfo<'a> {
+    pub name: &'a str,
+    pub params: &'a [ParamInfo<'a>],
+}
+
+// stateful effects' parameters don't depend on their LED count
+pub const EFFECTS: &[EffectInfo<'static>] = &[
+    EffectInfo {
+        name: "color_wheel",
+        params: ColorWheel::PARAMS,
+    },
+    EffectInfo {
+        name: "color_pattern",
+        params: ColorPattern::<2>::PARAMS,
+    },
+    EffectInfo {
+        name: "bounce",
+        params: Bounce::PARAMS,
+    },
+    EffectInfo {
+        name: "twinkle",
+        params: Twinkle::<0>::PARAMS,
+    },
+    EffectInfo {
+        name: "fire",
+        params: Fire::<0>::PARAMS,
+    },
+    EffectInfo {
+        name: "meteor",
+        params: Meteor::<0>::PARAMS,
+    },
+];
+
+/// The number of registered effects.
+pub const COUNT: usize = EFFECTS.len();
+
+/// Look up an effect's id by name.
+pub fn find(name: &str) -> Option<u8> {
+    EFFECTS
+        .iter()
+        .position(|effect| effect.name == name)
+        .map(|id| id as u8)
+}
+
+/// Build a registered effect for strips of up to `N` LEDs, with its default parameters.
+pub fn build<const N: usize>(id: u8, seed: u32) -> Option<Box<dyn EffectMode>> {
+    let mut effect: Box<dyn EffectMode> = match id {
+        0 => Box::new(ColorWheel::default()),
+        1 => Box::new(ColorPattern {
+            colors: [Rgb8::zero(); 2],
+            speed: 0.0,
+        }),
+        2 => Box::new(Bounce {
+            color: Rgb8::zero(),
+            speed: 0.0,
+        }),
+        3 => Box::new(Twinkle::<N>::new(Rgb8::zero(), seed)),
+        4 => Box::new(Fire::<N>::new(seed)),
+        5 => Box::new(Meteor::<N>::new(Rgb8::zero(), seed)),
+        _ => return None,
+    };
+
+    for (param, info) in effect.params().iter().enumerate() {
+        // registered defaults are always in range
+        _ = effect.set_param(param as u8, info.default);
+    }
+    Some(effect)
+}
@@ -0,0 +1,124 @@
+use crate::{
+    color::{HsvF32, Rgb8, RgbF32},
+    math::{Vec3, noise3},
+};
+
+use super::rem_euclid;
+
+/// An effect defined over world space rather than strip indices, so it flows
+/// continuously across every strip in an installation.
+///
+/// Colors are streamed to nodes as-is; they gamma correct on output.
+pub trait SpatialEffect {
+    /// The color at a point in world space, `time` milliseconds in.
+    fn sample(&self, pos: Vec3, time: u64) -> Rgb8;
+
+    /// Sample the effect at every LED position into a color buffer.
+    fn render(&self, positions: &[Vec3], buf: &mut [Rgb8], time: u64) {
+        for (px, pos) in buf.iter_mut().zip(positions) {
+            *px = self.sample(*pos, time);
+        }
+    }
+}
+
+/// Brightness of a band of `width` that repeats every `spacing` units, at `offset` into it.
+fn band(offset: f32, width: f32, spacing: f32) -> f32 {
+    let d = rem_euclid(offset, spacing);
+    let d = d.min(spacing - d);
+    (1.0 - d / (width / 2.0)).max(0.0)
+}
+
+/// Bands of color moving through space along a direction.
+#[derive(better_default::Default)]
+pub struct PlaneSweep {
+    #[default(Vec3::new(1.0, 0.0, 0.0))]
+    pub direction: Vec3,
+
+    #[default(Rgb8::new(255, 255, 255))]
+    pub color: Rgb8,
+
+    /// Units per second.
+    #[default(1.0)]
+    pub speed: f32,
+
+    #[default(0.5)]
+    pub width: f32,
+
+    /// Distance between the centers of consecutive bands.
+    #[default(4.0)]
+    pub spacing: f32,
+}
+
+impl SpatialEffect for PlaneSweep {
+    fn sample(&self, pos: Vec3, time: u64) -> Rgb8 {
+        let offset = pos.dot(self.direction.normalize()) - time as f32 / 1000.0 * self.speed;
+        let brightness = band(offset, self.width, self.spacing);
+        Rgb8::from(RgbF32::from(self.color) * brightness)
+    }
+}
+
+/// Rings of color expanding outwards from a point.
+#[derive(better_default::Default)]
+pub struct RadialPulse {
+    pub center: Vec3,
+
+    #[default(Rgb8::new(255, 255, 255))]
+    pub color: Rgb8,
+
+    /// Units per second.
+    #[default(1.0)]
+    pub speed: f32,
+
+    #[default(0.5)]
+    pub width: f32,
+
+    /// Distance between consecutive rings.
+    #[default(3.0)]
+    pub spacing: f32,
+}
+
+impl SpatialEffect for RadialPulse {
+    fn sample(&self, pos: Vec3, time: u64) -> Rgb8 {
+        let offset = pos.distance(self.center) - time as f32 / 1000.0 * self.speed;
+        let brightness = band(offset, self.width, self.spacing);
+        Rgb8::from(RgbF32::from(self.color) * brightness)
+    }
+}
+
+/// Hues drifting through a 3D noise field.
+#[derive(better_default::Default)]
+pub struct NoiseField {
+    /// Noise features per unit.
+    #[default(0.5)]
+    pub scale: f32,
+
+    /// Units per second the field drifts through.
+    #[default(0.2)]
+    pub speed: f32,
+
+    /// Degrees of hue covered by the field.
+    #[default(240.0)]
+    pub hue_range: f32,
+
+    #[default(30.0)]
+    pub deg_per_sec: f32,
+
+    #[default(1.0)]
+    pub saturation: f32,
+
+    #[default(1.0)]
+    pub value: f32,
+}
+
+impl SpatialEffect for NoiseField {
+    fn sample(&self, pos: Vec3, time: u64) -> Rgb8 {
+        let secs = time as f32 / 1000.0;
+        let p = pos * self.scale + Vec3::new(0.0, 0.0, secs * self.speed);
+        let hsv = HsvF32::new(
+            rem_euclid(noise3(p) * self.hue_range + secs * self.deg_per_sec, 360.0),
+            self.saturation,
+            self.value,
+        );
+        Rgb8::from(RgbF32::from(hsv))
+    }
+}
@@ -0,0 +1,297 @@
+//! Effects that carry state from one frame to the next. Each keeps a buffer of
+//! up to `N` LEDs, so an instance must not be shared between strips.
+
+use crate::{
+    color::{Rgb8, RgbF32, RgbaF32},
+    math::Rng,
+};
+
+use super::{
+    EffectMode, StripInfo,
+    param::{ParamError, ParamInfo, ParamValue, check},
+};
+
+/// Seconds since the previous frame, or zero on the first.
+fn elapsed(last: &mut Option<u64>, time: u64) -> f32 {
+    let dt = last.map_or(0, |last| time.saturating_sub(last));
+    *last = Some(time);
+    dt as f32 / 1000.0
+}
+
+/// Random LEDs flash on and fade out.
+pub struct Twinkle<const N: usize> {
+    pub color: Rgb8,
+    /// Average number of times each LED flashes per second.
+    pub rate: f32,
+    /// Seconds for a flash to fade out.
+    pub fade: f32,
+    rng: Rng,
+    levels: [f32; N],
+    last: Option<u64>,
+}
+
+impl<const N: usize> Twinkle<N> {
+    pub const PARAMS: &'static [ParamInfo<'static>] = &[
+        ParamInfo::color("color", Rgb8::new(255, 180, 100)),
+        ParamInfo::f32("rate", 0.0, 20.0, 0.2),
+        ParamInfo::f32("fade", 0.05, 10.0, 1.0),
+    ];
+
+    pub fn new(color: Rgb8, seed: u32) -> Self {
+        Self {
+            color,
+            rate: 0.2,
+            fade: 1.0,
+            rng: Rng::new(seed),
+            levels: [0.0; N],
+            last: None,
+        }
+    }
+}
+
+impl<const N: usize> EffectMode for Twinkle<N> {
+    fn update(&mut self, _: &StripInfo, buf: &mut [RgbaF32], time: u64) {
+        let dt = elapsed(&mut self.last, time);
+        for (px, level) in buf.iter_mut().zip(&mut self.levels) {
+            *level = (*level - dt / self.fade).max(0.0);
+            if self.rng.next_f32() < self.rate * dt {
+                *level = 1.0;
+            }
+            *px = RgbaF32::from_rgb(RgbF32::from(self.color), *level);
+        }
+    }
+
+    fn params(&self) -> &'static [ParamInfo<'static>] {
+        Self::PARAMS
+    }
+
+    fn param(&self, id: u8) -> Option<ParamValue> {
+        Some(match id {
+            0 => self.color.into(),
+            1 => self.rate.into(),
+            2 => self.fade.into(),
+            _ => return None,
+        })
+    }
+
+    fn set_param(&mut self, id: u8, value: ParamValue) -> Result<(), ParamError> {
+        check(Self::PARAMS, id, value)?;
+        match (id, value) {
+            (0, ParamValue::Color(c)) => self.color = c,
+            (1, ParamValue::F32(x)) => self.rate = x,
+            (2, ParamValue::F32(x)) => self.fade = x,
+            _ => return Err(ParamError::UnknownParam(id)),
+        }
+        Ok(())
+    }
+}
+
+/// A simulated fire rising from the start of the strip, after Mark Kriegsman's Fire2012.
+pub struct Fire<const N: usize> {
+    /// How quickly the flames cool as they rise. Higher values make shorter flames.
+    pub cooling: u8,
+    /// Chance out of 255 of a new spark each step. Higher values make a more roaring fire.
+    pub sparking: u8,
+    rng: Rng,
+    heat: [u8; N],
+    last: Option<u64>,
+}
+
+impl<const N: usize> Fire<N> {
+    /// Milliseconds per simulation step, independent of the frame rate.
+    pub const STEP_MS: u64 = 16;
+    /// Most steps simulated in one frame, so one slow frame doesn't cause another.
+    const MAX_STEPS: u64 = 8;
+
+    pub const PARAMS: &'static [ParamInfo<'static>] = &[
+        ParamInfo::u8("cooling", 20, 100, 55),
+        ParamInfo::u8("sparking", 50, 200, 120),
+    ];
+
+    pub fn new(seed: u32) -> Self {
+        Self {
+            cooling: 55,
+            sparking: 120,
+            rng: Rng::new(seed),
+            heat: [0; N],
+            last: None,
+        }
+    }
+
+    fn step(&mut self, len: usize) {
+        let heat = &mut self.heat[..len];
+
+        // every cell cools down a little
+        let max_cooling = self.cooling as u32 * 10 / len as u32 + 2;
+        for cell in heat.iter_mut() {
+            *cell = cell.saturating_sub(self.rng.below(max_cooling).min(255) as u8);
+        }
+
+        // heat drifts up and diffuses
+        for k in (2..len).rev() {
+            heat[k] = ((heat[k - 1] as u16 + 2 * heat[k - 2] as u16) / 3) as u8;
+        }
+
+        // new sparks ignite near the bottom
+        if self.rng.below(255) < self.sparking as u32 {
+            let y = self.rng.below(len.min(7) as u32) as usize;
+            heat[y] = heat[y].saturating_add(160 + self.rng.below(96) as u8);
+        }
+    }
+}
+
+/// Black through red and yellow to white.
+pub fn heat_color(heat: u8) -> Rgb8 {
+    let t = (heat as u16 * 191 / 255) as u8;
+    let ramp = (t & 0x3f) << 2;
+    if t & 0x80 != 0 {
+        Rgb8::new(255, 255, ramp)
+    } else if t & 0x40 != 0 {
+        Rgb8::new(255, ramp, 0)
+    } else {
+        Rgb8::new(ramp, 0, 0)
+    }
+}
+
+impl<const N: usize> EffectMode for Fire<N> {
+    fn update(&mut self, _: &StripInfo, buf: &mut [RgbaF32], time: u64) {
+        let len = buf.len().min(N);
+        if len == 0 {
+            return;
+        }
+
+        // time jumps back when a node syncs to a server that started more
+        // recently, so step on from there rather than wait for it to catch up
+        let last = self.last.filter(|&last| last <= time).unwrap_or(time);
+        let steps = time.saturating_sub(last) / Self::STEP_MS;
+        for _ in 0..steps.min(Self::MAX_STEPS) {
+            self.step(len);
+        }
+        self.last = Some(last + steps * Self::STEP_MS);
+
+        for (px, &heat) in buf.iter_mut().zip(&self.heat) {
+            *px = heat_color(heat).into();
+        }
+    }
+
+    fn params(&self) -> &'static [ParamInfo<'static>] {
+        Self::PARAMS
+    }
+
+    fn param(&self, id: u8) -> Option<ParamValue> {
+        Some(match id {
+            0 => self.cooling.into(),
+            1 => self.sparking.into(),
+            _ => return None,
+        })
+    }
+
+    fn set_param(&mut self, id: u8, value: ParamValue) -> Result<(), ParamError> {
+        check(Self::PARAMS, id, value)?;
+        match (id, value) {
+            (0, ParamValue::U8(x)) => self.cooling = x,
+            (1, ParamValue::U8(x)) => self.sparking = x,
+            _ => return Err(ParamError::UnknownParam(id)),
+        }
+        Ok(())
+    }
+}
+
+/// A bright head travelling along the strip, leaving a randomly decaying tail.
+pub struct Meteor<const N: usize> {
+    pub color: Rgb8,
+    /// LEDs per second.
+    pub speed: f32,
+    /// Length of the head in LEDs.
+    pub size: usize,
+    /// Fraction of the tail's brightness lost per second.
+    pub decay: f32,
+    rng: Rng,
+    trail: [f32; N],
+    head: f32,
+    last: Option<u64>,
+}
+
+impl<const N: usize> Meteor<N> {
+    pub const PARAMS: &'static [ParamInfo<'static>] = &[
+        ParamInfo::color("color", Rgb8::new(180, 200, 255)),
+        ParamInfo::f32("speed", 1.0, 600.0, 60.0),
+        ParamInfo::u8("size", 1, 32, 4),
+        ParamInfo::f32("decay", 0.0, 20.0, 3.0),
+    ];
+
+    pub fn new(color: Rgb8, seed: u32) -> Self {
+        Self {
+            color,
+            speed: 60.0,
+            size: 4,
+            decay: 3.0,
+            rng: Rng::new(seed),
+            trail: [0.0; N],
+            head: 0.0,
+            last: None,
+        }
+    }
+
+    /// The index of the leading LED, which may be past the end while the tail fades.
+    pub fn head(&self) -> usize {
+        self.head as usize
+    }
+}
+
+impl<const N: usize> EffectMode for Meteor<N> {
+    fn update(&mut self, _: &StripInfo, buf: &mut [RgbaF32], time: u64) {
+        let len = buf.len().min(N);
+        let dt = elapsed(&mut self.last, time);
+
+        // leave room for the tail to fade out before starting over
+        self.head += self.speed * dt;
+        if self.head >= len as f32 * 1.5 {
+            self.head = 0.0;
+        }
+
+        let fade = (1.0 - self.decay * dt).max(0.0);
+        for level in &mut self.trail[..len] {
+            // decaying only some of the tail each frame breaks it up
+            if self.rng.next_f32() < 0.5 {
+                *level *= fade;
+            }
+        }
+
+        let head = self.head();
+        let tail = (head + 1).saturating_sub(self.size);
+        for level in self.trail[..len].iter_mut().take(head + 1).skip(tail) {
+            *level = 1.0;
+        }
+
+        for (px, level) in buf.iter_mut().zip(&self.trail) {
+            *px = RgbaF32::from_rgb(RgbF32::from(self.color), *level);
+        }
+    }
+
+    fn params(&self) -> &'static [ParamInfo<'static>] {
+        Self::PARAMS
+    }
+
+    fn param(&self, id: u8) -> Option<ParamValue> {
+        Some(match id {
+            0 => self.color.into(),
+            1 => self.speed.into(),
+            2 => (self.size as u8).into(),
+            3 => self.decay.into(),
+            _ => return None,
+        })
+    }
+
+    fn set_param(&mut self, id: u8, value: ParamValue) -> Result<(), ParamError> {
+        check(Self::PARAMS, id, value)?;
+        match (id, value) {
+            (0, ParamValue::Color(c)) => self.color = c,
+            (1, ParamValue::F32(x)) => self.speed = x,
+            (2, ParamValue::U8(x)) => self.size = x as usize,
+            (3, ParamValue::F32(x)) => self.decay = x,
+            _ => return Err(ParamError::UnknownParam(id)),
+        }
+        Ok(())
+    }
+}
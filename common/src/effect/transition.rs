@@ -0,0 +1,86 @@
+//! Transitions between two effects, blending what each renders while switching.
+
+use serde::{Deserialize, Serialize};
+
+use crate::{color::RgbaF32, effect::StripInfo, math::lerp};
+
+/// How the outgoing effect gives way to the incoming one.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TransitionKind {
+    /// Blend linearly from one effect to the other.
+    #[default]
+    Crossfade,
+    /// Sweep the new effect along the strip, starting from its first LED.
+    Wipe,
+    /// Fade the old effect out to black, then the new one in.
+    FadeThroughBlack,
+}
+
+/// A transition and how long it takes.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Transition {
+    pub kind: TransitionKind,
+    pub duration_ms: u32,
+}
+
+impl Transition {
+    pub const fn new(kind: TransitionKind, duration_ms: u32) -> Self {
+        Self { kind, duration_ms }
+    }
+
+    /// How far through the transition `elapsed_ms` is, in [0..1].
+    pub fn progress(&self, elapsed_ms: u64) -> f32 {
+        if self.duration_ms == 0 {
+            return 1.0;
+        }
+        (elapsed_ms as f32 / self.duration_ms as f32).min(1.0)
+    }
+
+    /// Blend the outgoing effect's colors into the incoming effect's, at `progress`.
+    ///
+    /// A wipe runs across the strip's own LEDs, from data index 0 or from its
+    /// last LED when it's reversed, so it always follows the strip's physical
+    /// direction.
+    pub fn blend(&self, from: &[RgbaF32], to: &mut [RgbaF32], progress: f32, strip: &StripInfo) {
+        let progress = progress.clamp(0.0, 1.0);
+        match self.kind {
+            TransitionKind::Crossfade => {
+                for (to, from) in to.iter_mut().zip(from) {
+                    *to = mix(*from, *to, progress);
+                }
+            }
+            TransitionKind::Wipe => {
+                let len = strip.leds.min(to.len());
+                let edge = progress * len as f32;
+                for (i, (to, from)) in to[..len].iter_mut().zip(from).enumerate() {
+                    let pos = if strip.rev { len - 1 - i } else { i };
+                    // soften the edge over a single LED
+                    *to = mix(*from, *to, (edge - pos as f32).clamp(0.0, 1.0));
+                }
+            }
+            TransitionKind::FadeThroughBlack => {
+                for (to, from) in to.iter_mut().zip(from) {
+                    *to = if progress < 0.5 {
+                        darken(*from, 1.0 - progress * 2.0)
+                    } else {
+                        darken(*to, progress * 2.0 - 1.0)
+                    };
+                }
+            }
+        }
+    }
+}
+
+fn mix(a: RgbaF32, b: RgbaF32, delta: f32) -> RgbaF32 {
+    RgbaF32::new_premultiplied(
+        lerp(a.r, b.r, delta),
+        lerp(a.g, b.g, delta),
+        lerp(a.b, b.b, delta),
+        lerp(a.a, b.a, delta),
+    )
+}
+
+/// Scale a color towards black, keeping its coverage.
+fn darken(c: RgbaF32, x: f32) -> RgbaF32 {
+    RgbaF32::new_premultiplied(c.r * x, c.g * x, c.b * x, c.a)
+}
@@ -0,0 +1,136 @@
+use std::{fmt, vec::Vec};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{effect::StripInfo, math::Vec3};
+
+/// LEDs per unit length, for strips that don't say otherwise (60/m strips, in meters).
+pub const DEFAULT_DENSITY: f32 = 60.0;
+
+/// A straight run of LEDs between two points.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Segment {
+    pub start: Vec3,
+    pub end: Vec3,
+    /// LEDs per unit length along this segment, overriding the strip's density.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub density: Option<f32>,
+}
+
+impl Segment {
+    pub fn new(start: Vec3, end: Vec3) -> Self {
+        Self {
+            start,
+            end,
+            density: None,
+        }
+    }
+
+    /// The number of LEDs that fit on this segment at a density.
+    pub fn leds(&self, density: f32) -> usize {
+        (self.start.distance(self.end) * density).round() as usize
+    }
+}
+
+/// The physical geometry of a strip in world space.
+///
+/// Geometry is described from the strip's first physical LED onwards, either as
+/// a polyline of [`Segment`]s or as explicit `positions`, which take precedence.
+/// When `rev` is set, data index 0 is the *last* physical LED.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StripLayout {
+    pub leds: usize,
+    #[serde(default)]
+    pub rev: bool,
+    /// LEDs per unit length, for segments that don't specify their own.
+    #[serde(default = "default_density")]
+    pub density: f32,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub segments: Vec<Segment>,
+    /// Explicit position of every LED, overriding `segments`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub positions: Vec<Vec3>,
+}
+
+fn default_density() -> f32 {
+    DEFAULT_DENSITY
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum LayoutError {
+    /// The geometry places a different number of LEDs than the strip has.
+    LedCountMismatch { leds: usize, placed: usize },
+    /// A density was zero, negative or not a number.
+    InvalidDensity(f32),
+}
+
+impl fmt::Display for LayoutError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::LedCountMismatch { leds, placed } => {
+                write!(f, "strip has {leds} LEDs but its geometry places {placed}")
+            }
+            Self::InvalidDensity(density) => write!(f, "invalid LED density {density}"),
+        }
+    }
+}
+
+impl std::error::Error for LayoutError {}
+
+impl StripLayout {
+    /// A strip laid out along a polyline at the default density.
+    pub fn polyline(leds: usize, segments: Vec<Segment>) -> Self {
+        Self {
+            leds,
+            rev: false,
+            density: DEFAULT_DENSITY,
+            segments,
+            positions: Vec::new(),
+        }
+    }
+
+    pub fn info(&self) -> StripInfo {
+        StripInfo {
+            leds: self.leds,
+            rev: self.rev,
+        }
+    }
+
+    /// The world-space position of every LED, indexed the same as the strip's color buffer.
+    pub fn positions(&self) -> Result<Vec<Vec3>, LayoutError> {
+        let mut positions = if self.positions.is_empty() {
+            self.trace()?
+        } else {
+            self.positions.clone()
+        };
+
+        if positions.len() != self.leds {
+            return Err(LayoutError::LedCountMismatch {
+                leds: self.leds,
+                placed: positions.len(),
+            });
+        }
+
+        if self.rev {
+            positions.reverse();
+        }
+        Ok(positions)
+    }
+
+    /// Place LEDs along the polyline, each centered in its share of a segment.
+    fn trace(&self) -> Result<Vec<Vec3>, LayoutError> {
+        let mut positions = Vec::with_capacity(self.leds);
+        for segment in &self.segments {
+            let density = segment.density.unwrap_or(self.density);
+            if density.is_nan() || density <= 0.0 {
+                return Err(LayoutError::InvalidDensity(density));
+            }
+
+            let n = segment.leds(density);
+            positions.extend(
+                (0..n).map(|i| segment.start.lerp(segment.end, (i as f32 + 0.5) / n as f32)),
+            );
+        }
+        Ok(positions)
+    }
+}
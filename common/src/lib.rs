@@ -1,6 +1,16 @@
 #![cfg_attr(not(feature = "std"), no_std)]
 
+extern crate alloc;
+
+pub mod clock;
 pub mod color;
+pub mod config;
 pub mod effect;
+#[cfg(feature = "std")]
+pub mod layout;
 pub mod math;
 pub mod net;
+pub mod ota;
+#[cfg(feature = "std")]
+pub mod preview;
+pub mod provision;
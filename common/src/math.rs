@@ -1,3 +1,7 @@
+use core::ops::{Add, AddAssign, Mul, Neg, Sub, SubAssign};
+
+use serde::{Deserialize, Serialize};
+
 #[cfg(feature = "firmware")]
 use num_traits::Float;
 
@@ -12,3 +16,180 @@ pub fn lerp(a: f32, b: f32, c: f32) -> f32 {
 pub fn f32_to_u8(x: f32) -> u8 {
     (x * 255.0).round().clamp(0.0, 255.0) as u8
 }
+
+/// Smooth Hermite interpolation of `x` from 0 at `edge0` to 1 at `edge1`.
+#[inline(always)]
+pub fn smoothstep(edge0: f32, edge1: f32, x: f32) -> f32 {
+    let t = ((x - edge0) / (edge1 - edge0)).clamp(0.0, 1.0);
+    t * t * (3.0 - 2.0 * t)
+}
+
+/// Hash a lattice point to a pseudo-random [0..1] f32.
+fn hash3(x: i32, y: i32, z: i32) -> f32 {
+    let mut h = (x as u32)
+        .wrapping_mul(0x8da6_b343)
+        .wrapping_add((y as u32).wrapping_mul(0xd816_3841))
+        .wrapping_add((z as u32).wrapping_mul(0xcb1a_b31f));
+    h ^= h >> 15;
+    h = h.wrapping_mul(0x2c1b_3c6d);
+    h ^= h >> 12;
+    (h >> 8) as f32 / (1 << 24) as f32
+}
+
+/// Smooth 3D value noise in [0..1], varying over roughly one unit.
+pub fn noise3(p: Vec3) -> f32 {
+    let (fx, fy, fz) = (p.x.floor(), p.y.floor(), p.z.floor());
+    let (x, y, z) = (fx as i32, fy as i32, fz as i32);
+    let (tx, ty, tz) = (
+        smoothstep(0.0, 1.0, p.x - fx),
+        smoothstep(0.0, 1.0, p.y - fy),
+        smoothstep(0.0, 1.0, p.z - fz),
+    );
+
+    let plane = |z| {
+        lerp(
+            lerp(hash3(x, y, z), hash3(x + 1, y, z), tx),
+            lerp(hash3(x, y + 1, z), hash3(x + 1, y + 1, z), tx),
+            ty,
+        )
+    };
+    lerp(plane(z), plane(z + 1), tz)
+}
+
+/// A small, fast xorshift PRNG for effects. Not suitable for anything that
+/// needs real randomness.
+#[derive(Debug, Clone)]
+pub struct Rng(u32);
+
+impl Rng {
+    pub const fn new(seed: u32) -> Self {
+        // xorshift gets stuck on zero
+        Self(if seed == 0 { 0x9e37_79b9 } else { seed })
+    }
+
+    pub fn next_u32(&mut self) -> u32 {
+        let mut x = self.0;
+        x ^= x << 13;
+        x ^= x >> 17;
+        x ^= x << 5;
+        self.0 = x;
+        x
+    }
+
+    /// A uniformly distributed f32 in [0..1).
+    pub fn next_f32(&mut self) -> f32 {
+        (self.next_u32() >> 8) as f32 / (1 << 24) as f32
+    }
+
+    /// A uniformly distributed u32 in `0..n`.
+    pub fn below(&mut self, n: u32) -> u32 {
+        ((self.next_u32() as u64 * n as u64) >> 32) as u32
+    }
+}
+
+/// A point or direction in 3D world space.
+///
+/// Serialized as an `[x, y, z]` array.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(from = "[f32; 3]", into = "[f32; 3]")]
+pub struct Vec3 {
+    pub x: f32,
+    pub y: f32,
+    pub z: f32,
+}
+
+impl Vec3 {
+    pub const fn new(x: f32, y: f32, z: f32) -> Self {
+        Self { x, y, z }
+    }
+
+    pub const fn zero() -> Self {
+        Self::new(0.0, 0.0, 0.0)
+    }
+
+    pub fn dot(self, other: Self) -> f32 {
+        self.x * other.x + self.y * other.y + self.z * other.z
+    }
+
+    pub fn length(self) -> f32 {
+        self.dot(self).sqrt()
+    }
+
+    pub fn distance(self, other: Self) -> f32 {
+        (self - other).length()
+    }
+
+    /// This vector scaled to a length of 1, or zero if it has no length.
+    pub fn normalize(self) -> Self {
+        let len = self.length();
+        if len > 0.0 {
+            self * (1.0 / len)
+        } else {
+            Self::zero()
+        }
+    }
+
+    pub fn lerp(self, other: Self, delta: f32) -> Self {
+        Self {
+            x: lerp(self.x, other.x, delta),
+            y: lerp(self.y, other.y, delta),
+            z: lerp(self.z, other.z, delta),
+        }
+    }
+}
+
+impl From<[f32; 3]> for Vec3 {
+    fn from([x, y, z]: [f32; 3]) -> Self {
+        Self { x, y, z }
+    }
+}
+
+impl From<Vec3> for [f32; 3] {
+    fn from(value: Vec3) -> Self {
+        [value.x, value.y, value.z]
+    }
+}
+
+impl Add for Vec3 {
+    type Output = Self;
+
+    fn add(self, rhs: Self) -> Self::Output {
+        Self::new(self.x + rhs.x, self.y + rhs.y, self.z + rhs.z)
+    }
+}
+
+impl AddAssign for Vec3 {
+    fn add_assign(&mut self, rhs: Self) {
+        *self = *self + rhs;
+    }
+}
+
+impl Sub for Vec3 {
+    type Output = Self;
+
+    fn sub(self, rhs: Self) -> Self::Output {
+        Self::new(self.x - rhs.x, self.y - rhs.y, self.z - rhs.z)
+    }
+}
+
+impl SubAssign for Vec3 {
+    fn sub_assign(&mut self, rhs: Self) {
+        *self = *self - rhs;
+    }
+}
+
+impl Mul<f32> for Vec3 {
+    type Output = Self;
+
+    fn mul(self, rhs: f32) -> Self::Output {
+        Self::new(self.x * rhs, self.y * rhs, self.z * rhs)
+    }
+}
+
+impl Neg for Vec3 {
+    type Output = Self;
+
+    fn neg(self) -> Self::Output {
+        Self::new(-self.x, -self.y, -self.z)
+    }
+}
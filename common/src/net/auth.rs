@@ -0,0 +1,311 @@
+//! Authenticating the server's traffic with a key shared by every node and
+//! server in an installation.
+//!
+//! Every MAC is HMAC-SHA256, cut down to its first [`MAC_LEN`] bytes.
+//!
+//! On a control session, a node holding a key follows its
+//! [`super::NodeMessage::Hello`] with a [`super::NodeMessage::Challenge`]
+//! carrying a fresh random nonce. From then on every [`super::ServerMessage`]
+//! arrives as a [`SealedFrame`]: the encoded message, a sequence number that
+//! counts up from 0 and a MAC over both and the nonce. The node drops the
+//! session on a frame that doesn't check out, so frames can't be forged,
+//! replayed from an earlier session or replayed within this one.
+//!
+//! Streamed [`super::udp`] packets set [`FLAG_MAC`] in their header and end
+//! with a MAC over everything before it and the packet's place in the
+//! session, made with a key derived from the installation's key and the
+//! session's nonce (see [`Key::for_session`]). A packet captured during one
+//! session is no good in any other, a node only takes packets newer than the
+//! last one it took (see [`PacketOpener`]), and it takes no signed packets at
+//! all until a session is open. DDP can't carry a MAC, so a node holding a key
+//! only takes it from the server holding its authenticated session.
+
+use core::fmt;
+
+use hmac::{Hmac, Mac as _};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+
+use crate::net::udp::{FLAG_MAC, PACKET_HEADER_LEN};
+
+/// The size of a [`Key`], in bytes.
+pub const KEY_LEN: usize = 32;
+
+/// The size of a MAC, in bytes.
+pub const MAC_LEN: usize = 8;
+
+/// Kept apart from [`UDP_CONTEXT`] so a MAC from one can't pass as the other.
+const TCP_CONTEXT: &[u8] = b"lightspace tcp";
+const UDP_CONTEXT: &[u8] = b"lightspace udp";
+const SESSION_CONTEXT: &[u8] = b"lightspace session";
+
+/// A truncated HMAC-SHA256.
+pub type Mac = [u8; MAC_LEN];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AuthError {
+    /// Traffic arrived without a MAC where one is needed.
+    Unsigned,
+    /// The MAC doesn't match, so it wasn't sent with this key or was changed
+    /// on the way.
+    BadMac,
+    /// A frame's sequence number didn't count up from the last one, or a
+    /// packet's didn't come after the last one taken.
+    Replayed(u32),
+    /// The frame or packet couldn't be decoded.
+    Malformed,
+}
+
+/// `Key` is an installation's pre-shared key.
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Key([u8; KEY_LEN]);
+
+impl fmt::Debug for Key {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // keep the key out of logs
+        f.write_str("Key(..)")
+    }
+}
+
+impl Key {
+    pub const fn new(bytes: [u8; KEY_LEN]) -> Self {
+        Self(bytes)
+    }
+
+    /// Parse a key written as [`KEY_LEN`] bytes of hex.
+    pub fn parse(hex: &str) -> Option<Self> {
+        let hex = hex.as_bytes();
+        if hex.len() != 2 * KEY_LEN {
+            return None;
+        }
+
+        let mut bytes = [0u8; KEY_LEN];
+        for (byte, pair) in bytes.iter_mut().zip(hex.chunks_exact(2)) {
+            let hi = (pair[0] as char).to_digit(16)?;
+            let lo = (pair[1] as char).to_digit(16)?;
+            *byte = (hi * 16 + lo) as u8;
+        }
+        Some(Self(bytes))
+    }
+
+    /// The full HMAC-SHA256 of `parts`, one after the other.
+    pub fn hmac(&self, parts: &[&[u8]]) -> [u8; 32] {
+        // HMAC takes keys of any length, so this can't fail
+        let mut hmac = Hmac::<Sha256>::new_from_slice(&self.0).unwrap();
+        for part in parts {
+            hmac.update(part);
+        }
+        hmac.finalize().into_bytes().into()
+    }
+
+    /// The MAC of `parts`, one after the other.
+    pub fn mac(&self, parts: &[&[u8]]) -> Mac {
+        let mut mac = [0u8; MAC_LEN];
+        mac.copy_from_slice(&self.hmac(parts)[..MAC_LEN]);
+        mac
+    }
+
+    /// Whether `mac` is the MAC of `parts`, taking as long whatever the answer.
+    pub fn verify(&self, parts: &[&[u8]], mac: &[u8]) -> bool {
+        bytes_match(&self.mac(parts), mac)
+    }
+
+    /// Whether `other` is the same key, taking as long whatever the answer.
+    pub fn matches(&self, other: &Key) -> bool {
+        bytes_match(&self.0, &other.0)
+    }
+
+    /// The key streamed packets are signed with during one session, given the
+    /// nonce from the node's challenge.
+    pub fn for_session(&self, nonce: u64) -> Self {
+        Self(self.hmac(&[SESSION_CONTEXT, &nonce.to_le_bytes()]))
+    }
+}
+
+/// Compare a MAC or key without stopping at the first difference, so the
+/// time taken doesn't give away how much of a guess was right.
+fn bytes_match(expected: &[u8], actual: &[u8]) -> bool {
+    actual.len() == expected.len()
+        && expected
+            .iter()
+            .zip(actual)
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+/// A [`super::ServerMessage`] as it goes over an authenticated control
+/// session, COBS-framed like any other.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SealedFrame<'a> {
+    pub seq: u32,
+    /// The postcard-encoded message.
+    pub msg: &'a [u8],
+    pub mac: Mac,
+}
+
+impl SealedFrame<'_> {
+    fn expected_mac(&self, key: &Key, nonce: u64) -> Mac {
+        key.mac(&[
+            TCP_CONTEXT,
+            &nonce.to_le_bytes(),
+            &self.seq.to_le_bytes(),
+            self.msg,
+        ])
+    }
+}
+
+/// `Sealer` seals the server's messages on one authenticated control session.
+#[derive(Debug, Clone)]
+pub struct Sealer {
+    key: Key,
+    nonce: u64,
+    seq: u32,
+}
+
+impl Sealer {
+    /// Start sealing a session, given the nonce from the node's challenge.
+    pub fn new(key: Key, nonce: u64) -> Self {
+        Self { key, nonce, seq: 0 }
+    }
+
+    /// Encode and seal the next message into a frame, like
+    /// [`super::encode_frame`].
+    pub fn seal<'a, T: Serialize>(
+        &mut self,
+        msg: &T,
+        buf: &'a mut [u8],
+    ) -> postcard::Result<&'a mut [u8]> {
+        let mut scratch = [0u8; super::MAX_FRAME_LEN];
+        let msg = postcard::to_slice(msg, &mut scratch)?;
+        let mut frame = SealedFrame {
+            seq: self.seq,
+            msg,
+            mac: [0; MAC_LEN],
+        };
+        frame.mac = frame.expected_mac(&self.key, self.nonce);
+        let encoded = postcard::to_slice_cobs(&frame, buf)?;
+        self.seq = self.seq.wrapping_add(1);
+        Ok(encoded)
+    }
+}
+
+/// `Opener` checks the frames of one authenticated control session on the
+/// node, in the order they arrive.
+#[derive(Debug, Clone)]
+pub struct Opener {
+    key: Key,
+    nonce: u64,
+    /// The sequence number expected next.
+    seq: u32,
+}
+
+impl Opener {
+    /// Start a session, given the nonce the node sent in its challenge.
+    pub fn new(key: Key, nonce: u64) -> Self {
+        Self { key, nonce, seq: 0 }
+    }
+
+    /// Check a frame and decode the message in it.
+    pub fn open<'de, T: Deserialize<'de>>(
+        &mut self,
+        frame: &SealedFrame<'de>,
+    ) -> Result<T, AuthError> {
+        if !bytes_match(&frame.expected_mac(&self.key, self.nonce), &frame.mac) {
+            return Err(AuthError::BadMac);
+        }
+        if frame.seq != self.seq {
+            return Err(AuthError::Replayed(frame.seq));
+        }
+        self.seq = self.seq.wrapping_add(1);
+        postcard::from_bytes(frame.msg).map_err(|_| AuthError::Malformed)
+    }
+}
+
+/// Sign the packet in `buf[..len]` with a session's key (see
+/// [`Key::for_session`]) as the `counter`th packet of the session, counting
+/// from 0. Sets [`FLAG_MAC`] and the header's sequence number, which is the
+/// counter's low 16 bits, and appends a MAC over the packet and the whole
+/// counter. Returns the signed length, or `None` if there's no room for the
+/// MAC.
+pub fn sign_packet(key: &Key, counter: u64, buf: &mut [u8], len: usize) -> Option<usize> {
+    if len < PACKET_HEADER_LEN || len + MAC_LEN > buf.len() {
+        return None;
+    }
+    buf[1] |= FLAG_MAC;
+    buf[2..4].copy_from_slice(&(counter as u16).to_le_bytes());
+    let mac = key.mac(&[UDP_CONTEXT, &counter.to_le_bytes(), &buf[..len]]);
+    buf[len..len + MAC_LEN].copy_from_slice(&mac);
+    Some(len + MAC_LEN)
+}
+
+/// `PacketOpener` checks the signed packets streamed during one session on
+/// the node, taking each only if it comes after the last one taken.
+///
+/// Packets carry the low 16 bits of their counter, and the opener takes the
+/// rest from the last packet it took, assuming the packet is less than half
+/// a wrap away. Since the MAC covers the whole counter, a packet replayed once
+/// the numbering has wrapped past it doesn't check out either.
+#[derive(Debug, Clone)]
+pub struct PacketOpener {
+    key: Key,
+    /// The counter of the last packet taken.
+    last: Option<u64>,
+}
+
+impl PacketOpener {
+    /// Start checking a session's packets, given its key (see [`Key::for_session`]).
+    pub fn new(key: Key) -> Self {
+        Self { key, last: None }
+    }
+
+    /// Check a packet's MAC and counter, remembering the counter if it's taken.
+    fn open(&mut self, packet: &[u8], mac: &[u8]) -> Result<(), AuthError> {
+        let sequence = u16::from_le_bytes([packet[2], packet[3]]);
+        let counter = match self.last {
+            None => sequence as u64,
+            Some(last) => {
+                let delta = sequence.wrapping_sub(last as u16) as i16;
+                last.checked_add_signed(delta as i64)
+                    .ok_or(AuthError::Replayed(sequence as u32))?
+            }
+        };
+        if !self
+            .key
+            .verify(&[UDP_CONTEXT, &counter.to_le_bytes(), packet], mac)
+        {
+            return Err(AuthError::BadMac);
+        }
+        if self.last.is_some_and(|last| counter <= last) {
+            return Err(AuthError::Replayed(sequence as u32));
+        }
+        self.last = Some(counter);
+        Ok(())
+    }
+}
+
+/// Check a streamed packet with the session's [`PacketOpener`], returning it
+/// without its MAC, ready for [`super::udp::UdpDecoder`].
+///
+/// A node without a key takes any packet, dropping the MAC unchecked if it
+/// has one.
+pub fn check_packet<'a>(
+    opener: Option<&mut PacketOpener>,
+    packet: &'a [u8],
+) -> Result<&'a [u8], AuthError> {
+    let signed = packet.get(1).ok_or(AuthError::Malformed)? & FLAG_MAC != 0;
+    if !signed {
+        return match opener {
+            Some(_) => Err(AuthError::Unsigned),
+            None => Ok(packet),
+        };
+    }
+
+    let (packet, mac) = packet
+        .split_at_checked(packet.len().saturating_sub(MAC_LEN))
+        .filter(|(packet, _)| packet.len() >= PACKET_HEADER_LEN)
+        .ok_or(AuthError::Malformed)?;
+    if let Some(opener) = opener {
+        opener.open(packet, mac)?;
+    }
+    Ok(packet)
+}
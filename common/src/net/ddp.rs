@@ -0,0 +1,161 @@
+//! Decoding and encoding of DDP (Distributed Display Protocol) packets, as
+//! sent by WLED and xLights.
+//!
+//! DDP addresses all of a node's strips as one run of RGB pixels, laid end to
+//! end in strip order, at a byte offset given by each packet. Pixels are held
+//! until a packet with the PUSH flag arrives, so a frame split across several
+//! packets and strips is shown all at once.
+//!
+//! | Byte  | Field                                               |
+//! |-------|-----------------------------------------------------|
+//! | 0     | flags: version (bits 6-7), timecode, query, push... |
+//! | 1     | sequence number (low 4 bits), 0 if unused           |
+//! | 2     | data type                                           |
+//! | 3     | destination id, 1 for the display                   |
+//! | 4-7   | data offset in bytes, big-endian                    |
+//! | 8-9   | data length in bytes, big-endian                    |
+//! | 10-13 | timecode, only present with the timecode flag       |
+
+use crate::color::Rgb8;
+
+use super::udp::{EncodeError, Pixels};
+
+/// The size of a header without a timecode.
+pub const HEADER_LEN: usize = 10;
+
+/// The most pixels senders put in one packet, keeping it under an Ethernet frame.
+pub const MAX_PIXELS: usize = 480;
+
+const VERSION_MASK: u8 = 0xc0;
+const VERSION_1: u8 = 0x40;
+
+pub const FLAG_TIMECODE: u8 = 0x10;
+pub const FLAG_QUERY: u8 = 0x02;
+pub const FLAG_PUSH: u8 = 0x01;
+
+/// The destination id of the display, the only one nodes accept.
+pub const ID_DISPLAY: u8 = 1;
+
+/// Data types that mean 8-bit RGB: undefined, the original RGB and RGB24.
+const RGB_TYPES: [u8; 3] = [0x00, 0x01, 0x0b];
+const TYPE_RGB24: u8 = 0x0b;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DdpError {
+    /// The packet isn't DDP version 1.
+    Version(u8),
+    /// The packet ended in the middle of its header or data.
+    Truncated,
+    /// The data isn't 8-bit RGB.
+    UnsupportedType(u8),
+    /// The packet is for a destination other than the display.
+    UnsupportedId(u8),
+    /// The data offset doesn't fall on a pixel boundary.
+    Misaligned(u32),
+}
+
+/// A decoded DDP data packet, borrowing its pixels.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DdpPacket<'a> {
+    /// Show every pixel received so far once this packet is written.
+    pub push: bool,
+    pub sequence: u8,
+    /// The index of the first pixel, across all strips.
+    pub start: usize,
+    pub pixels: Pixels<'a, Rgb8>,
+}
+
+impl<'a> DdpPacket<'a> {
+    pub fn parse(packet: &'a [u8]) -> Result<Self, DdpError> {
+        let header = packet.get(..HEADER_LEN).ok_or(DdpError::Truncated)?;
+        let flags = header[0];
+        if flags & VERSION_MASK != VERSION_1 {
+            return Err(DdpError::Version(flags >> 6));
+        }
+        if !RGB_TYPES.contains(&header[2]) {
+            return Err(DdpError::UnsupportedType(header[2]));
+        }
+        if header[3] != ID_DISPLAY {
+            return Err(DdpError::UnsupportedId(header[3]));
+        }
+
+        let offset = u32::from_be_bytes([header[4], header[5], header[6], header[7]]);
+        if offset % 3 != 0 {
+            return Err(DdpError::Misaligned(offset));
+        }
+
+        let len = u16::from_be_bytes([header[8], header[9]]) as usize;
+        let data_start = if flags & FLAG_TIMECODE != 0 {
+            HEADER_LEN + 4
+        } else {
+            HEADER_LEN
+        };
+        // queries carry no data, and a trailing partial pixel is ignored
+        let len = if flags & FLAG_QUERY != 0 {
+            0
+        } else {
+            len - len % 3
+        };
+        let data = packet
+            .get(data_start..data_start + len)
+            .ok_or(DdpError::Truncated)?;
+
+        Ok(Self {
+            push: flags & FLAG_PUSH != 0,
+            sequence: header[1] & 0x0f,
+            start: offset as usize / 3,
+            pixels: Pixels::new(data),
+        })
+    }
+
+    /// Write this packet's pixels into strips laid end to end.
+    ///
+    /// Pixels that fall past the last strip are ignored.
+    pub fn write<'b>(&self, strips: impl IntoIterator<Item = &'b mut [Rgb8]>) {
+        let mut pixels = self.pixels.iter();
+        let mut skip = self.start;
+        for strip in strips {
+            if pixels.len() == 0 {
+                return;
+            }
+            if skip >= strip.len() {
+                skip -= strip.len();
+                continue;
+            }
+
+            for (dst, color) in strip[skip..].iter_mut().zip(&mut pixels) {
+                *dst = color;
+            }
+            skip = 0;
+        }
+    }
+}
+
+/// Encode a data packet for the display, starting at pixel `start`.
+pub fn encode<'b>(
+    buf: &'b mut [u8],
+    sequence: u8,
+    push: bool,
+    start: usize,
+    colors: &[Rgb8],
+) -> Result<&'b [u8], EncodeError> {
+    let data_len = colors.len() * 3;
+    let len = HEADER_LEN + data_len;
+    let data_len = u16::try_from(data_len).map_err(|_| EncodeError::TooManyPixels)?;
+    let offset = u32::try_from(start * 3).map_err(|_| EncodeError::TooManyPixels)?;
+    if len > buf.len() {
+        return Err(EncodeError::BufferFull);
+    }
+
+    let packet = &mut buf[..len];
+    packet[0] = VERSION_1 | if push { FLAG_PUSH } else { 0 };
+    packet[1] = sequence & 0x0f;
+    packet[2] = TYPE_RGB24;
+    packet[3] = ID_DISPLAY;
+    packet[4..8].copy_from_slice(&offset.to_be_bytes());
+    packet[8..10].copy_from_slice(&data_len.to_be_bytes());
+    for (color, dst) in colors.iter().zip(packet[HEADER_LEN..].chunks_exact_mut(3)) {
+        dst.copy_from_slice(&[color.r, color.g, color.b]);
+    }
+    Ok(packet)
+}
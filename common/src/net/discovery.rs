@@ -0,0 +1,87 @@
+//! Nodes announcing themselves, so the server can find them without being
+//! told their addresses.
+//!
+//! Once it's on the network, a node broadcasts a [`Beacon`] to
+//! [`DISCOVERY_PORT`] every [`BEACON_INTERVAL_MS`]. The server registers any
+//! node it doesn't know yet at the beacon's source address and
+//! [`Beacon::tcp_port`], and knows it by [`Beacon::id`] from then on, so it
+//! follows the node to a new address.
+//!
+//! A beacon is [`MAGIC`], then [`BEACON_VERSION`], then the postcard-encoded
+//! [`Beacon`], so anything else arriving on the port is easy to tell apart.
+
+use alloc::string::String;
+
+use serde::{Deserialize, Serialize};
+
+use crate::net::{Mcu, Version};
+
+/// The UDP port the server listens on for beacons.
+pub const DISCOVERY_PORT: u16 = 1339;
+
+/// How often a node broadcasts its beacon.
+pub const BEACON_INTERVAL_MS: u64 = 5000;
+
+/// The bytes every beacon starts with.
+pub const MAGIC: [u8; 4] = *b"LSNB";
+
+/// The version of the beacon layout, bumped whenever it changes.
+pub const BEACON_VERSION: u8 = 1;
+
+/// Room for a beacon with the longest node name.
+pub const MAX_BEACON_LEN: usize = 64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BeaconError {
+    /// The packet doesn't start with [`MAGIC`].
+    NotABeacon,
+    /// The beacon was encoded with a different [`BEACON_VERSION`].
+    UnsupportedVersion(u8),
+    /// The beacon's payload couldn't be decoded.
+    Malformed,
+}
+
+/// A node's announcement of itself.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Beacon {
+    /// Unique to the node and stable across restarts, like its MAC address.
+    pub id: [u8; 6],
+    /// The name the node was set up with, or empty.
+    pub name: String,
+    pub version: Version,
+    pub mcu: Mcu,
+    /// The number of strips the node drives.
+    pub num_strips: u8,
+    /// The port the node accepts the server's control session on.
+    pub tcp_port: u16,
+    /// The port the node receives streamed colors on.
+    pub udp_port: u16,
+    /// The port the node receives DDP pixels on.
+    pub ddp_port: u16,
+}
+
+impl Beacon {
+    /// Encode the beacon into `buf`, returning the bytes to send.
+    pub fn encode<'a>(&self, buf: &'a mut [u8]) -> postcard::Result<&'a mut [u8]> {
+        let header = MAGIC.len() + 1;
+        if buf.len() < header {
+            return Err(postcard::Error::SerializeBufferFull);
+        }
+        buf[..MAGIC.len()].copy_from_slice(&MAGIC);
+        buf[MAGIC.len()] = BEACON_VERSION;
+        let len = postcard::to_slice(self, &mut buf[header..])?.len();
+        Ok(&mut buf[..header + len])
+    }
+
+    /// Decode a received beacon.
+    pub fn decode(bytes: &[u8]) -> Result<Self, BeaconError> {
+        let rest = bytes.strip_prefix(&MAGIC).ok_or(BeaconError::NotABeacon)?;
+        match rest.split_first() {
+            Some((&BEACON_VERSION, payload)) => {
+                postcard::from_bytes(payload).map_err(|_| BeaconError::Malformed)
+            }
+            Some((&version, _)) => Err(BeaconError::UnsupportedVersion(version)),
+            None => Err(BeaconError::Malformed),
+        }
+    }
+}
@@ -0,0 +1,121 @@
+//! What a streaming strip does when the server stops sending it colors, so a
+//! crashed server doesn't leave its last frame up forever.
+//!
+//! Each strip starts with [`DEFAULT_TIMEOUT`], and the server can set another
+//! with [`super::ServerMessage::SetStreamTimeout`], which the node keeps in
+//! its config. Once that long passes without a packet for the strip, it falls
+//! back, and it picks up the stream again as soon as packets return. The
+//! strip's [`StripMode`] never changes, so the server doesn't need to set it
+//! again.
+
+use serde::{Deserialize, Serialize};
+
+use crate::net::StripMode;
+
+/// How long [`Fallback::Fade`] takes to fade the streamed layer out.
+pub const FADE_MS: u32 = 1000;
+
+/// The timeout a strip has until the server sets another.
+pub const DEFAULT_TIMEOUT: StreamTimeout = StreamTimeout::new(5000, Fallback::Fade);
+
+/// What a strip shows once its stream times out.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Fallback {
+    /// Run the node's effects, as in [`StripMode::Effects`].
+    Effects,
+    /// Turn the LEDs off, as in [`StripMode::Off`].
+    Off,
+    /// Fade the streamed layer out over [`FADE_MS`], uncovering the effects
+    /// beneath it in [`StripMode::Hybrid`] or going dark in [`StripMode::Dynamic`].
+    #[default]
+    Fade,
+}
+
+/// How long a streaming strip waits for packets before falling back.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StreamTimeout {
+    pub timeout_ms: u32,
+    pub fallback: Fallback,
+}
+
+impl StreamTimeout {
+    pub const fn new(timeout_ms: u32, fallback: Fallback) -> Self {
+        Self {
+            timeout_ms,
+            fallback,
+        }
+    }
+}
+
+/// `StreamWatchdog` tracks when a strip last heard from the server, and how
+/// it should render given that.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StreamWatchdog {
+    timeout: Option<StreamTimeout>,
+    /// When the last packet arrived, in local milliseconds.
+    last_packet: Option<u64>,
+}
+
+impl StreamWatchdog {
+    pub const fn new() -> Self {
+        Self {
+            timeout: Some(DEFAULT_TIMEOUT),
+            last_packet: None,
+        }
+    }
+
+    pub fn timeout(&self) -> Option<StreamTimeout> {
+        self.timeout
+    }
+
+    /// Set the timeout, or hold the last frame forever with `None`.
+    pub fn set_timeout(&mut self, timeout: Option<StreamTimeout>) {
+        self.timeout = timeout;
+    }
+
+    /// Note that a packet for the strip arrived at `now`.
+    pub fn feed(&mut self, now: u64) {
+        self.last_packet = Some(now);
+    }
+
+    /// Whether the stream has gone quiet for longer than the timeout.
+    ///
+    /// A stream that never started can't time out.
+    pub fn is_timed_out(&self, now: u64) -> bool {
+        self.quiet_past_timeout(now).is_some()
+    }
+
+    /// How long the stream has been quiet past its timeout, if it has.
+    fn quiet_past_timeout(&self, now: u64) -> Option<u64> {
+        let timeout = self.timeout?;
+        let quiet = now.saturating_sub(self.last_packet?);
+        quiet.checked_sub(timeout.timeout_ms as u64)
+    }
+
+    /// How a strip in `mode` renders at `now`: the mode it behaves as, and
+    /// the opacity of its streamed layer.
+    pub fn apply(&self, mode: StripMode, now: u64) -> (StripMode, f32) {
+        let timeout = match self.timeout {
+            Some(timeout) if mode.streams() => timeout,
+            _ => return (mode, 1.0),
+        };
+        let Some(past) = self.quiet_past_timeout(now) else {
+            return (mode, 1.0);
+        };
+
+        match timeout.fallback {
+            Fallback::Effects => (StripMode::Effects, 1.0),
+            Fallback::Off => (StripMode::Off, 1.0),
+            Fallback::Fade => {
+                let faded = past as f32 / FADE_MS as f32;
+                (mode, (1.0 - faded).max(0.0))
+            }
+        }
+    }
+}
+
+impl Default for StreamWatchdog {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,195 @@
+//! Holding streamed frames back until their timestamps, so network jitter
+//! doesn't show up as uneven motion and every node shows a frame at once.
+//!
+//! The server stamps each frame with when it should be shown, in server time
+//! [`PRESENTATION_DELAY_MS`](super::PRESENTATION_DELAY_MS) ahead of when it
+//! was rendered. A node queues frames as they arrive and presents each one
+//! once its synced clock (see [`crate::clock`]) reaches the timestamp.
+//!
+//! Timestamps are `u32` milliseconds and compared with wrapping arithmetic,
+//! so they can roll over after about 49 days.
+
+use crate::{color::RgbaF32, math::lerp};
+
+/// The most frames that can be missing in a row and still be interpolated
+/// over. Longer gaps mean the stream paused, so the next frame just waits.
+pub const MAX_MISSING: u32 = 3;
+
+/// Whether timestamp `a` comes at or before `b`.
+fn at_or_before(a: u32, b: u32) -> bool {
+    (b.wrapping_sub(a) as i32) >= 0
+}
+
+/// What a strip should show after [`JitterBuffer::present`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Presented<'a, T> {
+    /// A frame whose time has come.
+    Frame(&'a T),
+    /// A point `t` of the way between two frames, standing in for frames
+    /// that never arrived.
+    Between { from: &'a T, to: &'a T, t: f32 },
+}
+
+impl<T: AsRef<[RgbaF32]>> Presented<'_, T> {
+    /// Write the presented colors into a strip's color buffer.
+    pub fn write(&self, buf: &mut [RgbaF32]) {
+        match *self {
+            Self::Frame(frame) => {
+                for (dst, src) in buf.iter_mut().zip(frame.as_ref()) {
+                    *dst = *src;
+                }
+            }
+            Self::Between { from, to, t } => {
+                let pairs = from.as_ref().iter().zip(to.as_ref());
+                for (dst, (a, b)) in buf.iter_mut().zip(pairs) {
+                    *dst = RgbaF32::new_premultiplied(
+                        lerp(a.r, b.r, t),
+                        lerp(a.g, b.g, t),
+                        lerp(a.b, b.b, t),
+                        lerp(a.a, b.a, t),
+                    );
+                }
+            }
+        }
+    }
+}
+
+/// `JitterBuffer` queues up to `N` timestamped frames for one strip and
+/// hands each back when it's due.
+#[derive(Debug)]
+pub struct JitterBuffer<T, const N: usize> {
+    /// The frame presented last, kept to interpolate from.
+    current: Option<(u32, T)>,
+    /// Frames waiting for their time, in timestamp order. Only the first
+    /// `len` are `Some`.
+    queue: [Option<(u32, T)>; N],
+    len: usize,
+    /// How far apart frames are expected to be, if missing ones should be
+    /// interpolated over.
+    interval: Option<u32>,
+}
+
+impl<T, const N: usize> JitterBuffer<T, N> {
+    /// An empty buffer, interpolating over missing frames if given the
+    /// interval frames are expected at, in milliseconds.
+    pub const fn new(interval: Option<u32>) -> Self {
+        Self {
+            current: None,
+            queue: [const { None }; N],
+            len: 0,
+            interval,
+        }
+    }
+
+    /// The number of frames waiting to be presented.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// The timestamp of the frame presented last.
+    pub fn current(&self) -> Option<u32> {
+        self.current.as_ref().map(|(timestamp, _)| *timestamp)
+    }
+
+    /// Forget every frame, e.g. when the clock timestamps are read against
+    /// starts over.
+    pub fn reset(&mut self) {
+        self.current = None;
+        self.queue.iter_mut().for_each(|slot| *slot = None);
+        self.len = 0;
+    }
+
+    /// The frame to fill in for `timestamp`, queueing it if it's new.
+    ///
+    /// A new frame starts as a copy of the newest one queued, or of `base` if
+    /// none are, since one frame's commands may not cover the whole strip.
+    /// Once the queue is full, the oldest frame is dropped to make room.
+    ///
+    /// Returns `None` if the frame is late: something at or after
+    /// `timestamp` has already been presented.
+    pub fn stage(&mut self, timestamp: u32, base: &T) -> Option<&mut T>
+    where
+        T: Clone,
+    {
+        let late = self
+            .current()
+            .is_some_and(|cur| at_or_before(timestamp, cur));
+        if N == 0 || late {
+            return None;
+        }
+
+        let queued = &self.queue[..self.len];
+        let pos = queued
+            .iter()
+            .flatten()
+            .position(|(ts, _)| !at_or_before(*ts, timestamp))
+            .unwrap_or(self.len);
+        if pos > 0 && self.timestamp(pos - 1) == timestamp {
+            return self.frame(pos - 1);
+        }
+
+        let frame = match self.len {
+            0 => base.clone(),
+            len => self.queue[len - 1].as_ref().map(|(_, f)| f.clone())?,
+        };
+        let mut pos = pos;
+        if self.len == N {
+            // nothing older than the oldest frame gets queued in its place
+            if pos == 0 {
+                return None;
+            }
+            self.queue.rotate_left(1);
+            self.queue[N - 1] = None;
+            self.len -= 1;
+            pos -= 1;
+        }
+
+        self.queue[self.len] = Some((timestamp, frame));
+        self.len += 1;
+        self.queue[pos..self.len].rotate_right(1);
+        self.frame(pos)
+    }
+
+    fn timestamp(&self, i: usize) -> u32 {
+        self.queue[i].as_ref().map_or(0, |(ts, _)| *ts)
+    }
+
+    fn frame(&mut self, i: usize) -> Option<&mut T> {
+        self.queue[i].as_mut().map(|(_, f)| f)
+    }
+
+    /// Present whatever is due at `now`, skipping frames that were due
+    /// earlier but never presented.
+    ///
+    /// Returns `None` when there's nothing new to show, so the strip keeps
+    /// its colors.
+    pub fn present(&mut self, now: u32) -> Option<Presented<'_, T>> {
+        let mut changed = false;
+        while self.len > 0 && at_or_before(self.timestamp(0), now) {
+            self.current = self.queue[0].take();
+            self.queue[..self.len].rotate_left(1);
+            self.len -= 1;
+            changed = true;
+        }
+
+        let from = self.current.as_ref()?;
+        let next = self.queue.first().and_then(Option::as_ref);
+        if let (Some(interval), Some(to)) = (self.interval, next) {
+            let gap = to.0.wrapping_sub(from.0);
+            let missing = gap > interval + interval / 2 && gap <= interval * (MAX_MISSING + 1);
+            if missing {
+                let t = now.wrapping_sub(from.0) as f32 / gap as f32;
+                return Some(Presented::Between {
+                    from: &from.1,
+                    to: &to.1,
+                    t,
+                });
+            }
+        }
+        changed.then_some(Presented::Frame(&from.1))
+    }
+}
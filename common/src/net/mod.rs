@@ -0,0 +1,336 @@
+use alloc::vec::Vec;
+use core::str::FromStr;
+
+use num_enum::TryFromPrimitive;
+use serde::{
+    Deserialize, Serialize,
+    de::{IntoDeserializer, value},
+};
+
+use crate::{
+    color::ColorOrder,
+    effect::{
+        StripInfo,
+        fx::OverlayConfig,
+        param::{ParamError, ParamValue},
+        transition::Transition,
+    },
+    ota::{UpdateChunk, UpdateHeader, UpdateStatus},
+};
+
+pub mod auth;
+pub mod ddp;
+pub mod discovery;
+pub mod failsafe;
+pub mod jitter;
+pub mod udp;
+
+/// The UDP port nodes listen on for streamed color data.
+pub const UDP_PORT: u16 = 1337;
+
+/// The UDP port nodes listen on for [`ddp`] color data.
+pub const DDP_PORT: u16 = 4048;
+
+/// The TCP port nodes listen on for the server's control session.
+pub const TCP_PORT: u16 = 1338;
+
+/// How often the server sends [`ServerMessage::KeepAlive`] on an idle control session.
+pub const KEEP_ALIVE_INTERVAL_MS: u64 = 2000;
+
+/// How long a node waits without hearing from the server before dropping the session.
+pub const SESSION_TIMEOUT_MS: u64 = 10000;
+
+/// How often the server streams frames, in milliseconds.
+pub const STREAM_INTERVAL_MS: u32 = 16;
+
+/// How far ahead of the server's clock streamed frames are timestamped, so
+/// nodes have time to receive and queue them (see [`jitter`]).
+pub const PRESENTATION_DELAY_MS: u32 = 50;
+
+/// How many timestamped frames a node queues per strip, enough to cover
+/// [`PRESENTATION_DELAY_MS`] at [`STREAM_INTERVAL_MS`] with room to spare.
+pub const QUEUED_FRAMES: usize = 4;
+
+/// The maximum size of a single encoded TCP frame, including the delimiter.
+pub const MAX_FRAME_LEN: usize = 256;
+
+/// A TCP message from the server to a node.
+///
+/// On the wire, every message is postcard-encoded and COBS-framed, so frames
+/// are delimited by a single `0x00` byte (see [`encode_frame`]).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum ServerMessage {
+    /// Set a strip's mode, given a [`StripMode`].
+    SetStripMode(u8, StripMode),
+    /// Shift the current effect mode by a delta, with a transition or the node's default.
+    ShiftEffectMode(i8, Option<Transition>),
+    /// Switch to an effect by its id in [`crate::effect::registry`], with a
+    /// transition or the node's default.
+    SelectEffect(u8, Option<Transition>),
+    /// Set a parameter of an effect, given the effect's id and the parameter's id.
+    SetEffectParam(u8, u8, ParamValue),
+    /// Sent periodically to keep an otherwise idle session alive.
+    KeepAlive,
+    /// Answers a [`NodeMessage::TimeRequest`] with the node's time from the
+    /// request, then the server's time, both in milliseconds.
+    TimeResponse(u64, u64),
+    /// Set how long a strip waits for streamed colors before falling back,
+    /// or `None` to hold the last frame forever (see [`failsafe`]).
+    SetStreamTimeout(u8, Option<failsafe::StreamTimeout>),
+    /// Replace a strip's overlays, bottom first, with up to
+    /// [`crate::effect::fx::MAX_OVERLAYS`] registered effects at their
+    /// default parameters.
+    SetOverlays(u8, Vec<OverlayConfig>),
+    /// Rewire a strip, given a [`StripConfig`]. The node answers with
+    /// [`NodeMessage::StripConfigured`] once it's applied.
+    ConfigureStrip(u8, StripConfig),
+    /// Open a firmware update, replacing any update already open (see [`crate::ota`]).
+    BeginUpdate(UpdateHeader),
+    /// The next piece of the open update's image.
+    UpdateChunk(UpdateChunk),
+}
+
+/// Encode a message into a COBS-framed postcard frame, terminated by `0x00`.
+pub fn encode_frame<'a, T: Serialize>(
+    msg: &T,
+    buf: &'a mut [u8],
+) -> postcard::Result<&'a mut [u8]> {
+    postcard::to_slice_cobs(msg, buf)
+}
+
+/// Decode a single COBS-framed postcard frame, with or without its trailing `0x00`.
+///
+/// The frame is decoded in place, so `frame` is clobbered.
+pub fn decode_frame<'a, T: Deserialize<'a>>(frame: &'a mut [u8]) -> postcard::Result<T> {
+    postcard::from_bytes_cobs(frame)
+}
+
+/// How often a node sends [`NodeMessage::Heartbeat`] to the server.
+pub const HEARTBEAT_INTERVAL_MS: u64 = 1000;
+
+/// A TCP message from a node to the server.
+///
+/// When a session opens, the node sends [`NodeMessage::Hello`], a
+/// [`NodeMessage::Challenge`] if it holds a key, one [`NodeMessage::Strip`]
+/// per strip and a [`NodeMessage::TimeRequest`], then a
+/// [`NodeMessage::Heartbeat`] and a [`NodeMessage::TimeRequest`] every
+/// [`HEARTBEAT_INTERVAL_MS`]. Framed the same way as [`ServerMessage`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum NodeMessage {
+    /// Handshake, sent once when the session opens.
+    Hello(NodeHello),
+    /// Describes a single strip, by index. Sent for every strip after [`NodeMessage::Hello`].
+    Strip(u8, StripInfo),
+    /// Periodic telemetry.
+    Heartbeat(Heartbeat),
+    /// Something went wrong on the node.
+    Error(NodeError),
+    /// Asks for the server's time, given the node's in milliseconds, to sync
+    /// clocks (see [`crate::clock`]).
+    TimeRequest(u64),
+    /// Acknowledges a [`ServerMessage::ConfigureStrip`] with the strip's new config.
+    StripConfigured(u8, StripConfig),
+    /// How a firmware update is going (see [`crate::ota`]).
+    Update(UpdateStatus),
+    /// A fresh nonce the server seals the rest of the session with, sent
+    /// right after [`NodeMessage::Hello`] by nodes holding a key (see [`auth`]).
+    Challenge(u64),
+}
+
+/// Identifies a node's firmware and hardware.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NodeHello {
+    pub version: Version,
+    pub mcu: Mcu,
+    /// The number of strips this node drives, i.e. `NUM_STRIPS`.
+    pub num_strips: u8,
+}
+
+/// A `major.minor.patch` firmware version.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct Version {
+    pub major: u16,
+    pub minor: u16,
+    pub patch: u16,
+}
+
+impl Version {
+    pub const fn new(major: u16, minor: u16, patch: u16) -> Self {
+        Self {
+            major,
+            minor,
+            patch,
+        }
+    }
+
+    /// Parse a `major.minor.patch` string at compile time, e.g. from `CARGO_PKG_VERSION`.
+    ///
+    /// Missing components are zero, and anything after the patch number
+    /// (like a `-pre` suffix) is ignored.
+    pub const fn parse(s: &str) -> Self {
+        let bytes = s.as_bytes();
+        let mut parts = [0u16; 3];
+        let mut part = 0;
+        let mut i = 0;
+        while i < bytes.len() && part < 3 {
+            match bytes[i] {
+                b'.' => part += 1,
+                c @ b'0'..=b'9' => parts[part] = parts[part] * 10 + (c - b'0') as u16,
+                _ => break,
+            }
+            i += 1;
+        }
+        Self::new(parts[0], parts[1], parts[2])
+    }
+}
+
+/// The microcontroller a node runs on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Mcu {
+    Esp32s3,
+    Esp32c6,
+    /// A virtual node running on a host machine.
+    Host,
+}
+
+/// Periodic node telemetry.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Heartbeat {
+    /// Milliseconds since boot.
+    pub uptime_ms: u64,
+    /// Free heap, in bytes.
+    pub free_heap: u32,
+    /// Frames transmitted per second over the last heartbeat interval.
+    pub frame_rate: f32,
+    /// UDP packets dropped since boot.
+    pub dropped_packets: u32,
+    /// UDP packets dropped since boot for arriving after a newer one.
+    pub stale_packets: u32,
+    /// UDP packets and TCP frames dropped since boot for failing
+    /// authentication (see [`auth`]).
+    pub rejected_packets: u32,
+    /// Wi-Fi signal strength in dBm, if connected.
+    pub rssi: Option<i8>,
+}
+
+/// An error reported by a node.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum NodeError {
+    /// A TCP frame could not be decoded into a [`ServerMessage`].
+    MalformedFrame,
+    /// A message targeted a strip index the node doesn't have.
+    UnknownStrip(u8),
+    /// A message targeted an effect id the node doesn't have.
+    UnknownEffect(u8),
+    /// A parameter could not be set on an effect, given the effect's id.
+    InvalidParam(u8, ParamError),
+    /// A strip was configured with more LEDs than the node can drive.
+    StripTooLong(u8),
+    /// A strip was given more than [`crate::effect::fx::MAX_OVERLAYS`], or
+    /// one with an opacity outside 0 to 1, given the strip's index.
+    InvalidOverlay(u8),
+    /// A TCP frame failed authentication, so the node is dropping the session.
+    Unauthenticated(auth::AuthError),
+}
+
+/// The LED chip on a strip, which sets the timing of its data signal.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Chipset {
+    #[default]
+    Ws2812b,
+    Sk6812,
+    Ws2811,
+}
+
+/// Parses the chipset's lowercase name, as in configs.
+impl FromStr for Chipset {
+    type Err = value::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::deserialize(s.into_deserializer())
+    }
+}
+
+/// How a strip is wired, which the server can change at runtime with
+/// [`ServerMessage::ConfigureStrip`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StripConfig {
+    pub leds: usize,
+    pub rev: bool,
+    /// The order the chips take their color channels in.
+    pub order: ColorOrder,
+    pub chipset: Chipset,
+}
+
+impl StripConfig {
+    /// A strip of WS2812Bs, which take green first.
+    pub const fn new(info: StripInfo) -> Self {
+        Self {
+            leds: info.leds,
+            rev: info.rev,
+            order: ColorOrder::Grb,
+            chipset: Chipset::Ws2812b,
+        }
+    }
+
+    /// What effects need to know about the strip.
+    pub const fn info(&self) -> StripInfo {
+        StripInfo {
+            leds: self.leds,
+            rev: self.rev,
+        }
+    }
+}
+
+/// A UDP message from the server to a node.
+///
+/// See [`udp`] for the wire format of each message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, TryFromPrimitive)]
+#[repr(u8)]
+pub enum UdpMessage {
+    /// Set the entire buffer to a bunch of individually specified colors.
+    SetBufferToMany,
+    /// Set the entire buffer to a single color.
+    SetBufferToSingle,
+    /// Set a single pixel index to a certain color.
+    SetSinglePixel,
+
+    /// Set the entire buffer to a bunch of individually specified colors, with alpha control.
+    SetBufferToManyAlpha,
+    /// Set the entire buffer to a specific color, with alpha control.
+    SetBufferToSingleAlpha,
+
+    /// Set a contiguous range of pixels to individually specified colors.
+    SetPixelRange,
+    /// Set a contiguous range of pixels to individually specified colors, with alpha control.
+    SetPixelRangeAlpha,
+}
+
+/// Describes the current state of a node.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[repr(u8)]
+pub enum StripMode {
+    /// LEDs are turned off.
+    Off,
+    /// LEDs use effect modes that render on the node.
+    #[default]
+    Effects,
+    /// LEDs use streamed color data from the server.
+    Dynamic,
+    /// LEDs use effect modes, but streamed data can include an alpha channel
+    /// that will dictate how much of streamed data versus effects data to use.
+    Hybrid,
+}
+
+impl StripMode {
+    /// Whether strips in this mode render the node's effects.
+    pub const fn runs_effects(self) -> bool {
+        matches!(self, Self::Effects | Self::Hybrid)
+    }
+
+    /// Whether strips in this mode show colors streamed from the server.
+    pub const fn streams(self) -> bool {
+        matches!(self, Self::Dynamic | Self::Hybrid)
+    }
+}
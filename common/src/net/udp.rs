@@ -0,0 +1,673 @@
+//! Encoding and decoding of [`UdpMessage`] packets.
+//!
+//! A packet starts with a [`PacketHeader`], then holds any number of commands
+//! back to back. Every command starts with its [`UdpMessage`] byte and the
+//! target strip index, followed by a payload. Multi-byte integers are
+//! little-endian, and alpha colors are pre-multiplied.
+//!
+//! | Header byte | Field                                                  |
+//! |-------------|--------------------------------------------------------|
+//! | 0           | protocol version, [`PROTOCOL_VERSION`]                 |
+//! | 1           | flags: [`FLAG_TIMESTAMP`], [`FLAG_MAC`]                |
+//! | 2-3         | `sequence: u16`                                        |
+//! | 4-7         | `timestamp: u32` in milliseconds, only with its flag   |
+//!
+//! A packet with [`FLAG_MAC`] ends with a MAC after its last command, and its
+//! sequence number counts the packets of its session (see [`super::auth`]).
+//!
+//! | Message                  | Payload                                         |
+//! |--------------------------|-------------------------------------------------|
+//! | `SetBufferToMany`        | `count: u16`, `count` × `r g b`                 |
+//! | `SetBufferToSingle`      | `r g b`                                         |
+//! | `SetSinglePixel`         | `index: u16`, `r g b`                           |
+//! | `SetBufferToManyAlpha`   | `count: u16`, `count` × `r g b a`               |
+//! | `SetBufferToSingleAlpha` | `r g b a`                                       |
+//! | `SetPixelRange`          | `start: u16`, `count: u16`, `count` × `r g b`   |
+//! | `SetPixelRangeAlpha`     | `start: u16`, `count: u16`, `count` × `r g b a` |
+
+use core::marker::PhantomData;
+
+use crate::{
+    color::{Rgb8, Rgba8, RgbaF32},
+    net::UdpMessage,
+};
+
+/// The largest packet that fits in a single Ethernet frame without IP fragmentation.
+pub const MAX_PACKET_LEN: usize = 1472;
+
+/// The size of every command's header (message and strip index).
+pub const HEADER_LEN: usize = 2;
+
+/// The version of the packet layout, bumped whenever it changes.
+pub const PROTOCOL_VERSION: u8 = 1;
+
+/// The size of a packet header without a timestamp.
+pub const PACKET_HEADER_LEN: usize = 4;
+
+/// Set when the packet header carries a frame timestamp.
+pub const FLAG_TIMESTAMP: u8 = 0x01;
+
+/// Set when the packet ends with a MAC (see [`super::auth::sign_packet`]).
+pub const FLAG_MAC: u8 = 0x02;
+
+/// How far behind the last applied sequence number a packet can be and still
+/// count as stale. Anything further back means the sender restarted.
+pub const STALE_WINDOW: u16 = 256;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DecodeError {
+    /// The message byte doesn't name a [`UdpMessage`].
+    UnknownMessage(u8),
+    /// The packet ended in the middle of its header or a command.
+    Truncated,
+    /// The packet was encoded with a different [`PROTOCOL_VERSION`].
+    UnsupportedVersion(u8),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EncodeError {
+    /// The command doesn't fit in the remaining buffer.
+    BufferFull,
+    /// More pixels than a `u16` count can describe.
+    TooManyPixels,
+}
+
+/// A color with a fixed-size wire representation.
+pub trait WireColor: Copy + 'static {
+    /// Encoded size, in bytes.
+    const SIZE: usize;
+
+    fn read(bytes: &[u8]) -> Self;
+    fn write(self, bytes: &mut [u8]);
+}
+
+impl WireColor for Rgb8 {
+    const SIZE: usize = 3;
+
+    fn read(bytes: &[u8]) -> Self {
+        Self::new(bytes[0], bytes[1], bytes[2])
+    }
+
+    fn write(self, bytes: &mut [u8]) {
+        bytes[..3].copy_from_slice(&[self.r, self.g, self.b]);
+    }
+}
+
+impl WireColor for Rgba8 {
+    const SIZE: usize = 4;
+
+    fn read(bytes: &[u8]) -> Self {
+        Self::new(bytes[0], bytes[1], bytes[2], bytes[3])
+    }
+
+    fn write(self, bytes: &mut [u8]) {
+        bytes[..4].copy_from_slice(&[self.r, self.g, self.b, self.a]);
+    }
+}
+
+/// What comes before a packet's commands.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct PacketHeader {
+    /// Counts up with every packet a sender sends, wrapping around.
+    pub sequence: u16,
+    /// When the frame in this packet should be shown, in milliseconds.
+    pub timestamp: Option<u32>,
+}
+
+impl PacketHeader {
+    pub const fn new(sequence: u16) -> Self {
+        Self {
+            sequence,
+            timestamp: None,
+        }
+    }
+
+    /// The encoded size of this header.
+    pub const fn encoded_len(&self) -> usize {
+        match self.timestamp {
+            Some(_) => PACKET_HEADER_LEN + 4,
+            None => PACKET_HEADER_LEN,
+        }
+    }
+
+    /// Decode the header at the start of a packet, returning it and the commands after it.
+    pub fn decode(packet: &[u8]) -> Result<(Self, &[u8]), DecodeError> {
+        let (header, rest) = take(packet, PACKET_HEADER_LEN)?;
+        if header[0] != PROTOCOL_VERSION {
+            return Err(DecodeError::UnsupportedVersion(header[0]));
+        }
+
+        let sequence = u16::from_le_bytes([header[2], header[3]]);
+        if header[1] & FLAG_TIMESTAMP == 0 {
+            return Ok((Self::new(sequence), rest));
+        }
+        let (timestamp, rest) = take(rest, 4)?;
+        let timestamp =
+            u32::from_le_bytes([timestamp[0], timestamp[1], timestamp[2], timestamp[3]]);
+        Ok((
+            Self {
+                sequence,
+                timestamp: Some(timestamp),
+            },
+            rest,
+        ))
+    }
+
+    fn write(&self, buf: &mut [u8]) {
+        buf[0] = PROTOCOL_VERSION;
+        buf[1] = if self.timestamp.is_some() {
+            FLAG_TIMESTAMP
+        } else {
+            0
+        };
+        buf[2..4].copy_from_slice(&self.sequence.to_le_bytes());
+        if let Some(timestamp) = self.timestamp {
+            buf[4..8].copy_from_slice(&timestamp.to_le_bytes());
+        }
+    }
+}
+
+/// `SequenceFilter` drops packets that arrive after a newer one, so a
+/// reordered packet can't flash a strip back to an older frame.
+///
+/// A packet more than [`STALE_WINDOW`] behind is taken as the server having
+/// restarted, so it's no defence against replays; signed packets are checked
+/// by a [`super::auth::PacketOpener`] first.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct SequenceFilter {
+    last: Option<u16>,
+}
+
+impl SequenceFilter {
+    pub const fn new() -> Self {
+        Self { last: None }
+    }
+
+    /// Whether a packet should be applied, remembering its sequence number if so.
+    ///
+    /// Packets with the last applied sequence number are accepted, since one
+    /// packet can hold several commands for a strip.
+    pub fn accept(&mut self, sequence: u16) -> bool {
+        if let Some(last) = self.last {
+            let behind = last.wrapping_sub(sequence);
+            if behind != 0 && behind <= STALE_WINDOW {
+                return false;
+            }
+        }
+        self.last = Some(sequence);
+        true
+    }
+
+    /// Forget the last sequence number, accepting whatever comes next.
+    pub fn reset(&mut self) {
+        self.last = None;
+    }
+}
+
+/// A run of encoded colors, borrowed from a packet.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Pixels<'a, C> {
+    bytes: &'a [u8],
+    _phantom: PhantomData<C>,
+}
+
+impl<'a, C: WireColor> Pixels<'a, C> {
+    pub(crate) fn new(bytes: &'a [u8]) -> Self {
+        debug_assert_eq!(bytes.len() % C::SIZE, 0);
+        Self {
+            bytes,
+            _phantom: PhantomData,
+        }
+    }
+
+    /// The number of colors.
+    pub fn len(&self) -> usize {
+        self.bytes.len() / C::SIZE
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+
+    pub fn get(&self, index: usize) -> Option<C> {
+        self.bytes
+            .get(index * C::SIZE..(index + 1) * C::SIZE)
+            .map(C::read)
+    }
+
+    pub fn iter(&self) -> impl ExactSizeIterator<Item = C> + 'a {
+        self.bytes.chunks_exact(C::SIZE).map(C::read)
+    }
+}
+
+/// A single decoded command.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UdpCommand<'a> {
+    SetBufferToMany {
+        strip: u8,
+        colors: Pixels<'a, Rgb8>,
+    },
+    SetBufferToSingle {
+        strip: u8,
+        color: Rgb8,
+    },
+    SetSinglePixel {
+        strip: u8,
+        index: u16,
+        color: Rgb8,
+    },
+    SetBufferToManyAlpha {
+        strip: u8,
+        colors: Pixels<'a, Rgba8>,
+    },
+    SetBufferToSingleAlpha {
+        strip: u8,
+        color: Rgba8,
+    },
+    SetPixelRange {
+        strip: u8,
+        start: u16,
+        colors: Pixels<'a, Rgb8>,
+    },
+    SetPixelRangeAlpha {
+        strip: u8,
+        start: u16,
+        colors: Pixels<'a, Rgba8>,
+    },
+}
+
+impl UdpCommand<'_> {
+    /// The strip this command targets.
+    pub fn strip(&self) -> u8 {
+        match *self {
+            Self::SetBufferToMany { strip, .. }
+            | Self::SetBufferToSingle { strip, .. }
+            | Self::SetSinglePixel { strip, .. }
+            | Self::SetBufferToManyAlpha { strip, .. }
+            | Self::SetBufferToSingleAlpha { strip, .. }
+            | Self::SetPixelRange { strip, .. }
+            | Self::SetPixelRangeAlpha { strip, .. } => strip,
+        }
+    }
+
+    pub fn message(&self) -> UdpMessage {
+        match self {
+            Self::SetBufferToMany { .. } => UdpMessage::SetBufferToMany,
+            Self::SetBufferToSingle { .. } => UdpMessage::SetBufferToSingle,
+            Self::SetSinglePixel { .. } => UdpMessage::SetSinglePixel,
+            Self::SetBufferToManyAlpha { .. } => UdpMessage::SetBufferToManyAlpha,
+            Self::SetBufferToSingleAlpha { .. } => UdpMessage::SetBufferToSingleAlpha,
+            Self::SetPixelRange { .. } => UdpMessage::SetPixelRange,
+            Self::SetPixelRangeAlpha { .. } => UdpMessage::SetPixelRangeAlpha,
+        }
+    }
+
+    /// Write this command's colors into a strip's color buffer.
+    ///
+    /// Pixels that fall past the end of `buf` are ignored.
+    pub fn apply(&self, buf: &mut [RgbaF32]) {
+        match *self {
+            Self::SetBufferToMany { colors, .. } => copy_pixels(colors, buf),
+            Self::SetBufferToSingle { color, .. } => buf.fill(color.into()),
+            Self::SetSinglePixel { index, color, .. } => {
+                if let Some(dst) = buf.get_mut(index as usize) {
+                    *dst = color.into();
+                }
+            }
+            Self::SetBufferToManyAlpha { colors, .. } => copy_pixels(colors, buf),
+            Self::SetBufferToSingleAlpha { color, .. } => buf.fill(color.into()),
+            Self::SetPixelRange { start, colors, .. } => {
+                copy_pixels(colors, buf.get_mut(start as usize..).unwrap_or_default())
+            }
+            Self::SetPixelRangeAlpha { start, colors, .. } => {
+                copy_pixels(colors, buf.get_mut(start as usize..).unwrap_or_default())
+            }
+        }
+    }
+}
+
+fn copy_pixels<C: WireColor + Into<RgbaF32>>(src: Pixels<'_, C>, dst: &mut [RgbaF32]) {
+    for (src, dst) in src.iter().zip(dst.iter_mut()) {
+        *dst = src.into();
+    }
+}
+
+/// Split `n` bytes off the front of a packet.
+fn take(bytes: &[u8], n: usize) -> Result<(&[u8], &[u8]), DecodeError> {
+    bytes.split_at_checked(n).ok_or(DecodeError::Truncated)
+}
+
+fn take_u16(bytes: &[u8]) -> Result<(u16, &[u8]), DecodeError> {
+    let (n, rest) = take(bytes, 2)?;
+    Ok((u16::from_le_bytes([n[0], n[1]]), rest))
+}
+
+fn take_color<C: WireColor>(bytes: &[u8]) -> Result<(C, &[u8]), DecodeError> {
+    let (color, rest) = take(bytes, C::SIZE)?;
+    Ok((C::read(color), rest))
+}
+
+fn take_pixels<C: WireColor>(bytes: &[u8]) -> Result<(Pixels<'_, C>, &[u8]), DecodeError> {
+    let (count, rest) = take_u16(bytes)?;
+    let (colors, rest) = take(rest, count as usize * C::SIZE)?;
+    Ok((Pixels::new(colors), rest))
+}
+
+/// Decode the first command in a packet, returning it and the rest of the packet.
+pub fn decode_command(packet: &[u8]) -> Result<(UdpCommand<'_>, &[u8]), DecodeError> {
+    let (header, rest) = take(packet, HEADER_LEN)?;
+    let (msg, strip) = (header[0], header[1]);
+
+    let msg = UdpMessage::try_from(msg).map_err(|_| DecodeError::UnknownMessage(msg))?;
+    Ok(match msg {
+        UdpMessage::SetBufferToMany => {
+            let (colors, rest) = take_pixels(rest)?;
+            (UdpCommand::SetBufferToMany { strip, colors }, rest)
+        }
+        UdpMessage::SetBufferToSingle => {
+            let (color, rest) = take_color(rest)?;
+            (UdpCommand::SetBufferToSingle { strip, color }, rest)
+        }
+        UdpMessage::SetSinglePixel => {
+            let (index, rest) = take_u16(rest)?;
+            let (color, rest) = take_color(rest)?;
+            (
+                UdpCommand::SetSinglePixel {
+                    strip,
+                    index,
+                    color,
+                },
+                rest,
+            )
+        }
+        UdpMessage::SetBufferToManyAlpha => {
+            let (colors, rest) = take_pixels(rest)?;
+            (UdpCommand::SetBufferToManyAlpha { strip, colors }, rest)
+        }
+        UdpMessage::SetBufferToSingleAlpha => {
+            let (color, rest) = take_color(rest)?;
+            (UdpCommand::SetBufferToSingleAlpha { strip, color }, rest)
+        }
+        UdpMessage::SetPixelRange => {
+            let (start, rest) = take_u16(rest)?;
+            let (colors, rest) = take_pixels(rest)?;
+            (
+                UdpCommand::SetPixelRange {
+                    strip,
+                    start,
+                    colors,
+                },
+                rest,
+            )
+        }
+        UdpMessage::SetPixelRangeAlpha => {
+            let (start, rest) = take_u16(rest)?;
+            let (colors, rest) = take_pixels(rest)?;
+            (
+                UdpCommand::SetPixelRangeAlpha {
+                    strip,
+                    start,
+                    colors,
+                },
+                rest,
+            )
+        }
+    })
+}
+
+/// Iterates over the commands in a packet.
+///
+/// Stops after the first error, since nothing after a bad command can be trusted.
+pub struct UdpDecoder<'a> {
+    header: PacketHeader,
+    rest: &'a [u8],
+}
+
+impl<'a> UdpDecoder<'a> {
+    /// Decode a packet's header, ready to iterate over its commands.
+    pub fn new(packet: &'a [u8]) -> Result<Self, DecodeError> {
+        let (header, rest) = PacketHeader::decode(packet)?;
+        Ok(Self { header, rest })
+    }
+
+    pub fn header(&self) -> PacketHeader {
+        self.header
+    }
+}
+
+impl<'a> Iterator for UdpDecoder<'a> {
+    type Item = Result<UdpCommand<'a>, DecodeError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.rest.is_empty() {
+            return None;
+        }
+
+        match decode_command(self.rest) {
+            Ok((cmd, rest)) => {
+                self.rest = rest;
+                Some(Ok(cmd))
+            }
+            Err(e) => {
+                self.rest = &[];
+                Some(Err(e))
+            }
+        }
+    }
+}
+
+/// Writes commands into a packet buffer, after a header.
+///
+/// A command that doesn't fit is rejected whole, leaving the packet as it was.
+pub struct UdpEncoder<'a> {
+    buf: &'a mut [u8],
+    header_len: usize,
+    len: usize,
+}
+
+impl<'a> UdpEncoder<'a> {
+    /// Start a packet with a header.
+    ///
+    /// Panics if `buf` can't hold the header.
+    pub fn new(buf: &'a mut [u8], header: PacketHeader) -> Self {
+        header.write(buf);
+        let len = header.encoded_len();
+        Self {
+            buf,
+            header_len: len,
+            len,
+        }
+    }
+
+    /// The encoded packet so far.
+    pub fn packet(&self) -> &[u8] {
+        &self.buf[..self.len]
+    }
+
+    /// The encoded packet's length, header included.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Whether no commands have been written yet.
+    pub fn is_empty(&self) -> bool {
+        self.len == self.header_len
+    }
+
+    /// Bytes left in the buffer.
+    pub fn remaining(&self) -> usize {
+        self.buf.len() - self.len
+    }
+
+    /// Start over with no commands, keeping the header.
+    pub fn clear(&mut self) {
+        self.len = self.header_len;
+    }
+
+    /// Reserve space for a command and write its header, returning its payload.
+    fn command(
+        &mut self,
+        msg: UdpMessage,
+        strip: u8,
+        payload_len: usize,
+    ) -> Result<&mut [u8], EncodeError> {
+        let len = HEADER_LEN + payload_len;
+        if len > self.remaining() {
+            return Err(EncodeError::BufferFull);
+        }
+
+        let cmd = &mut self.buf[self.len..self.len + len];
+        self.len += len;
+        cmd[0] = msg as u8;
+        cmd[1] = strip;
+        Ok(&mut cmd[HEADER_LEN..])
+    }
+
+    /// Write a run of pixels, prefixed by a start index for ranged messages.
+    fn pixels<C: WireColor>(
+        &mut self,
+        msg: UdpMessage,
+        strip: u8,
+        start: Option<u16>,
+        colors: impl ExactSizeIterator<Item = C>,
+    ) -> Result<(), EncodeError> {
+        let count = u16::try_from(colors.len()).map_err(|_| EncodeError::TooManyPixels)?;
+        let prefix = if start.is_some() { 4 } else { 2 };
+        let payload = self.command(msg, strip, prefix + count as usize * C::SIZE)?;
+
+        let (header, payload) = payload.split_at_mut(prefix);
+        if let Some(start) = start {
+            header[..2].copy_from_slice(&start.to_le_bytes());
+        }
+        header[prefix - 2..].copy_from_slice(&count.to_le_bytes());
+
+        for (color, dst) in colors.zip(payload.chunks_exact_mut(C::SIZE)) {
+            color.write(dst);
+        }
+        Ok(())
+    }
+
+    fn single<C: WireColor>(
+        &mut self,
+        msg: UdpMessage,
+        strip: u8,
+        color: C,
+    ) -> Result<(), EncodeError> {
+        color.write(self.command(msg, strip, C::SIZE)?);
+        Ok(())
+    }
+
+    pub fn set_buffer_to_many(&mut self, strip: u8, colors: &[Rgb8]) -> Result<(), EncodeError> {
+        self.pixels(
+            UdpMessage::SetBufferToMany,
+            strip,
+            None,
+            colors.iter().copied(),
+        )
+    }
+
+    pub fn set_buffer_to_single(&mut self, strip: u8, color: Rgb8) -> Result<(), EncodeError> {
+        self.single(UdpMessage::SetBufferToSingle, strip, color)
+    }
+
+    pub fn set_single_pixel(
+        &mut self,
+        strip: u8,
+        index: u16,
+        color: Rgb8,
+    ) -> Result<(), EncodeError> {
+        let payload = self.command(UdpMessage::SetSinglePixel, strip, 2 + Rgb8::SIZE)?;
+        payload[..2].copy_from_slice(&index.to_le_bytes());
+        color.write(&mut payload[2..]);
+        Ok(())
+    }
+
+    pub fn set_buffer_to_many_alpha(
+        &mut self,
+        strip: u8,
+        colors: &[Rgba8],
+    ) -> Result<(), EncodeError> {
+        self.pixels(
+            UdpMessage::SetBufferToManyAlpha,
+            strip,
+            None,
+            colors.iter().copied(),
+        )
+    }
+
+    pub fn set_buffer_to_single_alpha(
+        &mut self,
+        strip: u8,
+        color: Rgba8,
+    ) -> Result<(), EncodeError> {
+        self.single(UdpMessage::SetBufferToSingleAlpha, strip, color)
+    }
+
+    /// Set `colors.len()` pixels starting at `start`, leaving the rest of the strip alone.
+    pub fn set_pixel_range(
+        &mut self,
+        strip: u8,
+        start: u16,
+        colors: &[Rgb8],
+    ) -> Result<(), EncodeError> {
+        self.pixels(
+            UdpMessage::SetPixelRange,
+            strip,
+            Some(start),
+            colors.iter().copied(),
+        )
+    }
+
+    /// Set `colors.len()` pixels starting at `start`, leaving the rest of the strip alone.
+    pub fn set_pixel_range_alpha(
+        &mut self,
+        strip: u8,
+        start: u16,
+        colors: &[Rgba8],
+    ) -> Result<(), EncodeError> {
+        self.pixels(
+            UdpMessage::SetPixelRangeAlpha,
+            strip,
+            Some(start),
+            colors.iter().copied(),
+        )
+    }
+
+    /// Encode an already decoded command, e.g. to forward it.
+    pub fn encode(&mut self, cmd: &UdpCommand<'_>) -> Result<(), EncodeError> {
+        match *cmd {
+            UdpCommand::SetBufferToMany { strip, colors } => {
+                self.pixels(UdpMessage::SetBufferToMany, strip, None, colors.iter())
+            }
+            UdpCommand::SetBufferToSingle { strip, color } => {
+                self.set_buffer_to_single(strip, color)
+            }
+            UdpCommand::SetSinglePixel {
+                strip,
+                index,
+                color,
+            } => self.set_single_pixel(strip, index, color),
+            UdpCommand::SetBufferToManyAlpha { strip, colors } => {
+                self.pixels(UdpMessage::SetBufferToManyAlpha, strip, None, colors.iter())
+            }
+            UdpCommand::SetBufferToSingleAlpha { strip, color } => {
+                self.set_buffer_to_single_alpha(strip, color)
+            }
+            UdpCommand::SetPixelRange {
+                strip,
+                start,
+                colors,
+            } => self.pixels(UdpMessage::SetPixelRange, strip, Some(start), colors.iter()),
+            UdpCommand::SetPixelRangeAlpha {
+                strip,
+                start,
+                colors,
+            } => self.pixels(
+                UdpMessage::SetPixelRangeAlpha,
+                strip,
+                Some(start),
+                colors.iter(),
+            ),
+        }
+    }
+}
@@ -0,0 +1,77 @@
+//! Where an update's image is written.
+
+use alloc::{vec, vec::Vec};
+
+use crate::config::storage::OutOfRange;
+
+/// `UpdateFlash` is the partition an update's image goes in, addressed from
+/// its start.
+pub trait UpdateFlash {
+    type Error;
+
+    /// The largest image the partition holds.
+    fn capacity(&self) -> u32;
+
+    /// Fill `buf` from `offset` bytes in.
+    fn read(&mut self, offset: u32, buf: &mut [u8]) -> Result<(), Self::Error>;
+
+    /// Write `data` at `offset` bytes in, erasing first if the flash needs it.
+    fn write(&mut self, offset: u32, data: &[u8]) -> Result<(), Self::Error>;
+}
+
+/// `RamFlash` keeps the partition in memory, for hosts and tests. It starts
+/// out erased, like blank flash.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RamFlash {
+    bytes: Vec<u8>,
+    writes: usize,
+}
+
+impl RamFlash {
+    pub fn new(capacity: u32) -> Self {
+        Self {
+            bytes: vec![0xff; capacity as usize],
+            writes: 0,
+        }
+    }
+
+    pub fn bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    /// The raw partition, e.g. to corrupt it.
+    pub fn bytes_mut(&mut self) -> &mut [u8] {
+        &mut self.bytes
+    }
+
+    /// How many times the partition has been written to.
+    pub fn writes(&self) -> usize {
+        self.writes
+    }
+}
+
+impl UpdateFlash for RamFlash {
+    type Error = OutOfRange;
+
+    fn capacity(&self) -> u32 {
+        self.bytes.len() as u32
+    }
+
+    fn read(&mut self, offset: u32, buf: &mut [u8]) -> Result<(), OutOfRange> {
+        let start = offset as usize;
+        let src = self.bytes.get(start..start + buf.len()).ok_or(OutOfRange)?;
+        buf.copy_from_slice(src);
+        Ok(())
+    }
+
+    fn write(&mut self, offset: u32, data: &[u8]) -> Result<(), OutOfRange> {
+        let start = offset as usize;
+        let dst = self
+            .bytes
+            .get_mut(start..start + data.len())
+            .ok_or(OutOfRange)?;
+        dst.copy_from_slice(data);
+        self.writes += 1;
+        Ok(())
+    }
+}
@@ -0,0 +1,248 @@
+//! Firmware updates pushed from the server over the control session.
+//!
+//! The server opens an update with [`crate::net::ServerMessage::BeginUpdate`],
+//! giving the image's size and SHA-256, and the node answers
+//! [`UpdateStatus::Ready`]. The image then follows in order, as
+//! [`UpdateChunk`]s of up to [`CHUNK_LEN`] bytes that each carry a CRC-32 of
+//! their data. The node writes it to its inactive app partition a sector at a
+//! time with an [`UpdateWriter`], acknowledging each sector with
+//! [`UpdateStatus::Written`], and the server keeps no more than [`WINDOW`]
+//! bytes in flight. Once the last chunk is written the node reads the image
+//! back, checks its hash, answers [`UpdateStatus::Verified`] and restarts into
+//! it.
+//!
+//! A new image has to check in, by opening a control session with the server,
+//! within [`CHECK_IN_TIMEOUT_MS`] of booting. If it doesn't, the node restarts
+//! into the image it was updated from.
+
+use alloc::vec::Vec;
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::config::crc32;
+
+pub mod flash;
+
+pub use flash::{RamFlash, UpdateFlash};
+
+/// The most image bytes in one [`UpdateChunk`], so it fits in a control
+/// session frame.
+pub const CHUNK_LEN: usize = 128;
+
+/// The flash erase size. The image is written a whole sector at a time.
+pub const SECTOR_LEN: usize = 4096;
+
+/// How far the server runs ahead of the last [`UpdateStatus::Written`].
+pub const WINDOW: u32 = 2 * SECTOR_LEN as u32;
+
+/// How long a freshly updated node has to reach the server before it rolls
+/// back.
+pub const CHECK_IN_TIMEOUT_MS: u64 = 60_000;
+
+/// Describes the image an update will write.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UpdateHeader {
+    /// The image's length, in bytes.
+    pub size: u32,
+    pub sha256: [u8; 32],
+}
+
+impl UpdateHeader {
+    /// The header for `image`, or `None` if it's too large to describe.
+    pub fn for_image(image: &[u8]) -> Option<Self> {
+        Some(Self {
+            size: u32::try_from(image.len()).ok()?,
+            sha256: Sha256::digest(image).into(),
+        })
+    }
+}
+
+/// A piece of the image, at `offset` bytes in.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UpdateChunk {
+    pub offset: u32,
+    pub data: Vec<u8>,
+    /// CRC-32 of `data`.
+    pub crc: u32,
+}
+
+impl UpdateChunk {
+    pub fn new(offset: u32, data: &[u8]) -> Self {
+        Self {
+            offset,
+            data: data.into(),
+            crc: crc32(data),
+        }
+    }
+
+    /// Whether `data` still matches its CRC.
+    pub fn is_intact(&self) -> bool {
+        crc32(&self.data) == self.crc
+    }
+}
+
+/// Split an image into the chunks that send it, in order.
+pub fn chunks(image: &[u8]) -> impl Iterator<Item = UpdateChunk> + '_ {
+    image
+        .chunks(CHUNK_LEN)
+        .enumerate()
+        .map(|(i, data)| UpdateChunk::new((i * CHUNK_LEN) as u32, data))
+}
+
+/// How an update is going, as reported by the node.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum UpdateStatus {
+    /// The update is open and the node is waiting for the first chunk.
+    Ready,
+    /// This many bytes of the image are in flash.
+    Written(u32),
+    /// The whole image is in flash and matches its hash. The node restarts
+    /// into it.
+    Verified,
+    /// The node booted a new image, checked in and is keeping it.
+    Confirmed,
+    /// The update was abandoned, and the node keeps running its current image.
+    Failed(UpdateError),
+}
+
+/// Why an update was abandoned.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum UpdateError {
+    /// A chunk arrived without an open update.
+    NotStarted,
+    /// The node has nowhere to put an image, e.g. no OTA partitions.
+    Unavailable,
+    /// The image doesn't fit in the partition, or runs past its size.
+    TooLarge,
+    /// A chunk skipped ahead or went back, given the offset expected.
+    OutOfOrder(u32),
+    /// The chunk at this offset doesn't match its CRC or is too long.
+    BadChunk(u32),
+    /// The image in flash doesn't match the update's hash.
+    HashMismatch,
+    /// Writing or reading back the image failed.
+    Flash,
+}
+
+/// `UpdateWriter` assembles an image from its chunks and writes it out a
+/// sector at a time.
+///
+/// It doesn't hold the flash itself, so the node can share it with the
+/// config while an update runs.
+#[derive(Debug, Clone)]
+pub struct UpdateWriter {
+    header: UpdateHeader,
+    /// Image bytes taken so far, in flash or waiting in `sector`.
+    received: u32,
+    sector: Vec<u8>,
+}
+
+impl UpdateWriter {
+    /// Open an update, checking the image fits in `flash`.
+    pub fn begin<F: UpdateFlash>(flash: &F, header: UpdateHeader) -> Result<Self, UpdateError> {
+        if header.size > flash.capacity() {
+            return Err(UpdateError::TooLarge);
+        }
+        Ok(Self {
+            header,
+            received: 0,
+            sector: Vec::with_capacity(SECTOR_LEN),
+        })
+    }
+
+    pub fn header(&self) -> &UpdateHeader {
+        &self.header
+    }
+
+    /// Image bytes written to flash so far.
+    pub fn written(&self) -> u32 {
+        self.received - self.sector.len() as u32
+    }
+
+    /// Whether every chunk has been written.
+    pub fn is_complete(&self) -> bool {
+        self.received == self.header.size
+    }
+
+    /// Take the next chunk, writing out every sector it fills and the last,
+    /// partial one. Returns [`Self::written`] if anything was written.
+    pub fn write<F: UpdateFlash>(
+        &mut self,
+        flash: &mut F,
+        chunk: &UpdateChunk,
+    ) -> Result<Option<u32>, UpdateError> {
+        if chunk.offset != self.received {
+            return Err(UpdateError::OutOfOrder(self.received));
+        }
+        if chunk.data.len() > CHUNK_LEN || !chunk.is_intact() {
+            return Err(UpdateError::BadChunk(chunk.offset));
+        }
+        if self.received as usize + chunk.data.len() > self.header.size as usize {
+            return Err(UpdateError::TooLarge);
+        }
+
+        let mut written = false;
+        let mut data = &chunk.data[..];
+        while !data.is_empty() {
+            let n = (SECTOR_LEN - self.sector.len()).min(data.len());
+            self.sector.extend_from_slice(&data[..n]);
+            self.received += n as u32;
+            data = &data[n..];
+
+            if self.sector.len() == SECTOR_LEN || self.is_complete() {
+                let offset = self.written();
+                flash
+                    .write(offset, &self.sector)
+                    .map_err(|_| UpdateError::Flash)?;
+                self.sector.clear();
+                written = true;
+            }
+        }
+        Ok(written.then(|| self.written()))
+    }
+
+    /// Take the next chunk as [`Self::write`] does, verifying the image once
+    /// it's all in, and return what to report to the server, if anything.
+    /// The update is over once this returns anything but
+    /// [`UpdateStatus::Written`].
+    pub fn apply<F: UpdateFlash>(
+        &mut self,
+        flash: &mut F,
+        chunk: &UpdateChunk,
+    ) -> Option<UpdateStatus> {
+        match self.write(flash, chunk) {
+            Ok(_) if self.is_complete() => Some(match self.verify(flash) {
+                Ok(()) => UpdateStatus::Verified,
+                Err(e) => UpdateStatus::Failed(e),
+            }),
+            Ok(written) => written.map(UpdateStatus::Written),
+            Err(e) => Some(UpdateStatus::Failed(e)),
+        }
+    }
+
+    /// Read the whole image back from flash and check it against the
+    /// update's hash. Until every chunk is in, this fails with the offset
+    /// expected next.
+    pub fn verify<F: UpdateFlash>(&self, flash: &mut F) -> Result<(), UpdateError> {
+        if !self.is_complete() {
+            return Err(UpdateError::OutOfOrder(self.received));
+        }
+
+        let mut hash = Sha256::new();
+        let mut buf = [0u8; 256];
+        let mut offset = 0;
+        while offset < self.header.size {
+            let n = (self.header.size - offset).min(buf.len() as u32);
+            let buf = &mut buf[..n as usize];
+            flash.read(offset, buf).map_err(|_| UpdateError::Flash)?;
+            hash.update(buf);
+            offset += n;
+        }
+
+        if hash.finalize()[..] != self.header.sha256 {
+            return Err(UpdateError::HashMismatch);
+        }
+        Ok(())
+    }
+}
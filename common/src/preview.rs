@@ -0,0 +1,133 @@
+//! Drawing strips' colors as truecolor blocks in a terminal, for developing
+//! without LEDs in front of you.
+
+use std::{fmt::Write, time::Instant};
+
+use crate::{color::Rgb8, net::StripMode};
+
+/// One strip's row in a preview.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PreviewRow<'a> {
+    pub label: String,
+    /// The strip's mode, when the source knows it.
+    pub mode: Option<StripMode>,
+    /// The colors sent to the strip's LEDs.
+    pub pixels: &'a [Rgb8],
+}
+
+/// What the status line below the strips shows.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct PreviewStatus {
+    /// The index of the running effect, when there is one.
+    pub effect: Option<usize>,
+    pub fps: f32,
+}
+
+/// `Preview` draws a frame of strips, one row each, redrawing over the previous
+/// frame in place.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Preview {
+    /// The most cells a row can use; longer strips are downsampled to fit.
+    pub width: usize,
+}
+
+impl Default for Preview {
+    fn default() -> Self {
+        Self { width: 100 }
+    }
+}
+
+impl Preview {
+    pub const fn new(width: usize) -> Self {
+        Self { width }
+    }
+
+    /// Draw a frame as a string of ANSI escapes, ready to be printed.
+    pub fn draw<'a>(
+        &self,
+        rows: impl IntoIterator<Item = PreviewRow<'a>>,
+        status: PreviewStatus,
+    ) -> String {
+        let rows = rows.into_iter().collect::<Vec<_>>();
+        let label_width = rows.iter().map(|row| row.label.len()).max().unwrap_or(0);
+
+        // move home, then clear what's left of each line as it's overwritten
+        let mut out = String::from("\x1b[H");
+        for row in &rows {
+            let mode = row.mode.map(|mode| format!("{mode:?}")).unwrap_or_default();
+            _ = write!(out, "{:label_width$} {mode:8} ", row.label);
+
+            let mut last = None;
+            for px in downsample(row.pixels, self.width) {
+                if last != Some(px) {
+                    _ = write!(out, "\x1b[48;2;{};{};{}m", px.r, px.g, px.b);
+                    last = Some(px);
+                }
+                out.push(' ');
+            }
+            out.push_str("\x1b[0m\x1b[K\n");
+        }
+
+        match status.effect {
+            Some(effect) => _ = write!(out, "effect {effect}"),
+            None => out.push_str("effect -"),
+        }
+        _ = write!(out, " | {:.1} fps\x1b[K\n\x1b[J", status.fps);
+        out
+    }
+}
+
+/// Shrink a strip to at most `width` pixels, averaging each group of LEDs.
+pub fn downsample(pixels: &[Rgb8], width: usize) -> Vec<Rgb8> {
+    if width == 0 || pixels.len() <= width {
+        return pixels.to_vec();
+    }
+
+    (0..width)
+        .map(|i| {
+            let group = &pixels[i * pixels.len() / width..(i + 1) * pixels.len() / width];
+            let sum = group.iter().fold([0u32; 3], |[r, g, b], px| {
+                [r + px.r as u32, g + px.g as u32, b + px.b as u32]
+            });
+            let n = group.len() as u32;
+            Rgb8::new((sum[0] / n) as u8, (sum[1] / n) as u8, (sum[2] / n) as u8)
+        })
+        .collect()
+}
+
+/// `FpsCounter` measures a frame rate, averaged over roughly a second.
+#[derive(Debug, Clone)]
+pub struct FpsCounter {
+    since: Instant,
+    frames: u32,
+    fps: f32,
+}
+
+impl Default for FpsCounter {
+    fn default() -> Self {
+        Self {
+            since: Instant::now(),
+            frames: 0,
+            fps: 0.0,
+        }
+    }
+}
+
+impl FpsCounter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Count `frames` more frames, returning the latest frame rate.
+    pub fn tick(&mut self, frames: u32) -> f32 {
+        self.frames += frames;
+
+        let elapsed = self.since.elapsed().as_secs_f32();
+        if elapsed >= 1.0 {
+            self.fps = self.frames as f32 / elapsed;
+            self.frames = 0;
+            self.since = Instant::now();
+        }
+        self.fps
+    }
+}
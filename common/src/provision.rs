@@ -0,0 +1,387 @@
+//! Setting a node's network up from a phone or laptop, over the setup network
+//! the node starts when it can't join one.
+//!
+//! The node runs an access point at [`AP_ADDR`], answers every DNS query with
+//! that address and serves one page with a form over HTTP, so joining the
+//! network pops the page up as a captive portal. This module does everything
+//! but the sockets: parsing requests and the submitted form, checking the
+//! values, rendering the page and answering DNS.
+//!
+//! The access point is open until the node holds an installation key. From
+//! then on it's protected with [`ap_password`], and the form only changes
+//! anything given the key the node already has, so the setup network can't
+//! be used to take a node over.
+
+use alloc::{format, string::String, vec::Vec};
+use core::net::Ipv4Addr;
+
+use crate::{
+    config::NetworkConfig,
+    net::auth::{KEY_LEN, Key},
+};
+
+/// The node's address on its setup network.
+pub const AP_ADDR: Ipv4Addr = Ipv4Addr::new(192, 168, 4, 1);
+
+/// Where the setup page is, handed out over DHCP for devices that ask.
+pub const PAGE_URL: &str = "http://192.168.4.1/";
+
+pub const HTTP_PORT: u16 = 80;
+
+pub const DNS_PORT: u16 = 53;
+
+/// Starts the SSID of every node's setup network.
+pub const AP_PREFIX: &str = "lightspace-";
+
+/// The longest SSID Wi-Fi allows, in bytes.
+pub const MAX_SSID_LEN: usize = 32;
+
+/// The longest node name, so [`ap_ssid`] stays a valid SSID.
+pub const MAX_NAME_LEN: usize = MAX_SSID_LEN - AP_PREFIX.len();
+
+/// Kept apart from the contexts in [`crate::net::auth`].
+const SETUP_CONTEXT: &[u8] = b"lightspace setup";
+
+/// Why a submitted form was turned down.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProvisionError {
+    /// The form isn't valid `application/x-www-form-urlencoded` UTF-8.
+    Encoding,
+    /// A field the form always sends is missing.
+    Missing(&'static str),
+    /// The SSID is empty or longer than [`MAX_SSID_LEN`].
+    SsidLength,
+    /// The password is neither empty, for an open network, nor 8 to 63 bytes.
+    PasswordLength,
+    /// The name isn't 1 to [`MAX_NAME_LEN`] letters, digits and inner dashes.
+    InvalidName,
+    /// The server isn't an IPv4 address.
+    InvalidServer,
+    /// The key isn't [`KEY_LEN`] bytes of hex.
+    InvalidKey,
+    /// The node holds a key, and the form didn't give it.
+    WrongKey,
+}
+
+impl ProvisionError {
+    /// What to tell the person filling the form in.
+    pub fn message(&self) -> &'static str {
+        match self {
+            Self::Encoding => "The form couldn't be read.",
+            Self::Missing(_) => "The form is missing a field.",
+            Self::SsidLength => "The network name must be 1 to 32 bytes.",
+            Self::PasswordLength => {
+                "The password must be empty, for an open network, or 8 to 63 characters."
+            }
+            Self::InvalidName => {
+                "The node name must be 1 to 21 letters, digits and dashes, not starting or ending with a dash."
+            }
+            Self::InvalidServer => "The server must be an IPv4 address, like 192.168.1.10.",
+            Self::InvalidKey => "The key must be 64 hex digits, as set on the server.",
+            Self::WrongKey => {
+                "Enter the installation key the node already holds to change its settings."
+            }
+        }
+    }
+}
+
+/// The SSID of a node's setup network.
+pub fn ap_ssid(network: &NetworkConfig) -> String {
+    format!("{AP_PREFIX}{}", network.name)
+}
+
+/// The password of a node's setup network once it holds `key`, which the
+/// server shows too.
+pub fn ap_password(key: &Key) -> String {
+    key.mac(&[SETUP_CONTEXT])
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect()
+}
+
+/// Parse a submitted form into the network settings it describes, checking
+/// every value against the node's `current` settings.
+///
+/// Leaving the server empty lets any server in. Leaving the key empty or out
+/// keeps the current one, if any, or takes the server's traffic
+/// unauthenticated. A node holding a key changes nothing unless the form gives
+/// it as `current_key`.
+pub fn parse_form(body: &[u8], current: &NetworkConfig) -> Result<NetworkConfig, ProvisionError> {
+    let (mut ssid, mut password, mut name, mut server) = (None, None, None, None);
+    let (mut key_hex, mut current_hex) = (None, None);
+    for pair in body.split(|&b| b == b'&').filter(|pair| !pair.is_empty()) {
+        let (key, value) = match pair.iter().position(|&b| b == b'=') {
+            Some(i) => (&pair[..i], &pair[i + 1..]),
+            None => (pair, &[][..]),
+        };
+        let value = Some(url_decode(value)?);
+        match &*url_decode(key)? {
+            "ssid" => ssid = value,
+            "password" => password = value,
+            "name" => name = value,
+            "server" => server = value,
+            "key" => key_hex = value,
+            "current_key" => current_hex = value,
+            _ => (),
+        }
+    }
+
+    if let Some(key) = &current.key {
+        let given = current_hex.as_deref().map(str::trim).and_then(Key::parse);
+        if !given.is_some_and(|given| key.matches(&given)) {
+            return Err(ProvisionError::WrongKey);
+        }
+    }
+
+    let ssid = ssid.ok_or(ProvisionError::Missing("ssid"))?;
+    let password = password.ok_or(ProvisionError::Missing("password"))?;
+    let name = name.ok_or(ProvisionError::Missing("name"))?;
+    let server = server.ok_or(ProvisionError::Missing("server"))?;
+
+    if ssid.is_empty() || ssid.len() > MAX_SSID_LEN {
+        return Err(ProvisionError::SsidLength);
+    }
+    if !password.is_empty() && !(8..=63).contains(&password.len()) {
+        return Err(ProvisionError::PasswordLength);
+    }
+    let name = name.trim();
+    if !is_valid_name(name) {
+        return Err(ProvisionError::InvalidName);
+    }
+    let server = match server.trim() {
+        "" => None,
+        addr => Some(addr.parse().map_err(|_| ProvisionError::InvalidServer)?),
+    };
+    let key = match key_hex.as_deref().map(str::trim) {
+        None | Some("") => current.key.clone(),
+        Some(hex) => Some(Key::parse(hex).ok_or(ProvisionError::InvalidKey)?),
+    };
+
+    Ok(NetworkConfig {
+        ssid,
+        password,
+        name: name.into(),
+        server,
+        key,
+    })
+}
+
+/// Whether `name` works as a hostname label and fits in [`ap_ssid`].
+fn is_valid_name(name: &str) -> bool {
+    (1..=MAX_NAME_LEN).contains(&name.len())
+        && name.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'-')
+        && !name.starts_with('-')
+        && !name.ends_with('-')
+}
+
+/// Decode a form field, with `+` for spaces and `%XX` escapes.
+fn url_decode(bytes: &[u8]) -> Result<String, ProvisionError> {
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut rest = bytes.iter();
+    while let Some(&b) = rest.next() {
+        out.push(match b {
+            b'+' => b' ',
+            b'%' => {
+                let hi = rest.next().and_then(|&b| (b as char).to_digit(16));
+                let lo = rest.next().and_then(|&b| (b as char).to_digit(16));
+                match (hi, lo) {
+                    (Some(hi), Some(lo)) => (hi * 16 + lo) as u8,
+                    _ => return Err(ProvisionError::Encoding),
+                }
+            }
+            b => b,
+        });
+    }
+    String::from_utf8(out).map_err(|_| ProvisionError::Encoding)
+}
+
+/// The request wasn't HTTP/1.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MalformedRequest;
+
+/// `Request` is an HTTP request read off a socket.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Request<'a> {
+    pub method: &'a str,
+    pub path: &'a str,
+    pub body: &'a [u8],
+}
+
+impl<'a> Request<'a> {
+    /// Parse the request at the start of `buf`, or `None` if it hasn't all
+    /// arrived yet.
+    pub fn parse(buf: &'a [u8]) -> Result<Option<Self>, MalformedRequest> {
+        let Some(end) = buf.windows(4).position(|w| w == b"\r\n\r\n") else {
+            return Ok(None);
+        };
+        let head = core::str::from_utf8(&buf[..end]).map_err(|_| MalformedRequest)?;
+        let mut lines = head.split("\r\n");
+
+        let mut request_line = lines.next().unwrap_or_default().split(' ');
+        let (Some(method), Some(path), Some(version)) = (
+            request_line.next(),
+            request_line.next(),
+            request_line.next(),
+        ) else {
+            return Err(MalformedRequest);
+        };
+        if !version.starts_with("HTTP/1.") {
+            return Err(MalformedRequest);
+        }
+
+        let mut len = 0;
+        for line in lines {
+            let (name, value) = line.split_once(':').ok_or(MalformedRequest)?;
+            if name.trim().eq_ignore_ascii_case("content-length") {
+                len = value.trim().parse().map_err(|_| MalformedRequest)?;
+            }
+        }
+
+        let start = end + 4;
+        let Some(body) = start.checked_add(len).and_then(|end| buf.get(start..end)) else {
+            return Ok(None);
+        };
+        Ok(Some(Self { method, path, body }))
+    }
+}
+
+/// What the page says above the form.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PageStatus {
+    /// Nothing yet, the form is waiting to be filled in.
+    Blank,
+    /// The last submission was turned down.
+    Rejected(ProvisionError),
+    /// The settings were saved and the node is restarting to use them.
+    Saved,
+}
+
+/// Render the setup page, its form filled in from `network`. The password and
+/// key are never sent back, and a node holding a key asks for it.
+pub fn render_page(network: &NetworkConfig, status: PageStatus) -> String {
+    let message = match status {
+        PageStatus::Blank => String::new(),
+        PageStatus::Rejected(e) => format!("<p class=\"error\">{}</p>", e.message()),
+        PageStatus::Saved => String::from(
+            "<p>Saved. The node is restarting to join the network; you can close this page.</p>",
+        ),
+    };
+    let server = network.server.map(|s| format!("{s}")).unwrap_or_default();
+    let (key_hint, current_key) = match network.key {
+        Some(_) => (
+            "leave empty to keep the current one",
+            format!(
+                "<label>Current installation key <input name=\"current_key\" type=\"password\" \
+maxlength=\"{}\" required></label>",
+                2 * KEY_LEN
+            ),
+        ),
+        None => ("optional", String::new()),
+    };
+
+    format!(
+        "<!DOCTYPE html>\
+<html><head><meta charset=\"utf-8\"><meta name=\"viewport\" content=\"width=device-width\">\
+<title>lightspace setup</title>\
+<style>body{{font-family:sans-serif;max-width:24em;margin:auto;padding:1em}}\
+label{{display:block;margin-top:1em}}input{{width:100%}}.error{{color:#c00}}</style></head>\
+<body><h1>lightspace setup</h1>{message}\
+<form method=\"post\" action=\"/\">\
+<label>Network <input name=\"ssid\" value=\"{ssid}\" maxlength=\"32\" required></label>\
+<label>Password <input name=\"password\" type=\"password\" maxlength=\"63\"></label>\
+<label>Node name <input name=\"name\" value=\"{name}\" maxlength=\"{MAX_NAME_LEN}\" required></label>\
+<label>Server address (optional) <input name=\"server\" value=\"{server}\"></label>\
+<label>Installation key ({key_hint}) <input name=\"key\" type=\"password\" maxlength=\"{key_len}\"></label>\
+{current_key}<p><button type=\"submit\">Save and restart</button></p>\
+</form></body></html>",
+        ssid = escape_html(&network.ssid),
+        name = escape_html(&network.name),
+        server = escape_html(&server),
+        key_len = 2 * KEY_LEN,
+    )
+}
+
+fn escape_html(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// A complete HTTP response carrying an HTML `body`, closing the connection
+/// after it.
+pub fn response(status: &str, body: &str) -> String {
+    format!(
+        "HTTP/1.1 {status}\r\nContent-Type: text/html; charset=utf-8\r\n\
+Content-Length: {}\r\nCache-Control: no-store\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    )
+}
+
+/// Answer a DNS query with `addr` for whatever name it asks about, writing
+/// the reply to `out`.
+///
+/// Returns the reply's length, or `None` for anything but a standard query
+/// for one name, or if `out` is too small.
+pub fn dns_reply(query: &[u8], addr: Ipv4Addr, out: &mut [u8]) -> Option<usize> {
+    const HEADER_LEN: usize = 12;
+    const TYPE_A: u16 = 1;
+    const CLASS_IN: u16 = 1;
+    const TTL_SECS: u32 = 60;
+
+    let header = query.get(..HEADER_LEN)?;
+    let flags = u16::from_be_bytes([header[2], header[3]]);
+    let is_query = flags & 0x8000 == 0;
+    let opcode = (flags >> 11) & 0xf;
+    let questions = u16::from_be_bytes([header[4], header[5]]);
+    if !is_query || opcode != 0 || questions != 1 {
+        return None;
+    }
+
+    // the name is a run of length-prefixed labels, ending with an empty one
+    let mut end = HEADER_LEN;
+    loop {
+        let len = *query.get(end)? as usize;
+        if len & 0xc0 != 0 {
+            return None;
+        }
+        end += 1 + len;
+        if len == 0 {
+            break;
+        }
+    }
+    let question = query.get(HEADER_LEN..end + 4)?;
+    let kind = u16::from_be_bytes([question[question.len() - 4], question[question.len() - 3]]);
+    let answers = u16::from(kind == TYPE_A);
+
+    let len = HEADER_LEN + question.len() + answers as usize * 16;
+    let out = out.get_mut(..len)?;
+    out[..2].copy_from_slice(&header[..2]);
+    // a response, authoritative, recursion desired copied, recursion available
+    let reply_flags = 0x8400 | (flags & 0x0100) | 0x0080;
+    out[2..4].copy_from_slice(&reply_flags.to_be_bytes());
+    out[4..6].copy_from_slice(&1u16.to_be_bytes());
+    out[6..8].copy_from_slice(&answers.to_be_bytes());
+    out[8..12].fill(0);
+    out[HEADER_LEN..HEADER_LEN + question.len()].copy_from_slice(question);
+
+    if answers == 1 {
+        let answer = &mut out[HEADER_LEN + question.len()..];
+        // a pointer back to the question's name
+        answer[..2].copy_from_slice(&[0xc0, HEADER_LEN as u8]);
+        answer[2..4].copy_from_slice(&TYPE_A.to_be_bytes());
+        answer[4..6].copy_from_slice(&CLASS_IN.to_be_bytes());
+        answer[6..10].copy_from_slice(&TTL_SECS.to_be_bytes());
+        answer[10..12].copy_from_slice(&4u16.to_be_bytes());
+        answer[12..16].copy_from_slice(&addr.octets());
+    }
+    Some(len)
+}
@@ -0,0 +1,297 @@
+use common::{
+    color::Rgb8,
+    net::{
+        MAX_FRAME_LEN, ServerMessage, StripMode,
+        auth::{
+            AuthError, KEY_LEN, Key, MAC_LEN, Opener, PacketOpener, SealedFrame, Sealer,
+            check_packet, sign_packet,
+        },
+        decode_frame, encode_frame,
+        udp::{FLAG_MAC, MAX_PACKET_LEN, PacketHeader, UdpCommand, UdpDecoder, UdpEncoder},
+    },
+    ota::{CHUNK_LEN, UpdateChunk},
+};
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+/// A key shorter than [`KEY_LEN`], zero-padded the way HMAC pads it anyway.
+fn padded(short: &[u8]) -> Key {
+    let mut bytes = [0u8; KEY_LEN];
+    bytes[..short.len()].copy_from_slice(short);
+    Key::new(bytes)
+}
+
+fn key() -> Key {
+    Key::new([0x42; KEY_LEN])
+}
+
+/// Seal `msgs` in order, returning each frame.
+fn seal_all(sealer: &mut Sealer, msgs: &[ServerMessage]) -> Vec<Vec<u8>> {
+    let mut buf = [0u8; MAX_FRAME_LEN];
+    msgs.iter()
+        .map(|msg| sealer.seal(msg, &mut buf).unwrap().to_vec())
+        .collect()
+}
+
+fn open(opener: &mut Opener, frame: &[u8]) -> Result<ServerMessage, AuthError> {
+    let mut frame = frame.to_vec();
+    let sealed = decode_frame::<SealedFrame>(&mut frame).map_err(|_| AuthError::Malformed)?;
+    opener.open(&sealed)
+}
+
+#[test]
+fn hmac_matches_reference() {
+    // RFC 4231 test cases 1 and 2
+    assert_eq!(
+        hex(&padded(&[0x0b; 20]).hmac(&[b"Hi There"])),
+        "b0344c61d8db38535ca8afceaf0bf12b881dc200c9833da726e9376c2e32cff7"
+    );
+    let key = padded(b"Jefe");
+    assert_eq!(
+        hex(&key.hmac(&[b"what do ya want for nothing?"])),
+        "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+    );
+
+    // the same however the data is split up, cut down to a MAC
+    let mac = key.mac(&[b"what do ya ", b"", b"want for nothing?"]);
+    assert_eq!(hex(&mac), "5bdcc146bf60754e");
+    assert!(key.verify(&[b"what do ya want for nothing?"], &mac));
+    assert!(!key.verify(&[b"what do ya want for nothing!"], &mac));
+    assert!(!key.verify(&[b"what do ya want for nothing?"], &mac[..MAC_LEN - 1]));
+}
+
+#[test]
+fn parses_keys() {
+    let key = Key::parse(&"0f".repeat(KEY_LEN)).unwrap();
+    assert_eq!(key, Key::new([0x0f; KEY_LEN]));
+    assert_eq!(Key::parse(&"0F".repeat(KEY_LEN)), Some(key.clone()));
+    assert_eq!(Key::parse("0f0f"), None);
+    assert_eq!(Key::parse(&"g0".repeat(KEY_LEN)), None);
+
+    // the key never shows up in logs
+    assert_eq!(format!("{key:?}"), "Key(..)");
+}
+
+#[test]
+fn opens_sealed_frames_in_order() {
+    let msgs = [
+        ServerMessage::SetStripMode(0, StripMode::Dynamic),
+        ServerMessage::KeepAlive,
+        ServerMessage::TimeResponse(1, 2),
+    ];
+    let frames = seal_all(&mut Sealer::new(key(), 7), &msgs);
+
+    let mut opener = Opener::new(key(), 7);
+    for (frame, msg) in frames.iter().zip(&msgs) {
+        assert_eq!(frame.last(), Some(&0), "frames end in a delimiter");
+        assert_eq!(open(&mut opener, frame).as_ref(), Ok(msg));
+    }
+
+    // the biggest message still fits in a frame once it's sealed
+    let chunk = ServerMessage::UpdateChunk(UpdateChunk::new(u32::MAX, &[0xff; CHUNK_LEN]));
+    let frames = seal_all(&mut Sealer::new(key(), 7), std::slice::from_ref(&chunk));
+    assert!(frames[0].len() <= MAX_FRAME_LEN);
+    assert_eq!(open(&mut Opener::new(key(), 7), &frames[0]), Ok(chunk));
+}
+
+#[test]
+fn rejects_forged_and_replayed_frames() {
+    let msgs = [
+        ServerMessage::SetStripMode(0, StripMode::Off),
+        ServerMessage::SetStripMode(1, StripMode::Off),
+    ];
+    let frames = seal_all(&mut Sealer::new(key(), 7), &msgs);
+
+    // another key, or a frame from a session with another nonce
+    let other = Key::new([0x43; KEY_LEN]);
+    assert_eq!(
+        open(&mut Opener::new(other, 7), &frames[0]),
+        Err(AuthError::BadMac)
+    );
+    assert_eq!(
+        open(&mut Opener::new(key(), 8), &frames[0]),
+        Err(AuthError::BadMac)
+    );
+
+    // a message changed on the way
+    let mut buf = frames[0].clone();
+    let sealed = decode_frame::<SealedFrame>(&mut buf).unwrap();
+    let mut msg = sealed.msg.to_vec();
+    *msg.last_mut().unwrap() = StripMode::Dynamic as u8;
+    let mut out = [0u8; MAX_FRAME_LEN];
+    let forged = postcard::to_slice_cobs(
+        &SealedFrame {
+            msg: &msg,
+            ..sealed
+        },
+        &mut out,
+    )
+    .unwrap();
+    assert_eq!(
+        open(&mut Opener::new(key(), 7), forged),
+        Err(AuthError::BadMac)
+    );
+
+    // the same frame twice, or one skipped
+    let mut opener = Opener::new(key(), 7);
+    assert_eq!(open(&mut opener, &frames[0]), Ok(msgs[0].clone()));
+    assert_eq!(open(&mut opener, &frames[0]), Err(AuthError::Replayed(0)));
+    assert_eq!(
+        open(&mut Opener::new(key(), 7), &frames[1]),
+        Err(AuthError::Replayed(1))
+    );
+
+    // and a plain frame isn't a sealed one
+    let mut buf = [0u8; MAX_FRAME_LEN];
+    let plain = encode_frame(&msgs[0], &mut buf).unwrap();
+    assert!(open(&mut Opener::new(key(), 7), plain).is_err());
+}
+
+fn opener(key: &Key) -> PacketOpener {
+    PacketOpener::new(key.clone())
+}
+
+/// A packet filling a strip with one color, signed if there's a key.
+fn packet(key: Option<&Key>, counter: u64) -> Vec<u8> {
+    let mut buf = [0u8; MAX_PACKET_LEN];
+    let header = PacketHeader::new(counter as u16);
+    let mut enc = UdpEncoder::new(&mut buf[..MAX_PACKET_LEN - MAC_LEN], header);
+    enc.set_buffer_to_single(1, Rgb8::new(1, 2, 3)).unwrap();
+    let len = enc.len();
+    let len = match key {
+        Some(key) => sign_packet(key, counter, &mut buf, len).unwrap(),
+        None => len,
+    };
+    buf[..len].to_vec()
+}
+
+#[test]
+fn checks_signed_packets() {
+    let signed = packet(Some(&key()), 9);
+    assert_eq!(signed[1] & FLAG_MAC, FLAG_MAC);
+
+    let checked = check_packet(Some(&mut opener(&key())), &signed).unwrap();
+    assert_eq!(checked.len(), signed.len() - MAC_LEN);
+    let decoder = UdpDecoder::new(checked).unwrap();
+    assert_eq!(decoder.header().sequence, 9);
+    assert_eq!(
+        decoder.collect::<Result<Vec<_>, _>>(),
+        Ok(vec![UdpCommand::SetBufferToSingle {
+            strip: 1,
+            color: Rgb8::new(1, 2, 3),
+        }])
+    );
+
+    // a node without a key takes it too, MAC and all
+    assert_eq!(check_packet(None, &signed), Ok(checked));
+}
+
+#[test]
+fn rejects_unauthenticated_packets() {
+    let signed = packet(Some(&key()), 9);
+
+    let other = Key::new([0x43; KEY_LEN]);
+    assert_eq!(
+        check_packet(Some(&mut opener(&other)), &signed),
+        Err(AuthError::BadMac)
+    );
+
+    // any byte changed on the way
+    for i in 0..signed.len() {
+        let mut tampered = signed.clone();
+        tampered[i] ^= 0x04;
+        assert!(
+            check_packet(Some(&mut opener(&key())), &tampered).is_err(),
+            "byte {i}"
+        );
+    }
+
+    let unsigned = packet(None, 9);
+    assert_eq!(
+        check_packet(Some(&mut opener(&key())), &unsigned),
+        Err(AuthError::Unsigned)
+    );
+    assert_eq!(check_packet(None, &unsigned), Ok(&unsigned[..]));
+
+    // too short to hold a MAC, or anything at all
+    assert_eq!(
+        check_packet(Some(&mut opener(&key())), &signed[..MAC_LEN]),
+        Err(AuthError::Malformed)
+    );
+    assert_eq!(check_packet(None, &[]), Err(AuthError::Malformed));
+
+    // and a packet is only signed if the MAC fits
+    let mut buf = unsigned.clone();
+    assert_eq!(sign_packet(&key(), 9, &mut buf, unsigned.len()), None);
+}
+
+#[test]
+fn rejects_packets_from_other_sessions() {
+    let session = key().for_session(7);
+    let signed = packet(Some(&session), 9);
+    assert!(check_packet(Some(&mut opener(&session)), &signed).is_ok());
+
+    // captured during an earlier session, or signed with the key itself
+    for other in [key().for_session(8), key()] {
+        assert_eq!(
+            check_packet(Some(&mut opener(&other)), &signed),
+            Err(AuthError::BadMac)
+        );
+    }
+    assert_ne!(key().for_session(7), key().for_session(8));
+    assert_ne!(
+        key().for_session(7),
+        Key::new([0x43; KEY_LEN]).for_session(7)
+    );
+}
+
+#[test]
+fn rejects_replayed_packets() {
+    let session = key().for_session(7);
+    let mut opener = opener(&session);
+    let first = packet(Some(&session), 1);
+    let second = packet(Some(&session), 2);
+    assert!(check_packet(Some(&mut opener), &first).is_ok());
+    assert!(check_packet(Some(&mut opener), &second).is_ok());
+
+    // the same packet again, or an older one
+    assert_eq!(
+        check_packet(Some(&mut opener), &second),
+        Err(AuthError::Replayed(2))
+    );
+    assert_eq!(
+        check_packet(Some(&mut opener), &first),
+        Err(AuthError::Replayed(1))
+    );
+
+    // packets go missing, and the count carries on past a wrap
+    for counter in [30_000, 60_000, 65_535, 65_536, 65_537] {
+        let signed = packet(Some(&session), counter);
+        assert!(
+            check_packet(Some(&mut opener), &signed).is_ok(),
+            "{counter}"
+        );
+    }
+
+    // however far behind a packet is, it isn't taken as the server restarting
+    let old = packet(Some(&session), 65_537 - 300);
+    assert_eq!(
+        check_packet(Some(&mut opener), &old),
+        Err(AuthError::Replayed(65_237))
+    );
+    // and the first packets come round again a wrap later, but their MAC is
+    // for the counters they were sent with
+    for replayed in [&first, &second] {
+        assert_eq!(
+            check_packet(Some(&mut opener), replayed),
+            Err(AuthError::BadMac)
+        );
+    }
+
+    // the next session counts from the start again
+    let next = key().for_session(8);
+    let signed = packet(Some(&next), 0);
+    assert!(check_packet(Some(&mut PacketOpener::new(next)), &signed).is_ok());
+}
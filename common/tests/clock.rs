@@ -0,0 +1,94 @@
+use common::{
+    clock::{ClockSync, SAMPLES, Sample},
+    math::Rng,
+};
+
+/// Server time minus node time in every simulation.
+const OFFSET: i64 = 123_456;
+
+/// Simulate a round trip starting at local time `sent`, taking `up` ms to
+/// reach the server and `down` ms to come back.
+fn round_trip(sync: &mut ClockSync, sent: u64, up: u64, down: u64) {
+    let server = (sent + up) as i64 + OFFSET;
+    sync.add_sample(sent, server as u64, sent + up + down);
+}
+
+/// A few ms one way, with occasional Wi-Fi stalls of up to 200ms.
+fn delay(rng: &mut Rng) -> u64 {
+    let stall = if rng.below(4) == 0 { rng.below(200) } else { 0 };
+    (2 + rng.below(5) + stall) as u64
+}
+
+#[test]
+fn symmetric_latency_is_exact() {
+    let mut sync = ClockSync::new();
+    assert!(!sync.is_synced());
+    assert_eq!(sync.server_time(500), 500);
+
+    round_trip(&mut sync, 1000, 20, 20);
+    assert!(sync.is_synced());
+    assert_eq!(sync.offset(), Some(OFFSET));
+    assert_eq!(sync.best().unwrap().rtt, 40);
+    assert_eq!(sync.server_time(2000), 2000 + OFFSET as u64);
+}
+
+#[test]
+fn asymmetric_latency_errs_by_half_the_difference() {
+    let sample = Sample::new(1000, (1000 + 30 + OFFSET) as u64, 1000 + 30 + 10);
+    assert_eq!(sample.offset, OFFSET + 10);
+    assert_eq!(sample.rtt, 40);
+}
+
+#[test]
+fn picks_the_quickest_round_trip() {
+    let mut sync = ClockSync::new();
+    round_trip(&mut sync, 1000, 80, 5);
+    round_trip(&mut sync, 2000, 3, 4);
+    round_trip(&mut sync, 3000, 5, 90);
+    assert_eq!(sync.best().unwrap().rtt, 7);
+    assert_eq!(sync.offset(), Some(OFFSET));
+}
+
+#[test]
+fn converges_under_jitter() {
+    let mut rng = Rng::new(7);
+    let mut sync = ClockSync::new();
+    for i in 0..100 {
+        let (up, down) = (delay(&mut rng), delay(&mut rng));
+        round_trip(&mut sync, i * 1000, up, down);
+
+        // never worse than the quickest round trip allows
+        let error = (sync.offset().unwrap() - OFFSET).abs();
+        assert!(error <= sync.best().unwrap().rtt.div_ceil(2) as i64);
+        // and within a few ms once the window has filled
+        if i >= SAMPLES as u64 {
+            assert!(error <= 3, "off by {error}ms after {i} round trips");
+        }
+    }
+}
+
+#[test]
+fn old_round_trips_age_out() {
+    let mut sync = ClockSync::new();
+    round_trip(&mut sync, 0, 1, 1);
+
+    // the server restarted with a clock 5 seconds behind, over a slower link
+    for i in 1..=SAMPLES as u64 {
+        let sent = i * 1000;
+        sync.add_sample(sent, sent + 10 + OFFSET as u64 - 5000, sent + 20);
+    }
+    assert_eq!(sync.offset(), Some(OFFSET - 5000));
+
+    sync.reset();
+    assert!(!sync.is_synced());
+}
+
+#[test]
+fn negative_offsets() {
+    let mut sync = ClockSync::new();
+    sync.add_sample(10_000, 2_000, 10_010);
+    assert_eq!(sync.offset(), Some(-8_005));
+    assert_eq!(sync.server_time(20_000), 11_995);
+    // local times before the server's clock started clamp to zero
+    assert_eq!(sync.server_time(5_000), 0);
+}
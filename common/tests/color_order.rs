@@ -0,0 +1,26 @@
+use common::color::{ColorOrder, Rgb8};
+
+#[test]
+fn reads_back_what_it_writes() {
+    let color = Rgb8::new(1, 2, 3);
+    let cases = [
+        (ColorOrder::Rgb, [1, 2, 3]),
+        (ColorOrder::Rbg, [1, 3, 2]),
+        (ColorOrder::Grb, [2, 1, 3]),
+        (ColorOrder::Gbr, [2, 3, 1]),
+        (ColorOrder::Brg, [3, 1, 2]),
+        (ColorOrder::Bgr, [3, 2, 1]),
+    ];
+    for (order, channels) in cases {
+        assert_eq!(order.write(color), channels, "{order:?}");
+        assert_eq!(order.read(channels), color, "{order:?}");
+    }
+}
+
+#[test]
+fn parses_its_config_name() {
+    assert_eq!("grb".parse(), Ok(ColorOrder::Grb));
+    assert_eq!("bgr".parse(), Ok(ColorOrder::Bgr));
+    assert!("GRB".parse::<ColorOrder>().is_err());
+    assert!("rgbw".parse::<ColorOrder>().is_err());
+}
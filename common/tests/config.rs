@@ -0,0 +1,136 @@
+use common::{
+    color::{ColorOrder, Rgb8},
+    config::{
+        ConfigError, ConfigStorage, HEADER_LEN, NetworkConfig, NodeConfig, RamStorage, StoredStrip,
+        VERSION, crc32,
+    },
+    effect::{StripInfo, param::ParamValue},
+    net::{
+        Chipset, StripConfig, StripMode,
+        auth::Key,
+        failsafe::{Fallback, StreamTimeout},
+    },
+};
+
+fn network() -> NetworkConfig {
+    NetworkConfig {
+        ssid: "lightspace".into(),
+        password: "hunter22".into(),
+        name: "stage-left".into(),
+        server: Some([10, 0, 0, 2].into()),
+        key: Some(Key::new([0x5a; 32])),
+    }
+}
+
+fn config() -> NodeConfig {
+    NodeConfig {
+        network: network(),
+        strips: vec![
+            StoredStrip {
+                config: StripConfig {
+                    leds: 120,
+                    rev: true,
+                    order: ColorOrder::Rgb,
+                    chipset: Chipset::Ws2811,
+                },
+                mode: StripMode::Hybrid,
+                stream_timeout: Some(StreamTimeout::new(2000, Fallback::Effects)),
+            },
+            StoredStrip {
+                config: StripConfig::new(StripInfo::empty()),
+                mode: StripMode::Off,
+                stream_timeout: None,
+            },
+        ],
+        effect: 3,
+        params: vec![(3, 0, ParamValue::Color(Rgb8::new(255, 0, 0)))],
+        brightness: 0.25,
+    }
+}
+
+fn defaults() -> NodeConfig {
+    NodeConfig {
+        network: NetworkConfig::default(),
+        strips: Vec::new(),
+        effect: 0,
+        params: Vec::new(),
+        brightness: 0.5,
+    }
+}
+
+#[test]
+fn crc32_matches_reference() {
+    assert_eq!(crc32(b""), 0);
+    assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+}
+
+#[test]
+fn saves_and_loads() {
+    let mut storage = RamStorage::new(4096);
+    assert_eq!(NodeConfig::load(&mut storage), Err(ConfigError::Missing));
+
+    config().save(&mut storage).unwrap();
+    assert_eq!(storage.bytes()[4], VERSION);
+    assert_eq!(NodeConfig::load(&mut storage), Ok(config()));
+
+    // a smaller config overwrites a bigger one cleanly
+    defaults().save(&mut storage).unwrap();
+    assert_eq!(NodeConfig::load(&mut storage), Ok(defaults()));
+
+    let mut tiny = RamStorage::new(HEADER_LEN);
+    assert_eq!(config().save(&mut tiny), Err(ConfigError::TooLarge));
+}
+
+#[test]
+fn keeps_the_latest_value_of_each_param() {
+    let mut config = defaults();
+    config.set_param(1, 0, ParamValue::F32(0.5));
+    config.set_param(1, 1, ParamValue::Bool(true));
+    config.set_param(1, 0, ParamValue::F32(0.75));
+    assert_eq!(
+        config.params,
+        [
+            (1, 0, ParamValue::F32(0.75)),
+            (1, 1, ParamValue::Bool(true))
+        ]
+    );
+}
+
+#[test]
+fn falls_back_on_a_bad_record() {
+    let mut storage = RamStorage::new(4096);
+    config().save(&mut storage).unwrap();
+
+    // a flipped bit in the payload fails the CRC
+    storage.bytes_mut()[HEADER_LEN + 3] ^= 0x10;
+    let (loaded, error) = NodeConfig::load_or(&mut storage, defaults);
+    assert_eq!(loaded, defaults());
+    assert_eq!(error, Some(ConfigError::Corrupt));
+
+    // as does a length running past the storage
+    config().save(&mut storage).unwrap();
+    storage.bytes_mut()[5..7].copy_from_slice(&u16::MAX.to_le_bytes());
+    assert_eq!(NodeConfig::load(&mut storage), Err(ConfigError::Corrupt));
+
+    // a layout from newer firmware can't be read
+    config().save(&mut storage).unwrap();
+    storage.bytes_mut()[4] = VERSION + 1;
+    assert_eq!(
+        NodeConfig::load(&mut storage),
+        Err(ConfigError::UnsupportedVersion(VERSION + 1))
+    );
+
+    // and a record that passes its CRC but isn't a config is malformed
+    let mut record = config().encode();
+    record.truncate(HEADER_LEN);
+    record.extend([0xff; 4]);
+    record[5..7].copy_from_slice(&4u16.to_le_bytes());
+    record[7..11].copy_from_slice(&crc32(&[0xff; 4]).to_le_bytes());
+    storage.write(&record).unwrap();
+    assert_eq!(NodeConfig::load(&mut storage), Err(ConfigError::Malformed));
+
+    // nothing but the header survived a torn write
+    let mut storage = RamStorage::new(4096);
+    storage.write(&config().encode()[..HEADER_LEN]).unwrap();
+    assert_eq!(NodeConfig::load(&mut storage), Err(ConfigError::Corrupt));
+}
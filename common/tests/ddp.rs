@@ -0,0 +1,126 @@
+use common::{
+    color::Rgb8,
+    net::{
+        ddp::{self, DdpError, DdpPacket, FLAG_PUSH, FLAG_QUERY, FLAG_TIMECODE, HEADER_LEN},
+        udp::EncodeError,
+    },
+};
+
+const RED: Rgb8 = Rgb8::new(255, 0, 0);
+const GREEN: Rgb8 = Rgb8::new(0, 255, 0);
+const BLUE: Rgb8 = Rgb8::new(0, 0, 255);
+
+/// A data packet laid out as xLights sends it.
+fn packet(flags: u8, offset: u32, data: &[u8]) -> Vec<u8> {
+    let mut packet = vec![0x40 | flags, 0x05, 0x0b, 0x01];
+    packet.extend(offset.to_be_bytes());
+    packet.extend((data.len() as u16).to_be_bytes());
+    if flags & FLAG_TIMECODE != 0 {
+        packet.extend([0xde, 0xad, 0xbe, 0xef]);
+    }
+    packet.extend(data);
+    packet
+}
+
+#[test]
+fn parses_data_packets() {
+    let packet = packet(FLAG_PUSH, 6, &[255, 0, 0, 0, 255, 0]);
+    let parsed = DdpPacket::parse(&packet).unwrap();
+    assert!(parsed.push);
+    assert_eq!(parsed.sequence, 5);
+    assert_eq!(parsed.start, 2);
+    assert_eq!(parsed.pixels.iter().collect::<Vec<_>>(), [RED, GREEN]);
+
+    let packet = self::packet(FLAG_TIMECODE, 0, &[0, 0, 255]);
+    let parsed = DdpPacket::parse(&packet).unwrap();
+    assert!(!parsed.push);
+    assert_eq!(parsed.pixels.iter().collect::<Vec<_>>(), [BLUE]);
+}
+
+#[test]
+fn queries_and_partial_pixels_carry_no_extra_data() {
+    let packet = packet(FLAG_QUERY, 0, &[]);
+    assert!(DdpPacket::parse(&packet).unwrap().pixels.is_empty());
+
+    let packet = self::packet(0, 0, &[1, 2, 3, 4]);
+    assert_eq!(DdpPacket::parse(&packet).unwrap().pixels.len(), 1);
+}
+
+#[test]
+fn rejects_bad_packets() {
+    let mut v2 = packet(0, 0, &[1, 2, 3]);
+    v2[0] = 0x80;
+    assert_eq!(DdpPacket::parse(&v2), Err(DdpError::Version(2)));
+
+    let mut rgb16 = packet(0, 0, &[1, 2, 3]);
+    rgb16[2] = 0x13;
+    assert_eq!(
+        DdpPacket::parse(&rgb16),
+        Err(DdpError::UnsupportedType(0x13))
+    );
+
+    let mut config = packet(0, 0, &[1, 2, 3]);
+    config[3] = 250;
+    assert_eq!(DdpPacket::parse(&config), Err(DdpError::UnsupportedId(250)));
+
+    let misaligned = packet(0, 4, &[1, 2, 3]);
+    assert_eq!(DdpPacket::parse(&misaligned), Err(DdpError::Misaligned(4)));
+
+    let short = packet(0, 0, &[1, 2, 3]);
+    assert_eq!(
+        DdpPacket::parse(&short[..HEADER_LEN - 1]),
+        Err(DdpError::Truncated)
+    );
+    assert_eq!(
+        DdpPacket::parse(&short[..HEADER_LEN + 2]),
+        Err(DdpError::Truncated)
+    );
+}
+
+#[test]
+fn writes_across_strips_end_to_end() {
+    let mut a = [Rgb8::zero(); 3];
+    let mut b = [Rgb8::zero(); 2];
+    let mut c = [Rgb8::zero(); 2];
+
+    // pixels 2..6 span the end of a, all of b and the start of c
+    let data = [RED, GREEN, BLUE, RED]
+        .iter()
+        .flat_map(|px| [px.r, px.g, px.b])
+        .collect::<Vec<_>>();
+    let packet = packet(0, 6, &data);
+    DdpPacket::parse(&packet)
+        .unwrap()
+        .write([&mut a[..], &mut b[..], &mut c[..]]);
+
+    assert_eq!(a, [Rgb8::zero(), Rgb8::zero(), RED]);
+    assert_eq!(b, [GREEN, BLUE]);
+    assert_eq!(c, [RED, Rgb8::zero()]);
+
+    // pixels past the last strip are ignored
+    let packet = self::packet(0, 18, &[255; 9]);
+    DdpPacket::parse(&packet)
+        .unwrap()
+        .write([&mut a[..], &mut b[..], &mut c[..]]);
+    assert_eq!(c, [RED, Rgb8::new(255, 255, 255)]);
+}
+
+#[test]
+fn encodes_what_it_parses() {
+    let colors = [RED, GREEN, BLUE];
+    let mut buf = [0u8; 64];
+    let packet = ddp::encode(&mut buf, 7, true, 10, &colors).unwrap();
+    assert_eq!(packet.len(), HEADER_LEN + 9);
+
+    let parsed = DdpPacket::parse(packet).unwrap();
+    assert!(parsed.push);
+    assert_eq!(parsed.sequence, 7);
+    assert_eq!(parsed.start, 10);
+    assert_eq!(parsed.pixels.iter().collect::<Vec<_>>(), colors);
+
+    let mut small = [0u8; HEADER_LEN + 3];
+    assert_eq!(
+        ddp::encode(&mut small, 0, false, 0, &colors),
+        Err(EncodeError::BufferFull)
+    );
+}
@@ -0,0 +1,78 @@
+use common::{
+    net::{
+        DDP_PORT, Mcu, TCP_PORT, UDP_PORT, Version,
+        discovery::{BEACON_VERSION, Beacon, BeaconError, MAGIC, MAX_BEACON_LEN},
+    },
+    provision::MAX_NAME_LEN,
+};
+
+fn beacon() -> Beacon {
+    Beacon {
+        id: [0x24, 0x6f, 0x28, 0x01, 0x02, 0x03],
+        name: "stage-left".into(),
+        version: Version::new(0, 1, 0),
+        mcu: Mcu::Esp32s3,
+        num_strips: 2,
+        tcp_port: TCP_PORT,
+        udp_port: UDP_PORT,
+        ddp_port: DDP_PORT,
+    }
+}
+
+#[test]
+fn round_trips() {
+    let mut buf = [0u8; MAX_BEACON_LEN];
+    let encoded = beacon().encode(&mut buf).unwrap();
+    assert_eq!(encoded[..4], MAGIC);
+    assert_eq!(encoded[4], BEACON_VERSION);
+    assert_eq!(Beacon::decode(encoded), Ok(beacon()));
+
+    let unnamed = Beacon {
+        name: String::new(),
+        mcu: Mcu::Host,
+        ..beacon()
+    };
+    let encoded = unnamed.encode(&mut buf).unwrap();
+    assert_eq!(Beacon::decode(encoded), Ok(unnamed));
+}
+
+#[test]
+fn fits_the_longest_name() {
+    let beacon = Beacon {
+        name: "n".repeat(MAX_NAME_LEN),
+        version: Version::new(u16::MAX, u16::MAX, u16::MAX),
+        tcp_port: u16::MAX,
+        udp_port: u16::MAX,
+        ddp_port: u16::MAX,
+        ..beacon()
+    };
+    let mut buf = [0u8; MAX_BEACON_LEN];
+    assert!(beacon.encode(&mut buf).is_ok());
+
+    let mut small = [0u8; 16];
+    assert!(beacon.encode(&mut small).is_err());
+    assert!(beacon.encode(&mut small[..3]).is_err());
+}
+
+#[test]
+fn rejects_other_packets() {
+    let mut buf = [0u8; MAX_BEACON_LEN];
+    let encoded = beacon().encode(&mut buf).unwrap().to_vec();
+
+    assert_eq!(Beacon::decode(b""), Err(BeaconError::NotABeacon));
+    assert_eq!(
+        Beacon::decode(b"GET / HTTP/1.1\r\n"),
+        Err(BeaconError::NotABeacon)
+    );
+    assert_eq!(Beacon::decode(&MAGIC), Err(BeaconError::Malformed));
+
+    let mut newer = encoded.clone();
+    newer[4] = BEACON_VERSION + 1;
+    assert_eq!(
+        Beacon::decode(&newer),
+        Err(BeaconError::UnsupportedVersion(BEACON_VERSION + 1))
+    );
+
+    // cut off partway through the name
+    assert_eq!(Beacon::decode(&encoded[..10]), Err(BeaconError::Malformed));
+}
@@ -0,0 +1,96 @@
+use common::net::{
+    StripMode,
+    failsafe::{DEFAULT_TIMEOUT, FADE_MS, Fallback, StreamTimeout, StreamWatchdog},
+};
+
+fn watchdog(fallback: Fallback) -> StreamWatchdog {
+    let mut watchdog = StreamWatchdog::new();
+    watchdog.set_timeout(Some(StreamTimeout::new(500, fallback)));
+    watchdog.feed(1000);
+    watchdog
+}
+
+#[test]
+fn times_out_by_default() {
+    let mut watchdog = StreamWatchdog::new();
+    assert_eq!(watchdog.timeout(), Some(DEFAULT_TIMEOUT));
+    watchdog.feed(1000);
+    let timeout = DEFAULT_TIMEOUT.timeout_ms as u64;
+    assert!(!watchdog.is_timed_out(999 + timeout));
+    assert!(watchdog.is_timed_out(1000 + timeout));
+}
+
+#[test]
+fn holds_the_stream_without_a_timeout() {
+    let mut watchdog = StreamWatchdog::new();
+    watchdog.set_timeout(None);
+    watchdog.feed(1000);
+    assert!(!watchdog.is_timed_out(u64::MAX));
+    assert_eq!(
+        watchdog.apply(StripMode::Dynamic, 100_000),
+        (StripMode::Dynamic, 1.0)
+    );
+}
+
+#[test]
+fn waits_for_a_stream_to_start() {
+    let mut watchdog = StreamWatchdog::new();
+    watchdog.set_timeout(Some(StreamTimeout::new(500, Fallback::Off)));
+    assert!(!watchdog.is_timed_out(100_000));
+    assert_eq!(
+        watchdog.apply(StripMode::Dynamic, 100_000),
+        (StripMode::Dynamic, 1.0)
+    );
+}
+
+#[test]
+fn falls_back_once_quiet() {
+    let effects = watchdog(Fallback::Effects);
+    assert_eq!(
+        effects.apply(StripMode::Dynamic, 1499),
+        (StripMode::Dynamic, 1.0)
+    );
+    assert!(effects.is_timed_out(1500));
+    assert_eq!(
+        effects.apply(StripMode::Dynamic, 1500),
+        (StripMode::Effects, 1.0)
+    );
+
+    let off = watchdog(Fallback::Off);
+    assert_eq!(off.apply(StripMode::Hybrid, 2000), (StripMode::Off, 1.0));
+
+    // strips that don't stream have nothing to time out
+    assert_eq!(
+        off.apply(StripMode::Effects, 2000),
+        (StripMode::Effects, 1.0)
+    );
+}
+
+#[test]
+fn fades_the_stream_out() {
+    let fade = watchdog(Fallback::Fade);
+    assert_eq!(
+        fade.apply(StripMode::Hybrid, 1500),
+        (StripMode::Hybrid, 1.0)
+    );
+    assert_eq!(
+        fade.apply(StripMode::Hybrid, 1500 + FADE_MS as u64 / 4),
+        (StripMode::Hybrid, 0.75)
+    );
+    assert_eq!(
+        fade.apply(StripMode::Hybrid, 1500 + FADE_MS as u64 * 2),
+        (StripMode::Hybrid, 0.0)
+    );
+}
+
+#[test]
+fn resumes_when_packets_return() {
+    let mut off = watchdog(Fallback::Off);
+    assert!(off.is_timed_out(3000));
+    off.feed(3000);
+    assert!(!off.is_timed_out(3000));
+    assert_eq!(
+        off.apply(StripMode::Dynamic, 3100),
+        (StripMode::Dynamic, 1.0)
+    );
+}
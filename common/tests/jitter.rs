@@ -0,0 +1,191 @@
+use common::{
+    color::RgbaF32,
+    math::Rng,
+    net::{
+        PRESENTATION_DELAY_MS, QUEUED_FRAMES, STREAM_INTERVAL_MS,
+        jitter::{JitterBuffer, Presented},
+    },
+};
+
+type Frame = Vec<RgbaF32>;
+
+/// A one-pixel frame whose gray level identifies it.
+fn frame(level: f32) -> Frame {
+    vec![RgbaF32::new_premultiplied(level, level, level, 1.0)]
+}
+
+fn level(frame: &Frame) -> f32 {
+    frame[0].r
+}
+
+/// Queue a whole frame, as a `SetBufferToMany` would.
+fn push<const N: usize>(buf: &mut JitterBuffer<Frame, N>, timestamp: u32, level: f32) -> bool {
+    match buf.stage(timestamp, &frame(0.0)) {
+        Some(staged) => {
+            *staged = frame(level);
+            true
+        }
+        None => false,
+    }
+}
+
+/// The level a strip would show after presenting at `now`, if anything changed.
+fn present<const N: usize>(buf: &mut JitterBuffer<Frame, N>, now: u32) -> Option<f32> {
+    let presented = buf.present(now)?;
+    let mut out = frame(-1.0);
+    presented.write(&mut out);
+    Some(level(&out))
+}
+
+#[test]
+fn presents_frames_at_their_time() {
+    let mut buf = JitterBuffer::<Frame, 4>::new(None);
+    assert_eq!(present(&mut buf, 0), None);
+
+    assert!(push(&mut buf, 116, 2.0));
+    assert!(push(&mut buf, 100, 1.0));
+    assert!(push(&mut buf, 132, 3.0));
+    assert_eq!(buf.len(), 3);
+
+    assert_eq!(present(&mut buf, 99), None);
+    assert_eq!(present(&mut buf, 100), Some(1.0));
+    assert_eq!(present(&mut buf, 110), None);
+    assert_eq!(buf.current(), Some(100));
+    // frames due while nothing presented are skipped past
+    assert_eq!(present(&mut buf, 140), Some(3.0));
+    assert!(buf.is_empty());
+}
+
+#[test]
+fn rejects_frames_already_passed() {
+    let mut buf = JitterBuffer::<Frame, 4>::new(None);
+    assert!(push(&mut buf, 100, 1.0));
+    assert!(push(&mut buf, 116, 2.0));
+    assert_eq!(present(&mut buf, 116), Some(2.0));
+
+    assert!(!push(&mut buf, 100, 1.0));
+    assert!(!push(&mut buf, 116, 2.0));
+    assert!(push(&mut buf, 117, 3.0));
+
+    buf.reset();
+    assert_eq!(buf.current(), None);
+    assert!(buf.is_empty());
+    assert!(push(&mut buf, 5, 1.0));
+}
+
+#[test]
+fn builds_frames_from_several_commands() {
+    let mut buf = JitterBuffer::<Frame, 4>::new(None);
+    let base = vec![RgbaF32::zero(); 3];
+    let red = RgbaF32::new_premultiplied(1.0, 0.0, 0.0, 1.0);
+    let blue = RgbaF32::new_premultiplied(0.0, 0.0, 1.0, 1.0);
+
+    buf.stage(100, &base).unwrap()[0] = red;
+    buf.stage(100, &base).unwrap()[1] = red;
+    assert_eq!(buf.len(), 1);
+    // the next frame starts from this one, not the base
+    buf.stage(116, &base).unwrap()[2] = blue;
+
+    let mut out = base.clone();
+    buf.present(100).unwrap().write(&mut out);
+    assert_eq!(out, [red, red, RgbaF32::zero()]);
+    buf.present(116).unwrap().write(&mut out);
+    assert_eq!(out, [red, red, blue]);
+}
+
+#[test]
+fn drops_the_oldest_frame_when_full() {
+    let mut buf = JitterBuffer::<Frame, 2>::new(None);
+    assert!(push(&mut buf, 100, 1.0));
+    assert!(push(&mut buf, 116, 2.0));
+    assert!(push(&mut buf, 132, 3.0));
+    assert_eq!(buf.len(), 2);
+    // older than everything queued, so it would only be dropped again
+    assert!(!push(&mut buf, 90, 0.5));
+
+    assert_eq!(present(&mut buf, 116), Some(2.0));
+    assert_eq!(present(&mut buf, 132), Some(3.0));
+}
+
+#[test]
+fn interpolates_over_missing_frames() {
+    let interval = STREAM_INTERVAL_MS;
+    let mut buf = JitterBuffer::<Frame, 4>::new(Some(interval));
+    assert!(push(&mut buf, 0, 0.0));
+    // the frame at one interval never arrives
+    assert!(push(&mut buf, 2 * interval, 1.0));
+
+    assert_eq!(present(&mut buf, 0), Some(0.0));
+    match buf.present(interval / 2) {
+        Some(Presented::Between { t, .. }) => assert_eq!(t, 0.25),
+        other => panic!("expected interpolation, got {other:?}"),
+    }
+    assert_eq!(present(&mut buf, interval), Some(0.5));
+    assert_eq!(present(&mut buf, 2 * interval), Some(1.0));
+
+    // a stream that paused isn't faded across
+    assert!(push(&mut buf, 10 * interval, 0.0));
+    assert_eq!(present(&mut buf, 5 * interval), None);
+
+    // and nothing is interpolated unless asked for
+    let mut buf = JitterBuffer::<Frame, 4>::new(None);
+    assert!(push(&mut buf, 0, 0.0));
+    assert!(push(&mut buf, 2 * interval, 1.0));
+    assert_eq!(present(&mut buf, 0), Some(0.0));
+    assert_eq!(present(&mut buf, interval), None);
+}
+
+#[test]
+fn timestamps_wrap_around() {
+    let mut buf = JitterBuffer::<Frame, 4>::new(None);
+    let start = u32::MAX - 10;
+    assert!(push(&mut buf, start.wrapping_add(16), 2.0));
+    assert!(push(&mut buf, start, 1.0));
+
+    assert_eq!(present(&mut buf, start), Some(1.0));
+    assert_eq!(present(&mut buf, 0), None);
+    assert_eq!(present(&mut buf, 5), Some(2.0));
+    assert!(!push(&mut buf, start.wrapping_add(8), 1.5));
+}
+
+/// Stream frames with a few to tens of ms of jitter, occasionally losing one,
+/// and check a node polling every ms shows each on time and in order.
+#[test]
+fn smooths_jittery_arrivals() {
+    let mut rng = Rng::new(7);
+    let mut buf = JitterBuffer::<Frame, QUEUED_FRAMES>::new(Some(STREAM_INTERVAL_MS));
+
+    let frames = 500;
+    let mut arrivals = Vec::new();
+    for i in 0..frames {
+        if rng.below(20) == 0 {
+            continue;
+        }
+        let rendered = 1000 + i * STREAM_INTERVAL_MS;
+        let arrival = rendered + 2 + rng.below(PRESENTATION_DELAY_MS - 10);
+        arrivals.push((arrival, rendered + PRESENTATION_DELAY_MS, i as f32));
+    }
+    arrivals.sort_by_key(|&(arrival, ..)| arrival);
+
+    let mut arrivals = arrivals.into_iter().peekable();
+    let mut shown = Vec::new();
+    let end = 1000 + frames * STREAM_INTERVAL_MS + PRESENTATION_DELAY_MS;
+    for now in 0..end {
+        while let Some((_, timestamp, level)) = arrivals.next_if(|&(arrival, ..)| arrival <= now) {
+            assert!(push(&mut buf, timestamp, level), "frame {level} was late");
+        }
+        if let Some(level) = present(&mut buf, now) {
+            shown.push((now, level));
+        }
+    }
+
+    // every frame shows exactly when it was stamped for, missing ones
+    // blended in between
+    for (now, level) in shown {
+        let expected = (now - 1000 - PRESENTATION_DELAY_MS) as f32 / STREAM_INTERVAL_MS as f32;
+        assert!(
+            (level - expected).abs() < 1e-3,
+            "showed {level} at {now}, expected {expected}"
+        );
+    }
+}
@@ -0,0 +1,137 @@
+use common::{
+    color::{BlendMode, Rgb8, RgbaF32, rgb8::GAMMA},
+    effect::layer::{blend_layer, flatten},
+};
+
+const MODES: [BlendMode; 5] = [
+    BlendMode::Over,
+    BlendMode::Add,
+    BlendMode::Multiply,
+    BlendMode::Screen,
+    BlendMode::Max,
+];
+
+fn rgba(r: f32, g: f32, b: f32, a: f32) -> RgbaF32 {
+    RgbaF32::new(r, g, b, a)
+}
+
+fn assert_close(actual: RgbaF32, expected: RgbaF32) {
+    let d = [
+        actual.r - expected.r,
+        actual.g - expected.g,
+        actual.b - expected.b,
+        actual.a - expected.a,
+    ];
+    assert!(
+        d.iter().all(|d| d.abs() < 1e-5),
+        "{actual:?} != {expected:?}"
+    );
+}
+
+#[test]
+fn opaque_modes() {
+    let fg = rgba(0.5, 1.0, 0.0, 1.0);
+    let bg = rgba(0.5, 0.25, 1.0, 1.0);
+
+    assert_close(fg.blend(bg, BlendMode::Over), fg);
+    assert_close(fg.blend(bg, BlendMode::Add), rgba(1.0, 1.0, 1.0, 1.0));
+    assert_close(
+        fg.blend(bg, BlendMode::Multiply),
+        rgba(0.25, 0.25, 0.0, 1.0),
+    );
+    assert_close(fg.blend(bg, BlendMode::Screen), rgba(0.75, 1.0, 1.0, 1.0));
+    assert_close(fg.blend(bg, BlendMode::Max), rgba(0.5, 1.0, 1.0, 1.0));
+}
+
+#[test]
+fn transparent_layers_change_nothing() {
+    let bg = rgba(0.2, 0.4, 0.6, 0.8);
+    for mode in MODES {
+        assert_close(RgbaF32::zero().blend(bg, mode), bg);
+        assert_close(bg.blend(RgbaF32::zero(), mode), bg);
+    }
+}
+
+#[test]
+fn modes_apply_where_layers_overlap() {
+    // half coverage of white over half coverage of 50% gray
+    let fg = rgba(1.0, 1.0, 1.0, 0.5);
+    let bg = rgba(0.5, 0.5, 0.5, 0.5);
+
+    // a quarter of the pixel is only white, a quarter only gray, and a quarter
+    // both, where the blend mode decides
+    let expected = |overlap: f32| {
+        let x = 0.25 * 1.0 + 0.25 * 0.5 + 0.25 * overlap;
+        RgbaF32::new_premultiplied(x, x, x, 0.75)
+    };
+    assert_close(fg.blend(bg, BlendMode::Over), expected(1.0));
+    assert_close(fg.blend(bg, BlendMode::Multiply), expected(0.5));
+    assert_close(fg.blend(bg, BlendMode::Screen), expected(1.0));
+    assert_close(fg.blend(bg, BlendMode::Max), expected(1.0));
+}
+
+#[test]
+fn blend_results_stay_in_range() {
+    let values = [0.0, 0.1, 0.5, 0.9, 1.0];
+    for mode in MODES {
+        for &a in &values {
+            for &b in &values {
+                for &c in &values {
+                    let out = rgba(a, b, c, b).blend(rgba(c, a, b, a), mode);
+                    for x in [out.r, out.g, out.b, out.a] {
+                        assert!((-1e-6..=1.0 + 1e-6).contains(&x), "{mode:?}: {out:?}");
+                    }
+                    // premultiplied colors never exceed their alpha
+                    assert!(
+                        out.r.max(out.g).max(out.b) <= out.a + 1e-6,
+                        "{mode:?}: {out:?}"
+                    );
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn layers_stack_with_opacity() {
+    let mut buf = [rgba(1.0, 0.0, 0.0, 1.0); 2];
+    let overlay = [rgba(0.0, 0.0, 1.0, 1.0), RgbaF32::zero()];
+    blend_layer(&mut buf, &overlay, BlendMode::Over, 0.5);
+    assert_close(buf[0], rgba(0.5, 0.0, 0.5, 1.0));
+    assert_close(buf[1], rgba(1.0, 0.0, 0.0, 1.0));
+
+    blend_layer(&mut buf, &overlay, BlendMode::Add, 1.0);
+    assert_close(buf[0], rgba(0.5, 0.0, 1.0, 1.0));
+}
+
+#[test]
+fn flatten_corrects_gamma_once() {
+    let buf = [
+        RgbaF32::from(Rgb8::new(128, 64, 255)),
+        rgba(1.0, 1.0, 1.0, 0.5),
+        RgbaF32::zero(),
+    ];
+    let out = flatten(&buf, 1.0).collect::<Vec<_>>();
+    assert_eq!(
+        out,
+        [
+            Rgb8::new(GAMMA[128], GAMMA[64], 255),
+            Rgb8::gray(GAMMA[128]),
+            Rgb8::zero(),
+        ]
+    );
+
+    let dim = flatten(&buf[..1], 0.5).collect::<Vec<_>>();
+    assert_eq!(dim, [Rgb8::new(GAMMA[128], GAMMA[64], 255) * 0.5]);
+}
+
+#[test]
+fn modes_parse_their_names() {
+    for (name, mode) in ["over", "add", "multiply", "screen", "max"]
+        .into_iter()
+        .zip(MODES)
+    {
+        assert_eq!(name.parse::<BlendMode>().unwrap(), mode);
+    }
+    assert!("lighten".parse::<BlendMode>().is_err());
+}
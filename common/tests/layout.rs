@@ -0,0 +1,97 @@
+use common::{
+    layout::{LayoutError, Segment, StripLayout},
+    math::Vec3,
+};
+
+fn assert_close(actual: &[Vec3], expected: &[Vec3]) {
+    assert_eq!(actual.len(), expected.len());
+    for (i, (a, e)) in actual.iter().zip(expected).enumerate() {
+        assert!(a.distance(*e) < 1e-4, "LED {i}: {a:?} != {e:?}");
+    }
+}
+
+fn x(x: f32) -> Vec3 {
+    Vec3::new(x, 0.0, 0.0)
+}
+
+#[test]
+fn straight_run() {
+    let mut layout = StripLayout::polyline(4, vec![Segment::new(x(0.0), x(1.0))]);
+    layout.density = 4.0;
+    assert_close(
+        &layout.positions().unwrap(),
+        &[x(0.125), x(0.375), x(0.625), x(0.875)],
+    );
+}
+
+#[test]
+fn corner() {
+    let mut layout = StripLayout::polyline(
+        4,
+        vec![
+            Segment::new(x(0.0), x(1.0)),
+            Segment::new(x(1.0), Vec3::new(1.0, 1.0, 0.0)),
+        ],
+    );
+    layout.density = 2.0;
+    assert_close(
+        &layout.positions().unwrap(),
+        &[
+            x(0.25),
+            x(0.75),
+            Vec3::new(1.0, 0.25, 0.0),
+            Vec3::new(1.0, 0.75, 0.0),
+        ],
+    );
+}
+
+#[test]
+fn reversed_strip() {
+    let mut layout = StripLayout::polyline(2, vec![Segment::new(x(0.0), x(1.0))]);
+    layout.density = 2.0;
+    layout.rev = true;
+    assert_close(&layout.positions().unwrap(), &[x(0.75), x(0.25)]);
+    assert!(layout.info().rev);
+}
+
+#[test]
+fn segment_density_overrides_strip() {
+    let mut fine = Segment::new(x(0.0), x(1.0));
+    fine.density = Some(3.0);
+    let mut layout = StripLayout::polyline(4, vec![fine, Segment::new(x(1.0), x(2.0))]);
+    layout.density = 1.0;
+    assert_close(
+        &layout.positions().unwrap(),
+        &[x(1.0 / 6.0), x(0.5), x(5.0 / 6.0), x(1.5)],
+    );
+}
+
+#[test]
+fn explicit_positions_override_segments() {
+    let mut layout = StripLayout::polyline(2, vec![Segment::new(x(0.0), x(10.0))]);
+    layout.positions = vec![x(3.0), Vec3::new(0.0, 0.0, 3.0)];
+    layout.rev = true;
+    assert_close(
+        &layout.positions().unwrap(),
+        &[Vec3::new(0.0, 0.0, 3.0), x(3.0)],
+    );
+}
+
+#[test]
+fn led_count_mismatch() {
+    let layout = StripLayout::polyline(10, vec![Segment::new(x(0.0), x(0.1))]);
+    assert_eq!(
+        layout.positions(),
+        Err(LayoutError::LedCountMismatch {
+            leds: 10,
+            placed: 6
+        })
+    );
+}
+
+#[test]
+fn invalid_density() {
+    let mut layout = StripLayout::polyline(1, vec![Segment::new(x(0.0), x(1.0))]);
+    layout.density = 0.0;
+    assert_eq!(layout.positions(), Err(LayoutError::InvalidDensity(0.0)));
+}
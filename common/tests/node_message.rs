@@ -0,0 +1,98 @@
+use common::{
+    effect::StripInfo,
+    net::{
+        Chipset, Heartbeat, MAX_FRAME_LEN, Mcu, NodeError, NodeHello, NodeMessage, Version,
+        auth::AuthError, decode_frame, encode_frame,
+    },
+};
+
+fn round_trip(msg: NodeMessage) {
+    let mut buf = [0u8; MAX_FRAME_LEN];
+    let frame = encode_frame(&msg, &mut buf).unwrap();
+    assert_eq!(frame.last(), Some(&0), "frames end in a delimiter");
+    assert!(
+        !frame[..frame.len() - 1].contains(&0),
+        "frames contain no other zeroes"
+    );
+
+    let decoded: NodeMessage = decode_frame(frame).unwrap();
+    assert_eq!(decoded, msg);
+}
+
+#[test]
+fn hello_round_trips() {
+    round_trip(NodeMessage::Hello(NodeHello {
+        version: Version::new(1, 2, 3),
+        mcu: Mcu::Esp32s3,
+        num_strips: 2,
+    }));
+    round_trip(NodeMessage::Hello(NodeHello {
+        version: Version::default(),
+        mcu: Mcu::Esp32c6,
+        num_strips: 0,
+    }));
+}
+
+#[test]
+fn strip_round_trips() {
+    round_trip(NodeMessage::Strip(
+        1,
+        StripInfo {
+            leds: 300,
+            rev: true,
+        },
+    ));
+}
+
+#[test]
+fn heartbeat_round_trips() {
+    round_trip(NodeMessage::Heartbeat(Heartbeat {
+        uptime_ms: u64::MAX,
+        free_heap: 73744,
+        frame_rate: 58.5,
+        dropped_packets: 12,
+        stale_packets: 4,
+        rejected_packets: 2,
+        rssi: Some(-67),
+    }));
+    round_trip(NodeMessage::Heartbeat(Heartbeat::default()));
+}
+
+#[test]
+fn error_round_trips() {
+    round_trip(NodeMessage::Error(NodeError::MalformedFrame));
+    round_trip(NodeMessage::Error(NodeError::UnknownStrip(7)));
+    round_trip(NodeMessage::Error(NodeError::Unauthenticated(
+        AuthError::Replayed(3),
+    )));
+}
+
+#[test]
+fn frames_split_on_delimiters() {
+    let mut buf = [0u8; MAX_FRAME_LEN * 2];
+    let first = NodeMessage::Error(NodeError::MalformedFrame);
+    let second = NodeMessage::Strip(0, StripInfo::empty());
+
+    let n = encode_frame(&first, &mut buf).unwrap().len();
+    let m = encode_frame(&second, &mut buf[n..]).unwrap().len();
+
+    let (a, b) = buf[..n + m].split_at_mut(n);
+    assert_eq!(decode_frame::<NodeMessage>(a).unwrap(), first);
+    assert_eq!(decode_frame::<NodeMessage>(b).unwrap(), second);
+}
+
+#[test]
+fn version_parses() {
+    assert_eq!(Version::parse("0.1.0"), Version::new(0, 1, 0));
+    assert_eq!(Version::parse("12.34.56"), Version::new(12, 34, 56));
+    assert_eq!(Version::parse("1.2.3-rc.1"), Version::new(1, 2, 3));
+    assert_eq!(Version::parse("4"), Version::new(4, 0, 0));
+}
+
+#[test]
+fn chipset_parses_its_config_name() {
+    assert_eq!("ws2812b".parse(), Ok(Chipset::Ws2812b));
+    assert_eq!("sk6812".parse(), Ok(Chipset::Sk6812));
+    assert_eq!("ws2811".parse(), Ok(Chipset::Ws2811));
+    assert!("apa102".parse::<Chipset>().is_err());
+}
@@ -0,0 +1,179 @@
+use common::{
+    net::{MAX_FRAME_LEN, NodeMessage, ServerMessage, decode_frame, encode_frame},
+    ota::{
+        CHUNK_LEN, RamFlash, SECTOR_LEN, UpdateChunk, UpdateError, UpdateHeader, UpdateStatus,
+        UpdateWriter, chunks,
+    },
+};
+
+fn hex(digest: [u8; 32]) -> String {
+    digest.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+/// A made-up image that ends partway through a sector and a chunk.
+fn image() -> Vec<u8> {
+    (0..2 * SECTOR_LEN + 1000)
+        .map(|i| (i * 7 + i / 251) as u8)
+        .collect()
+}
+
+/// Write every chunk of `image`, returning what each write reported.
+fn write_all(writer: &mut UpdateWriter, flash: &mut RamFlash, image: &[u8]) -> Vec<Option<u32>> {
+    chunks(image)
+        .map(|chunk| writer.write(flash, &chunk).unwrap())
+        .collect()
+}
+
+#[test]
+fn header_carries_the_image_sha256() {
+    let header = UpdateHeader::for_image(b"abc").unwrap();
+    assert_eq!(header.size, 3);
+    assert_eq!(
+        hex(header.sha256),
+        "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+    );
+}
+
+#[test]
+fn writes_and_verifies_an_image() {
+    let image = image();
+    let mut flash = RamFlash::new(4 * SECTOR_LEN as u32);
+    let mut writer = UpdateWriter::begin(&flash, UpdateHeader::for_image(&image).unwrap()).unwrap();
+
+    let reports = write_all(&mut writer, &mut flash, &image);
+    assert!(writer.is_complete());
+    // one write per sector, the last one as soon as the image is in
+    assert_eq!(flash.writes(), 3);
+    let written = reports.into_iter().flatten().collect::<Vec<_>>();
+    assert_eq!(
+        written,
+        [SECTOR_LEN as u32, 2 * SECTOR_LEN as u32, image.len() as u32]
+    );
+
+    assert_eq!(writer.verify(&mut flash), Ok(()));
+    assert_eq!(flash.bytes()[..image.len()], image);
+
+    // a bit flipped in flash fails the hash
+    flash.bytes_mut()[5000] ^= 0x01;
+    assert_eq!(writer.verify(&mut flash), Err(UpdateError::HashMismatch));
+}
+
+#[test]
+fn rejects_bad_chunks() {
+    let image = image();
+    let header = UpdateHeader::for_image(&image).unwrap();
+    let mut flash = RamFlash::new(4 * SECTOR_LEN as u32);
+
+    let tiny = RamFlash::new(SECTOR_LEN as u32);
+    assert!(matches!(
+        UpdateWriter::begin(&tiny, header),
+        Err(UpdateError::TooLarge)
+    ));
+
+    let mut writer = UpdateWriter::begin(&flash, header).unwrap();
+    let mut chunks = chunks(&image);
+    let first = chunks.next().unwrap();
+    let second = chunks.next().unwrap();
+
+    assert_eq!(
+        writer.write(&mut flash, &second),
+        Err(UpdateError::OutOfOrder(0))
+    );
+    let mut corrupt = first.clone();
+    corrupt.data[3] ^= 0x10;
+    assert_eq!(
+        writer.write(&mut flash, &corrupt),
+        Err(UpdateError::BadChunk(0))
+    );
+    let long = UpdateChunk::new(0, &[0; CHUNK_LEN + 1]);
+    assert_eq!(
+        writer.write(&mut flash, &long),
+        Err(UpdateError::BadChunk(0))
+    );
+
+    // none of that counted, so the image still goes in from the start
+    assert_eq!(writer.write(&mut flash, &first), Ok(None));
+    assert_eq!(
+        writer.write(&mut flash, &first),
+        Err(UpdateError::OutOfOrder(CHUNK_LEN as u32))
+    );
+    assert_eq!(
+        writer.verify(&mut flash),
+        Err(UpdateError::OutOfOrder(CHUNK_LEN as u32))
+    );
+
+    // nothing past the size in the header
+    let mut writer =
+        UpdateWriter::begin(&flash, UpdateHeader::for_image(&image[..100]).unwrap()).unwrap();
+    assert_eq!(writer.write(&mut flash, &first), Err(UpdateError::TooLarge));
+}
+
+#[test]
+fn reports_progress_then_the_verdict() {
+    let image = image();
+    let mut flash = RamFlash::new(4 * SECTOR_LEN as u32);
+    let mut writer = UpdateWriter::begin(&flash, UpdateHeader::for_image(&image).unwrap()).unwrap();
+    let reports = chunks(&image)
+        .filter_map(|chunk| writer.apply(&mut flash, &chunk))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        reports,
+        [
+            UpdateStatus::Written(SECTOR_LEN as u32),
+            UpdateStatus::Written(2 * SECTOR_LEN as u32),
+            UpdateStatus::Verified,
+        ]
+    );
+
+    // the last chunk is checked against the hash as it lands
+    let mut header = UpdateHeader::for_image(&image).unwrap();
+    header.sha256[0] ^= 0xff;
+    let mut writer = UpdateWriter::begin(&flash, header).unwrap();
+    let last = chunks(&image)
+        .filter_map(|chunk| writer.apply(&mut flash, &chunk))
+        .last();
+    assert_eq!(last, Some(UpdateStatus::Failed(UpdateError::HashMismatch)));
+
+    let mut writer = UpdateWriter::begin(&flash, UpdateHeader::for_image(&image).unwrap()).unwrap();
+    let chunk = UpdateChunk::new(CHUNK_LEN as u32, &image[CHUNK_LEN..2 * CHUNK_LEN]);
+    assert_eq!(
+        writer.apply(&mut flash, &chunk),
+        Some(UpdateStatus::Failed(UpdateError::OutOfOrder(0)))
+    );
+}
+
+#[test]
+fn verifies_an_empty_image() {
+    let mut flash = RamFlash::new(SECTOR_LEN as u32);
+    let writer = UpdateWriter::begin(&flash, UpdateHeader::for_image(&[]).unwrap()).unwrap();
+    assert!(writer.is_complete());
+    assert_eq!(writer.verify(&mut flash), Ok(()));
+}
+
+#[test]
+fn update_messages_fit_in_a_frame() {
+    let mut buf = [0u8; MAX_FRAME_LEN];
+
+    let chunk = ServerMessage::UpdateChunk(UpdateChunk::new(u32::MAX, &[0xff; CHUNK_LEN]));
+    let frame = encode_frame(&chunk, &mut buf).unwrap();
+    assert_eq!(decode_frame::<ServerMessage>(frame), Ok(chunk));
+
+    let begin = ServerMessage::BeginUpdate(UpdateHeader {
+        size: u32::MAX,
+        sha256: [0xff; 32],
+    });
+    let frame = encode_frame(&begin, &mut buf).unwrap();
+    assert_eq!(decode_frame::<ServerMessage>(frame), Ok(begin));
+
+    for status in [
+        UpdateStatus::Ready,
+        UpdateStatus::Written(u32::MAX),
+        UpdateStatus::Verified,
+        UpdateStatus::Confirmed,
+        UpdateStatus::Failed(UpdateError::BadChunk(u32::MAX)),
+    ] {
+        let msg = NodeMessage::Update(status);
+        let frame = encode_frame(&msg, &mut buf).unwrap();
+        assert_eq!(decode_frame::<NodeMessage>(frame), Ok(msg));
+    }
+}
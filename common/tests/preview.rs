@@ -0,0 +1,55 @@
+use common::{
+    color::Rgb8,
+    net::StripMode,
+    preview::{Preview, PreviewRow, PreviewStatus, downsample},
+};
+
+const RED: Rgb8 = Rgb8::new(255, 0, 0);
+const BLUE: Rgb8 = Rgb8::new(0, 0, 255);
+
+#[test]
+fn downsampling_averages_groups() {
+    let pixels = [RED, RED, BLUE, BLUE, RED, BLUE];
+    assert_eq!(downsample(&pixels, 3), [RED, BLUE, Rgb8::new(127, 0, 127)]);
+    assert_eq!(downsample(&pixels, 10), pixels);
+    assert_eq!(downsample(&[RED; 300], 100), [RED; 100]);
+}
+
+#[test]
+fn draws_a_row_per_strip_and_a_status_line() {
+    let a = [RED, RED, BLUE];
+    let b = [BLUE; 8];
+    let rows = [
+        PreviewRow {
+            label: "a".into(),
+            mode: Some(StripMode::Hybrid),
+            pixels: &a,
+        },
+        PreviewRow {
+            label: "bb".into(),
+            mode: None,
+            pixels: &b,
+        },
+    ];
+    let status = PreviewStatus {
+        effect: Some(3),
+        fps: 59.94,
+    };
+    let frame = Preview::new(4).draw(rows, status);
+
+    let lines = frame.lines().collect::<Vec<_>>();
+    assert_eq!(lines.len(), 4);
+    assert_eq!(
+        lines[0],
+        "\x1b[Ha  Hybrid   \x1b[48;2;255;0;0m  \x1b[48;2;0;0;255m \x1b[0m\x1b[K"
+    );
+    // downsampled to the preview's width, with one escape per run of a color
+    assert_eq!(lines[1], "bb          \x1b[48;2;0;0;255m    \x1b[0m\x1b[K");
+    assert_eq!(lines[2], "effect 3 | 59.9 fps\x1b[K");
+}
+
+#[test]
+fn status_without_an_effect() {
+    let frame = Preview::default().draw([], PreviewStatus::default());
+    assert_eq!(frame, "\x1b[Heffect - | 0.0 fps\x1b[K\n\x1b[J");
+}
@@ -0,0 +1,267 @@
+use common::{
+    config::NetworkConfig,
+    net::auth::Key,
+    provision::{
+        AP_ADDR, MalformedRequest, PAGE_URL, PageStatus, ProvisionError, Request, ap_password,
+        ap_ssid, dns_reply, parse_form, render_page, response,
+    },
+};
+
+/// A node with nothing set up yet.
+fn unset() -> NetworkConfig {
+    NetworkConfig::default()
+}
+
+fn form(ssid: &str, password: &str, name: &str, server: &str) -> Vec<u8> {
+    format!("ssid={ssid}&password={password}&name={name}&server={server}").into_bytes()
+}
+
+#[test]
+fn parses_a_submitted_form() {
+    let network = parse_form(
+        &form("My+Wi-Fi%21", "p%26ss+word", "stage-left", "10.0.0.2"),
+        &unset(),
+    )
+    .unwrap();
+    assert_eq!(
+        network,
+        NetworkConfig {
+            ssid: "My Wi-Fi!".into(),
+            password: "p&ss word".into(),
+            name: "stage-left".into(),
+            server: Some([10, 0, 0, 2].into()),
+            key: None,
+        }
+    );
+    assert_eq!(ap_ssid(&network), "lightspace-stage-left");
+
+    // an open network, any server, fields in any order and extras ignored
+    let network = parse_form(b"server=&name=a&submit=1&password=&ssid=cafe", &unset()).unwrap();
+    assert_eq!(network.password, "");
+    assert_eq!(network.server, None);
+}
+
+#[test]
+fn parses_an_installation_key() {
+    let hex = "00112233445566778899aabbccddeeff00112233445566778899AABBCCDDEEFF";
+    let body = [form("x", "", "a", ""), format!("&key={hex}").into_bytes()].concat();
+    let network = parse_form(&body, &unset()).unwrap();
+    assert_eq!(network.key, Key::parse(hex));
+    assert!(network.key.is_some());
+
+    // left empty, the node takes traffic from anyone
+    let body = [form("x", "", "a", ""), b"&key=".to_vec()].concat();
+    assert_eq!(parse_form(&body, &unset()).unwrap().key, None);
+
+    for bad in ["00112233", &"zz".repeat(32), &"0".repeat(65)] {
+        let body = [form("x", "", "a", ""), format!("&key={bad}").into_bytes()].concat();
+        assert_eq!(
+            parse_form(&body, &unset()),
+            Err(ProvisionError::InvalidKey),
+            "{bad}"
+        );
+    }
+}
+
+#[test]
+fn asks_a_node_holding_a_key_for_it() {
+    let key = Key::new([0xab; 32]);
+    let current = NetworkConfig {
+        key: Some(key.clone()),
+        ..parse_form(&form("x", "", "a", ""), &unset()).unwrap()
+    };
+
+    // without the key, or with the wrong one, nothing changes
+    let body = form("evil", "", "a", "");
+    assert_eq!(parse_form(&body, &current), Err(ProvisionError::WrongKey));
+    for wrong in ["", "00112233", &"cd".repeat(32)] {
+        let body = [
+            form("evil", "", "a", ""),
+            format!("&current_key={wrong}").into_bytes(),
+        ]
+        .concat();
+        assert_eq!(
+            parse_form(&body, &current),
+            Err(ProvisionError::WrongKey),
+            "{wrong}"
+        );
+    }
+
+    // with it, an empty key keeps the current one
+    let given = format!("&current_key={}", "AB".repeat(32));
+    let body = [form("cafe", "", "a", ""), given.clone().into_bytes()].concat();
+    let network = parse_form(&body, &current).unwrap();
+    assert_eq!(
+        (network.ssid.as_str(), network.key),
+        ("cafe", Some(key.clone()))
+    );
+    let new = "cd".repeat(32);
+    let body = [
+        form("cafe", "", "a", ""),
+        format!("{given}&key={new}").into_bytes(),
+    ]
+    .concat();
+    assert_eq!(parse_form(&body, &current).unwrap().key, Key::parse(&new));
+
+    // the page asks for it, and the setup network is no longer open
+    assert!(render_page(&current, PageStatus::Blank).contains("name=\"current_key\""));
+    assert!(!render_page(&unset(), PageStatus::Blank).contains("current_key"));
+    let password = ap_password(&key);
+    assert!((8..=63).contains(&password.len()));
+    assert_ne!(password, ap_password(&Key::new([0xcd; 32])));
+}
+
+#[test]
+fn rejects_bad_values() {
+    let long_ssid = "s".repeat(33);
+    let long_name = "n".repeat(22);
+    let cases = [
+        (form("", "", "a", ""), ProvisionError::SsidLength),
+        (form(&long_ssid, "", "a", ""), ProvisionError::SsidLength),
+        (form("x", "short", "a", ""), ProvisionError::PasswordLength),
+        (
+            form("x", &"p".repeat(64), "a", ""),
+            ProvisionError::PasswordLength,
+        ),
+        (form("x", "", "", ""), ProvisionError::InvalidName),
+        (form("x", "", &long_name, ""), ProvisionError::InvalidName),
+        (form("x", "", "-stage", ""), ProvisionError::InvalidName),
+        (form("x", "", "stage+left", ""), ProvisionError::InvalidName),
+        (
+            form("x", "", "a", "server.local"),
+            ProvisionError::InvalidServer,
+        ),
+        (
+            form("x", "", "a", "10.0.0.256"),
+            ProvisionError::InvalidServer,
+        ),
+        (form("x%zz", "", "a", ""), ProvisionError::Encoding),
+        (form("%ff", "", "a", ""), ProvisionError::Encoding),
+        (
+            b"ssid=x&name=a&server=".to_vec(),
+            ProvisionError::Missing("password"),
+        ),
+    ];
+    for (body, error) in cases {
+        assert_eq!(
+            parse_form(&body, &unset()),
+            Err(error),
+            "{}",
+            String::from_utf8_lossy(&body)
+        );
+    }
+
+    // a 21 character name still fits in the setup network's SSID
+    let network = parse_form(&form("x", "", &"n".repeat(21), ""), &unset()).unwrap();
+    assert_eq!(ap_ssid(&network).len(), 32);
+}
+
+#[test]
+fn parses_requests_as_they_arrive() {
+    let body = form("cafe", "", "a", "");
+    let mut raw = format!(
+        "POST / HTTP/1.1\r\nHost: 192.168.4.1\r\ncontent-length: {}\r\n\r\n",
+        body.len()
+    )
+    .into_bytes();
+    raw.extend(&body);
+
+    for end in 0..raw.len() {
+        assert_eq!(Request::parse(&raw[..end]), Ok(None), "{end} bytes in");
+    }
+    assert_eq!(
+        Request::parse(&raw),
+        Ok(Some(Request {
+            method: "POST",
+            path: "/",
+            body: &body,
+        }))
+    );
+
+    let get = b"GET /hotspot-detect.html HTTP/1.0\r\n\r\n";
+    let request = Request::parse(get).unwrap().unwrap();
+    assert_eq!(
+        (request.method, request.path),
+        ("GET", "/hotspot-detect.html")
+    );
+    assert!(request.body.is_empty());
+
+    assert_eq!(Request::parse(b"hello\r\n\r\n"), Err(MalformedRequest));
+    assert_eq!(
+        Request::parse(b"GET / SPDY/3\r\n\r\n"),
+        Err(MalformedRequest)
+    );
+    assert_eq!(
+        Request::parse(b"POST / HTTP/1.1\r\nContent-Length: lots\r\n\r\n"),
+        Err(MalformedRequest)
+    );
+}
+
+#[test]
+fn renders_the_page_escaped() {
+    let network = NetworkConfig {
+        ssid: "<script>\"cafe\"".into(),
+        password: "secret-password".into(),
+        name: "a".into(),
+        server: Some([10, 0, 0, 2].into()),
+        key: Some(Key::new([0xab; 32])),
+    };
+    assert_eq!(PAGE_URL, format!("http://{AP_ADDR}/"));
+    let page = render_page(&network, PageStatus::Rejected(ProvisionError::InvalidName));
+    assert!(page.contains("value=\"&lt;script&gt;&quot;cafe&quot;\""));
+    assert!(page.contains("value=\"10.0.0.2\""));
+    assert!(page.contains(ProvisionError::InvalidName.message()));
+    assert!(!page.contains("secret-password"));
+    assert!(!page.contains("abab"));
+
+    let response = response("200 OK", &page);
+    let (head, body) = response.split_once("\r\n\r\n").unwrap();
+    assert!(head.starts_with("HTTP/1.1 200 OK\r\n"));
+    assert!(head.contains(&format!("Content-Length: {}", page.len())));
+    assert_eq!(body, page);
+}
+
+/// A query for `name`, as a resolver would send it.
+fn dns_query(name: &str, kind: u16) -> Vec<u8> {
+    let mut query = vec![0x12, 0x34, 0x01, 0x00, 0, 1, 0, 0, 0, 0, 0, 0];
+    for label in name.split('.') {
+        query.push(label.len() as u8);
+        query.extend(label.as_bytes());
+    }
+    query.push(0);
+    query.extend(kind.to_be_bytes());
+    query.extend(1u16.to_be_bytes());
+    query
+}
+
+#[test]
+fn answers_every_name_with_the_node() {
+    let query = dns_query("captive.apple.com", 1);
+    let mut out = [0u8; 512];
+    let len = dns_reply(&query, AP_ADDR, &mut out).unwrap();
+    let reply = &out[..len];
+
+    assert_eq!(reply[..2], [0x12, 0x34]);
+    // a response, with recursion desired echoed
+    assert_eq!(reply[2] & 0x81, 0x81);
+    assert_eq!(reply[6..8], [0, 1]);
+    assert_eq!(reply[12..query.len()], query[12..]);
+    assert_eq!(reply[len - 4..], AP_ADDR.octets());
+
+    // other record types get no answers
+    let query = dns_query("captive.apple.com", 28);
+    let len = dns_reply(&query, AP_ADDR, &mut out).unwrap();
+    assert_eq!(out[6..8], [0, 0]);
+    assert_eq!(len, query.len());
+
+    // responses and truncated queries are ignored
+    let mut response = dns_query("a.b", 1);
+    response[2] |= 0x80;
+    assert_eq!(dns_reply(&response, AP_ADDR, &mut out), None);
+    let query = dns_query("a.b", 1);
+    assert_eq!(
+        dns_reply(&query[..query.len() - 2], AP_ADDR, &mut out),
+        None
+    );
+    assert_eq!(dns_reply(&query, AP_ADDR, &mut out[..20]), None);
+}
@@ -0,0 +1,150 @@
+use common::{
+    color::{Rgb8, RgbaF32},
+    effect::{
+        StripInfo,
+        param::{ParamError, ParamInfo, ParamKind, ParamValue},
+        registry::{self, EFFECTS},
+        transition::{Transition, TransitionKind},
+    },
+    net::{MAX_FRAME_LEN, NodeError, NodeMessage, ServerMessage, decode_frame, encode_frame},
+};
+
+const LEDS: usize = 16;
+
+/// A value of the right type for a parameter, other than its default.
+fn other_value(info: &ParamInfo) -> ParamValue {
+    match (info.kind, info.default) {
+        (ParamKind::F32 { min, max }, ParamValue::F32(x)) => {
+            ParamValue::F32(if x == max { min } else { max })
+        }
+        (ParamKind::U8 { min, max }, ParamValue::U8(x)) => {
+            ParamValue::U8(if x == max { min } else { max })
+        }
+        (ParamKind::Bool, ParamValue::Bool(x)) => ParamValue::Bool(!x),
+        (ParamKind::Color, ParamValue::Color(c)) => ParamValue::Color(Rgb8::new(!c.r, c.g, c.b)),
+        _ => panic!("{} has a default of the wrong type", info.name),
+    }
+}
+
+#[test]
+fn params_round_trip() {
+    for effect in EFFECTS {
+        for info in effect.params {
+            let mut buf = [0u8; 64];
+            let bytes = postcard::to_slice(info, &mut buf).unwrap();
+            let decoded: ParamInfo = postcard::from_bytes(bytes).unwrap();
+            assert_eq!(&decoded, info, "{}", effect.name);
+
+            for value in [info.default, other_value(info)] {
+                let mut buf = [0u8; 16];
+                let bytes = postcard::to_slice(&value, &mut buf).unwrap();
+                assert_eq!(postcard::from_bytes::<ParamValue>(bytes).unwrap(), value);
+            }
+        }
+    }
+}
+
+#[test]
+fn built_effects_match_the_registry() {
+    for (id, info) in EFFECTS.iter().enumerate() {
+        let effect = registry::build::<LEDS>(id as u8, 1).unwrap();
+        assert_eq!(effect.params(), info.params, "{}", info.name);
+        for (param, param_info) in info.params.iter().enumerate() {
+            param_info.check(param_info.default).unwrap();
+            assert_eq!(
+                effect.param(param as u8),
+                Some(param_info.default),
+                "{}.{}",
+                info.name,
+                param_info.name
+            );
+        }
+        assert_eq!(effect.param(info.params.len() as u8), None);
+    }
+    assert!(registry::build::<LEDS>(registry::COUNT as u8, 1).is_none());
+}
+
+#[test]
+fn params_can_be_set() {
+    for (id, info) in EFFECTS.iter().enumerate() {
+        let mut effect = registry::build::<LEDS>(id as u8, 1).unwrap();
+        for (param, param_info) in info.params.iter().enumerate() {
+            let value = other_value(param_info);
+            effect.set_param(param as u8, value).unwrap();
+            assert_eq!(effect.param(param as u8), Some(value));
+        }
+
+        let unknown = info.params.len() as u8;
+        assert_eq!(
+            effect.set_param(unknown, ParamValue::Bool(true)),
+            Err(ParamError::UnknownParam(unknown))
+        );
+    }
+}
+
+#[test]
+fn every_effect_renders_over_time() {
+    let info = StripInfo {
+        leds: LEDS,
+        rev: false,
+    };
+    for (id, effect) in EFFECTS.iter().enumerate() {
+        let mut fx = registry::build::<LEDS>(id as u8, 1).unwrap();
+        let mut buf = [RgbaF32::zero(); LEDS];
+        for time in (0..10_000).step_by(250) {
+            fx.update(&info, &mut buf, time);
+        }
+        assert!(
+            buf.iter().any(|px| px.a > 0.0),
+            "{} rendered nothing",
+            effect.name
+        );
+    }
+}
+
+#[test]
+fn bad_values_are_rejected() {
+    let mut wheel = registry::build::<LEDS>(registry::find("color_wheel").unwrap(), 1).unwrap();
+    assert_eq!(
+        wheel.set_param(0, ParamValue::F32(2.0)),
+        Err(ParamError::OutOfRange)
+    );
+    assert_eq!(
+        wheel.set_param(0, ParamValue::F32(f32::NAN)),
+        Err(ParamError::OutOfRange)
+    );
+    assert_eq!(
+        wheel.set_param(0, ParamValue::U8(1)),
+        Err(ParamError::WrongType)
+    );
+    assert_eq!(wheel.param(0), Some(ParamValue::F32(1.0)));
+}
+
+#[test]
+fn effects_are_found_by_name() {
+    for (id, info) in EFFECTS.iter().enumerate() {
+        assert_eq!(registry::find(info.name), Some(id as u8));
+    }
+    assert_eq!(registry::find("nope"), None);
+}
+
+#[test]
+fn messages_round_trip() {
+    let mut buf = [0u8; MAX_FRAME_LEN];
+    for msg in [
+        ServerMessage::SelectEffect(4, None),
+        ServerMessage::SelectEffect(
+            1,
+            Some(Transition::new(TransitionKind::FadeThroughBlack, 2000)),
+        ),
+        ServerMessage::SetEffectParam(5, 0, ParamValue::Color(Rgb8::new(1, 2, 3))),
+        ServerMessage::SetEffectParam(0, 3, ParamValue::F32(-90.0)),
+    ] {
+        let frame = encode_frame(&msg, &mut buf).unwrap();
+        assert_eq!(decode_frame::<ServerMessage>(frame).unwrap(), msg);
+    }
+
+    let msg = NodeMessage::Error(NodeError::InvalidParam(2, ParamError::OutOfRange));
+    let frame = encode_frame(&msg, &mut buf).unwrap();
+    assert_eq!(decode_frame::<NodeMessage>(frame).unwrap(), msg);
+}
@@ -0,0 +1,70 @@
+use common::{
+    color::Rgb8,
+    effect::spatial::{NoiseField, PlaneSweep, RadialPulse, SpatialEffect},
+    math::{Vec3, noise3},
+};
+
+const WHITE: Rgb8 = Rgb8::new(255, 255, 255);
+
+fn x(x: f32) -> Vec3 {
+    Vec3::new(x, 0.0, 0.0)
+}
+
+#[test]
+fn sweep_moves_along_its_direction() {
+    let sweep = PlaneSweep {
+        direction: x(2.0),
+        speed: 1.0,
+        ..Default::default()
+    };
+
+    assert_eq!(sweep.sample(x(0.0), 0), WHITE);
+    assert_eq!(sweep.sample(x(1.0), 0), Rgb8::zero());
+    assert_eq!(sweep.sample(x(1.0), 1000), WHITE);
+    // the band repeats every `spacing` units
+    assert_eq!(sweep.sample(x(5.0), 1000), WHITE);
+    // and is the same across the whole plane
+    assert_eq!(sweep.sample(Vec3::new(1.0, 7.0, -3.0), 1000), WHITE);
+}
+
+#[test]
+fn pulse_expands_from_its_center() {
+    let pulse = RadialPulse {
+        center: Vec3::new(1.0, 1.0, 1.0),
+        speed: 2.0,
+        ..Default::default()
+    };
+
+    assert_eq!(pulse.sample(pulse.center, 0), WHITE);
+    assert_eq!(pulse.sample(Vec3::new(1.0, 2.0, 1.0), 0), Rgb8::zero());
+    assert_eq!(pulse.sample(Vec3::new(1.0, 2.0, 1.0), 500), WHITE);
+    assert_eq!(pulse.sample(Vec3::new(0.0, 1.0, 1.0), 500), WHITE);
+}
+
+#[test]
+fn noise_is_smooth_and_bounded() {
+    let at = |i| noise3(Vec3::new(i as f32 * 0.01, 0.3, -0.7));
+    let mut prev = at(0);
+    for i in 1..1000 {
+        let n = at(i);
+        assert!((0.0..=1.0).contains(&n));
+        assert!((n - prev).abs() < 0.05, "noise jumped at {i}");
+        prev = n;
+    }
+}
+
+#[test]
+fn noise_field_is_deterministic() {
+    let field = NoiseField::default();
+    let pos = Vec3::new(0.3, 1.2, 2.5);
+    assert_eq!(field.sample(pos, 1234), field.sample(pos, 1234));
+}
+
+#[test]
+fn render_samples_each_position() {
+    let sweep = PlaneSweep::default();
+    let positions = [x(0.0), x(1.0), x(4.0)];
+    let mut buf = [Rgb8::gray(1); 3];
+    sweep.render(&positions, &mut buf, 0);
+    assert_eq!(buf, [WHITE, Rgb8::zero(), WHITE]);
+}
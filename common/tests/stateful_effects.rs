@@ -0,0 +1,128 @@
+use common::{
+    color::{Rgb8, RgbaF32},
+    effect::{EffectMode, Fire, Meteor, StripInfo, Twinkle, stateful::heat_color},
+    math::Rng,
+};
+
+const LEDS: usize = 60;
+const INFO: StripInfo = StripInfo {
+    leds: LEDS,
+    rev: false,
+};
+
+/// Run an effect at 60 fps up to `until` milliseconds, returning the last frame.
+fn run(effect: &mut dyn EffectMode, until: u64) -> [RgbaF32; LEDS] {
+    let mut buf = [RgbaF32::zero(); LEDS];
+    for time in (0..=until).step_by(16) {
+        effect.update(&INFO, &mut buf, time);
+    }
+    buf
+}
+
+fn lit(buf: &[RgbaF32]) -> usize {
+    buf.iter()
+        .filter(|px| px.r + px.g + px.b > 1.0 / 255.0)
+        .count()
+}
+
+#[test]
+fn rng_is_uniform_enough() {
+    let mut rng = Rng::new(0);
+    let mut buckets = [0; 10];
+    for _ in 0..10_000 {
+        let x = rng.next_f32();
+        assert!((0.0..1.0).contains(&x));
+        buckets[(x * 10.0) as usize] += 1;
+        assert!(rng.below(7) < 7);
+    }
+    assert!(buckets.iter().all(|&n| (800..1200).contains(&n)));
+}
+
+#[test]
+fn twinkles_flash_and_fade() {
+    let mut twinkle = Twinkle::<LEDS>::new(Rgb8::gray(255), 1);
+    twinkle.rate = 2.0;
+    twinkle.fade = 0.5;
+    assert!(lit(&run(&mut twinkle, 1000)) > 0);
+
+    // with no new flashes, everything fades out within `fade` seconds
+    twinkle.rate = 0.0;
+    let mut buf = [RgbaF32::zero(); LEDS];
+    twinkle.update(&INFO, &mut buf, 1600);
+    assert_eq!(lit(&buf), 0);
+}
+
+#[test]
+fn fire_burns_from_the_bottom() {
+    let mut fire = Fire::<LEDS>::new(7);
+    fire.sparking = 255;
+    let buf = run(&mut fire, 5000);
+    assert!(lit(&buf[..10]) > 0);
+    assert!(lit(&buf[..10]) >= lit(&buf[LEDS - 10..]));
+}
+
+#[test]
+fn fire_steps_independently_of_frame_rate() {
+    let mut fast = Fire::<LEDS>::new(3);
+    let mut slow = Fire::<LEDS>::new(3);
+    let (mut a, mut b) = ([RgbaF32::zero(); LEDS], [RgbaF32::zero(); LEDS]);
+    for time in (0..=960).step_by(16) {
+        fast.update(&INFO, &mut a, time);
+        if time % 64 == 0 {
+            slow.update(&INFO, &mut b, time);
+        }
+    }
+    assert_eq!(a, b);
+}
+
+#[test]
+fn fire_keeps_burning_when_time_jumps_back() {
+    let mut fire = Fire::<LEDS>::new(5);
+    fire.sparking = 255;
+    let mut buf = [RgbaF32::zero(); LEDS];
+    for time in (3_600_000..=3_601_000).step_by(16) {
+        fire.update(&INFO, &mut buf, time);
+    }
+
+    // as when a long-running node syncs to a server that just started
+    let mut frames = (1000..=2000).step_by(64).map(|time| {
+        fire.update(&INFO, &mut buf, time);
+        buf
+    });
+    let first = frames.next().unwrap();
+    assert!(frames.any(|frame| frame != first));
+}
+
+#[test]
+fn heat_ramps_from_black_to_white() {
+    assert_eq!(heat_color(0), Rgb8::zero());
+    assert_eq!(heat_color(255), Rgb8::new(255, 255, 252));
+    let reds = (0..=255).map(|h| heat_color(h).r).collect::<Vec<_>>();
+    assert!(reds.is_sorted());
+}
+
+#[test]
+fn meteor_leaves_a_tail() {
+    let mut meteor = Meteor::<LEDS>::new(Rgb8::gray(255), 5);
+    meteor.speed = 30.0;
+    let buf = run(&mut meteor, 1000);
+
+    let head = meteor.head();
+    assert!((28..=30).contains(&head));
+    assert!(
+        buf[head - meteor.size + 1..=head]
+            .iter()
+            .all(|&px| px == RgbaF32::from(Rgb8::gray(255)))
+    );
+    assert_eq!(lit(&buf[head + 1..]), 0);
+    assert!(lit(&buf[..head - meteor.size]) > 0);
+}
+
+#[test]
+fn instances_keep_separate_state() {
+    let mut a = Meteor::<LEDS>::new(Rgb8::gray(255), 5);
+    let b = Meteor::<LEDS>::new(Rgb8::gray(255), 5);
+    run(&mut a, 500);
+    assert!(a.head() > 0);
+    assert_eq!(b.head(), 0);
+}
@@ -0,0 +1,173 @@
+use common::{
+    color::RgbaF32,
+    effect::{
+        EffectMode, StripInfo,
+        fx::Effects,
+        transition::{Transition, TransitionKind},
+    },
+    net::{MAX_FRAME_LEN, ServerMessage, decode_frame, encode_frame},
+};
+
+const RED: RgbaF32 = RgbaF32::new(1.0, 0.0, 0.0, 1.0);
+const BLUE: RgbaF32 = RgbaF32::new(0.0, 0.0, 1.0, 1.0);
+
+fn blend(kind: TransitionKind, progress: f32, rev: bool) -> Vec<RgbaF32> {
+    let from = [RED; 4];
+    let mut to = [BLUE; 4];
+    Transition::new(kind, 1000).blend(&from, &mut to, progress, &StripInfo { leds: 4, rev });
+    to.to_vec()
+}
+
+struct Solid(RgbaF32);
+
+impl EffectMode for Solid {
+    fn update(&mut self, _info: &StripInfo, buf: &mut [RgbaF32], _time: u64) {
+        buf.fill(self.0);
+    }
+}
+
+#[test]
+fn progress_is_clamped() {
+    let t = Transition::new(TransitionKind::Crossfade, 1000);
+    assert_eq!(t.progress(0), 0.0);
+    assert_eq!(t.progress(250), 0.25);
+    assert_eq!(t.progress(5000), 1.0);
+    assert_eq!(Transition::default().progress(0), 1.0);
+}
+
+#[test]
+fn every_kind_starts_old_and_ends_new() {
+    for kind in [
+        TransitionKind::Crossfade,
+        TransitionKind::Wipe,
+        TransitionKind::FadeThroughBlack,
+    ] {
+        for rev in [false, true] {
+            assert_eq!(blend(kind, 0.0, rev), [RED; 4], "{kind:?}");
+            assert_eq!(blend(kind, 1.0, rev), [BLUE; 4], "{kind:?}");
+        }
+    }
+}
+
+#[test]
+fn crossfade_mixes() {
+    assert_eq!(
+        blend(TransitionKind::Crossfade, 0.25, false),
+        [RgbaF32::new(0.75, 0.0, 0.25, 1.0); 4]
+    );
+}
+
+#[test]
+fn wipe_follows_strip_direction() {
+    assert_eq!(
+        blend(TransitionKind::Wipe, 0.5, false),
+        [BLUE, BLUE, RED, RED]
+    );
+    assert_eq!(
+        blend(TransitionKind::Wipe, 0.5, true),
+        [RED, RED, BLUE, BLUE]
+    );
+    // the edge is softened across one LED
+    assert_eq!(
+        blend(TransitionKind::Wipe, 0.375, false)[1],
+        RgbaF32::new(0.5, 0.0, 0.5, 1.0)
+    );
+}
+
+#[test]
+fn wipe_covers_only_the_strip() {
+    // buffers can be longer than the strip they're rendered for
+    let from = [RED; 6];
+    let mut to = [BLUE; 6];
+    let strip = StripInfo { leds: 4, rev: true };
+    Transition::new(TransitionKind::Wipe, 1000).blend(&from, &mut to, 0.5, &strip);
+    assert_eq!(to, [RED, RED, BLUE, BLUE, BLUE, BLUE]);
+}
+
+#[test]
+fn every_strip_renders_the_whole_transition() {
+    let mut effects = Effects::<2>::new(2, |_| [Box::new(Solid(RED)), Box::new(Solid(BLUE))]);
+    effects.set_effect(1, Some(Transition::new(TransitionKind::Crossfade, 1000)));
+    let info = StripInfo {
+        leds: 1,
+        rev: false,
+    };
+    let mut scratch = [RgbaF32::zero()];
+
+    let mut frame = |effects: &mut Effects<2>, now| {
+        [0, 1].map(|strip| {
+            let mut buf = [RgbaF32::zero()];
+            effects.update(strip, &info, &mut buf, &mut scratch, now);
+            buf[0]
+        })
+    };
+    assert_eq!(frame(&mut effects, 0), [RED; 2]);
+    assert_eq!(
+        frame(&mut effects, 500),
+        [RgbaF32::new(0.5, 0.0, 0.5, 1.0); 2]
+    );
+    assert_eq!(frame(&mut effects, 1000), [BLUE; 2]);
+
+    // once every strip has had the last frame, the transition is over and a
+    // new one starts from the current effect
+    effects.set_effect(0, Some(Transition::new(TransitionKind::Crossfade, 1000)));
+    assert_eq!(frame(&mut effects, 2000), [BLUE; 2]);
+    assert_eq!(
+        frame(&mut effects, 2500),
+        [RgbaF32::new(0.5, 0.0, 0.5, 1.0); 2]
+    );
+}
+
+#[test]
+fn transitions_carry_on_when_time_jumps_back() {
+    let mut effects = Effects::<2>::new(1, |_| [Box::new(Solid(RED)), Box::new(Solid(BLUE))]);
+    effects.set_effect(1, Some(Transition::new(TransitionKind::Crossfade, 1000)));
+    let info = StripInfo {
+        leds: 1,
+        rev: false,
+    };
+    let mut scratch = [RgbaF32::zero()];
+    let mut frame = |effects: &mut Effects<2>, now| {
+        let mut buf = [RgbaF32::zero()];
+        effects.update(0, &info, &mut buf, &mut scratch, now);
+        buf[0]
+    };
+
+    let half = RgbaF32::new(0.5, 0.0, 0.5, 1.0);
+    assert_eq!(frame(&mut effects, 3_600_000), RED);
+    assert_eq!(frame(&mut effects, 3_600_500), half);
+    // as when a long-running node syncs to a server that just started
+    assert_eq!(frame(&mut effects, 100), RED);
+    assert_eq!(frame(&mut effects, 600), half);
+    assert_eq!(frame(&mut effects, 1100), BLUE);
+    assert_eq!(frame(&mut effects, 50), BLUE);
+}
+
+#[test]
+fn fade_passes_through_black() {
+    let black = RgbaF32::new(0.0, 0.0, 0.0, 1.0);
+    assert_eq!(
+        blend(TransitionKind::FadeThroughBlack, 0.5, false),
+        [black; 4]
+    );
+    assert_eq!(
+        blend(TransitionKind::FadeThroughBlack, 0.25, false),
+        [RgbaF32::new(0.5, 0.0, 0.0, 1.0); 4]
+    );
+    assert_eq!(
+        blend(TransitionKind::FadeThroughBlack, 0.75, false),
+        [RgbaF32::new(0.0, 0.0, 0.5, 1.0); 4]
+    );
+}
+
+#[test]
+fn shift_messages_round_trip() {
+    for msg in [
+        ServerMessage::ShiftEffectMode(-1, None),
+        ServerMessage::ShiftEffectMode(3, Some(Transition::new(TransitionKind::Wipe, 1500))),
+    ] {
+        let mut buf = [0u8; MAX_FRAME_LEN];
+        let frame = encode_frame(&msg, &mut buf).unwrap();
+        assert_eq!(decode_frame::<ServerMessage>(frame).unwrap(), msg);
+    }
+}
@@ -0,0 +1,94 @@
+use common::{
+    color::{Rgb8, Rgba8, RgbaF32},
+    net::udp::{PacketHeader, UdpDecoder, UdpEncoder},
+};
+
+const BG: RgbaF32 = RgbaF32::new_premultiplied(0.25, 0.25, 0.25, 1.0);
+
+/// Encode a single command, decode it, and apply it to a fresh strip buffer.
+fn apply<F>(leds: usize, f: F) -> Vec<RgbaF32>
+where
+    F: FnOnce(&mut UdpEncoder),
+{
+    let mut packet = [0u8; 1024];
+    let mut enc = UdpEncoder::new(&mut packet, PacketHeader::default());
+    f(&mut enc);
+
+    let mut buf = vec![BG; leds];
+    for cmd in UdpDecoder::new(enc.packet()).unwrap() {
+        cmd.unwrap().apply(&mut buf);
+    }
+    buf
+}
+
+#[test]
+fn single_pixel_touches_only_its_index() {
+    let color = Rgb8::new(255, 0, 0);
+    for index in [0, 1, 150, 299] {
+        let buf = apply(300, |enc| enc.set_single_pixel(0, index, color).unwrap());
+        for (i, px) in buf.iter().enumerate() {
+            if i == index as usize {
+                assert_eq!(*px, color.into());
+            } else {
+                assert_eq!(*px, BG, "pixel {i} changed when setting {index}");
+            }
+        }
+    }
+}
+
+#[test]
+fn single_pixel_out_of_range_is_ignored() {
+    let buf = apply(10, |enc| {
+        enc.set_single_pixel(0, 10, Rgb8::gray(255)).unwrap()
+    });
+    assert!(buf.iter().all(|&px| px == BG));
+}
+
+#[test]
+fn single_alpha_fills_with_premultiplied_color() {
+    let buf = apply(8, |enc| {
+        enc.set_buffer_to_single_alpha(0, Rgba8::new(51, 0, 0, 102))
+            .unwrap()
+    });
+    let expected = RgbaF32::new_premultiplied(0.2, 0.0, 0.0, 0.4);
+    assert!(buf.iter().all(|&px| px == expected));
+}
+
+#[test]
+fn range_touches_only_start_to_end() {
+    let colors = [Rgb8::new(1, 2, 3), Rgb8::new(4, 5, 6), Rgb8::new(7, 8, 9)];
+    let buf = apply(300, |enc| enc.set_pixel_range(0, 100, &colors).unwrap());
+
+    assert!(buf[..100].iter().all(|&px| px == BG));
+    for (px, color) in buf[100..103].iter().zip(colors) {
+        assert_eq!(*px, color.into());
+    }
+    assert!(buf[103..].iter().all(|&px| px == BG));
+}
+
+#[test]
+fn range_alpha_is_clipped_to_the_strip() {
+    let colors = [Rgba8::new(10, 10, 10, 10); 4];
+    let buf = apply(10, |enc| enc.set_pixel_range_alpha(0, 8, &colors).unwrap());
+
+    assert!(buf[..8].iter().all(|&px| px == BG));
+    assert!(buf[8..].iter().all(|&px| px == colors[0].into()));
+}
+
+#[test]
+fn range_past_the_end_is_ignored() {
+    let buf = apply(10, |enc| {
+        enc.set_pixel_range(0, 500, &[Rgb8::gray(255)]).unwrap()
+    });
+    assert!(buf.iter().all(|&px| px == BG));
+}
+
+#[test]
+fn many_writes_from_the_start() {
+    let colors = [Rgb8::gray(10), Rgb8::gray(20)];
+    let buf = apply(4, |enc| enc.set_buffer_to_many(0, &colors).unwrap());
+
+    assert_eq!(buf[0], colors[0].into());
+    assert_eq!(buf[1], colors[1].into());
+    assert_eq!(&buf[2..], [BG, BG]);
+}
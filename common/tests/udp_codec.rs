@@ -0,0 +1,316 @@
+use common::{
+    color::{Rgb8, Rgba8},
+    net::udp::{
+        DecodeError, EncodeError, MAX_PACKET_LEN, PACKET_HEADER_LEN, PacketHeader, STALE_WINDOW,
+        SequenceFilter, UdpCommand, UdpDecoder, UdpEncoder,
+    },
+};
+use proptest::prelude::*;
+
+/// An owned copy of a [`UdpCommand`], so tests can hold on to what they sent.
+#[derive(Debug, Clone, PartialEq)]
+enum Command {
+    Many(u8, Vec<Rgb8>),
+    Single(u8, Rgb8),
+    Pixel(u8, u16, Rgb8),
+    ManyAlpha(u8, Vec<Rgba8>),
+    SingleAlpha(u8, Rgba8),
+    Range(u8, u16, Vec<Rgb8>),
+    RangeAlpha(u8, u16, Vec<Rgba8>),
+}
+
+impl Command {
+    fn encode(&self, enc: &mut UdpEncoder) -> Result<(), EncodeError> {
+        match self {
+            Self::Many(strip, colors) => enc.set_buffer_to_many(*strip, colors),
+            Self::Single(strip, color) => enc.set_buffer_to_single(*strip, *color),
+            Self::Pixel(strip, index, color) => enc.set_single_pixel(*strip, *index, *color),
+            Self::ManyAlpha(strip, colors) => enc.set_buffer_to_many_alpha(*strip, colors),
+            Self::SingleAlpha(strip, color) => enc.set_buffer_to_single_alpha(*strip, *color),
+            Self::Range(strip, start, colors) => enc.set_pixel_range(*strip, *start, colors),
+            Self::RangeAlpha(strip, start, colors) => {
+                enc.set_pixel_range_alpha(*strip, *start, colors)
+            }
+        }
+    }
+}
+
+impl From<UdpCommand<'_>> for Command {
+    fn from(cmd: UdpCommand<'_>) -> Self {
+        match cmd {
+            UdpCommand::SetBufferToMany { strip, colors } => {
+                Self::Many(strip, colors.iter().collect())
+            }
+            UdpCommand::SetBufferToSingle { strip, color } => Self::Single(strip, color),
+            UdpCommand::SetSinglePixel {
+                strip,
+                index,
+                color,
+            } => Self::Pixel(strip, index, color),
+            UdpCommand::SetBufferToManyAlpha { strip, colors } => {
+                Self::ManyAlpha(strip, colors.iter().collect())
+            }
+            UdpCommand::SetBufferToSingleAlpha { strip, color } => Self::SingleAlpha(strip, color),
+            UdpCommand::SetPixelRange {
+                strip,
+                start,
+                colors,
+            } => Self::Range(strip, start, colors.iter().collect()),
+            UdpCommand::SetPixelRangeAlpha {
+                strip,
+                start,
+                colors,
+            } => Self::RangeAlpha(strip, start, colors.iter().collect()),
+        }
+    }
+}
+
+fn rgb8() -> impl Strategy<Value = Rgb8> {
+    any::<[u8; 3]>().prop_map(|[r, g, b]| Rgb8::new(r, g, b))
+}
+
+fn rgba8() -> impl Strategy<Value = Rgba8> {
+    any::<[u8; 4]>().prop_map(|[r, g, b, a]| Rgba8::new(r, g, b, a))
+}
+
+fn command() -> impl Strategy<Value = Command> {
+    prop_oneof![
+        (any::<u8>(), prop::collection::vec(rgb8(), 0..64)).prop_map(|(s, c)| Command::Many(s, c)),
+        (any::<u8>(), rgb8()).prop_map(|(s, c)| Command::Single(s, c)),
+        (any::<u8>(), any::<u16>(), rgb8()).prop_map(|(s, i, c)| Command::Pixel(s, i, c)),
+        (any::<u8>(), prop::collection::vec(rgba8(), 0..64))
+            .prop_map(|(s, c)| Command::ManyAlpha(s, c)),
+        (any::<u8>(), rgba8()).prop_map(|(s, c)| Command::SingleAlpha(s, c)),
+        (
+            any::<u8>(),
+            any::<u16>(),
+            prop::collection::vec(rgb8(), 0..64)
+        )
+            .prop_map(|(s, i, c)| Command::Range(s, i, c)),
+        (
+            any::<u8>(),
+            any::<u16>(),
+            prop::collection::vec(rgba8(), 0..64)
+        )
+            .prop_map(|(s, i, c)| Command::RangeAlpha(s, i, c)),
+    ]
+}
+
+/// Encode commands into a packet, returning the packet and where each command ends.
+fn encode(cmds: &[Command]) -> (Vec<u8>, Vec<usize>) {
+    let mut buf = vec![0u8; 64 * 1024];
+    let mut enc = UdpEncoder::new(&mut buf, PacketHeader::default());
+    let mut ends = Vec::new();
+    for cmd in cmds {
+        cmd.encode(&mut enc).unwrap();
+        ends.push(enc.len());
+    }
+    (enc.packet().to_vec(), ends)
+}
+
+/// Decode a packet's commands, with a bad header as a single error.
+fn decode(packet: &[u8]) -> Vec<Result<Command, DecodeError>> {
+    match UdpDecoder::new(packet) {
+        Ok(decoder) => decoder.map(|cmd| cmd.map(Command::from)).collect(),
+        Err(e) => vec![Err(e)],
+    }
+}
+
+#[test]
+fn wire_layout() {
+    let mut buf = [0u8; 128];
+    let mut enc = UdpEncoder::new(&mut buf, PacketHeader::default());
+    enc.set_buffer_to_many(1, &[Rgb8::new(1, 2, 3), Rgb8::new(4, 5, 6)])
+        .unwrap();
+    enc.set_buffer_to_single(0, Rgb8::new(7, 8, 9)).unwrap();
+    enc.set_single_pixel(1, 0x0102, Rgb8::new(10, 11, 12))
+        .unwrap();
+    enc.set_buffer_to_many_alpha(0, &[Rgba8::new(13, 14, 15, 16)])
+        .unwrap();
+    enc.set_buffer_to_single_alpha(1, Rgba8::new(17, 18, 19, 20))
+        .unwrap();
+    enc.set_pixel_range(0, 0x0304, &[Rgb8::new(21, 22, 23)])
+        .unwrap();
+    enc.set_pixel_range_alpha(1, 5, &[Rgba8::new(24, 25, 26, 27)])
+        .unwrap();
+
+    #[rustfmt::skip]
+    assert_eq!(enc.packet(), [
+        1, 0, 0, 0,
+        0, 1, 2, 0, 1, 2, 3, 4, 5, 6,
+        1, 0, 7, 8, 9,
+        2, 1, 0x02, 0x01, 10, 11, 12,
+        3, 0, 1, 0, 13, 14, 15, 16,
+        4, 1, 17, 18, 19, 20,
+        5, 0, 0x04, 0x03, 1, 0, 21, 22, 23,
+        6, 1, 5, 0, 1, 0, 24, 25, 26, 27,
+    ]);
+}
+
+#[test]
+fn header_layout() {
+    let header = PacketHeader {
+        sequence: 0x0102,
+        timestamp: Some(0x03040506),
+    };
+    let mut buf = [0u8; 16];
+    let mut enc = UdpEncoder::new(&mut buf, header);
+    assert!(enc.is_empty());
+    enc.set_buffer_to_single(0, Rgb8::gray(1)).unwrap();
+    assert_eq!(
+        enc.packet(),
+        [1, 1, 0x02, 0x01, 0x06, 0x05, 0x04, 0x03, 1, 0, 1, 1, 1]
+    );
+
+    let decoder = UdpDecoder::new(enc.packet()).unwrap();
+    assert_eq!(decoder.header(), header);
+    assert_eq!(decoder.count(), 1);
+
+    // the header stays when the commands are cleared
+    enc.clear();
+    assert_eq!(enc.packet(), [1, 1, 0x02, 0x01, 0x06, 0x05, 0x04, 0x03]);
+}
+
+#[test]
+fn rejects_bad_headers() {
+    assert_eq!(
+        decode(&[2, 0, 0, 0]),
+        [Err(DecodeError::UnsupportedVersion(2))]
+    );
+    assert_eq!(decode(&[1, 0, 0]), [Err(DecodeError::Truncated)]);
+    // the timestamp flag without a timestamp
+    assert_eq!(decode(&[1, 1, 0, 0, 0, 0]), [Err(DecodeError::Truncated)]);
+    assert_eq!(decode(&[1, 0, 0, 0]), []);
+}
+
+#[test]
+fn drops_stale_sequence_numbers() {
+    let mut filter = SequenceFilter::new();
+    assert!(filter.accept(10));
+    assert!(filter.accept(10));
+    assert!(filter.accept(12));
+    assert!(!filter.accept(11));
+    assert!(filter.accept(13));
+
+    filter.reset();
+    assert!(filter.accept(5));
+}
+
+#[test]
+fn sequence_numbers_wrap_around() {
+    let mut filter = SequenceFilter::new();
+    assert!(filter.accept(u16::MAX - 1));
+    assert!(filter.accept(u16::MAX));
+    assert!(filter.accept(0));
+    assert!(filter.accept(1));
+    // from before the wrap
+    assert!(!filter.accept(u16::MAX));
+    assert!(!filter.accept(u16::MAX - 100));
+}
+
+#[test]
+fn sequence_numbers_far_behind_mean_a_restart() {
+    let mut filter = SequenceFilter::new();
+    assert!(filter.accept(5000));
+    assert!(!filter.accept(5000 - STALE_WINDOW));
+    assert!(filter.accept(5000 - STALE_WINDOW - 1));
+    assert!(filter.accept(0));
+    assert!(filter.accept(1));
+}
+
+#[test]
+fn rejects_unknown_messages() {
+    assert_eq!(
+        decode(&[1, 0, 0, 0, 7, 0, 1, 2, 3]),
+        [Err(DecodeError::UnknownMessage(7))]
+    );
+}
+
+#[test]
+fn full_buffer_leaves_packet_intact() {
+    let mut buf = [0u8; PACKET_HEADER_LEN + 8];
+    let mut enc = UdpEncoder::new(&mut buf, PacketHeader::default());
+    enc.set_buffer_to_single(0, Rgb8::gray(1)).unwrap();
+    assert_eq!(
+        enc.set_buffer_to_single(0, Rgb8::gray(2)),
+        Err(EncodeError::BufferFull)
+    );
+    assert_eq!(enc.packet(), [1, 0, 0, 0, 1, 0, 1, 1, 1]);
+}
+
+#[test]
+fn too_many_pixels() {
+    let mut buf = vec![0u8; 256 * 1024];
+    let mut enc = UdpEncoder::new(&mut buf, PacketHeader::default());
+    assert_eq!(
+        enc.set_buffer_to_many(0, &vec![Rgb8::zero(); u16::MAX as usize + 1]),
+        Err(EncodeError::TooManyPixels)
+    );
+    assert!(enc.is_empty());
+}
+
+#[test]
+fn full_strip_fits_in_a_packet() {
+    let mut buf = [0u8; MAX_PACKET_LEN];
+    let mut enc = UdpEncoder::new(&mut buf, PacketHeader::default());
+    enc.set_buffer_to_many_alpha(0, &[Rgba8::zero(); 300])
+        .unwrap();
+}
+
+proptest! {
+    #[test]
+    fn round_trips(cmds in prop::collection::vec(command(), 0..16)) {
+        let (packet, _) = encode(&cmds);
+        let decoded = decode(&packet);
+        prop_assert_eq!(decoded, cmds.into_iter().map(Ok).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn reencodes_identically(cmds in prop::collection::vec(command(), 0..16)) {
+        let (packet, _) = encode(&cmds);
+        let mut buf = vec![0u8; packet.len()];
+        let mut enc = UdpEncoder::new(&mut buf, PacketHeader::default());
+        for cmd in UdpDecoder::new(&packet).unwrap() {
+            enc.encode(&cmd.unwrap()).unwrap();
+        }
+        prop_assert_eq!(enc.packet(), &packet[..]);
+    }
+
+    #[test]
+    fn truncated_packets(cmds in prop::collection::vec(command(), 1..8), cut in any::<prop::sample::Index>()) {
+        let (packet, ends) = encode(&cmds);
+        let cut = cut.index(packet.len());
+        let decoded = decode(&packet[..cut]);
+
+        // every command that fit survives, and anything cut short is an error
+        let whole = ends.iter().filter(|&&end| end <= cut).count();
+        let mut expected = cmds[..whole].iter().cloned().map(Ok).collect::<Vec<_>>();
+        if !ends.contains(&cut) && cut != PACKET_HEADER_LEN {
+            expected.push(Err(DecodeError::Truncated));
+        }
+        prop_assert_eq!(decoded, expected);
+    }
+
+    #[test]
+    fn oversized_packets(
+        cmds in prop::collection::vec(command(), 1..8),
+        garbage in prop::collection::vec(any::<u8>(), 1..256),
+    ) {
+        let (mut packet, _) = encode(&cmds);
+        packet.extend_from_slice(&garbage);
+
+        let decoded = decode(&packet);
+        prop_assert!(decoded.len() > cmds.len());
+        prop_assert_eq!(&decoded[..cmds.len()], &cmds.into_iter().map(Ok).collect::<Vec<_>>()[..]);
+    }
+
+    #[test]
+    fn arbitrary_bytes_never_panic(packet in prop::collection::vec(any::<u8>(), 0..2048)) {
+        let decoded = decode(&packet);
+        let errors = decoded.iter().filter(|cmd| cmd.is_err()).count();
+        prop_assert!(errors <= 1);
+        if errors == 1 {
+            prop_assert!(decoded.last().unwrap().is_err());
+        }
+    }
+}
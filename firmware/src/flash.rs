@@ -0,0 +1,102 @@
+//! Keeping the node's [`NodeConfig`] in flash, which it shares with
+//! firmware updates.
+
+use common::config::{ConfigStorage, NodeConfig};
+use embassy_futures::select::{Either, select};
+use embassy_sync::{blocking_mutex::raw::CriticalSectionRawMutex, mutex::Mutex, signal::Signal};
+use embassy_time::Timer;
+use embedded_storage::{ReadStorage, Storage};
+use esp_hal::peripherals::FLASH;
+use esp_println::println;
+use esp_storage::{FlashStorage, FlashStorageError};
+
+use crate::{STATE, uptime_ms};
+
+/// Where the config lives: the `nvs` partition in `partitions.csv`, which
+/// nothing else on the node uses.
+const CONFIG_OFFSET: u32 = 0x9000;
+const CONFIG_LEN: usize = 0x4000;
+
+/// How long the config has to stay unchanged before it's saved, so a burst of
+/// changes, like the server replaying its state, costs one flash write.
+const SAVE_DELAY_MS: u64 = 2000;
+
+/// Signalled after a change that only takes effect on a restart, like a new
+/// network, so the config is saved at once and the node restarts.
+pub static RESTART: Signal<CriticalSectionRawMutex, ()> = Signal::new();
+
+/// The node's flash, locked by whoever is writing it.
+pub type SharedFlash = Mutex<CriticalSectionRawMutex, FlashStorage<'static>>;
+
+pub fn open(flash: FLASH<'static>) -> FlashStorage<'static> {
+    // `data_tx` runs from flash on the second core, which has to be parked
+    // while a sector is rewritten
+    FlashStorage::new(flash).multicore_auto_park()
+}
+
+pub struct ConfigFlash<'a> {
+    flash: &'a mut FlashStorage<'static>,
+}
+
+impl<'a> ConfigFlash<'a> {
+    pub fn new(flash: &'a mut FlashStorage<'static>) -> Self {
+        Self { flash }
+    }
+}
+
+impl ConfigStorage for ConfigFlash<'_> {
+    type Error = FlashStorageError;
+
+    fn capacity(&self) -> usize {
+        CONFIG_LEN
+    }
+
+    fn read(&mut self, buf: &mut [u8]) -> Result<(), FlashStorageError> {
+        ReadStorage::read(self.flash, CONFIG_OFFSET, buf)
+    }
+
+    fn write(&mut self, data: &[u8]) -> Result<(), FlashStorageError> {
+        // erases and rewrites every sector `data` touches
+        Storage::write(self.flash, CONFIG_OFFSET, data)
+    }
+}
+
+/// Save the config once it settles after changing, skipping saves that
+/// wouldn't change what's in flash, or right away before a [`RESTART`].
+#[embassy_executor::task]
+pub async fn persist(flash: &'static SharedFlash, mut saved: NodeConfig) {
+    loop {
+        let restart = match select(Timer::after_millis(500), RESTART.wait()).await {
+            Either::First(()) => false,
+            Either::Second(()) => true,
+        };
+
+        let config = {
+            let mut state = STATE.lock().await;
+            let settled = state
+                .unsaved_since
+                .is_some_and(|t| uptime_ms().saturating_sub(t) >= SAVE_DELAY_MS);
+            if !settled && !restart {
+                continue;
+            }
+            state.unsaved_since = None;
+            state.to_config()
+        };
+
+        if config != saved {
+            let mut flash = flash.lock().await;
+            match config.save(&mut ConfigFlash::new(&mut flash)) {
+                Ok(()) => {
+                    println!("config saved");
+                    saved = config;
+                }
+                Err(e) => println!("warn: failed to save config: {e:?}"),
+            }
+        }
+
+        if restart {
+            println!("restarting");
+            esp_hal::system::software_reset();
+        }
+    }
+}
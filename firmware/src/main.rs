@@ -6,19 +6,30 @@
     holding buffers for the duration of a data transfer."
 )]
 
-mod fx;
+mod flash;
 mod net;
+mod ota;
+mod provision;
 mod rmt_led;
+mod stats;
 mod strip;
 
-use alloc::boxed::Box;
+use alloc::{vec, vec::Vec};
 use common::{
-    color::Rgb8,
-    effect::{ColorPattern, ColorWheel, StripInfo},
-    net::StripMode,
+    color::{BlendMode, RgbaF32},
+    config::{NetworkConfig, NodeConfig, StoredStrip},
+    effect::{
+        StripInfo,
+        fx::Effects,
+        layer::{blend_layer, flatten},
+        registry,
+        transition::{Transition, TransitionKind},
+    },
+    net::{StripConfig, StripMode, auth::Key, failsafe::DEFAULT_TIMEOUT},
+    provision::AP_ADDR,
 };
 use embassy_executor::Spawner;
-use embassy_net::StackResources;
+use embassy_net::{Ipv4Cidr, StackResources, StaticConfigV4};
 use embassy_sync::{blocking_mutex::raw::CriticalSectionRawMutex, mutex::Mutex};
 // use embassy_sync::{blocking_mutex::raw::CriticalSectionRawMutex, mutex::Mutex};
 use embedded_io::Write;
@@ -28,12 +39,13 @@ use esp_hal::time::Rate;
 use esp_hal::timer::timg::TimerGroup;
 use esp_hal::{Blocking, rng::Rng};
 use esp_hal::{clock::CpuClock, delay::Delay};
+use esp_println::println;
 use esp_rtos::embassy::Executor;
 use static_cell::StaticCell;
 
 use crate::{
-    fx::Effects,
-    rmt_led::{RmtBuf, RmtStrip, Ws2812b},
+    flash::{ConfigFlash, SharedFlash},
+    rmt_led::{RmtBuf, RmtStrip},
     strip::{MAX_STRIP_BUF_LEN, MAX_STRIP_LEN, State, StripState},
 };
 
@@ -41,23 +53,56 @@ extern crate alloc;
 
 esp_bootloader_esp_idf::esp_app_desc!();
 
-static STACK_RESOURCES: StaticCell<StackResources<3>> = StaticCell::new();
+static STACK_RESOURCES: StaticCell<StackResources<5>> = StaticCell::new();
+static AP_STACK_RESOURCES: StaticCell<StackResources<4>> = StaticCell::new();
+static FLASH: StaticCell<SharedFlash> = StaticCell::new();
+
+/// How effects switch when the server doesn't ask for a particular transition.
+const DEFAULT_TRANSITION: Transition = Transition::new(TransitionKind::Crossfade, 1000);
 
 #[cfg(feature = "esp32s3")]
 pub const NUM_STRIPS: usize = 2; // TODO: can we shift memory around a bit to hit 4?
 #[cfg(feature = "esp32c6")]
 pub const NUM_STRIPS: usize = 2;
 
-pub static STATE: Mutex<CriticalSectionRawMutex, State<MAX_STRIP_LEN>> = Mutex::new(State::new([
-    StripState::new(StripInfo {
-        leds: 300,
-        rev: true,
-    }),
-    StripState::new(StripInfo {
-        leds: 300,
-        rev: false,
-    }),
-]));
+/// Set up from the config in flash once `main` has loaded it.
+pub static STATE: Mutex<CriticalSectionRawMutex, State<MAX_STRIP_LEN>> =
+    Mutex::new(State::new([StripState::empty(), StripState::empty()]));
+
+/// What a node runs until it's configured: the Wi-Fi credentials baked in at
+/// build time, if any, and two full strips of WS2812Bs. Without credentials,
+/// the node goes straight to its setup network.
+fn default_config() -> NodeConfig {
+    let strip = |rev| StoredStrip {
+        config: StripConfig::new(StripInfo {
+            leds: MAX_STRIP_LEN,
+            rev,
+        }),
+        mode: StripMode::Hybrid,
+        stream_timeout: Some(DEFAULT_TIMEOUT),
+    };
+
+    NodeConfig {
+        network: NetworkConfig {
+            ssid: option_env!("FW_SSID").unwrap_or_default().into(),
+            password: option_env!("FW_PASSWORD").unwrap_or_default().into(),
+            name: "node".into(),
+            server: None,
+            key: option_env!("FW_KEY").and_then(Key::parse),
+        },
+        strips: vec![strip(true), strip(false)],
+        effect: 0,
+        params: Vec::new(),
+        brightness: 0.5,
+    }
+}
+
+/// Milliseconds since boot, the clock synced against the server's.
+pub fn uptime_ms() -> u64 {
+    esp_hal::time::Instant::now()
+        .duration_since_epoch()
+        .as_millis()
+}
 
 #[esp_rtos::main]
 async fn main(spawner: Spawner) -> ! {
@@ -77,11 +122,24 @@ async fn main(spawner: Spawner) -> ! {
         esp_hal::interrupt::software::SoftwareInterruptControl::new(peripherals.SW_INTERRUPT);
     esp_rtos::start(timg0.timer0, sw_interrupt.software_interrupt0);
 
+    let mut storage = flash::open(peripherals.FLASH);
+    ota::check_image(&mut storage);
+    let (node_config, error) =
+        NodeConfig::load_or(&mut ConfigFlash::new(&mut storage), default_config);
+    if let Some(e) = error {
+        println!("warn: using the default config: {e:?}");
+    }
+    STATE.lock().await.load(node_config.clone());
+    let flash = FLASH.init(Mutex::new(storage));
+    spawner.spawn(flash::persist(flash, node_config)).unwrap();
+    spawner.spawn(ota::check_in_deadline()).unwrap();
+
     let (wifi_controller, interfaces) = esp_radio::wifi::new(peripherals.WIFI, Default::default())
         .expect("Failed to initialize Wi-Fi controller");
 
     // init embassy net
     let wifi_interface = interfaces.station;
+    let mac = wifi_interface.mac_address();
     let config = embassy_net::Config::dhcpv4(Default::default());
     let rng = Rng::new();
     let seed = (rng.random() as u64) << 32 | rng.random() as u64;
@@ -89,6 +147,16 @@ async fn main(spawner: Spawner) -> ! {
     let stack_resources = STACK_RESOURCES.init(StackResources::new());
     let (stack, runner) = embassy_net::new(wifi_interface, config, stack_resources, seed);
 
+    // the setup network, idle unless the node can't join one
+    let ap_config = embassy_net::Config::ipv4_static(StaticConfigV4 {
+        address: Ipv4Cidr::new(AP_ADDR, 24),
+        gateway: Some(AP_ADDR),
+        dns_servers: Default::default(),
+    });
+    let ap_stack_resources = AP_STACK_RESOURCES.init(StackResources::new());
+    let (ap_stack, ap_runner) =
+        embassy_net::new(interfaces.access_point, ap_config, ap_stack_resources, seed);
+
     #[cfg(not(feature = "offline"))]
     {
         spawner
@@ -96,30 +164,38 @@ async fn main(spawner: Spawner) -> ! {
             .unwrap();
         spawner.spawn(net::task(runner)).unwrap();
         spawner.spawn(net::udp_socket(stack)).unwrap();
+        spawner.spawn(net::ddp_socket(stack)).unwrap();
+        spawner.spawn(net::tcp_socket(stack, flash)).unwrap();
         spawner.spawn(net::show_ipv4(stack)).unwrap();
+        spawner.spawn(net::beacon(stack, mac)).unwrap();
+        spawner.spawn(net::task(ap_runner)).unwrap();
+        spawner.spawn(provision::dhcp_server(ap_stack)).unwrap();
+        spawner.spawn(provision::dns_server(ap_stack)).unwrap();
+        spawner.spawn(provision::http_server(ap_stack)).unwrap();
     }
 
     // rmt init
     let rmt = Rmt::new(peripherals.RMT, Rate::from_mhz(80)).expect("failed to initialize RMT");
     let strips = [
-        RmtStrip::<_, Ws2812b>::new_on_channel(rmt.channel0, peripherals.GPIO4).unwrap(),
+        RmtStrip::new_on_channel(rmt.channel0, peripherals.GPIO4).unwrap(),
         RmtStrip::new_on_channel(rmt.channel1, peripherals.GPIO5).unwrap(),
     ];
 
-    static RMT_BUFS: StaticCell<[RmtBuf<Ws2812b, MAX_STRIP_BUF_LEN>; NUM_STRIPS]> =
-        StaticCell::new();
-    static EFFECT_BUFS: StaticCell<[[Rgb8; MAX_STRIP_LEN]; NUM_STRIPS]> = StaticCell::new();
+    static RMT_BUFS: StaticCell<[RmtBuf<MAX_STRIP_BUF_LEN>; NUM_STRIPS]> = StaticCell::new();
+    static EFFECT_BUFS: StaticCell<[[RgbaF32; MAX_STRIP_LEN]; NUM_STRIPS]> = StaticCell::new();
+    static SCRATCH_BUF: StaticCell<[RgbaF32; MAX_STRIP_LEN]> = StaticCell::new();
 
     let rmt_bufs = {
         let state = STATE.lock().await;
         RMT_BUFS.init_with(|| {
             core::array::from_fn::<_, NUM_STRIPS, _>(|i| {
-                RmtBuf::<Ws2812b, MAX_STRIP_BUF_LEN>::new(state.strips[i].info.leds)
+                RmtBuf::<MAX_STRIP_BUF_LEN>::new(state.strips[i].config())
             })
         })
     };
 
-    let effect_bufs = EFFECT_BUFS.init_with(|| [[Rgb8::zero(); MAX_STRIP_LEN]; NUM_STRIPS]);
+    let effect_bufs = EFFECT_BUFS.init_with(|| [[RgbaF32::zero(); MAX_STRIP_LEN]; NUM_STRIPS]);
+    let scratch_buf = SCRATCH_BUF.init_with(|| [RgbaF32::zero(); MAX_STRIP_LEN]);
 
     // on the ESP32-S3, we can pin LED data transmission to the second core
     #[cfg(feature = "esp32s3")]
@@ -132,7 +208,7 @@ async fn main(spawner: Spawner) -> ! {
             let executor = EXECUTOR.init(Executor::new());
             executor.run(|spawner| {
                 spawner
-                    .spawn(data_tx(strips, rmt_bufs, effect_bufs))
+                    .spawn(data_tx(strips, rmt_bufs, effect_bufs, scratch_buf))
                     .unwrap()
             });
         },
@@ -140,7 +216,7 @@ async fn main(spawner: Spawner) -> ! {
 
     #[cfg(feature = "esp32c6")]
     spawner
-        .spawn(data_tx(strips, rmt_bufs, effect_bufs))
+        .spawn(data_tx(strips, rmt_bufs, effect_bufs, scratch_buf))
         .unwrap();
 
     core::future::pending::<()>().await;
@@ -149,58 +225,82 @@ async fn main(spawner: Spawner) -> ! {
 
 #[embassy_executor::task]
 async fn data_tx(
-    mut strips: [RmtStrip<'static, Blocking, Ws2812b>; NUM_STRIPS],
-    rmt_bufs: &'static mut [RmtBuf<Ws2812b, MAX_STRIP_BUF_LEN>; NUM_STRIPS],
-    effect_bufs: &'static mut [[Rgb8; MAX_STRIP_LEN]; NUM_STRIPS],
+    mut strips: [RmtStrip<'static, Blocking>; NUM_STRIPS],
+    rmt_bufs: &'static mut [RmtBuf<MAX_STRIP_BUF_LEN>; NUM_STRIPS],
+    effect_bufs: &'static mut [[RgbaF32; MAX_STRIP_LEN]; NUM_STRIPS],
+    scratch_buf: &'static mut [RgbaF32; MAX_STRIP_LEN],
 ) {
     use esp_hal::time::Instant;
 
-    let fx = Effects::new([
-        Box::new(ColorWheel::default()),
-        Box::new(ColorPattern {
-            colors: [Rgb8::new(255, 0, 0), Rgb8::new(0, 255, 0)],
-            speed: 1.0,
-        }),
-    ]);
+    let rng = Rng::new();
+    let mut fx = Effects::from_registry::<MAX_STRIP_LEN, _>(NUM_STRIPS, || rng.random());
+    fx.set_transition(Some(DEFAULT_TRANSITION));
 
     let delay = Delay::new();
     loop {
-        let now = esp_hal::time::Instant::now()
-            .duration_since_epoch()
-            .as_millis();
+        let local = uptime_ms();
 
         let mut state = STATE.lock().await;
+        // effects run on the server's time, so they line up across nodes
+        let now = state.clock.server_time(local);
+        let shift = core::mem::take(&mut state.effect_shift);
+        let transition = state.effect_transition.take();
+        if shift != 0 {
+            fx.shift_effect(shift, transition);
+        }
+        if let Some((id, transition)) = state.effect_select.take() {
+            fx.set_effect(id as usize, transition);
+        }
+        state.config.effect = fx.index() as u8;
+        for (effect, param, value) in state.param_changes.drain(..) {
+            // already checked against the registry when received
+            _ = fx.set_param(effect as usize, param, value);
+        }
+        for (strip, overlays) in state.overlay_changes.drain(..) {
+            fx.clear_overlays(strip);
+            for overlay in overlays {
+                // already checked against the registry when received
+                if let Some(effect) = registry::build::<MAX_STRIP_LEN>(overlay.effect, rng.random())
+                {
+                    fx.add_overlay(strip, effect, overlay.mode, overlay.opacity);
+                }
+            }
+        }
 
-        // blend in rgba from state and add to rmt_bufs
+        // composite effects and streamed colors, then add to rmt_bufs
+        let brightness = state.config.brightness;
         for i in 0..NUM_STRIPS {
             let strip_state = &mut state.strips[i];
+            if core::mem::take(&mut strip_state.reconfigured) {
+                rmt_bufs[i].configure(strip_state.config());
+            }
             if strip_state.is_empty() {
                 continue;
             }
 
-            match strip_state.mode {
-                StripMode::Effects | StripMode::Hybrid => {
-                    fx.update(&strip_state.info, &mut effect_bufs[i], now)
-                }
-                _ => effect_bufs[i].fill(Rgb8::zero()),
+            if let Some(frame) = strip_state.jitter.present(now as u32) {
+                frame.write(&mut strip_state.colors);
             }
 
-            // TODO: effects should probably output to a RgbaF32 buffer, making this less important
-            match strip_state.mode {
-                StripMode::Dynamic | StripMode::Hybrid => {
-                    for (rgba, rgb) in state.strips[i].colors.iter().zip(effect_bufs[i].iter_mut())
-                    {
-                        *rgb = rgba.blend_over((*rgb).into()).into();
-                    }
-                }
-                _ => (),
+            // only the strip's own LEDs, since its `RmtBuf` is sized to them
+            let leds = strip_state.info.leds;
+            let buf = &mut effect_bufs[i][..leds];
+            buf.fill(RgbaF32::zero());
+
+            let (mode, opacity) = strip_state.watchdog.apply(strip_state.mode, local);
+            if mode.runs_effects() {
+                let scratch = &mut scratch_buf[..leds];
+                fx.update(i, &strip_state.info, buf, scratch, now);
+            }
+            if mode.streams() {
+                blend_layer(buf, &strip_state.colors, BlendMode::Over, opacity);
             }
 
             // fill rmt bufs
             let rmt_buf = &mut rmt_bufs[i];
             _ = rmt_buf.flush();
-            for &rgb in &effect_bufs[i] {
-                rmt_buf.write_color(rgb.gamma_correct().brightness(0.5));
+            for rgb in flatten(buf, brightness) {
+                rmt_buf.write_color(rgb);
             }
         }
 
@@ -218,6 +318,10 @@ async fn data_tx(
             }
         }
 
+        if t.is_some() {
+            stats::FRAMES.fetch_add(1, core::sync::atomic::Ordering::Relaxed);
+        }
+
         drop(state);
 
         match t {
@@ -1,44 +1,105 @@
+use core::sync::atomic::{AtomicU32, Ordering};
+
 use common::{
-    color::RgbaF32,
-    net::{StripMode, UdpMessage},
+    effect::{fx::MAX_OVERLAYS, param::check, registry},
+    net::{
+        DDP_PORT, HEARTBEAT_INTERVAL_MS, Heartbeat, MAX_FRAME_LEN, Mcu, NodeError, NodeHello,
+        NodeMessage, SESSION_TIMEOUT_MS, ServerMessage, TCP_PORT, UDP_PORT, Version,
+        auth::{self, AuthError, Opener, PacketOpener, SealedFrame},
+        ddp::DdpPacket,
+        discovery::{BEACON_INTERVAL_MS, Beacon, DISCOVERY_PORT, MAX_BEACON_LEN},
+        encode_frame,
+        udp::UdpDecoder,
+    },
+    ota::UpdateStatus,
 };
+use embassy_futures::select::{Either, select};
 use embassy_net::{
-    Runner, Stack,
-    tcp::TcpSocket,
+    IpAddress, Runner, Stack,
+    tcp::{self, TcpSocket, TcpWriter},
     udp::{PacketMetadata, UdpSocket},
 };
-use embassy_time::{Duration, Timer};
+use embassy_time::{Duration, Instant, Ticker, Timer};
+use esp_hal::rng::Rng;
 use esp_println::println;
 use esp_radio::wifi::{
     ModeConfig, WifiController, WifiDevice, WifiEvent, WifiStationState, scan::ScanConfig,
     sta::StationConfig, station_state,
 };
+use postcard::accumulator::{CobsAccumulator, FeedResult};
+
+use crate::{
+    NUM_STRIPS, STATE,
+    flash::{self, SharedFlash},
+    ota, provision, stats,
+    strip::MAX_STRIP_LEN,
+    uptime_ms,
+};
+
+/// Failed attempts to join the network in a row before starting the setup
+/// network instead.
+const JOIN_ATTEMPTS: u32 = 5;
+
+/// How long a node with a network saved keeps its setup network up before
+/// restarting to try joining again, in case the network was only down.
+const SETUP_WINDOW: Duration = Duration::from_secs(5 * 60);
 
-use crate::{NUM_STRIPS, STATE};
+const VERSION: Version = Version::parse(env!("CARGO_PKG_VERSION"));
 
-const SSID: &'static str = env!("FW_SSID");
-const PASSWORD: &'static str = env!("FW_PASSWORD");
+#[cfg(feature = "esp32s3")]
+const MCU: Mcu = Mcu::Esp32s3;
+#[cfg(feature = "esp32c6")]
+const MCU: Mcu = Mcu::Esp32c6;
+
+/// The IPv4 address of the server holding an authenticated session, or 0 if
+/// there's none. DDP can't be signed, so with a key it's only taken from there.
+static TRUSTED: AtomicU32 = AtomicU32::new(0);
 
 #[embassy_executor::task]
 pub async fn connection(mut controller: WifiController<'static>, _stack: Stack<'static>) {
     println!("device capabilities: {:?}", controller.capabilities());
 
+    // the network only changes through the setup network, which restarts the node
+    let network = STATE.lock().await.config.network.clone();
+    let mut failures = 0;
     loop {
         match station_state() {
             WifiStationState::Connected => {
-                controller
-                    .wait_for_event(WifiEvent::StationDisconnected)
-                    .await;
+                // keep the signal strength fresh until we drop off the network
+                loop {
+                    stats::set_rssi(controller.rssi().ok());
+                    let disconnected = controller.wait_for_event(WifiEvent::StationDisconnected);
+                    let refresh = Timer::after(Duration::from_millis(HEARTBEAT_INTERVAL_MS));
+                    if let Either::First(_) = select(disconnected, refresh).await {
+                        break;
+                    }
+                }
+                stats::set_rssi(None);
                 Timer::after(Duration::from_millis(5000)).await
             }
             _ => (),
         }
 
+        if network.ssid.is_empty() || failures >= JOIN_ATTEMPTS {
+            provision::start_access_point(&mut controller, &network).await;
+            if network.ssid.is_empty() {
+                core::future::pending::<()>().await;
+            }
+            // saving the page restarts the node sooner
+            Timer::after(SETUP_WINDOW).await;
+            println!(
+                "nobody set the node up, restarting to join {}",
+                network.ssid
+            );
+            flash::RESTART.signal(());
+            core::future::pending::<()>().await;
+        }
+
         if !controller.is_started().unwrap_or(false) {
             let sta_config = ModeConfig::Station(
                 StationConfig::default()
-                    .with_ssid(SSID.into())
-                    .with_password(PASSWORD.into()),
+                    .with_ssid(network.ssid.as_str().into())
+                    .with_password(network.password.as_str().into()),
             );
 
             controller.set_config(&sta_config).unwrap();
@@ -63,16 +124,19 @@ pub async fn connection(mut controller: WifiController<'static>, _stack: Stack<'
         match controller.connect_async().await {
             Ok(_) => {
                 println!("wifi connected!");
+                failures = 0;
             }
             Err(e) => {
-                println!("failed to connect to wifi: {e:?}");
+                failures += 1;
+                println!("failed to connect to wifi ({failures}/{JOIN_ATTEMPTS}): {e:?}");
                 Timer::after(Duration::from_millis(5000)).await;
             }
         }
     }
 }
 
-#[embassy_executor::task]
+/// Runs a network stack; one for the station and one for the setup network.
+#[embassy_executor::task(pool_size = 2)]
 pub async fn task(mut runner: Runner<'static, WifiDevice<'static>>) {
     runner.run().await
 }
@@ -88,6 +152,43 @@ pub async fn show_ipv4(stack: Stack<'static>) {
     }
 }
 
+/// Broadcast the node's [`Beacon`] so the server can find it, once the node
+/// has an address.
+#[embassy_executor::task]
+pub async fn beacon(stack: Stack<'static>, id: [u8; 6]) {
+    let mut rx_meta = [PacketMetadata::EMPTY; 1];
+    let mut rx_buf = [0u8; 16];
+    let mut tx_meta = [PacketMetadata::EMPTY; 1];
+    let mut tx_buf = [0u8; MAX_BEACON_LEN];
+    let mut buf = [0u8; MAX_BEACON_LEN];
+
+    let beacon = Beacon {
+        id,
+        name: STATE.lock().await.config.network.name.clone(),
+        version: VERSION,
+        mcu: MCU,
+        num_strips: NUM_STRIPS as u8,
+        tcp_port: TCP_PORT,
+        udp_port: UDP_PORT,
+        ddp_port: DDP_PORT,
+    };
+    // names are limited when they're set, so a beacon always fits
+    let packet = beacon.encode(&mut buf).unwrap();
+
+    stack.wait_config_up().await;
+    let mut socket = UdpSocket::new(stack, &mut rx_meta, &mut rx_buf, &mut tx_meta, &mut tx_buf);
+    socket.bind(0).unwrap();
+
+    let target = (IpAddress::v4(255, 255, 255, 255), DISCOVERY_PORT);
+    let mut ticker = Ticker::every(Duration::from_millis(BEACON_INTERVAL_MS));
+    loop {
+        if let Err(e) = socket.send_to(packet, target).await {
+            println!("warn: failed to send beacon: {e:?}");
+        }
+        ticker.next().await;
+    }
+}
+
 // TODO: unify somewhere else
 // fn post_process(color: Rgb8) -> Rgb8 {
 //     color.gamma_correct().brightness(0.4)
@@ -102,118 +203,473 @@ pub async fn udp_socket(stack: Stack<'static>) {
     let mut buf = [0u8; 8092];
 
     let mut socket = UdpSocket::new(stack, &mut rx_meta, &mut rx_buf, &mut tx_meta, &mut tx_buf);
-    socket.bind(1337).unwrap();
+    socket.bind(UDP_PORT).unwrap();
+    let keyed = STATE.lock().await.config.network.key.is_some();
 
     'recv: loop {
-        let (mut n, _) = socket.recv_from(&mut buf).await.unwrap();
-        let mut cursor = buf.as_slice();
-
-        // TODO: may want to eventually confirm that this packet is from a trusted endpoint
+        let n = match socket.recv_from(&mut buf).await {
+            Ok((n, _)) => n,
+            Err(e) => {
+                println!("warn: failed to receive a UDP packet: {e:?}");
+                continue;
+            }
+        };
 
         let mut state = STATE.lock().await;
-        while n >= 2 {
-            let mut msg_size = 0usize;
-            let (msg_type, target, rest) = (cursor[0], cursor[1], &cursor[2..]);
-            msg_size += 2;
+        // with a key, packets are only taken during a session
+        let checked = match (keyed, state.stream_opener.as_mut()) {
+            (true, None) => Err(AuthError::Unsigned),
+            (_, opener) => auth::check_packet(opener, &buf[..n]),
+        };
+        let Ok(packet) = checked else {
+            stats::reject_packet();
+            continue;
+        };
+        let Ok(decoder) = UdpDecoder::new(packet) else {
+            stats::drop_packet();
+            continue;
+        };
+        let header = decoder.header();
+
+        // timestamps are only meaningful against the server's clock
+        let timestamp = header.timestamp.filter(|_| state.clock.is_synced());
+        let now = uptime_ms();
+        for cmd in decoder {
+            let Ok(cmd) = cmd else {
+                stats::drop_packet();
+                continue 'recv;
+            };
 
-            if !(0..NUM_STRIPS).contains(&(target as usize)) {
+            let Some(strip) = state.strips.get_mut(cmd.strip() as usize) else {
+                stats::drop_packet();
                 continue 'recv;
-            }
+            };
 
-            if !matches!(
-                state.strips[target as usize].mode,
-                StripMode::Dynamic | StripMode::Hybrid
-            ) {
+            if !strip.mode.streams() {
+                stats::drop_packet();
                 continue 'recv;
             }
 
-            let strip = &mut state.strips[target as usize];
-
-            match UdpMessage::try_from(msg_type) {
-                Ok(UdpMessage::SetBufferToMany) => {
-                    let leds = strip.info.leds;
-                    msg_size += leds * 3;
-                    if n < msg_size {
-                        continue 'recv;
-                    }
+            if !strip.sequence.accept(header.sequence) {
+                stats::stale_packet();
+                continue 'recv;
+            }
 
-                    for (src, dst) in rest[0..(leds * 3)].chunks(3).zip(strip.colors.iter_mut()) {
-                        *dst = RgbaF32::new_premultiplied(
-                            src[0] as f32 / 255.0,
-                            src[1] as f32 / 255.0,
-                            src[2] as f32 / 255.0,
-                            1.0,
-                        );
-                    }
+            strip.watchdog.feed(now);
+            let leds = strip.info.leds;
+            let Some(timestamp) = timestamp else {
+                cmd.apply(&mut strip.colors[..leds]);
+                continue;
+            };
+            match strip.jitter.stage(timestamp, &strip.colors) {
+                Some(frame) => cmd.apply(&mut frame[..leds]),
+                None => {
+                    // its time already came and went
+                    stats::stale_packet();
+                    continue 'recv;
                 }
+            }
+        }
+    }
+}
 
-                Ok(UdpMessage::SetBufferToSingle) => {
-                    msg_size += 3;
-                    if n < msg_size {
-                        continue 'recv;
-                    }
-
-                    let (r, g, b) = (cursor[0], cursor[1], cursor[2]);
-                    let src = RgbaF32::new_premultiplied(
-                        r as f32 / 255.0,
-                        g as f32 / 255.0,
-                        b as f32 / 255.0,
-                        1.0,
-                    );
+#[embassy_executor::task]
+pub async fn ddp_socket(stack: Stack<'static>) {
+    let mut rx_meta = [PacketMetadata::EMPTY; 16];
+    let mut rx_buf = [0u8; 8092];
+    let mut tx_meta = [PacketMetadata::EMPTY; 4];
+    let mut tx_buf = [0u8; 512];
+    let mut buf = [0u8; 1500];
 
-                    for dst in strip.colors.iter_mut() {
-                        *dst = src;
-                    }
-                }
+    let mut socket = UdpSocket::new(stack, &mut rx_meta, &mut rx_buf, &mut tx_meta, &mut tx_buf);
+    socket.bind(DDP_PORT).unwrap();
+    let key = STATE.lock().await.config.network.key.clone();
 
-                Ok(UdpMessage::SetBufferToManyAlpha) => {
-                    let leds = strip.info.leds;
-                    msg_size += leds * 4;
-                    if n < msg_size {
-                        continue 'recv;
-                    }
+    loop {
+        let (n, meta) = match socket.recv_from(&mut buf).await {
+            Ok(received) => received,
+            Err(e) => {
+                println!("warn: failed to receive a DDP packet: {e:?}");
+                continue;
+            }
+        };
+        if key.is_some() && !is_trusted(meta.endpoint.addr) {
+            stats::reject_packet();
+            continue;
+        }
+        let Ok(packet) = DdpPacket::parse(&buf[..n]) else {
+            stats::drop_packet();
+            continue;
+        };
 
-                    for (src, dst) in rest[0..(leds * 4)].chunks(4).zip(strip.colors.iter_mut()) {
-                        *dst = RgbaF32::new_premultiplied(
-                            src[0] as f32 / 255.0,
-                            src[1] as f32 / 255.0,
-                            src[2] as f32 / 255.0,
-                            src[3] as f32 / 255.0,
-                        );
-                    }
+        let mut state = STATE.lock().await;
+        packet.write(
+            state
+                .strips
+                .iter_mut()
+                .map(|strip| &mut strip.pending[..strip.info.leds]),
+        );
+
+        // show every strip's pending pixels at once
+        if packet.push {
+            let now = uptime_ms();
+            for strip in state.strips.iter_mut().filter(|s| s.mode.streams()) {
+                strip.watchdog.feed(now);
+                let leds = strip.info.leds;
+                for (dst, &src) in strip.colors[..leds].iter_mut().zip(&strip.pending) {
+                    *dst = src.into();
                 }
-
-                Err(_) => continue 'recv,
-
-                _ => todo!(),
             }
-
-            // shift cursor forward by consumed msg size
-            n -= msg_size;
-            cursor = &cursor[msg_size..];
         }
     }
 }
 
 #[embassy_executor::task]
-pub async fn tcp_socket(stack: Stack<'static>) {
+pub async fn tcp_socket(stack: Stack<'static>, flash: &'static SharedFlash) {
     let mut rx = [0u8; 4096];
     let mut tx = [0u8; 4096];
-    let mut socket = TcpSocket::new(stack, &mut rx, &mut tx);
+    let mut buf = [0u8; 512];
 
-    let /* mut */ _buf = [0u8; 4096];
     loop {
         use embassy_net::tcp::AcceptError;
-        match socket.accept(1338).await {
-            Ok(_) => {
-                // TODO: decode into `ServerMessage`
-            }
+
+        let mut socket = TcpSocket::new(stack, &mut rx, &mut tx);
+        socket.set_timeout(Some(Duration::from_millis(SESSION_TIMEOUT_MS)));
+
+        match socket.accept(TCP_PORT).await {
+            Ok(_) => println!("server connected from {:?}", socket.remote_endpoint()),
             Err(AcceptError::ConnectionReset) => {
                 println!("warn: reset on TCP connection");
                 Timer::after_secs(5).await;
                 continue;
             }
-            Err(_) => panic!("error: TCP connection fail"),
+            Err(e) => {
+                println!("warn: failed to accept a TCP connection: {e:?}");
+                Timer::after_secs(5).await;
+                continue;
+            }
+        }
+
+        let (server, key) = {
+            let state = STATE.lock().await;
+            let network = &state.config.network;
+            (network.server, network.key.clone())
+        };
+        let remote = socket.remote_endpoint().map(|endpoint| endpoint.addr);
+        if let (Some(server), Some(remote)) = (server, remote)
+            && remote != IpAddress::Ipv4(server)
+        {
+            println!("warn: refusing a session from {remote}, only {server} is allowed");
+            socket.abort();
+            _ = socket.flush().await;
+            continue;
+        }
+
+        // the server may have restarted, along with its clock, the
+        // timestamps queued frames were stamped with and its packet numbering
+        {
+            let mut state = STATE.lock().await;
+            state.clock.reset();
+            for strip in &mut state.strips {
+                strip.jitter.reset();
+                strip.sequence.reset();
+            }
+        }
+
+        // with a key, the rest of the session is sealed with a fresh nonce
+        let nonce = key.is_some().then(|| {
+            let rng = Rng::new();
+            (rng.random() as u64) << 32 | rng.random() as u64
+        });
+        // a new opener per session, so packets from the last one can't be replayed
+        STATE.lock().await.stream_opener = key
+            .as_ref()
+            .zip(nonce)
+            .map(|(key, nonce)| PacketOpener::new(key.for_session(nonce)));
+        let mut opener = key.zip(nonce).map(|(key, nonce)| Opener::new(key, nonce));
+
+        let (mut reader, mut writer) = socket.split();
+        if let Err(e) = handshake(&mut writer, nonce).await {
+            println!("warn: TCP handshake failed: {e:?}");
+        }
+
+        let mut frames = CobsAccumulator::<MAX_FRAME_LEN>::new();
+        // updates don't survive a dropped session, the server starts over
+        let mut update = None;
+        let mut checked_in = false;
+        let mut heartbeat = Ticker::every(Duration::from_millis(HEARTBEAT_INTERVAL_MS));
+        let mut last_heartbeat = (Instant::now(), stats::FRAMES.load(Ordering::Relaxed));
+        'session: loop {
+            let n = match select(reader.read(&mut buf), heartbeat.next()).await {
+                Either::First(Ok(0)) => break,
+                Either::First(Ok(n)) => n,
+                Either::First(Err(e)) => {
+                    println!("warn: TCP read failed: {e:?}");
+                    break;
+                }
+                Either::Second(()) => {
+                    let heartbeat = NodeMessage::Heartbeat(next_heartbeat(&mut last_heartbeat));
+                    let time = NodeMessage::TimeRequest(uptime_ms());
+                    if let Err(e) = send(&mut writer, &heartbeat).await {
+                        println!("warn: TCP write failed: {e:?}");
+                        break;
+                    }
+                    if let Err(e) = send(&mut writer, &time).await {
+                        println!("warn: TCP write failed: {e:?}");
+                        break;
+                    }
+                    continue;
+                }
+            };
+
+            let mut window = &buf[..n];
+            while !window.is_empty() {
+                let Some((msg, rest)) = next_message(&mut frames, opener.as_mut(), window) else {
+                    break;
+                };
+                let reply = match msg {
+                    Err(error) => Err(error),
+                    Ok(data) => {
+                        if opener.is_some()
+                            && let Some(IpAddress::Ipv4(remote)) = remote
+                        {
+                            TRUSTED.store(remote.to_bits(), Ordering::Relaxed);
+                        }
+
+                        // hearing back from the server is what a new image
+                        // has to do to be kept
+                        if !checked_in {
+                            checked_in = true;
+                            if ota::confirm(flash).await {
+                                let confirmed = NodeMessage::Update(UpdateStatus::Confirmed);
+                                _ = send(&mut writer, &confirmed).await;
+                            }
+                        }
+
+                        match data {
+                            ServerMessage::BeginUpdate(header) => {
+                                let status = ota::begin(flash, &mut update, header).await;
+                                Ok(Some(NodeMessage::Update(status)))
+                            }
+                            ServerMessage::UpdateChunk(chunk) => {
+                                Ok(ota::apply(flash, &mut update, &chunk)
+                                    .await
+                                    .map(NodeMessage::Update))
+                            }
+                            msg => handle_server_message(msg).await,
+                        }
+                    }
+                };
+
+                match reply {
+                    Ok(Some(reply)) => {
+                        _ = send(&mut writer, &reply).await;
+                        if reply == NodeMessage::Update(UpdateStatus::Verified) {
+                            _ = writer.flush().await;
+                            flash::RESTART.signal(());
+                        }
+                    }
+                    Ok(None) => (),
+                    Err(error @ NodeError::Unauthenticated(_)) => {
+                        println!("warn: dropping the session: {error:?}");
+                        stats::reject_packet();
+                        _ = send(&mut writer, &NodeMessage::Error(error)).await;
+                        break 'session;
+                    }
+                    Err(error) => {
+                        println!("warn: {error:?}");
+                        _ = send(&mut writer, &NodeMessage::Error(error)).await;
+                    }
+                }
+                window = rest;
+            }
+        }
+
+        TRUSTED.store(0, Ordering::Relaxed);
+        STATE.lock().await.stream_opener = None;
+        println!("server disconnected");
+        socket.abort();
+        _ = socket.flush().await;
+    }
+}
+
+/// Whether `addr` is the server holding an authenticated session.
+fn is_trusted(addr: IpAddress) -> bool {
+    let trusted = TRUSTED.load(Ordering::Relaxed);
+    matches!(addr, IpAddress::Ipv4(addr) if trusted != 0 && addr.to_bits() == trusted)
+}
+
+/// Take the next message out of what's been read from the server, opening it
+/// if the session is sealed. Returns `None` once `window` is used up.
+fn next_message<'a>(
+    frames: &mut CobsAccumulator<MAX_FRAME_LEN>,
+    opener: Option<&mut Opener>,
+    window: &'a [u8],
+) -> Option<(Result<ServerMessage, NodeError>, &'a [u8])> {
+    let Some(opener) = opener else {
+        return match frames.feed::<ServerMessage>(window) {
+            FeedResult::Consumed => None,
+            FeedResult::OverFull(rest) | FeedResult::DeserError(rest) => {
+                Some((Err(NodeError::MalformedFrame), rest))
+            }
+            FeedResult::Success { data, remaining } => Some((Ok(data), remaining)),
+        };
+    };
+
+    match frames.feed_ref::<SealedFrame>(window) {
+        FeedResult::Consumed => None,
+        FeedResult::OverFull(rest) | FeedResult::DeserError(rest) => {
+            Some((Err(NodeError::Unauthenticated(AuthError::Malformed)), rest))
+        }
+        FeedResult::Success { data, remaining } => {
+            let msg = opener.open(&data).map_err(|e| match e {
+                // it checked out, the server just sent something odd
+                AuthError::Malformed => NodeError::MalformedFrame,
+                e => NodeError::Unauthenticated(e),
+            });
+            Some((msg, remaining))
+        }
+    }
+}
+
+/// Introduce this node to the server, with the nonce to seal the session with
+/// if there's a key.
+async fn handshake(writer: &mut TcpWriter<'_>, nonce: Option<u64>) -> Result<(), tcp::Error> {
+    let hello = NodeHello {
+        version: VERSION,
+        mcu: MCU,
+        num_strips: NUM_STRIPS as u8,
+    };
+    send(writer, &NodeMessage::Hello(hello)).await?;
+    if let Some(nonce) = nonce {
+        send(writer, &NodeMessage::Challenge(nonce)).await?;
+    }
+
+    let infos = {
+        let state = STATE.lock().await;
+        core::array::from_fn::<_, NUM_STRIPS, _>(|i| state.strips[i].info)
+    };
+    for (i, info) in infos.into_iter().enumerate() {
+        send(writer, &NodeMessage::Strip(i as u8, info)).await?;
+    }
+
+    // sync clocks now, rather than a heartbeat from now
+    send(writer, &NodeMessage::TimeRequest(uptime_ms())).await?;
+
+    Ok(())
+}
+
+/// Gather telemetry since the last heartbeat.
+fn next_heartbeat(last: &mut (Instant, u32)) -> Heartbeat {
+    let now = Instant::now();
+    let frames = stats::FRAMES.load(Ordering::Relaxed);
+    let elapsed = (now - last.0).as_millis().max(1);
+    let frame_rate = frames.wrapping_sub(last.1) as f32 * 1000.0 / elapsed as f32;
+    *last = (now, frames);
+
+    Heartbeat {
+        uptime_ms: now.as_millis(),
+        free_heap: esp_alloc::HEAP.free() as u32,
+        frame_rate,
+        dropped_packets: stats::DROPPED_PACKETS.load(Ordering::Relaxed),
+        stale_packets: stats::STALE_PACKETS.load(Ordering::Relaxed),
+        rejected_packets: stats::REJECTED_PACKETS.load(Ordering::Relaxed),
+        rssi: stats::rssi(),
+    }
+}
+
+/// Write a single framed [`NodeMessage`] to the server.
+async fn send(writer: &mut TcpWriter<'_>, msg: &NodeMessage) -> Result<(), tcp::Error> {
+    let mut buf = [0u8; MAX_FRAME_LEN];
+    let Ok(mut frame) = encode_frame(msg, &mut buf).map(|frame| &*frame) else {
+        return Ok(());
+    };
+
+    while !frame.is_empty() {
+        let n = writer.write(frame).await?;
+        frame = &frame[n..];
+    }
+    Ok(())
+}
+
+/// Apply a decoded [`ServerMessage`] to the shared state, returning the
+/// reply to send back, if any.
+async fn handle_server_message(msg: ServerMessage) -> Result<Option<NodeMessage>, NodeError> {
+    let mut state = STATE.lock().await;
+    match msg {
+        ServerMessage::SetStripMode(strip, mode) => {
+            match state.strips.get_mut(strip as usize) {
+                Some(s) => s.mode = mode,
+                None => return Err(NodeError::UnknownStrip(strip)),
+            }
+            state.mark_unsaved(uptime_ms());
+        }
+        ServerMessage::ShiftEffectMode(delta, transition) => {
+            state.effect_shift = state.effect_shift.saturating_add(delta);
+            state.effect_transition = transition;
+            state.mark_unsaved(uptime_ms());
+        }
+        ServerMessage::SelectEffect(id, transition) => {
+            if id as usize >= registry::COUNT {
+                return Err(NodeError::UnknownEffect(id));
+            }
+            state.effect_select = Some((id, transition));
+            state.mark_unsaved(uptime_ms());
+        }
+        ServerMessage::SetEffectParam(effect, param, value) => {
+            let Some(info) = registry::EFFECTS.get(effect as usize) else {
+                return Err(NodeError::UnknownEffect(effect));
+            };
+            if let Err(e) = check(info.params, param, value) {
+                return Err(NodeError::InvalidParam(effect, e));
+            }
+            state.param_changes.push((effect, param, value));
+            state.config.set_param(effect, param, value);
+            state.mark_unsaved(uptime_ms());
+        }
+        ServerMessage::KeepAlive => (),
+        ServerMessage::SetStreamTimeout(strip, timeout) => {
+            match state.strips.get_mut(strip as usize) {
+                Some(s) => s.watchdog.set_timeout(timeout),
+                None => return Err(NodeError::UnknownStrip(strip)),
+            }
+            state.mark_unsaved(uptime_ms());
+        }
+        ServerMessage::TimeResponse(sent, server) => {
+            state.clock.add_sample(sent, server, uptime_ms())
+        }
+        // handled in `tcp_socket`, which holds the update
+        ServerMessage::BeginUpdate(_) | ServerMessage::UpdateChunk(_) => (),
+        ServerMessage::SetOverlays(strip, overlays) => {
+            if strip as usize >= NUM_STRIPS {
+                return Err(NodeError::UnknownStrip(strip));
+            }
+            if let Some(overlay) = overlays
+                .iter()
+                .find(|overlay| overlay.effect as usize >= registry::COUNT)
+            {
+                return Err(NodeError::UnknownEffect(overlay.effect));
+            }
+            if overlays.len() > MAX_OVERLAYS
+                || overlays
+                    .iter()
+                    .any(|overlay| !(0.0..=1.0).contains(&overlay.opacity))
+            {
+                return Err(NodeError::InvalidOverlay(strip));
+            }
+            state.overlay_changes.push((strip as usize, overlays));
+        }
+        ServerMessage::ConfigureStrip(strip, config) => {
+            let Some(s) = state.strips.get_mut(strip as usize) else {
+                return Err(NodeError::UnknownStrip(strip));
+            };
+            if config.leds > MAX_STRIP_LEN {
+                return Err(NodeError::StripTooLong(strip));
+            }
+            s.configure(config);
+            state.mark_unsaved(uptime_ms());
+            return Ok(Some(NodeMessage::StripConfigured(strip, config)));
         }
     }
+    Ok(None)
 }
@@ -0,0 +1,222 @@
+//! Writing firmware updates from the server to the inactive OTA slot, and
+//! rolling back an update that never checks in (see [`common::ota`]).
+
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use common::ota::{
+    CHECK_IN_TIMEOUT_MS, UpdateChunk, UpdateError, UpdateFlash, UpdateHeader, UpdateStatus,
+    UpdateWriter,
+};
+use embassy_time::Timer;
+use embedded_storage::{ReadStorage, Storage};
+use esp_bootloader_esp_idf::{
+    ota::OtaImageState,
+    ota_updater::OtaUpdater,
+    partitions::{PARTITION_TABLE_MAX_LEN, PartitionType, read_partition_table},
+};
+use esp_println::println;
+use esp_storage::{FlashStorage, FlashStorageError};
+
+use crate::flash::SharedFlash;
+
+/// Set at boot while a freshly updated image has yet to check in.
+static PENDING: AtomicBool = AtomicBool::new(false);
+
+/// The OTA slot an update is written to, in flash.
+#[derive(Debug, Clone, Copy)]
+struct Slot {
+    offset: u32,
+    len: u32,
+}
+
+/// A [`Slot`] addressed from its start.
+struct SlotFlash<'a> {
+    flash: &'a mut FlashStorage<'static>,
+    slot: Slot,
+}
+
+impl SlotFlash<'_> {
+    fn range(&self, offset: u32, len: usize) -> Result<u32, FlashStorageError> {
+        match offset.checked_add(len as u32) {
+            Some(end) if end <= self.slot.len => Ok(self.slot.offset + offset),
+            _ => Err(FlashStorageError::OutOfBounds),
+        }
+    }
+}
+
+impl UpdateFlash for SlotFlash<'_> {
+    type Error = FlashStorageError;
+
+    fn capacity(&self) -> u32 {
+        self.slot.len
+    }
+
+    fn read(&mut self, offset: u32, buf: &mut [u8]) -> Result<(), FlashStorageError> {
+        let offset = self.range(offset, buf.len())?;
+        ReadStorage::read(self.flash, offset, buf)
+    }
+
+    fn write(&mut self, offset: u32, data: &[u8]) -> Result<(), FlashStorageError> {
+        let offset = self.range(offset, data.len())?;
+        Storage::write(self.flash, offset, data)
+    }
+}
+
+/// An update in progress.
+pub struct Update {
+    writer: UpdateWriter,
+    slot: Slot,
+}
+
+/// Open an update into the slot the node isn't running from, replacing any
+/// update already open.
+pub async fn begin(
+    flash: &SharedFlash,
+    update: &mut Option<Update>,
+    header: UpdateHeader,
+) -> UpdateStatus {
+    *update = None;
+    let mut flash = flash.lock().await;
+    let slot = match next_slot(&mut flash) {
+        Ok(slot) => slot,
+        Err(e) => return UpdateStatus::Failed(e),
+    };
+
+    let slot_flash = SlotFlash {
+        flash: &mut flash,
+        slot,
+    };
+    match UpdateWriter::begin(&slot_flash, header) {
+        Ok(writer) => {
+            println!(
+                "update: writing {} bytes at {:#x}",
+                header.size, slot.offset
+            );
+            *update = Some(Update { writer, slot });
+            UpdateStatus::Ready
+        }
+        Err(e) => UpdateStatus::Failed(e),
+    }
+}
+
+/// Write the next chunk of the open update. Once the image is in and
+/// verified, the node boots from it on its next restart.
+pub async fn apply(
+    flash: &SharedFlash,
+    update: &mut Option<Update>,
+    chunk: &UpdateChunk,
+) -> Option<UpdateStatus> {
+    let Some(Update { writer, slot }) = update else {
+        return Some(UpdateStatus::Failed(UpdateError::NotStarted));
+    };
+
+    let mut flash = flash.lock().await;
+    let mut slot_flash = SlotFlash {
+        flash: &mut flash,
+        slot: *slot,
+    };
+    let mut status = writer.apply(&mut slot_flash, chunk);
+    if status == Some(UpdateStatus::Verified)
+        && let Err(e) = activate(&mut flash)
+    {
+        status = Some(UpdateStatus::Failed(e));
+    }
+
+    if !matches!(status, None | Some(UpdateStatus::Written(_))) {
+        println!("update: {status:?}");
+        *update = None;
+    }
+    status
+}
+
+/// Find the OTA slot the node isn't running from.
+fn next_slot(flash: &mut FlashStorage<'static>) -> Result<Slot, UpdateError> {
+    let mut buf = [0u8; PARTITION_TABLE_MAX_LEN];
+    let mut ota = OtaUpdater::new(flash, &mut buf).map_err(|_| UpdateError::Unavailable)?;
+    let (_, next) = ota.next_partition().map_err(|_| UpdateError::Unavailable)?;
+
+    let mut buf = [0u8; PARTITION_TABLE_MAX_LEN];
+    let entry = read_partition_table(flash, &mut buf)
+        .ok()
+        .and_then(|table| table.find_partition(PartitionType::App(next)).ok())
+        .flatten()
+        .ok_or(UpdateError::Unavailable)?;
+    Ok(Slot {
+        offset: entry.offset(),
+        len: entry.len(),
+    })
+}
+
+/// Boot from the slot an update was just written to, on probation until it
+/// checks in.
+fn activate(flash: &mut FlashStorage<'static>) -> Result<(), UpdateError> {
+    let mut buf = [0u8; PARTITION_TABLE_MAX_LEN];
+    let mut ota = OtaUpdater::new(flash, &mut buf).map_err(|_| UpdateError::Unavailable)?;
+    ota.activate_next_partition()
+        .map_err(|_| UpdateError::Flash)?;
+    ota.set_current_ota_state(OtaImageState::New)
+        .map_err(|_| UpdateError::Flash)
+}
+
+/// Check the image the node booted, before anything else runs.
+///
+/// A new image is put on probation until it checks in with [`confirm`]. One
+/// still on probation from the last boot never did, so the node marks it bad
+/// and restarts into the image it was updated from.
+pub fn check_image(flash: &mut FlashStorage<'static>) {
+    let mut buf = [0u8; PARTITION_TABLE_MAX_LEN];
+    // without OTA slots there's nothing to check
+    let Ok(mut ota) = OtaUpdater::new(flash, &mut buf) else {
+        return;
+    };
+
+    match ota.current_ota_state() {
+        Ok(OtaImageState::New) => {
+            println!("update: running a new image, waiting to check in");
+            if ota
+                .set_current_ota_state(OtaImageState::PendingVerify)
+                .is_ok()
+            {
+                PENDING.store(true, Ordering::Relaxed);
+            }
+        }
+        Ok(OtaImageState::PendingVerify) => {
+            println!("warn: update never checked in, rolling back");
+            _ = ota.set_current_ota_state(OtaImageState::Invalid);
+            if ota.activate_next_partition().is_ok() {
+                esp_hal::system::software_reset();
+            }
+        }
+        _ => (),
+    }
+}
+
+/// Keep a new image now that it's reached the server, returning whether it
+/// was on probation.
+pub async fn confirm(flash: &SharedFlash) -> bool {
+    if !PENDING.load(Ordering::Relaxed) {
+        return false;
+    }
+
+    let mut flash = flash.lock().await;
+    let mut buf = [0u8; PARTITION_TABLE_MAX_LEN];
+    let confirmed = OtaUpdater::new(&mut *flash, &mut buf)
+        .and_then(|mut ota| ota.set_current_ota_state(OtaImageState::Valid))
+        .is_ok();
+    if confirmed {
+        println!("update: checked in, keeping the new image");
+        PENDING.store(false, Ordering::Relaxed);
+    }
+    confirmed
+}
+
+/// Restart a new image that hasn't checked in within [`CHECK_IN_TIMEOUT_MS`],
+/// so [`check_image`] rolls it back.
+#[embassy_executor::task]
+pub async fn check_in_deadline() {
+    Timer::after_millis(CHECK_IN_TIMEOUT_MS).await;
+    if PENDING.load(Ordering::Relaxed) {
+        println!("warn: update never checked in, restarting");
+        esp_hal::system::software_reset();
+    }
+}
@@ -0,0 +1,188 @@
+//! The setup network a node starts when it can't join one, serving the
+//! config page from [`common::provision`].
+
+use core::net::{Ipv4Addr, SocketAddr, SocketAddrV4};
+
+use common::{
+    config::NetworkConfig,
+    provision::{
+        AP_ADDR, DNS_PORT, HTTP_PORT, PAGE_URL, PageStatus, Request, ap_password, ap_ssid,
+        dns_reply, parse_form, render_page, response,
+    },
+};
+use edge_dhcp::{
+    io::{self, DEFAULT_SERVER_PORT},
+    server::{Server, ServerOptions},
+};
+use edge_nal::UdpBind;
+use edge_nal_embassy::{Udp, UdpBuffers};
+use embassy_net::{
+    Stack,
+    tcp::{self, TcpSocket},
+    udp::{PacketMetadata, UdpSocket},
+};
+use embassy_time::{Duration, Timer};
+use embedded_io_async::Write;
+use esp_println::println;
+use esp_radio::wifi::{AuthMethod, ModeConfig, WifiController, ap::AccessPointConfig};
+
+use crate::{STATE, flash};
+
+/// Switch the radio over to the setup network and leave it there, open
+/// unless the node holds a key. The node restarts once it's been set up.
+pub async fn start_access_point(controller: &mut WifiController<'static>, network: &NetworkConfig) {
+    let ssid = ap_ssid(network);
+    let mut ap_config = AccessPointConfig::default().with_ssid(ssid.as_str().into());
+    if let Some(key) = &network.key {
+        ap_config = ap_config
+            .with_auth_method(AuthMethod::Wpa2Personal)
+            .with_password(ap_password(key).as_str().into());
+    }
+    let ap_config = ModeConfig::AccessPoint(ap_config);
+
+    if controller.is_started().unwrap_or(false) {
+        _ = controller.stop_async().await;
+    }
+    controller.set_config(&ap_config).unwrap();
+    controller.start_async().await.unwrap();
+    println!("started setup network {ssid}, configure at {PAGE_URL}");
+}
+
+/// Hand out addresses on the setup network, pointing DNS at the node.
+#[embassy_executor::task]
+pub async fn dhcp_server(stack: Stack<'static>) {
+    let mut buf = [0u8; 1500];
+    let mut gateways = [Ipv4Addr::UNSPECIFIED];
+    let dns = [AP_ADDR];
+
+    let buffers = UdpBuffers::<1, 1024, 1024, 4>::new();
+    let mut socket = Udp::new(stack, &buffers)
+        .bind(SocketAddr::V4(SocketAddrV4::new(
+            Ipv4Addr::UNSPECIFIED,
+            DEFAULT_SERVER_PORT,
+        )))
+        .await
+        .unwrap();
+
+    loop {
+        let mut options = ServerOptions::new(AP_ADDR, Some(&mut gateways));
+        options.dns = &dns;
+        options.captive_url = Some(PAGE_URL);
+        if let Err(e) = io::server::run(
+            &mut Server::<_, 16>::new_with_et(AP_ADDR),
+            &options,
+            &mut socket,
+            &mut buf,
+        )
+        .await
+        {
+            println!("warn: DHCP server failed: {e:?}");
+        }
+        Timer::after_millis(500).await;
+    }
+}
+
+/// Answer every name with the node, so whatever a device opens to check for
+/// a captive portal lands on the setup page.
+#[embassy_executor::task]
+pub async fn dns_server(stack: Stack<'static>) {
+    let mut rx_meta = [PacketMetadata::EMPTY; 4];
+    let mut rx_buf = [0u8; 1024];
+    let mut tx_meta = [PacketMetadata::EMPTY; 4];
+    let mut tx_buf = [0u8; 1024];
+    let mut buf = [0u8; 512];
+    let mut reply = [0u8; 512];
+
+    let mut socket = UdpSocket::new(stack, &mut rx_meta, &mut rx_buf, &mut tx_meta, &mut tx_buf);
+    socket.bind(DNS_PORT).unwrap();
+
+    loop {
+        let Ok((n, meta)) = socket.recv_from(&mut buf).await else {
+            continue;
+        };
+        if let Some(len) = dns_reply(&buf[..n], AP_ADDR, &mut reply) {
+            _ = socket.send_to(&reply[..len], meta).await;
+        }
+    }
+}
+
+/// Serve the setup page, saving what's submitted and restarting to join the
+/// network.
+#[embassy_executor::task]
+pub async fn http_server(stack: Stack<'static>) {
+    let mut rx = [0u8; 1024];
+    let mut tx = [0u8; 4096];
+    let mut buf = [0u8; 1024];
+
+    loop {
+        let mut socket = TcpSocket::new(stack, &mut rx, &mut tx);
+        socket.set_timeout(Some(Duration::from_secs(10)));
+        if let Err(e) = socket.accept(HTTP_PORT).await {
+            println!("warn: setup page accept failed: {e:?}");
+            Timer::after_secs(1).await;
+            continue;
+        }
+
+        let saved = match serve(&mut socket, &mut buf).await {
+            Ok(saved) => saved,
+            Err(e) => {
+                println!("warn: setup page failed: {e:?}");
+                false
+            }
+        };
+        socket.close();
+        _ = socket.flush().await;
+
+        if saved {
+            flash::RESTART.signal(());
+        }
+    }
+}
+
+/// Answer one request, returning whether it set the network up.
+async fn serve(socket: &mut TcpSocket<'_>, buf: &mut [u8]) -> Result<bool, tcp::Error> {
+    let mut len = 0;
+    let request = loop {
+        if len == buf.len() {
+            let reply = response("413 Content Too Large", "");
+            socket.write_all(reply.as_bytes()).await?;
+            return Ok(false);
+        }
+        let n = socket.read(&mut buf[len..]).await?;
+        if n == 0 {
+            return Ok(false);
+        }
+        len += n;
+
+        match Request::parse(&buf[..len]) {
+            Ok(Some(request)) => break request,
+            Ok(None) => continue,
+            Err(_) => {
+                let reply = response("400 Bad Request", "");
+                socket.write_all(reply.as_bytes()).await?;
+                return Ok(false);
+            }
+        }
+    };
+
+    let current = STATE.lock().await.config.network.clone();
+    // any other path is a device checking for a captive portal, so it gets
+    // the page too
+    let (status, page, saved) = match request.method {
+        "POST" => match parse_form(request.body, &current) {
+            Ok(network) => {
+                let page = render_page(&network, PageStatus::Saved);
+                STATE.lock().await.config.network = network;
+                ("200 OK", page, true)
+            }
+            Err(e) => {
+                let page = render_page(&current, PageStatus::Rejected(e));
+                ("400 Bad Request", page, false)
+            }
+        },
+        _ => ("200 OK", render_page(&current, PageStatus::Blank), false),
+    };
+
+    socket.write_all(response(status, &page).as_bytes()).await?;
+    Ok(saved)
+}
@@ -1,7 +1,14 @@
+mod sk6812;
+mod ws2811;
 mod ws2812b;
 
 use core::convert::Infallible;
 
+use common::{
+    color::{ColorOrder, Rgb8},
+    effect::StripInfo,
+    net::{Chipset, StripConfig},
+};
 use embassy_time::{Duration, Timer};
 use embedded_io::{ErrorType, Write};
 use esp_hal::{
@@ -9,6 +16,8 @@ use esp_hal::{
     gpio::interconnect::PeripheralOutput,
     rmt::{Channel, Error, PulseCode, Tx, TxChannelConfig, TxChannelCreator},
 };
+pub use sk6812::*;
+pub use ws2811::*;
 pub use ws2812b::*;
 
 /// An LED protocol.
@@ -22,45 +31,89 @@ pub trait RmtLed {
     /// Number of `PulseCode`s per LED.
     const CODES_PER_LED: usize;
 
-    /// Write a byte to a buffer of pulse codes, at the beginning.
-    /// Returns the number of pulse codes written.
-    fn write_byte(buf: &mut [PulseCode], byte: u8) -> usize;
+    /// Write a byte to a buffer of pulse codes, at the beginning, most
+    /// significant bit first. Returns the number of pulse codes written.
+    fn write_byte(buf: &mut [PulseCode], mut byte: u8) -> usize {
+        for code in &mut buf[..8] {
+            *code = match byte & 0b1000_0000 {
+                0 => Self::LO,
+                _ => Self::HI,
+            };
+            byte <<= 1;
+        }
+
+        8
+    }
+}
+
+/// Write a byte in the protocol a chipset speaks.
+/// Returns the number of pulse codes written.
+fn write_byte(chipset: Chipset, buf: &mut [PulseCode], byte: u8) -> usize {
+    match chipset {
+        Chipset::Ws2812b => Ws2812b::write_byte(buf, byte),
+        Chipset::Sk6812 => Sk6812::write_byte(buf, byte),
+        Chipset::Ws2811 => Ws2811::write_byte(buf, byte),
+    }
+}
+
+/// Number of `PulseCode`s per LED for a chipset.
+const fn codes_per_led(chipset: Chipset) -> usize {
+    match chipset {
+        Chipset::Ws2812b => Ws2812b::CODES_PER_LED,
+        Chipset::Sk6812 => Sk6812::CODES_PER_LED,
+        Chipset::Ws2811 => Ws2811::CODES_PER_LED,
+    }
 }
 
-/// Implement on an LED protocol when a certain color type can be
-/// written to the bitstream.
-pub trait WriteColor<T> {
-    fn write_color(buf: &mut [PulseCode], color: T) -> usize;
+/// The time required for a chipset to "latch" on the new value.
+fn latch(chipset: Chipset) -> Duration {
+    match chipset {
+        Chipset::Ws2812b => Ws2812b::LATCH,
+        Chipset::Sk6812 => Sk6812::LATCH,
+        Chipset::Ws2811 => Ws2811::LATCH,
+    }
 }
 
-/// A buffer of RMT pulse codes that can be cleanly written to in sequence.
+/// A buffer of RMT pulse codes that can be cleanly written to in sequence,
+/// for a strip whose chipset and color order are picked at runtime.
 #[derive(Debug, Clone)]
-pub struct RmtBuf<T: RmtLed, const N: usize> {
+pub struct RmtBuf<const N: usize> {
     buf: [PulseCode; N],
     len: usize,
     pos: usize,
-    _phantom: core::marker::PhantomData<T>,
+    chipset: Chipset,
+    order: ColorOrder,
 }
 
-impl<T: RmtLed, const N: usize> RmtBuf<T, N> {
-    /// Instantiate a new buffer.
-    pub const fn new(num_px: usize) -> Self {
-        let len = num_px * T::CODES_PER_LED + 1;
-        assert!(len <= N);
-
-        let mut buf = [T::LO; N];
-        buf[len - 1] = PulseCode::end_marker();
-        Self {
-            buf,
-            len,
+impl<const N: usize> RmtBuf<N> {
+    /// Instantiate a new buffer for a strip.
+    pub const fn new(config: StripConfig) -> Self {
+        let mut buf = Self {
+            buf: [PulseCode::end_marker(); N],
+            len: 1,
             pos: 0,
-            _phantom: core::marker::PhantomData,
-        }
+            chipset: config.chipset,
+            order: config.order,
+        };
+        buf.configure(config);
+        buf
     }
 
     #[allow(unused)]
     pub const fn empty() -> Self {
-        Self::new(0)
+        Self::new(StripConfig::new(StripInfo::empty()))
+    }
+
+    /// Resize the buffer and switch protocols for a reconfigured strip.
+    pub const fn configure(&mut self, config: StripConfig) {
+        let len = config.leds * codes_per_led(config.chipset) + 1;
+        assert!(len <= N);
+
+        self.buf[len - 1] = PulseCode::end_marker();
+        self.len = len;
+        self.pos = 0;
+        self.chipset = config.chipset;
+        self.order = config.order;
     }
 
     #[allow(unused)]
@@ -77,26 +130,27 @@ impl<T: RmtLed, const N: usize> RmtBuf<T, N> {
         self.buf[self.pos..self.len].as_mut()
     }
 
-    /// Write a color into this buffer, if the LED protocol supports it.
-    pub fn write_color<C>(&mut self, color: C) -> usize
-    where
-        T: WriteColor<C>,
-    {
-        let s = T::write_color(self.cur_buf_mut(), color);
-        self.pos += s;
-        s
+    /// Write a color into this buffer, in the strip's color order.
+    pub fn write_color(&mut self, color: Rgb8) -> usize {
+        let mut written = 0usize;
+        for byte in self.order.write(color) {
+            let s = write_byte(self.chipset, self.cur_buf_mut(), byte);
+            written += s;
+            self.pos += s;
+        }
+        written
     }
 }
 
-impl<'a, T: RmtLed, const N: usize> ErrorType for RmtBuf<T, N> {
+impl<const N: usize> ErrorType for RmtBuf<N> {
     type Error = Infallible;
 }
 
-impl<T: RmtLed, const N: usize> Write for RmtBuf<T, N> {
+impl<const N: usize> Write for RmtBuf<N> {
     fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
         let mut written = 0usize;
         for &byte in buf {
-            let s = T::write_byte(self.cur_buf_mut(), byte);
+            let s = write_byte(self.chipset, self.cur_buf_mut(), byte);
             written += s;
             self.pos += s;
         }
@@ -110,19 +164,16 @@ impl<T: RmtLed, const N: usize> Write for RmtBuf<T, N> {
 }
 
 /// An `RmtStrip` wraps an RMT channel.
-pub struct RmtStrip<'ch, Dm, T>
+pub struct RmtStrip<'ch, Dm>
 where
     Dm: DriverMode,
-    T: RmtLed,
 {
     pub ch: Channel<'ch, Dm, Tx>,
-    _phantom: core::marker::PhantomData<T>,
 }
 
-impl<'ch, Dm, T> RmtStrip<'ch, Dm, T>
+impl<'ch, Dm> RmtStrip<'ch, Dm>
 where
     Dm: DriverMode,
-    T: RmtLed,
 {
     /// Create a new strip given a RMT channel and an output pin.
     pub fn new_on_channel(
@@ -139,34 +190,31 @@ where
             .unwrap()
             .with_pin(pin);
 
-        Ok(Self {
-            ch,
-            _phantom: Default::default(),
-        })
+        Ok(Self { ch })
     }
 
-    /// Wait the latch time defined by the LED protocol.
+    /// Wait the latch time defined by a chipset's protocol.
     #[allow(unused)]
-    pub async fn latch(&self) {
-        Timer::after(T::LATCH).await;
+    pub async fn latch(&self, chipset: Chipset) {
+        Timer::after(latch(chipset)).await;
     }
 }
 
-impl<'ch, T: RmtLed> RmtStrip<'ch, Async, T> {
+impl<'ch> RmtStrip<'ch, Async> {
     /// Transmit the current buffer over RMT asynchronously.
     #[allow(unused)]
     pub fn transmit<const SIZE: usize>(
         &mut self,
-        rmt_buf: &mut RmtBuf<T, SIZE>,
+        rmt_buf: &mut RmtBuf<SIZE>,
     ) -> impl Future<Output = Result<(), Error>> {
         self.ch.transmit(rmt_buf.buf())
     }
 }
 
-impl<'ch, T: RmtLed> RmtStrip<'ch, Blocking, T> {
+impl<'ch> RmtStrip<'ch, Blocking> {
     /// Transmit the current buffer over RMT, blocking the current thread.
     #[allow(unused)]
-    pub fn transmit_blocking<'a, const SIZE: usize>(&mut self, rmt_buf: &mut RmtBuf<T, SIZE>) {
+    pub fn transmit_blocking<'a, const SIZE: usize>(&mut self, rmt_buf: &mut RmtBuf<SIZE>) {
         self.ch
             .reborrow()
             .transmit(rmt_buf.buf())
@@ -0,0 +1,14 @@
+use embassy_time::Duration;
+use esp_hal::{gpio::Level, rmt::PulseCode};
+
+use crate::rmt_led::RmtLed;
+
+/// SK6812 LEDs, the RGB kind.
+pub enum Sk6812 {}
+
+impl RmtLed for Sk6812 {
+    const LO: PulseCode = PulseCode::new(Level::High, 24, Level::Low, 72);
+    const HI: PulseCode = PulseCode::new(Level::High, 48, Level::Low, 48);
+    const LATCH: Duration = Duration::from_micros(80);
+    const CODES_PER_LED: usize = 24;
+}
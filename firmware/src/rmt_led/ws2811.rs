@@ -0,0 +1,14 @@
+use embassy_time::Duration;
+use esp_hal::{gpio::Level, rmt::PulseCode};
+
+use crate::rmt_led::RmtLed;
+
+/// WS2811 drivers, in their 800kHz mode.
+pub enum Ws2811 {}
+
+impl RmtLed for Ws2811 {
+    const LO: PulseCode = PulseCode::new(Level::High, 20, Level::Low, 80);
+    const HI: PulseCode = PulseCode::new(Level::High, 48, Level::Low, 52);
+    const LATCH: Duration = Duration::from_micros(280);
+    const CODES_PER_LED: usize = 24;
+}
@@ -1,8 +1,7 @@
-use common::color::Rgb8;
 use embassy_time::Duration;
 use esp_hal::{gpio::Level, rmt::PulseCode};
 
-use crate::rmt_led::{RmtLed, WriteColor};
+use crate::rmt_led::RmtLed;
 
 /// WS2812B LEDs.
 pub enum Ws2812b {}
@@ -12,25 +11,4 @@ impl RmtLed for Ws2812b {
     const HI: PulseCode = PulseCode::new(Level::High, 56, Level::Low, 48);
     const LATCH: Duration = Duration::from_micros(100);
     const CODES_PER_LED: usize = 24;
-
-    fn write_byte(buf: &mut [PulseCode], mut byte: u8) -> usize {
-        for i in 0..8 {
-            buf[i] = match byte & 0b1000_0000 {
-                0 => Self::LO,
-                _ => Self::HI,
-            };
-            byte <<= 1;
-        }
-
-        8
-    }
-}
-
-impl WriteColor<Rgb8> for Ws2812b {
-    fn write_color(buf: &mut [PulseCode], Rgb8 { r, g, b }: Rgb8) -> usize {
-        Self::write_byte(buf, g);
-        Self::write_byte(buf[8..].as_mut(), r);
-        Self::write_byte(buf[16..].as_mut(), b);
-        24
-    }
 }
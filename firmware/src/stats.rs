@@ -0,0 +1,45 @@
+use core::sync::atomic::{AtomicI32, AtomicU32, Ordering};
+
+const NO_RSSI: i32 = i32::MIN;
+
+/// Frames transmitted by `data_tx` since boot.
+pub static FRAMES: AtomicU32 = AtomicU32::new(0);
+
+/// UDP packets dropped since boot.
+pub static DROPPED_PACKETS: AtomicU32 = AtomicU32::new(0);
+
+/// UDP packets dropped since boot for arriving after a newer one.
+pub static STALE_PACKETS: AtomicU32 = AtomicU32::new(0);
+
+/// UDP packets and TCP frames dropped since boot for failing authentication.
+pub static REJECTED_PACKETS: AtomicU32 = AtomicU32::new(0);
+
+static RSSI: AtomicI32 = AtomicI32::new(NO_RSSI);
+
+/// Count a UDP packet that was not applied.
+pub fn drop_packet() {
+    DROPPED_PACKETS.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Count a UDP packet that was older than one already applied.
+pub fn stale_packet() {
+    STALE_PACKETS.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Count a packet or frame that failed authentication.
+pub fn reject_packet() {
+    REJECTED_PACKETS.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Record the current Wi-Fi signal strength, or `None` if disconnected.
+pub fn set_rssi(rssi: Option<i32>) {
+    RSSI.store(rssi.unwrap_or(NO_RSSI), Ordering::Relaxed);
+}
+
+/// The last recorded Wi-Fi signal strength, in dBm.
+pub fn rssi() -> Option<i8> {
+    match RSSI.load(Ordering::Relaxed) {
+        NO_RSSI => None,
+        rssi => Some(rssi.clamp(i8::MIN as i32, i8::MAX as i32) as i8),
+    }
+}
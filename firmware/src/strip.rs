@@ -1,4 +1,19 @@
-use common::{color::RgbaF32, effect::StripInfo, net::StripMode};
+use alloc::{string::String, vec::Vec};
+use common::{
+    clock::ClockSync,
+    color::{ColorOrder, Rgb8, RgbaF32},
+    config::{NetworkConfig, NodeConfig, StoredStrip},
+    effect::{
+        StripInfo,
+        fx::OverlayConfig,
+        param::ParamValue,
+        transition::{Transition, TransitionKind},
+    },
+    net::{
+        Chipset, QUEUED_FRAMES, STREAM_INTERVAL_MS, StripConfig, StripMode, auth::PacketOpener,
+        failsafe::StreamWatchdog, jitter::JitterBuffer, udp::SequenceFilter,
+    },
+};
 
 use crate::NUM_STRIPS;
 
@@ -7,7 +22,19 @@ pub const MAX_STRIP_BUF_LEN: usize = MAX_STRIP_LEN * 24 + 1;
 
 pub struct StripState<const N: usize> {
     pub colors: [RgbaF32; N],
+    /// DDP pixels waiting for a push before they're shown.
+    pub pending: [Rgb8; N],
+    /// Drops streamed packets older than the last one applied.
+    pub sequence: SequenceFilter,
+    /// Timestamped frames waiting to be shown.
+    pub jitter: JitterBuffer<[RgbaF32; N], QUEUED_FRAMES>,
+    /// Falls back when the stream goes quiet.
+    pub watchdog: StreamWatchdog,
     pub info: StripInfo,
+    pub order: ColorOrder,
+    pub chipset: Chipset,
+    /// Set by [`StripState::configure`] until `data_tx` resizes the strip's `RmtBuf`.
+    pub reconfigured: bool,
     pub mode: StripMode,
 }
 
@@ -16,7 +43,14 @@ impl<const N: usize> StripState<N> {
     pub const fn new(info: StripInfo) -> Self {
         Self {
             colors: [RgbaF32::zero(); N],
+            pending: [Rgb8::zero(); N],
+            sequence: SequenceFilter::new(),
+            jitter: JitterBuffer::new(Some(STREAM_INTERVAL_MS)),
+            watchdog: StreamWatchdog::new(),
             info,
+            order: ColorOrder::Grb,
+            chipset: Chipset::Ws2812b,
+            reconfigured: false,
             mode: StripMode::Hybrid,
         }
     }
@@ -24,7 +58,14 @@ impl<const N: usize> StripState<N> {
     pub const fn empty() -> Self {
         Self {
             colors: [RgbaF32::zero(); N],
+            pending: [Rgb8::zero(); N],
+            sequence: SequenceFilter::new(),
+            jitter: JitterBuffer::new(Some(STREAM_INTERVAL_MS)),
+            watchdog: StreamWatchdog::new(),
             info: StripInfo::empty(),
+            order: ColorOrder::Grb,
+            chipset: Chipset::Ws2812b,
+            reconfigured: false,
             mode: StripMode::Off,
         }
     }
@@ -32,14 +73,120 @@ impl<const N: usize> StripState<N> {
     pub fn is_empty(&self) -> bool {
         self.info.leds == 0
     }
+
+    pub fn config(&self) -> StripConfig {
+        StripConfig {
+            leds: self.info.leds,
+            rev: self.info.rev,
+            order: self.order,
+            chipset: self.chipset,
+        }
+    }
+
+    /// Rewire the strip, clearing whatever was streamed to it.
+    pub fn configure(&mut self, config: StripConfig) {
+        self.info = config.info();
+        self.order = config.order;
+        self.chipset = config.chipset;
+        self.colors.fill(RgbaF32::zero());
+        self.pending.fill(Rgb8::zero());
+        self.jitter.reset();
+        self.reconfigured = true;
+    }
 }
 
 pub struct State<const BUF_LEN: usize> {
     pub strips: [StripState<BUF_LEN>; NUM_STRIPS],
+    /// Effect mode shift requested by the server, applied on the next frame.
+    pub effect_shift: i8,
+    /// Transition requested with `effect_shift`, or `None` for the default.
+    pub effect_transition: Option<Transition>,
+    /// Effect id selected by the server, and its transition, applied on the next frame.
+    pub effect_select: Option<(u8, Option<Transition>)>,
+    /// Validated `(effect, param, value)` changes, applied on the next frame.
+    pub param_changes: Vec<(u8, u8, ParamValue)>,
+    /// Validated overlays replacing each strip's, applied on the next frame.
+    pub overlay_changes: Vec<(usize, Vec<OverlayConfig>)>,
+    /// The server's clock, as estimated from time sync round trips.
+    pub clock: ClockSync,
+    /// What streamed packets are checked against during the open session,
+    /// when there's a key.
+    pub stream_opener: Option<PacketOpener>,
+    /// The config kept in flash. Each strip's wiring, mode and timeout live in
+    /// `strips` instead, and are gathered in by [`State::to_config`].
+    pub config: NodeConfig,
+    /// When the config last changed without being saved, in local milliseconds.
+    pub unsaved_since: Option<u64>,
 }
 
 impl<const BUF_LEN: usize> State<BUF_LEN> {
     pub const fn new(strips: [StripState<BUF_LEN>; NUM_STRIPS]) -> Self {
-        Self { strips }
+        Self {
+            strips,
+            effect_shift: 0,
+            effect_transition: None,
+            effect_select: None,
+            param_changes: Vec::new(),
+            overlay_changes: Vec::new(),
+            clock: ClockSync::new(),
+            stream_opener: None,
+            config: NodeConfig {
+                network: NetworkConfig {
+                    ssid: String::new(),
+                    password: String::new(),
+                    name: String::new(),
+                    server: None,
+                    key: None,
+                },
+                strips: Vec::new(),
+                effect: 0,
+                params: Vec::new(),
+                brightness: 0.5,
+            },
+            unsaved_since: None,
+        }
+    }
+
+    /// Set the node up from a config loaded at boot. Params aren't checked
+    /// again, since `data_tx` ignores any the effects reject.
+    pub fn load(&mut self, config: NodeConfig) {
+        for (strip, stored) in self.strips.iter_mut().zip(&config.strips) {
+            // saved by firmware that could drive longer strips, maybe
+            strip.configure(StripConfig {
+                leds: stored.config.leds.min(BUF_LEN),
+                ..stored.config
+            });
+            strip.mode = stored.mode;
+            strip.watchdog.set_timeout(stored.stream_timeout);
+        }
+        self.effect_select = Some((
+            config.effect,
+            Some(Transition::new(TransitionKind::Crossfade, 0)),
+        ));
+        self.param_changes.extend(config.params.iter().copied());
+        self.config = config;
+    }
+
+    /// The config to save, as the node is now.
+    pub fn to_config(&self) -> NodeConfig {
+        let strips = self
+            .strips
+            .iter()
+            .map(|s| StoredStrip {
+                config: s.config(),
+                mode: s.mode,
+                stream_timeout: s.watchdog.timeout(),
+            })
+            .collect();
+        NodeConfig {
+            strips,
+            ..self.config.clone()
+        }
+    }
+
+    /// Note that the config changed at local time `now`, so it's saved once
+    /// changes settle.
+    pub fn mark_unsaved(&mut self, now: u64) {
+        self.unsaved_since = Some(now);
     }
 }
@@ -0,0 +1,10 @@
+//! The server's clock, which nodes sync to so their effects line up.
+
+use std::{sync::LazyLock, time::Instant};
+
+static START: LazyLock<Instant> = LazyLock::new(Instant::now);
+
+/// Milliseconds since the server's clock was first read.
+pub fn now() -> u64 {
+    START.elapsed().as_millis() as u64
+}
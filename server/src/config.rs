@@ -0,0 +1,172 @@
+use std::{
+    fmt, io,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+};
+
+use common::net::{auth::Key, discovery::DISCOVERY_PORT};
+use serde::{Deserialize, Deserializer, de::Error as _};
+
+use crate::{dmx::ColorOrder, spatial::OutputProtocol};
+
+/// The server's configuration, loaded from a TOML file.
+#[derive(Debug, Default, Deserialize)]
+pub struct Config {
+    #[serde(default)]
+    pub nodes: Vec<NodeConfig>,
+    /// A TOML or JSON file describing where each node's LEDs are in space.
+    pub layout: Option<PathBuf>,
+    /// How spatial effects are streamed to the nodes.
+    #[serde(default)]
+    pub output: OutputProtocol,
+    #[serde(default)]
+    pub dmx: DmxConfig,
+    #[serde(default)]
+    pub opc: OpcConfig,
+    #[serde(default)]
+    pub discovery: DiscoveryConfig,
+    /// The installation's key, written as 64 hex digits. Nodes holding it
+    /// only take the server's traffic authenticated with it.
+    #[serde(default, deserialize_with = "key")]
+    pub key: Option<Key>,
+}
+
+fn key<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Key>, D::Error> {
+    let hex = String::deserialize(deserializer)?;
+    Key::parse(hex.trim())
+        .map(Some)
+        .ok_or_else(|| D::Error::custom("key must be 64 hex digits"))
+}
+
+/// A statically configured node.
+#[derive(Debug, Deserialize)]
+pub struct NodeConfig {
+    /// The node's control address, e.g. `192.168.1.50:1338`.
+    pub addr: SocketAddr,
+    /// The number of LEDs on each strip.
+    pub strips: Vec<usize>,
+}
+
+/// Art-Net and sACN input.
+#[derive(Debug, Default, Deserialize)]
+pub struct DmxConfig {
+    /// Listen for Art-Net on its standard port.
+    #[serde(default)]
+    pub artnet: bool,
+    /// Listen for sACN on its standard port, joining each mapped universe's
+    /// multicast group.
+    #[serde(default)]
+    pub sacn: bool,
+    /// Where each universe's channels go.
+    #[serde(default)]
+    pub map: Vec<DmxMapping>,
+}
+
+/// A run of pixels in a DMX universe, mapped onto part of a strip.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct DmxMapping {
+    /// The universe, as numbered by the sending protocol.
+    pub universe: u16,
+    /// The channel of the first pixel's first color, starting at 1.
+    #[serde(default = "first_channel")]
+    pub start_channel: u16,
+    /// The control address of the node, as in `nodes`.
+    pub node: SocketAddr,
+    pub strip: u8,
+    /// The LED the first pixel lands on.
+    #[serde(default)]
+    pub offset: usize,
+    /// How many pixels to map, or as many as fit in the universe and strip.
+    pub pixels: Option<usize>,
+    #[serde(default)]
+    pub order: ColorOrder,
+}
+
+fn first_channel() -> u16 {
+    1
+}
+
+/// Open Pixel Control input.
+#[derive(Debug, Default, Deserialize)]
+pub struct OpcConfig {
+    /// The address to accept OPC clients on, e.g. `0.0.0.0:7890`, or none to
+    /// disable OPC.
+    pub listen: Option<SocketAddr>,
+    /// Where each channel's pixels go.
+    #[serde(default)]
+    pub map: Vec<OpcMapping>,
+}
+
+/// A run of pixels in an OPC channel, mapped onto part of a strip.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct OpcMapping {
+    /// The channel, from 1 to 255.
+    pub channel: u8,
+    /// The channel's first pixel to map.
+    #[serde(default)]
+    pub first: usize,
+    /// The control address of the node, as in `nodes`.
+    pub node: SocketAddr,
+    pub strip: u8,
+    /// The LED the first pixel lands on.
+    #[serde(default)]
+    pub offset: usize,
+    /// How many pixels to map, or the rest of the strip.
+    pub pixels: Option<usize>,
+}
+
+/// Registering nodes from their beacons.
+#[derive(Debug, Deserialize)]
+pub struct DiscoveryConfig {
+    /// Listen for beacons, on by default.
+    #[serde(default = "enabled")]
+    pub enabled: bool,
+    /// The port to listen on, [`DISCOVERY_PORT`] by default.
+    #[serde(default = "discovery_port")]
+    pub port: u16,
+}
+
+impl Default for DiscoveryConfig {
+    fn default() -> Self {
+        Self {
+            enabled: enabled(),
+            port: discovery_port(),
+        }
+    }
+}
+
+fn enabled() -> bool {
+    true
+}
+
+fn discovery_port() -> u16 {
+    DISCOVERY_PORT
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(io::Error),
+    Parse(toml::de::Error),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "failed to read config: {e}"),
+            Self::Parse(e) => write!(f, "failed to parse config: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+impl Config {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, ConfigError> {
+        let text = std::fs::read_to_string(path).map_err(ConfigError::Io)?;
+        Self::parse(&text)
+    }
+
+    pub fn parse(text: &str) -> Result<Self, ConfigError> {
+        toml::from_str(text).map_err(ConfigError::Parse)
+    }
+}
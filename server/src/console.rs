@@ -0,0 +1,211 @@
+//! The server's console: one command per line, read from stdin.
+
+use std::sync::Arc;
+
+use common::{
+    color::Rgb8,
+    effect::{
+        param::{ParamKind, ParamValue},
+        registry::{self, EFFECTS},
+        spatial::{NoiseField, PlaneSweep, RadialPulse},
+        transition::{Transition, TransitionKind},
+    },
+    net::{
+        StripConfig, StripMode,
+        failsafe::{Fallback, StreamTimeout},
+    },
+};
+use tokio::sync::watch;
+
+use crate::{node::NodeRegistry, ota, spatial::SharedEffect};
+
+/// Run a single console command.
+pub fn command(
+    registry: &NodeRegistry,
+    effect: &watch::Sender<Option<SharedEffect>>,
+    line: &str,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let args = line.split_whitespace().collect::<Vec<_>>();
+    match args.as_slice() {
+        [] => (),
+        ["nodes"] => {
+            for (id, node) in registry.nodes().iter().enumerate() {
+                println!("{id}: {node:?}");
+            }
+        }
+        ["mode", node, strip, mode] => {
+            let mode = match *mode {
+                "off" => StripMode::Off,
+                "effects" => StripMode::Effects,
+                "dynamic" => StripMode::Dynamic,
+                "hybrid" => StripMode::Hybrid,
+                _ => return Err(format!("unknown strip mode `{mode}`").into()),
+            };
+            registry.set_strip_mode(node.parse()?, strip.parse()?, mode)?;
+        }
+        ["timeout", node, strip, "off"] => {
+            registry.set_stream_timeout(node.parse()?, strip.parse()?, None)?;
+        }
+        ["timeout", node, strip, ms, fallback] => {
+            let fallback = match *fallback {
+                "effects" => Fallback::Effects,
+                "off" => Fallback::Off,
+                "fade" => Fallback::Fade,
+                _ => return Err(format!("unknown fallback `{fallback}`").into()),
+            };
+            let timeout = StreamTimeout::new(ms.parse()?, fallback);
+            registry.set_stream_timeout(node.parse()?, strip.parse()?, Some(timeout))?;
+        }
+        ["strip", node, strip, leds, dir, order, chipset] => {
+            let rev = match *dir {
+                "fwd" => false,
+                "rev" => true,
+                _ => return Err(format!("unknown direction `{dir}`").into()),
+            };
+            let config = StripConfig {
+                leds: leds.parse()?,
+                rev,
+                order: order.parse()?,
+                chipset: chipset.parse()?,
+            };
+            registry.configure_strip(node.parse()?, strip.parse()?, config)?;
+        }
+        ["shift", node, delta, transition @ ..] => registry.shift_effect_mode(
+            node.parse()?,
+            delta.parse()?,
+            parse_transition(transition)?,
+        )?,
+        ["effects"] => {
+            for (id, effect) in EFFECTS.iter().enumerate() {
+                println!("{id}: {}", effect.name);
+                for (param, info) in effect.params.iter().enumerate() {
+                    println!(
+                        "    {param}: {} {:?} = {:?}",
+                        info.name, info.kind, info.default
+                    );
+                }
+            }
+        }
+        ["effect", node, name, transition @ ..] => registry.select_effect(
+            node.parse()?,
+            parse_effect(name)?,
+            parse_transition(transition)?,
+        )?,
+        ["param", node, name, param, value] => {
+            let effect = parse_effect(name)?;
+            let params = EFFECTS[effect as usize].params;
+            let param = params
+                .iter()
+                .position(|info| info.name == *param)
+                .map(|param| param as u8)
+                .or_else(|| param.parse().ok())
+                .ok_or_else(|| format!("unknown parameter `{param}`"))?;
+            let kind = params
+                .get(param as usize)
+                .map(|info| info.kind)
+                .ok_or_else(|| format!("unknown parameter `{param}`"))?;
+            let value = parse_param(kind, value)?;
+            registry.set_effect_param(node.parse()?, effect, param, value)?;
+        }
+        ["overlay", node, strip, "clear"] => {
+            registry.clear_overlays(node.parse()?, strip.parse()?)?;
+        }
+        ["overlay", node, strip, name, mode, opacity] => registry.add_overlay(
+            node.parse()?,
+            strip.parse()?,
+            parse_effect(name)?,
+            mode.parse()?,
+            opacity.parse()?,
+        )?,
+        ["update", node, path] => {
+            let id = node.parse()?;
+            let image = std::fs::read(path)?;
+            let registry = registry.clone();
+            tokio::spawn(async move {
+                println!("node {id}: sending a {} byte update", image.len());
+                let len = image.len() as u64;
+                let mut reported = 0;
+                let result = ota::push(&registry, id, &image, |written| {
+                    let percent = written as u64 * 100 / len;
+                    if percent >= reported + 10 {
+                        reported = percent;
+                        println!("node {id}: update {percent}% written");
+                    }
+                })
+                .await;
+                if let Err(e) = result {
+                    eprintln!("error: node {id}: update failed: {e}");
+                }
+            });
+        }
+        ["spatial", name] => {
+            let next: Option<SharedEffect> = match *name {
+                "off" => None,
+                "sweep" => Some(Arc::new(PlaneSweep::default())),
+                "pulse" => Some(Arc::new(RadialPulse::default())),
+                "noise" => Some(Arc::new(NoiseField::default())),
+                _ => return Err(format!("unknown spatial effect `{name}`").into()),
+            };
+            effect.send_replace(next);
+        }
+        _ => {
+            return Err(
+                "usage: nodes | mode <node> <strip> <off|effects|dynamic|hybrid> | timeout <node> <strip> <off | <ms> <effects|off|fade>> | strip <node> <strip> <leds> <fwd|rev> <rgb|grb|...> <ws2812b|sk6812|ws2811> | shift <node> <delta> [<transition> <ms>] | effects | effect <node> <effect> [<transition> <ms>] | param <node> <effect> <param> <value> | overlay <node> <strip> <clear | <effect> <over|add|multiply|screen|max> <opacity>> | spatial <off|sweep|pulse|noise> | update <node> <image.bin>"
+                    .into(),
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Parse an optional `<crossfade|wipe|black> <ms>` pair.
+fn parse_transition(args: &[&str]) -> Result<Option<Transition>, Box<dyn std::error::Error>> {
+    match args {
+        [] => Ok(None),
+        [kind, ms] => {
+            let kind = match *kind {
+                "crossfade" => TransitionKind::Crossfade,
+                "wipe" => TransitionKind::Wipe,
+                "black" => TransitionKind::FadeThroughBlack,
+                _ => return Err(format!("unknown transition `{kind}`").into()),
+            };
+            Ok(Some(Transition::new(kind, ms.parse()?)))
+        }
+        _ => Err("expected `<crossfade|wipe|black> <ms>`".into()),
+    }
+}
+
+/// Parse a registered effect's name or id.
+fn parse_effect(name: &str) -> Result<u8, Box<dyn std::error::Error>> {
+    registry::find(name)
+        .or_else(|| {
+            name.parse()
+                .ok()
+                .filter(|&id| (id as usize) < registry::COUNT)
+        })
+        .ok_or_else(|| format!("unknown effect `{name}`").into())
+}
+
+/// Parse a parameter value, with colors written as `#rrggbb` or `r,g,b`.
+fn parse_param(kind: ParamKind, value: &str) -> Result<ParamValue, Box<dyn std::error::Error>> {
+    Ok(match kind {
+        ParamKind::F32 { .. } => ParamValue::F32(value.parse()?),
+        ParamKind::U8 { .. } => ParamValue::U8(value.parse()?),
+        ParamKind::Bool => ParamValue::Bool(value.parse()?),
+        ParamKind::Color => {
+            let color = if let Some(hex) = value.strip_prefix('#') {
+                let rgb = u32::from_str_radix(hex, 16)?;
+                if hex.len() != 6 {
+                    return Err(format!("bad color `{value}`").into());
+                }
+                Rgb8::new((rgb >> 16) as u8, (rgb >> 8) as u8, rgb as u8)
+            } else {
+                match value.split(',').collect::<Vec<_>>().as_slice() {
+                    [r, g, b] => Rgb8::new(r.parse()?, g.parse()?, b.parse()?),
+                    _ => return Err(format!("bad color `{value}`").into()),
+                }
+            };
+            ParamValue::Color(color)
+        }
+    })
+}
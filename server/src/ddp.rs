@@ -0,0 +1,51 @@
+//! DDP output, for driving nodes or any other DDP display (like WLED) from the
+//! server's spatial effects.
+
+use std::{
+    net::SocketAddr,
+    sync::atomic::{AtomicU8, Ordering},
+};
+
+use common::{
+    color::Rgb8,
+    net::ddp::{self, HEADER_LEN, MAX_PIXELS},
+};
+use tokio::{io, net::UdpSocket};
+
+/// `DdpSender` streams whole frames to displays, splitting them into packets
+/// and pushing once the last one is sent.
+pub struct DdpSender {
+    socket: UdpSocket,
+    sequence: AtomicU8,
+}
+
+impl DdpSender {
+    /// Bind a sender to an ephemeral local port.
+    pub async fn bind() -> io::Result<Self> {
+        Ok(Self {
+            socket: UdpSocket::bind("0.0.0.0:0").await?,
+            sequence: AtomicU8::new(0),
+        })
+    }
+
+    /// Send a frame to a display, its strips laid end to end in order.
+    pub async fn send_frame<'a>(
+        &self,
+        addr: SocketAddr,
+        strips: impl IntoIterator<Item = &'a [Rgb8]>,
+    ) -> io::Result<()> {
+        let colors = strips.into_iter().flatten().copied().collect::<Vec<_>>();
+        // 1..=15, since 0 tells the display sequence numbers aren't used
+        let sequence = self.sequence.fetch_add(1, Ordering::Relaxed) % 15 + 1;
+
+        let mut buf = [0u8; HEADER_LEN + MAX_PIXELS * 3];
+        let chunks = colors.chunks(MAX_PIXELS).count();
+        for (i, chunk) in colors.chunks(MAX_PIXELS).enumerate() {
+            let push = i + 1 == chunks;
+            let packet = ddp::encode(&mut buf, sequence, push, i * MAX_PIXELS, chunk)
+                .map_err(|e| io::Error::other(format!("failed to encode packet: {e:?}")))?;
+            self.socket.send_to(packet, addr).await?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,60 @@
+//! Registering nodes from the beacons they broadcast (see
+//! [`common::net::discovery`]).
+
+use std::net::SocketAddr;
+
+use common::net::discovery::{Beacon, MAX_BEACON_LEN};
+use tokio::net::UdpSocket;
+
+use crate::node::{NodeId, NodeRegistry};
+
+/// Register every node whose beacon arrives on `socket` and isn't in the
+/// registry yet.
+///
+/// Discovered nodes start with no strips and pick them up from the node's
+/// handshake, like a configured node with fewer strips than it drives.
+pub async fn listen(socket: UdpSocket, registry: NodeRegistry) {
+    // anything longer isn't a beacon and fails to decode
+    let mut buf = [0u8; MAX_BEACON_LEN];
+    loop {
+        match socket.recv_from(&mut buf).await {
+            Ok((n, src)) => {
+                if let Ok(beacon) = Beacon::decode(&buf[..n]) {
+                    register(&registry, src, &beacon);
+                }
+            }
+            // like an oversized datagram on Windows, or an ICMP error from
+            // an earlier send; the socket itself is still fine
+            Err(e) => eprintln!("warn: failed to receive a beacon: {e}"),
+        }
+    }
+}
+
+/// Register the node behind a beacon from `src`, unless it's already known.
+///
+/// Nodes are known by their beacon's id, so a node that turns up at a new
+/// address, like after its DHCP lease changed, is moved there. A node from the
+/// config file is known by its address until its first beacon.
+fn register(registry: &NodeRegistry, src: SocketAddr, beacon: &Beacon) -> Option<NodeId> {
+    let addr = SocketAddr::new(src.ip(), beacon.tcp_port);
+    if let Some(id) = registry.find_beacon(beacon.id) {
+        if registry.get(id).is_some_and(|status| status.addr != addr) {
+            println!("node {id} moved to {addr}");
+            registry.set_addr(id, addr);
+        }
+        return None;
+    }
+    if let Some(id) = registry.find(addr) {
+        registry.set_beacon_id(id, beacon.id);
+        return None;
+    }
+
+    let id = registry.add(addr, &[]);
+    registry.set_beacon_id(id, beacon.id);
+    let name = match beacon.name.as_str() {
+        "" => "unnamed",
+        name => name,
+    };
+    println!("discovered node {id} ({name}) at {addr}");
+    Some(id)
+}
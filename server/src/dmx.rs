@@ -0,0 +1,285 @@
+//! Art-Net and sACN (E1.31) input, so lighting desks and sequencers can drive
+//! strips as DMX fixtures.
+//!
+//! Each incoming universe is split into pixels by the config's mapping table
+//! and copied into a per-strip buffer, which is forwarded whole (see
+//! [`crate::input`]) whenever a universe touches it.
+
+use std::{
+    fmt,
+    net::{Ipv4Addr, SocketAddr},
+};
+
+pub use common::color::ColorOrder;
+use common::color::Rgb8;
+use tokio::net::UdpSocket;
+
+use crate::{
+    config::{DmxMapping, NodeConfig},
+    input::{self, MapError, Router, Span, StripBuffers},
+    udp::UdpSender,
+};
+
+/// The port Art-Net is sent to.
+pub const ARTNET_PORT: u16 = 6454;
+/// The port sACN is sent to.
+pub const SACN_PORT: u16 = 5568;
+
+/// The number of channels in a DMX universe.
+pub const UNIVERSE_LEN: usize = 512;
+
+const ARTNET_ID: &[u8; 8] = b"Art-Net\0";
+const ARTNET_OP_DMX: u16 = 0x5000;
+/// Where levels start in an ArtDmx packet.
+const ARTNET_DATA: usize = 18;
+
+const ACN_ID: &[u8; 12] = b"ASC-E1.17\0\0\0";
+const E131_ROOT_DATA: u32 = 0x0000_0004;
+const E131_FRAMING_DATA: u32 = 0x0000_0002;
+const E131_OPTION_PREVIEW: u8 = 0x40;
+/// Where the DMX start code sits in an E1.31 data packet, followed by levels.
+const E131_START_CODE: usize = 125;
+
+/// The levels of one universe, borrowed from a packet.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DmxFrame<'a> {
+    pub universe: u16,
+    /// Channel levels, starting at channel 1.
+    pub data: &'a [u8],
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DmxError {
+    /// The packet is neither Art-Net nor sACN.
+    UnknownProtocol,
+    /// The packet ended before its header said it would.
+    Truncated,
+}
+
+impl fmt::Display for DmxError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnknownProtocol => write!(f, "not an Art-Net or sACN packet"),
+            Self::Truncated => write!(f, "truncated DMX packet"),
+        }
+    }
+}
+
+impl std::error::Error for DmxError {}
+
+/// Parse an ArtDmx or E1.31 data packet.
+///
+/// Valid packets that don't carry levels, like other Art-Net opcodes, sACN
+/// preview data or alternate start codes, are `Ok(None)`.
+pub fn parse(packet: &[u8]) -> Result<Option<DmxFrame<'_>>, DmxError> {
+    if packet.starts_with(ARTNET_ID) {
+        parse_artnet(packet)
+    } else if packet.get(4..16) == Some(ACN_ID) {
+        parse_sacn(packet)
+    } else {
+        Err(DmxError::UnknownProtocol)
+    }
+}
+
+fn parse_artnet(packet: &[u8]) -> Result<Option<DmxFrame<'_>>, DmxError> {
+    let header = packet.get(..ARTNET_DATA).ok_or(DmxError::Truncated)?;
+    if u16::from_le_bytes([header[8], header[9]]) != ARTNET_OP_DMX {
+        return Ok(None);
+    }
+
+    let universe = u16::from_le_bytes([header[14], header[15] & 0x7f]);
+    let len = u16::from_be_bytes([header[16], header[17]]) as usize;
+    let data = packet
+        .get(ARTNET_DATA..ARTNET_DATA + len.min(UNIVERSE_LEN))
+        .ok_or(DmxError::Truncated)?;
+    Ok(Some(DmxFrame { universe, data }))
+}
+
+fn parse_sacn(packet: &[u8]) -> Result<Option<DmxFrame<'_>>, DmxError> {
+    let header = packet.get(..=E131_START_CODE).ok_or(DmxError::Truncated)?;
+    let be32 =
+        |i: usize| u32::from_be_bytes([header[i], header[i + 1], header[i + 2], header[i + 3]]);
+    if be32(18) != E131_ROOT_DATA || be32(40) != E131_FRAMING_DATA {
+        // sync and discovery packets
+        return Ok(None);
+    }
+    if header[112] & E131_OPTION_PREVIEW != 0 || header[E131_START_CODE] != 0 {
+        return Ok(None);
+    }
+
+    let universe = u16::from_be_bytes([header[113], header[114]]);
+    // the property count includes the start code
+    let count = u16::from_be_bytes([header[123], header[124]]) as usize;
+    let len = count.saturating_sub(1).min(UNIVERSE_LEN);
+    let data = packet
+        .get(E131_START_CODE + 1..E131_START_CODE + 1 + len)
+        .ok_or(DmxError::Truncated)?;
+    Ok(Some(DmxFrame { universe, data }))
+}
+
+/// The multicast group sACN sends a universe to.
+pub fn sacn_group(universe: u16) -> Ipv4Addr {
+    let [hi, lo] = universe.to_be_bytes();
+    Ipv4Addr::new(239, 255, hi, lo)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DmxRouteError {
+    /// The mapping names a node that isn't configured.
+    UnknownNode(SocketAddr),
+    /// The node has no strip at this index.
+    UnknownStrip(SocketAddr, u8),
+    /// The start channel is outside 1..=512.
+    BadChannel(u16),
+    /// The mapped pixels run past the end of the strip.
+    PastStrip(SocketAddr, u8),
+}
+
+impl fmt::Display for DmxRouteError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnknownNode(addr) => write!(f, "DMX mapping for unknown node {addr}"),
+            Self::UnknownStrip(addr, strip) => write!(f, "node {addr} has no strip {strip}"),
+            Self::BadChannel(channel) => write!(f, "DMX channel {channel} is outside 1..=512"),
+            Self::PastStrip(addr, strip) => {
+                write!(f, "DMX mapping runs past the end of {addr} strip {strip}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for DmxRouteError {}
+
+impl From<MapError> for DmxRouteError {
+    fn from(error: MapError) -> Self {
+        match error {
+            MapError::UnknownNode(addr) => Self::UnknownNode(addr),
+            MapError::UnknownStrip(addr, strip) => Self::UnknownStrip(addr, strip),
+            MapError::PastStrip(addr, strip) => Self::PastStrip(addr, strip),
+        }
+    }
+}
+
+struct Route {
+    universe: u16,
+    /// Index of the first channel in the universe's data.
+    channel: usize,
+    span: Span,
+    order: ColorOrder,
+}
+
+/// `DmxRouter` copies DMX universes into strip buffers, following a mapping table.
+pub struct DmxRouter {
+    strips: StripBuffers,
+    routes: Vec<Route>,
+}
+
+impl DmxRouter {
+    /// Route each mapping to its node's strip, sending to the node's IP on `udp_port`.
+    pub fn new(
+        mappings: &[DmxMapping],
+        nodes: &[NodeConfig],
+        udp_port: u16,
+    ) -> Result<Self, DmxRouteError> {
+        let mut router = Self {
+            strips: StripBuffers::new(udp_port),
+            routes: Vec::new(),
+        };
+        for mapping in mappings {
+            if !(1..=UNIVERSE_LEN as u16).contains(&mapping.start_channel) {
+                return Err(DmxRouteError::BadChannel(mapping.start_channel));
+            }
+            let mut span = router.strips.map(
+                nodes,
+                mapping.node,
+                mapping.strip,
+                mapping.offset,
+                mapping.pixels,
+            )?;
+
+            let channel = mapping.start_channel as usize - 1;
+            // no more than fit in the rest of the universe
+            span.len = span.len.min((UNIVERSE_LEN - channel) / 3);
+
+            router.routes.push(Route {
+                universe: mapping.universe,
+                channel,
+                span,
+                order: mapping.order,
+            });
+        }
+        Ok(router)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.routes.is_empty()
+    }
+
+    /// Every universe with at least one mapping.
+    pub fn universes(&self) -> Vec<u16> {
+        let mut universes = self.routes.iter().map(|r| r.universe).collect::<Vec<_>>();
+        universes.sort_unstable();
+        universes.dedup();
+        universes
+    }
+
+    /// Copy a universe's levels into the strips it's mapped to. Returns whether
+    /// any strip changed.
+    pub fn apply(&mut self, frame: &DmxFrame) -> bool {
+        let mut applied = false;
+        for route in self.routes.iter().filter(|r| r.universe == frame.universe) {
+            let data = frame.data.get(route.channel..).unwrap_or_default();
+            let pixels = data.chunks_exact(3);
+            for (px, channels) in self.strips.span_mut(route.span).iter_mut().zip(pixels) {
+                *px = route.order.read([channels[0], channels[1], channels[2]]);
+            }
+            applied = true;
+        }
+        applied
+    }
+
+    /// The current colors of every mapped strip.
+    pub fn buffers(&self) -> impl Iterator<Item = (SocketAddr, u8, &[Rgb8])> {
+        self.strips.buffers()
+    }
+}
+
+impl Router for DmxRouter {
+    type Message = Vec<u8>;
+    type Error = DmxError;
+
+    fn route(&mut self, packet: &Vec<u8>) -> Result<bool, DmxError> {
+        Ok(parse(packet)?.is_some_and(|frame| self.apply(&frame)))
+    }
+
+    fn strips_mut(&mut self) -> &mut StripBuffers {
+        &mut self.strips
+    }
+}
+
+/// Receive DMX on every socket and forward it through a router until all the
+/// sockets fail.
+pub async fn forward(router: DmxRouter, sender: UdpSender, sockets: Vec<UdpSocket>) {
+    input::forward(router, sender, "DMX", |tx| {
+        for socket in sockets {
+            let tx = tx.clone();
+            tokio::spawn(async move {
+                let mut buf = [0u8; 1024];
+                loop {
+                    match socket.recv(&mut buf).await {
+                        Ok(n) => {
+                            if tx.send(buf[..n].to_vec()).await.is_err() {
+                                return;
+                            }
+                        }
+                        Err(e) => {
+                            eprintln!("warn: DMX socket failed: {e}");
+                            return;
+                        }
+                    }
+                }
+            });
+        }
+    })
+    .await
+}
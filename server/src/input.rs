@@ -0,0 +1,163 @@
+//! What the DMX and OPC inputs share: the strip buffers their mappings copy
+//! pixels into, and the loop that forwards changed strips to the nodes as
+//! `SetBufferToMany` commands.
+
+use std::{fmt, net::SocketAddr};
+
+use common::color::Rgb8;
+use tokio::{io, sync::mpsc};
+
+use crate::{config::NodeConfig, udp::UdpSender};
+
+/// How many messages can wait for the router before sources hold off.
+const QUEUE_LEN: usize = 64;
+
+/// Why a mapping can't be placed on a strip.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MapError {
+    /// The mapping names a node that isn't configured.
+    UnknownNode(SocketAddr),
+    /// The node has no strip at this index.
+    UnknownStrip(SocketAddr, u8),
+    /// The mapped pixels run past the end of the strip.
+    PastStrip(SocketAddr, u8),
+}
+
+/// A run of LEDs on one of a [`StripBuffers`]'s strips.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Span {
+    target: usize,
+    /// The LED the run starts at.
+    pub offset: usize,
+    pub len: usize,
+}
+
+struct StripBuffer {
+    addr: SocketAddr,
+    strip: u8,
+    colors: Vec<Rgb8>,
+    dirty: bool,
+}
+
+/// `StripBuffers` holds the colors of every strip an input is mapped onto,
+/// and which of them changed since they were last sent.
+pub struct StripBuffers {
+    strips: Vec<StripBuffer>,
+    udp_port: u16,
+}
+
+impl StripBuffers {
+    /// Buffers for strips sent to their node's IP on `udp_port`.
+    pub fn new(udp_port: u16) -> Self {
+        Self {
+            strips: Vec::new(),
+            udp_port,
+        }
+    }
+
+    /// Place `pixels` LEDs from `offset` on a node's strip, or the rest of
+    /// the strip with `None`, adding the strip's buffer if it's new.
+    pub fn map(
+        &mut self,
+        nodes: &[NodeConfig],
+        node: SocketAddr,
+        strip: u8,
+        offset: usize,
+        pixels: Option<usize>,
+    ) -> Result<Span, MapError> {
+        let config = nodes
+            .iter()
+            .find(|config| config.addr == node)
+            .ok_or(MapError::UnknownNode(node))?;
+        let &leds = config
+            .strips
+            .get(strip as usize)
+            .ok_or(MapError::UnknownStrip(node, strip))?;
+        let past_strip = pixels.is_some_and(|n| offset.checked_add(n).is_none_or(|end| end > leds));
+        if offset >= leds || past_strip {
+            return Err(MapError::PastStrip(node, strip));
+        }
+
+        let addr = SocketAddr::new(node.ip(), self.udp_port);
+        let target = match self
+            .strips
+            .iter()
+            .position(|s| s.addr == addr && s.strip == strip)
+        {
+            Some(i) => i,
+            None => {
+                self.strips.push(StripBuffer {
+                    addr,
+                    strip,
+                    colors: vec![Rgb8::zero(); leds],
+                    dirty: false,
+                });
+                self.strips.len() - 1
+            }
+        };
+        Ok(Span {
+            target,
+            offset,
+            len: pixels.unwrap_or(leds - offset),
+        })
+    }
+
+    /// The LEDs in a span, marking its strip as changed.
+    pub fn span_mut(&mut self, span: Span) -> &mut [Rgb8] {
+        let strip = &mut self.strips[span.target];
+        strip.dirty = true;
+        &mut strip.colors[span.offset..span.offset + span.len]
+    }
+
+    /// The current colors of every mapped strip.
+    pub fn buffers(&self) -> impl Iterator<Item = (SocketAddr, u8, &[Rgb8])> {
+        self.strips.iter().map(|s| (s.addr, s.strip, &s.colors[..]))
+    }
+
+    /// Send every strip changed since the last flush.
+    pub async fn flush(&mut self, sender: &UdpSender) -> io::Result<()> {
+        for strip in self.strips.iter_mut().filter(|s| s.dirty) {
+            strip.dirty = false;
+            sender
+                .set_buffer(strip.addr, strip.strip, &strip.colors)
+                .await?;
+        }
+        Ok(())
+    }
+}
+
+/// An input that copies its messages onto [`StripBuffers`].
+pub trait Router {
+    type Message: Send + 'static;
+    type Error: fmt::Display;
+
+    /// Copy a message onto the strips it's mapped to. Returns whether any
+    /// strip changed.
+    fn route(&mut self, msg: &Self::Message) -> Result<bool, Self::Error>;
+
+    fn strips_mut(&mut self) -> &mut StripBuffers;
+}
+
+/// Route the messages of every source `spawn` starts, forwarding the strips
+/// each one changes, until all the sources are gone. `name` labels warnings.
+pub async fn forward<R: Router>(
+    mut router: R,
+    sender: UdpSender,
+    name: &str,
+    spawn: impl FnOnce(mpsc::Sender<R::Message>),
+) {
+    let (tx, mut rx) = mpsc::channel(QUEUE_LEN);
+    spawn(tx);
+
+    while let Some(msg) = rx.recv().await {
+        match router.route(&msg) {
+            Ok(true) => {
+                if let Err(e) = router.strips_mut().flush(&sender).await {
+                    eprintln!("warn: failed to forward {name}: {e}");
+                }
+            }
+            Ok(false) => (),
+            Err(e) => eprintln!("warn: dropped {name} message: {e}"),
+        }
+    }
+}
@@ -0,0 +1,100 @@
+use std::{fmt, io, net::SocketAddr, path::Path};
+
+use common::{
+    layout::{LayoutError, StripLayout},
+    math::Vec3,
+};
+use serde::Deserialize;
+
+/// The physical layout of every node's strips, loaded from a TOML or JSON file.
+#[derive(Debug, Default, Deserialize)]
+pub struct LayoutFile {
+    #[serde(default)]
+    pub nodes: Vec<NodeLayout>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct NodeLayout {
+    /// The node's control address, matching its entry in the server config.
+    pub addr: SocketAddr,
+    pub strips: Vec<StripLayout>,
+}
+
+#[derive(Debug)]
+pub enum LayoutFileError {
+    Io(io::Error),
+    Toml(toml::de::Error),
+    Json(serde_json::Error),
+    /// A strip's geometry doesn't hold together.
+    Strip {
+        addr: SocketAddr,
+        strip: usize,
+        error: LayoutError,
+    },
+}
+
+impl fmt::Display for LayoutFileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "failed to read layout: {e}"),
+            Self::Toml(e) => write!(f, "failed to parse layout: {e}"),
+            Self::Json(e) => write!(f, "failed to parse layout: {e}"),
+            Self::Strip { addr, strip, error } => write!(f, "{addr} strip {strip}: {error}"),
+        }
+    }
+}
+
+impl std::error::Error for LayoutFileError {}
+
+impl LayoutFile {
+    /// Load a layout, parsing `.json` files as JSON and anything else as TOML.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, LayoutFileError> {
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(path).map_err(LayoutFileError::Io)?;
+        match path.extension() {
+            Some(ext) if ext.eq_ignore_ascii_case("json") => Self::from_json(&text),
+            _ => Self::from_toml(&text),
+        }
+    }
+
+    pub fn from_toml(text: &str) -> Result<Self, LayoutFileError> {
+        toml::from_str(text).map_err(LayoutFileError::Toml)
+    }
+
+    pub fn from_json(text: &str) -> Result<Self, LayoutFileError> {
+        serde_json::from_str(text).map_err(LayoutFileError::Json)
+    }
+
+    pub fn node(&self, addr: SocketAddr) -> Option<&NodeLayout> {
+        self.nodes.iter().find(|node| node.addr == addr)
+    }
+
+    /// Check that every strip's geometry places exactly as many LEDs as it has.
+    pub fn validate(&self) -> Result<(), LayoutFileError> {
+        self.nodes
+            .iter()
+            .try_for_each(|node| node.positions().map(|_| ()))
+    }
+}
+
+impl NodeLayout {
+    /// The world-space position of every LED, per strip.
+    pub fn positions(&self) -> Result<Vec<Vec<Vec3>>, LayoutFileError> {
+        self.strips
+            .iter()
+            .enumerate()
+            .map(|(strip, layout)| {
+                layout.positions().map_err(|error| LayoutFileError::Strip {
+                    addr: self.addr,
+                    strip,
+                    error,
+                })
+            })
+            .collect()
+    }
+
+    /// The number of LEDs on each strip.
+    pub fn leds(&self) -> Vec<usize> {
+        self.strips.iter().map(|strip| strip.leds).collect()
+    }
+}
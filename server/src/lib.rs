@@ -0,0 +1,13 @@
+pub mod clock;
+pub mod config;
+pub mod console;
+pub mod ddp;
+pub mod discovery;
+pub mod dmx;
+pub mod input;
+pub mod layout;
+pub mod node;
+pub mod opc;
+pub mod ota;
+pub mod spatial;
+pub mod udp;
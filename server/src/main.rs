@@ -1,4 +1,181 @@
+use std::net::Ipv4Addr;
+
+use common::{
+    net::{DDP_PORT, UDP_PORT},
+    preview::Preview,
+    provision::ap_password,
+};
+use server::{
+    config::{Config, DmxConfig},
+    console::command,
+    ddp::DdpSender,
+    discovery,
+    dmx::{self, ARTNET_PORT, DmxRouter, SACN_PORT},
+    layout::LayoutFile,
+    node::NodeRegistry,
+    opc::{self, OpcRouter},
+    spatial::{Output, OutputProtocol, SpatialRenderer},
+    udp::UdpSender,
+};
+use tokio::{
+    io::{AsyncBufReadExt, BufReader},
+    net::{TcpListener, UdpSocket},
+    sync::watch,
+};
+
+const DEFAULT_CONFIG_PATH: &str = "lightspace.toml";
+
 #[tokio::main]
 async fn main() {
-    println!("Hello, world!");
+    let mut path = DEFAULT_CONFIG_PATH.to_string();
+    let mut preview = None;
+    for arg in std::env::args().skip(1) {
+        match arg.as_str() {
+            "--preview" => preview = Some(Preview::default()),
+            _ => path = arg,
+        }
+    }
+
+    let config = match Config::load(&path) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("warn: {path}: {e}, starting with no nodes");
+            Config::default()
+        }
+    };
+
+    let layout = match &config.layout {
+        Some(path) => LayoutFile::load(path).and_then(|layout| {
+            layout.validate()?;
+            Ok(layout)
+        }),
+        None => Ok(LayoutFile::default()),
+    }
+    .unwrap_or_else(|e| {
+        eprintln!("warn: {e}, starting with no layout");
+        LayoutFile::default()
+    });
+
+    if let Some(key) = &config.key {
+        println!("authenticating traffic to nodes with the installation key");
+        println!(
+            "nodes' setup networks use the password {}",
+            ap_password(key)
+        );
+    }
+    let registry = NodeRegistry::with_key(config.key.clone());
+    for node in &config.nodes {
+        let id = registry.add(node.addr, &node.strips);
+        println!("registered node {id} at {}", node.addr);
+        match layout.node(node.addr) {
+            Some(layout) if layout.leds() != node.strips => {
+                eprintln!("warn: layout for {} doesn't match its strips", node.addr);
+            }
+            Some(_) => (),
+            None if config.layout.is_some() => {
+                eprintln!("warn: no layout for {}", node.addr);
+            }
+            None => (),
+        }
+    }
+
+    if config.discovery.enabled {
+        let port = config.discovery.port;
+        match UdpSocket::bind((Ipv4Addr::UNSPECIFIED, port)).await {
+            Ok(socket) => {
+                println!("listening for node beacons on port {port}");
+                tokio::spawn(discovery::listen(socket, registry.clone()));
+            }
+            Err(e) => eprintln!("warn: failed to bind discovery port: {e}, discovery disabled"),
+        }
+    }
+
+    let (effect, effect_rx) = watch::channel(None);
+    let (output, port) = match config.output {
+        OutputProtocol::Lightspace => (
+            UdpSender::bind()
+                .await
+                .map(|sender| Output::Lightspace(sender.with_keys(registry.stream_keys()))),
+            UDP_PORT,
+        ),
+        OutputProtocol::Ddp => (DdpSender::bind().await.map(Output::Ddp), DDP_PORT),
+    };
+    match (SpatialRenderer::from_layout(&layout, port), output) {
+        (Ok(renderer), Ok(Output::Ddp(_))) if let Some((addr, strip)) = renderer.ddp_gap() => {
+            eprintln!("warn: {addr} has no strip {strip} for DDP, spatial effects disabled");
+        }
+        (Ok(renderer), Ok(output)) => {
+            tokio::spawn(server::spatial::stream(
+                renderer, output, effect_rx, preview,
+            ));
+        }
+        (Err(e), _) => eprintln!("warn: {e}, spatial effects disabled"),
+        (_, Err(e)) => eprintln!("warn: failed to bind UDP socket: {e}, spatial effects disabled"),
+    }
+
+    if config.dmx.artnet || config.dmx.sacn {
+        match DmxRouter::new(&config.dmx.map, &config.nodes, UDP_PORT) {
+            Ok(router) => {
+                let sockets = bind_dmx(&config.dmx, &router).await;
+                match UdpSender::bind().await {
+                    Ok(sender) => {
+                        let sender = sender.with_keys(registry.stream_keys());
+                        _ = tokio::spawn(dmx::forward(router, sender, sockets));
+                    }
+                    Err(e) => eprintln!("warn: failed to bind UDP socket: {e}, DMX disabled"),
+                }
+            }
+            Err(e) => eprintln!("warn: {e}, DMX disabled"),
+        }
+    }
+
+    if let Some(addr) = config.opc.listen {
+        match OpcRouter::new(&config.opc.map, &config.nodes, UDP_PORT) {
+            Ok(router) => match (TcpListener::bind(addr).await, UdpSender::bind().await) {
+                (Ok(listener), Ok(sender)) => {
+                    println!("accepting OPC on {addr}");
+                    let sender = sender.with_keys(registry.stream_keys());
+                    tokio::spawn(opc::serve(listener, router, sender));
+                }
+                (Err(e), _) => eprintln!("warn: failed to bind OPC port: {e}, OPC disabled"),
+                (_, Err(e)) => eprintln!("warn: failed to bind UDP socket: {e}, OPC disabled"),
+            },
+            Err(e) => eprintln!("warn: {e}, OPC disabled"),
+        }
+    }
+
+    let mut lines = BufReader::new(tokio::io::stdin()).lines();
+    while let Ok(Some(line)) = lines.next_line().await {
+        if let Err(e) = command(&registry, &effect, &line) {
+            eprintln!("error: {e}");
+        }
+    }
+}
+
+/// Bind the sockets for each enabled DMX protocol, joining the sACN multicast
+/// group of every mapped universe.
+async fn bind_dmx(config: &DmxConfig, router: &DmxRouter) -> Vec<UdpSocket> {
+    let mut sockets = Vec::new();
+    if config.artnet {
+        match UdpSocket::bind((Ipv4Addr::UNSPECIFIED, ARTNET_PORT)).await {
+            Ok(socket) => sockets.push(socket),
+            Err(e) => eprintln!("warn: failed to bind Art-Net port: {e}"),
+        }
+    }
+    if config.sacn {
+        match UdpSocket::bind((Ipv4Addr::UNSPECIFIED, SACN_PORT)).await {
+            Ok(socket) => {
+                for universe in router.universes() {
+                    if let Err(e) =
+                        socket.join_multicast_v4(dmx::sacn_group(universe), Ipv4Addr::UNSPECIFIED)
+                    {
+                        eprintln!("warn: failed to join sACN universe {universe}: {e}");
+                    }
+                }
+                sockets.push(socket);
+            }
+            Err(e) => eprintln!("warn: failed to bind sACN port: {e}"),
+        }
+    }
+    sockets
 }
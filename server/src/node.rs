@@ -0,0 +1,771 @@
+use std::{
+    fmt,
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use common::{
+    color::BlendMode,
+    effect::{
+        fx::{MAX_OVERLAYS, OverlayConfig},
+        param::{self, ParamError, ParamValue},
+        registry,
+        transition::{Transition, TransitionKind},
+    },
+    net::{
+        Heartbeat, KEEP_ALIVE_INTERVAL_MS, MAX_FRAME_LEN, NodeError, NodeHello, NodeMessage,
+        SESSION_TIMEOUT_MS, ServerMessage, StripConfig, StripMode,
+        auth::{Key, Sealer},
+        decode_frame, encode_frame,
+        failsafe::{DEFAULT_TIMEOUT, StreamTimeout},
+    },
+    ota::UpdateStatus,
+};
+use tokio::{
+    io::{self, AsyncBufRead, AsyncBufReadExt, AsyncWrite, AsyncWriteExt, BufReader},
+    net::{TcpStream, tcp::OwnedWriteHalf},
+    sync::{broadcast, mpsc},
+    time::{self, MissedTickBehavior},
+};
+
+use crate::{clock, udp::StreamKeys};
+
+/// How long to wait before the first reconnection attempt to a dropped node.
+const RECONNECT_MIN: Duration = Duration::from_millis(500);
+/// The upper bound on the reconnection backoff.
+const RECONNECT_MAX: Duration = Duration::from_secs(10);
+
+/// Identifies a node in a [`NodeRegistry`].
+pub type NodeId = usize;
+
+/// The server's view of a single strip on a node.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StripStatus {
+    pub leds: usize,
+    pub mode: StripMode,
+    /// Set with [`NodeRegistry::set_stream_timeout`], and the node's
+    /// default until then.
+    pub stream_timeout: Option<StreamTimeout>,
+    /// Set once the node accepts a [`NodeRegistry::configure_strip`], or
+    /// `None` to leave the node's own wiring alone.
+    pub config: Option<StripConfig>,
+    /// Every overlay added with [`NodeRegistry::add_overlay`], bottom first.
+    pub overlays: Vec<OverlayConfig>,
+}
+
+/// The server's view of a node.
+#[derive(Debug, Clone, PartialEq)]
+pub struct NodeStatus {
+    pub addr: SocketAddr,
+    /// The id from the node's beacon, once one has arrived (see
+    /// [`crate::discovery`]).
+    pub beacon_id: Option<[u8; 6]>,
+    pub strips: Vec<StripStatus>,
+    pub connected: bool,
+    /// The node's handshake, once it has sent one.
+    pub hello: Option<NodeHello>,
+    /// The most recent telemetry from the node.
+    pub heartbeat: Option<Heartbeat>,
+    /// The most recent error reported by the node.
+    pub last_error: Option<NodeError>,
+    /// The registry effect selected with [`NodeRegistry::select_effect`], and
+    /// moved by any [`NodeRegistry::shift_effect_mode`] since, if any.
+    pub effect: Option<u8>,
+    /// Every `(effect, param, value)` set with [`NodeRegistry::set_effect_param`].
+    pub params: Vec<(u8, u8, ParamValue)>,
+    /// The most recent firmware update status from the node (see [`crate::ota`]).
+    pub update: Option<UpdateStatus>,
+}
+
+/// A message received from a node, as seen by [`NodeRegistry::subscribe`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct NodeEvent {
+    pub id: NodeId,
+    pub msg: NodeMessage,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RegistryError {
+    /// No node is registered under this id.
+    UnknownNode(NodeId),
+    /// The node has no strip at this index.
+    UnknownStrip(NodeId, u8),
+    /// The node's session task has stopped.
+    SessionClosed(NodeId),
+    /// No effect is registered under this id.
+    UnknownEffect(u8),
+    /// The value can't be set on the effect's parameter.
+    InvalidParam(u8, ParamError),
+    /// The strip already has [`MAX_OVERLAYS`].
+    TooManyOverlays(NodeId, u8),
+    /// An overlay's opacity isn't from 0 to 1.
+    InvalidOpacity,
+}
+
+impl fmt::Display for RegistryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnknownNode(id) => write!(f, "unknown node {id}"),
+            Self::UnknownStrip(id, strip) => write!(f, "node {id} has no strip {strip}"),
+            Self::SessionClosed(id) => write!(f, "session to node {id} is closed"),
+            Self::UnknownEffect(effect) => write!(f, "unknown effect {effect}"),
+            Self::InvalidParam(effect, e) => write!(f, "effect {effect}: {e}"),
+            Self::TooManyOverlays(id, strip) => {
+                write!(
+                    f,
+                    "node {id} strip {strip} has {MAX_OVERLAYS} overlays already"
+                )
+            }
+            Self::InvalidOpacity => write!(f, "opacity must be from 0 to 1"),
+        }
+    }
+}
+
+impl std::error::Error for RegistryError {}
+
+struct Node {
+    status: NodeStatus,
+    tx: mpsc::UnboundedSender<ServerMessage>,
+}
+
+/// `NodeRegistry` keeps track of every known node and owns a TCP control
+/// session to each of them.
+///
+/// Sessions reconnect on their own when a node drops. Every time a session
+/// (re)connects, the registry's strip modes and effect settings are replayed to the node so that
+/// it always matches what the server believes.
+#[derive(Clone)]
+pub struct NodeRegistry {
+    nodes: Arc<Mutex<Vec<Node>>>,
+    events: broadcast::Sender<NodeEvent>,
+    /// Seals every session to a node holding the installation's key.
+    key: Option<Key>,
+    /// The stream key of every sealed session.
+    stream_keys: StreamKeys,
+}
+
+impl Default for NodeRegistry {
+    fn default() -> Self {
+        Self {
+            nodes: Default::default(),
+            events: broadcast::channel(64).0,
+            key: None,
+            stream_keys: StreamKeys::default(),
+        }
+    }
+}
+
+impl NodeRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A registry that authenticates its sessions with the installation's
+    /// key, if there is one (see [`common::net::auth`]).
+    pub fn with_key(key: Option<Key>) -> Self {
+        Self {
+            key,
+            ..Self::default()
+        }
+    }
+
+    /// The keys to sign packets streamed to nodes with, kept up to date as
+    /// sealed sessions open and close.
+    pub fn stream_keys(&self) -> StreamKeys {
+        self.stream_keys.clone()
+    }
+
+    /// Receive every message sent by any node from now on.
+    pub fn subscribe(&self) -> broadcast::Receiver<NodeEvent> {
+        self.events.subscribe()
+    }
+
+    /// Register a node and spawn its control session.
+    ///
+    /// Must be called from within a Tokio runtime.
+    pub fn add(&self, addr: SocketAddr, strips: &[usize]) -> NodeId {
+        let (tx, rx) = mpsc::unbounded_channel();
+        let status = NodeStatus {
+            addr,
+            beacon_id: None,
+            strips: strips
+                .iter()
+                .map(|&leds| StripStatus {
+                    leds,
+                    mode: StripMode::default(),
+                    stream_timeout: Some(DEFAULT_TIMEOUT),
+                    config: None,
+                    overlays: Vec::new(),
+                })
+                .collect(),
+            connected: false,
+            hello: None,
+            heartbeat: None,
+            last_error: None,
+            effect: None,
+            params: Vec::new(),
+            update: None,
+        };
+
+        let id = {
+            let mut nodes = self.nodes.lock().unwrap();
+            nodes.push(Node { status, tx });
+            nodes.len() - 1
+        };
+
+        tokio::spawn(session(self.clone(), id, rx));
+        id
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodes.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// A snapshot of a node's current status.
+    pub fn get(&self, id: NodeId) -> Option<NodeStatus> {
+        self.nodes
+            .lock()
+            .unwrap()
+            .get(id)
+            .map(|node| node.status.clone())
+    }
+
+    /// The node with this control address, if it's registered.
+    pub fn find(&self, addr: SocketAddr) -> Option<NodeId> {
+        self.nodes
+            .lock()
+            .unwrap()
+            .iter()
+            .position(|node| node.status.addr == addr)
+    }
+
+    /// The node that sent beacons with this id, if it's registered.
+    pub fn find_beacon(&self, beacon_id: [u8; 6]) -> Option<NodeId> {
+        self.nodes
+            .lock()
+            .unwrap()
+            .iter()
+            .position(|node| node.status.beacon_id == Some(beacon_id))
+    }
+
+    /// Remember the id a node's beacons carry.
+    pub fn set_beacon_id(&self, id: NodeId, beacon_id: [u8; 6]) {
+        if let Some(node) = self.nodes.lock().unwrap().get_mut(id) {
+            node.status.beacon_id = Some(beacon_id);
+        }
+    }
+
+    /// Move a node to a new control address, like after its DHCP lease
+    /// changed. Its session connects there from its next attempt on.
+    pub fn set_addr(&self, id: NodeId, addr: SocketAddr) {
+        if let Some(node) = self.nodes.lock().unwrap().get_mut(id) {
+            node.status.addr = addr;
+        }
+    }
+
+    /// A snapshot of every node's current status, indexed by [`NodeId`].
+    pub fn nodes(&self) -> Vec<NodeStatus> {
+        self.nodes
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|node| node.status.clone())
+            .collect()
+    }
+
+    /// Set a strip's mode, both in the registry and on the node.
+    pub fn set_strip_mode(
+        &self,
+        id: NodeId,
+        strip: u8,
+        mode: StripMode,
+    ) -> Result<(), RegistryError> {
+        let mut nodes = self.nodes.lock().unwrap();
+        let node = nodes.get_mut(id).ok_or(RegistryError::UnknownNode(id))?;
+        let status = node
+            .status
+            .strips
+            .get_mut(strip as usize)
+            .ok_or(RegistryError::UnknownStrip(id, strip))?;
+
+        status.mode = mode;
+        node.tx
+            .send(ServerMessage::SetStripMode(strip, mode))
+            .map_err(|_| RegistryError::SessionClosed(id))
+    }
+
+    /// Set how long a strip waits for streamed colors before falling back,
+    /// both in the registry and on the node.
+    pub fn set_stream_timeout(
+        &self,
+        id: NodeId,
+        strip: u8,
+        timeout: Option<StreamTimeout>,
+    ) -> Result<(), RegistryError> {
+        let mut nodes = self.nodes.lock().unwrap();
+        let node = nodes.get_mut(id).ok_or(RegistryError::UnknownNode(id))?;
+        let status = node
+            .status
+            .strips
+            .get_mut(strip as usize)
+            .ok_or(RegistryError::UnknownStrip(id, strip))?;
+
+        status.stream_timeout = timeout;
+        node.tx
+            .send(ServerMessage::SetStreamTimeout(strip, timeout))
+            .map_err(|_| RegistryError::SessionClosed(id))
+    }
+
+    /// Rewire a strip on the node. The registry keeps the config, and takes
+    /// its LED count, once the node acknowledges it, so a config the node
+    /// refuses isn't replayed on every reconnect.
+    pub fn configure_strip(
+        &self,
+        id: NodeId,
+        strip: u8,
+        config: StripConfig,
+    ) -> Result<(), RegistryError> {
+        let nodes = self.nodes.lock().unwrap();
+        let node = nodes.get(id).ok_or(RegistryError::UnknownNode(id))?;
+        if node.status.strips.get(strip as usize).is_none() {
+            return Err(RegistryError::UnknownStrip(id, strip));
+        }
+
+        node.tx
+            .send(ServerMessage::ConfigureStrip(strip, config))
+            .map_err(|_| RegistryError::SessionClosed(id))
+    }
+
+    /// Shift the node's current effect mode by a delta, with a transition or
+    /// the node's default one.
+    pub fn shift_effect_mode(
+        &self,
+        id: NodeId,
+        delta: i8,
+        transition: Option<Transition>,
+    ) -> Result<(), RegistryError> {
+        let mut nodes = self.nodes.lock().unwrap();
+        let node = nodes.get_mut(id).ok_or(RegistryError::UnknownNode(id))?;
+        // nodes run every registered effect, and wrap around the same way
+        if let Some(effect) = &mut node.status.effect {
+            *effect =
+                (*effect as isize + delta as isize).rem_euclid(registry::COUNT as isize) as u8;
+        }
+        node.tx
+            .send(ServerMessage::ShiftEffectMode(delta, transition))
+            .map_err(|_| RegistryError::SessionClosed(id))
+    }
+
+    /// Switch the node to a registered effect, with a transition or the node's
+    /// default one.
+    pub fn select_effect(
+        &self,
+        id: NodeId,
+        effect: u8,
+        transition: Option<Transition>,
+    ) -> Result<(), RegistryError> {
+        if effect as usize >= registry::COUNT {
+            return Err(RegistryError::UnknownEffect(effect));
+        }
+
+        let mut nodes = self.nodes.lock().unwrap();
+        let node = nodes.get_mut(id).ok_or(RegistryError::UnknownNode(id))?;
+        node.status.effect = Some(effect);
+        node.tx
+            .send(ServerMessage::SelectEffect(effect, transition))
+            .map_err(|_| RegistryError::SessionClosed(id))
+    }
+
+    /// Set one of a registered effect's parameters on the node, whether or not
+    /// the effect is currently running.
+    pub fn set_effect_param(
+        &self,
+        id: NodeId,
+        effect: u8,
+        param: u8,
+        value: ParamValue,
+    ) -> Result<(), RegistryError> {
+        let info = registry::EFFECTS
+            .get(effect as usize)
+            .ok_or(RegistryError::UnknownEffect(effect))?;
+        param::check(info.params, param, value)
+            .map_err(|e| RegistryError::InvalidParam(effect, e))?;
+
+        let mut nodes = self.nodes.lock().unwrap();
+        let node = nodes.get_mut(id).ok_or(RegistryError::UnknownNode(id))?;
+        let params = &mut node.status.params;
+        match params
+            .iter_mut()
+            .find(|(e, p, _)| (*e, *p) == (effect, param))
+        {
+            Some(entry) => entry.2 = value,
+            None => params.push((effect, param, value)),
+        }
+        node.tx
+            .send(ServerMessage::SetEffectParam(effect, param, value))
+            .map_err(|_| RegistryError::SessionClosed(id))
+    }
+
+    /// Layer a registered effect over one of the node's strips, above any
+    /// overlays it has.
+    pub fn add_overlay(
+        &self,
+        id: NodeId,
+        strip: u8,
+        effect: u8,
+        mode: BlendMode,
+        opacity: f32,
+    ) -> Result<(), RegistryError> {
+        if effect as usize >= registry::COUNT {
+            return Err(RegistryError::UnknownEffect(effect));
+        }
+        if !(0.0..=1.0).contains(&opacity) {
+            return Err(RegistryError::InvalidOpacity);
+        }
+
+        let mut nodes = self.nodes.lock().unwrap();
+        let node = nodes.get_mut(id).ok_or(RegistryError::UnknownNode(id))?;
+        let status = node
+            .status
+            .strips
+            .get_mut(strip as usize)
+            .ok_or(RegistryError::UnknownStrip(id, strip))?;
+        if status.overlays.len() >= MAX_OVERLAYS {
+            return Err(RegistryError::TooManyOverlays(id, strip));
+        }
+
+        status.overlays.push(OverlayConfig {
+            effect,
+            mode,
+            opacity,
+        });
+        node.tx
+            .send(ServerMessage::SetOverlays(strip, status.overlays.clone()))
+            .map_err(|_| RegistryError::SessionClosed(id))
+    }
+
+    /// Remove every overlay from one of the node's strips.
+    pub fn clear_overlays(&self, id: NodeId, strip: u8) -> Result<(), RegistryError> {
+        let mut nodes = self.nodes.lock().unwrap();
+        let node = nodes.get_mut(id).ok_or(RegistryError::UnknownNode(id))?;
+        let status = node
+            .status
+            .strips
+            .get_mut(strip as usize)
+            .ok_or(RegistryError::UnknownStrip(id, strip))?;
+
+        status.overlays.clear();
+        node.tx
+            .send(ServerMessage::SetOverlays(strip, Vec::new()))
+            .map_err(|_| RegistryError::SessionClosed(id))
+    }
+
+    /// Queue a raw message on a node's session.
+    pub fn send(&self, id: NodeId, msg: ServerMessage) -> Result<(), RegistryError> {
+        let nodes = self.nodes.lock().unwrap();
+        let node = nodes.get(id).ok_or(RegistryError::UnknownNode(id))?;
+        node.tx
+            .send(msg)
+            .map_err(|_| RegistryError::SessionClosed(id))
+    }
+
+    fn set_connected(&self, id: NodeId, connected: bool) {
+        if let Some(node) = self.nodes.lock().unwrap().get_mut(id) {
+            node.status.connected = connected;
+        }
+    }
+
+    /// Record a message from a node and pass it on to subscribers.
+    fn handle(&self, id: NodeId, msg: NodeMessage) {
+        if let Some(node) = self.nodes.lock().unwrap().get_mut(id) {
+            let addr = node.status.addr;
+            match msg {
+                NodeMessage::Hello(hello) => {
+                    println!(
+                        "node {id} ({addr}): firmware {}.{}.{} on {:?} with {} strip(s)",
+                        hello.version.major,
+                        hello.version.minor,
+                        hello.version.patch,
+                        hello.mcu,
+                        hello.num_strips
+                    );
+                    node.status.hello = Some(hello);
+                }
+                NodeMessage::Strip(i, info) => {
+                    let strips = &mut node.status.strips;
+                    if let Some(strip) = strips.get_mut(i as usize) {
+                        strip.leds = info.leds;
+                    } else if i as usize == strips.len() {
+                        // a strip the config didn't know about, bring it in line
+                        let mode = StripMode::default();
+                        strips.push(StripStatus {
+                            leds: info.leds,
+                            mode,
+                            stream_timeout: Some(DEFAULT_TIMEOUT),
+                            config: None,
+                            overlays: Vec::new(),
+                        });
+                        _ = node.tx.send(ServerMessage::SetStripMode(i, mode));
+                    } else {
+                        println!("node {id} ({addr}): ignoring out of order strip {i}");
+                    }
+                }
+                NodeMessage::Heartbeat(heartbeat) => node.status.heartbeat = Some(heartbeat),
+                NodeMessage::Error(error) => {
+                    println!("node {id} ({addr}): reported error: {error:?}");
+                    node.status.last_error = Some(error);
+                }
+                // answered as soon as they're read, in `run_session`
+                NodeMessage::TimeRequest(_) | NodeMessage::Challenge(_) => (),
+                NodeMessage::StripConfigured(i, config) => {
+                    if let Some(strip) = node.status.strips.get_mut(i as usize) {
+                        strip.leds = config.leds;
+                        strip.config = Some(config);
+                    }
+                }
+                NodeMessage::Update(status) => {
+                    // progress and failures are reported by `ota::push`
+                    match status {
+                        UpdateStatus::Verified => {
+                            println!("node {id} ({addr}): update verified, restarting")
+                        }
+                        UpdateStatus::Confirmed => println!("node {id} ({addr}): update confirmed"),
+                        _ => (),
+                    }
+                    node.status.update = Some(status);
+                }
+            }
+        }
+
+        // no subscribers is fine
+        _ = self.events.send(NodeEvent { id, msg });
+    }
+
+    /// Messages that bring a freshly connected node in line with the registry.
+    fn replay(&self, id: NodeId) -> Vec<ServerMessage> {
+        let Some(status) = self.get(id) else {
+            return Vec::new();
+        };
+
+        let configs =
+            status.strips.iter().enumerate().filter_map(|(i, strip)| {
+                Some(ServerMessage::ConfigureStrip(i as u8, strip.config?))
+            });
+        let modes = status
+            .strips
+            .iter()
+            .enumerate()
+            .map(|(i, strip)| ServerMessage::SetStripMode(i as u8, strip.mode));
+        // nodes start with the default, so only the rest need sending
+        let timeouts = status
+            .strips
+            .iter()
+            .enumerate()
+            .filter(|(_, strip)| strip.stream_timeout != Some(DEFAULT_TIMEOUT))
+            .map(|(i, strip)| ServerMessage::SetStreamTimeout(i as u8, strip.stream_timeout));
+        let params = status
+            .params
+            .iter()
+            .map(|&(effect, param, value)| ServerMessage::SetEffectParam(effect, param, value));
+        // put back as it was, rather than transitioned to
+        let instant = Transition::new(TransitionKind::Crossfade, 0);
+        let effect = status
+            .effect
+            .map(|effect| ServerMessage::SelectEffect(effect, Some(instant)));
+        let overlays = status
+            .strips
+            .iter()
+            .enumerate()
+            .filter(|(_, strip)| !strip.overlays.is_empty())
+            .map(|(i, strip)| ServerMessage::SetOverlays(i as u8, strip.overlays.clone()));
+        configs
+            .chain(modes)
+            .chain(timeouts)
+            .chain(params)
+            .chain(effect)
+            .chain(overlays)
+            .collect()
+    }
+}
+
+/// Keep a control session open to a node for as long as its message channel
+/// is open, at whatever address the registry has for it.
+async fn session(
+    registry: NodeRegistry,
+    id: NodeId,
+    mut rx: mpsc::UnboundedReceiver<ServerMessage>,
+) {
+    let mut backoff = RECONNECT_MIN;
+    loop {
+        let Some(addr) = registry.get(id).map(|status| status.addr) else {
+            return;
+        };
+        match TcpStream::connect(addr).await {
+            Ok(stream) => {
+                println!("node {id} ({addr}): connected");
+                registry.set_connected(id, true);
+                backoff = RECONNECT_MIN;
+
+                let result = run_session(&registry, id, stream, &mut rx).await;
+                registry.set_connected(id, false);
+                registry.stream_keys.remove(addr.ip());
+                match result {
+                    Ok(()) => return,
+                    Err(e) => println!("node {id} ({addr}): session dropped: {e}"),
+                }
+            }
+            Err(e) => println!("node {id} ({addr}): failed to connect: {e}"),
+        }
+
+        if rx.is_closed() {
+            return;
+        }
+
+        time::sleep(backoff).await;
+        backoff = (backoff * 2).min(RECONNECT_MAX);
+    }
+}
+
+/// Drive a connected session until it fails (`Err`) or its message channel closes (`Ok`).
+async fn run_session(
+    registry: &NodeRegistry,
+    id: NodeId,
+    stream: TcpStream,
+    rx: &mut mpsc::UnboundedReceiver<ServerMessage>,
+) -> io::Result<()> {
+    stream.set_nodelay(true)?;
+    let peer = stream.peer_addr()?.ip();
+    let (reader, writer) = stream.into_split();
+    let mut reader = BufReader::new(reader);
+    let mut writer = SessionWriter {
+        writer,
+        sealer: None,
+    };
+
+    // a node holding the key sends its challenge right after its hello, and
+    // has to have it before anything else goes out
+    let mut pending = None;
+    if let Some(key) = &registry.key {
+        loop {
+            let msg = time::timeout(
+                Duration::from_millis(SESSION_TIMEOUT_MS),
+                read_message(id, &mut reader),
+            )
+            .await
+            .map_err(|_| io::ErrorKind::TimedOut)??;
+            match msg {
+                NodeMessage::Challenge(nonce) => {
+                    writer.sealer = Some(Sealer::new(key.clone(), nonce));
+                    registry.stream_keys.insert(peer, key.for_session(nonce));
+                    break;
+                }
+                // the end of the handshake, without a challenge
+                NodeMessage::TimeRequest(_) => {
+                    println!("warn: node {id} has no key, its session isn't authenticated");
+                    pending = Some(msg);
+                    break;
+                }
+                msg => registry.handle(id, msg),
+            }
+        }
+    }
+
+    for msg in registry.replay(id) {
+        writer.send(&msg).await?;
+    }
+    if let Some(msg) = pending {
+        on_message(registry, id, &mut writer, msg).await?;
+    }
+
+    let mut keep_alive = time::interval(Duration::from_millis(KEEP_ALIVE_INTERVAL_MS));
+    keep_alive.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    keep_alive.reset();
+
+    let mut frame = Vec::new();
+    loop {
+        tokio::select! {
+            msg = rx.recv() => match msg {
+                Some(msg) => writer.send(&msg).await?,
+                None => return Ok(()),
+            },
+            _ = keep_alive.tick() => writer.send(&ServerMessage::KeepAlive).await?,
+            n = reader.read_until(0, &mut frame) => {
+                if n? == 0 {
+                    return Err(io::ErrorKind::UnexpectedEof.into());
+                }
+                match decode_frame::<NodeMessage>(&mut frame) {
+                    Ok(msg) => on_message(registry, id, &mut writer, msg).await?,
+                    Err(e) => println!("node {id}: dropped malformed frame: {e}"),
+                }
+                frame.clear();
+            }
+        }
+    }
+}
+
+/// Answer a message from the node if it needs it, and record it.
+async fn on_message(
+    registry: &NodeRegistry,
+    id: NodeId,
+    writer: &mut SessionWriter,
+    msg: NodeMessage,
+) -> io::Result<()> {
+    if let NodeMessage::TimeRequest(sent) = msg {
+        writer
+            .send(&ServerMessage::TimeResponse(sent, clock::now()))
+            .await?;
+    }
+    registry.handle(id, msg);
+    Ok(())
+}
+
+/// Read the node's next message, skipping malformed frames.
+async fn read_message<R: AsyncBufRead + Unpin>(
+    id: NodeId,
+    reader: &mut R,
+) -> io::Result<NodeMessage> {
+    let mut frame = Vec::new();
+    loop {
+        if reader.read_until(0, &mut frame).await? == 0 {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        match decode_frame::<NodeMessage>(&mut frame) {
+            Ok(msg) => return Ok(msg),
+            Err(e) => println!("node {id}: dropped malformed frame: {e}"),
+        }
+        frame.clear();
+    }
+}
+
+/// The server's half of a session, sealing every frame once the node has
+/// sent its challenge.
+struct SessionWriter {
+    writer: OwnedWriteHalf,
+    sealer: Option<Sealer>,
+}
+
+impl SessionWriter {
+    async fn send(&mut self, msg: &ServerMessage) -> io::Result<()> {
+        let Some(sealer) = &mut self.sealer else {
+            return write_frame(&mut self.writer, msg).await;
+        };
+        let mut buf = [0u8; MAX_FRAME_LEN];
+        let frame = sealer.seal(msg, &mut buf).map_err(io::Error::other)?;
+        self.writer.write_all(frame).await
+    }
+}
+
+/// Write a single COBS-framed postcard message.
+pub async fn write_frame<W: AsyncWrite + Unpin>(
+    writer: &mut W,
+    msg: &ServerMessage,
+) -> io::Result<()> {
+    let mut buf = [0u8; MAX_FRAME_LEN];
+    let frame = encode_frame(msg, &mut buf).map_err(io::Error::other)?;
+    writer.write_all(frame).await
+}
@@ -0,0 +1,328 @@
+//! Open Pixel Control input, so generative-art tools like Processing sketches
+//! and fadecandy clients can drive strips over TCP.
+//!
+//! Each message is a 4-byte header (channel, command, big-endian data length)
+//! followed by its data. Channel 0 addresses every channel. Pixels are copied
+//! onto strips by the config's mapping table and forwarded whole, like DMX
+//! (see [`crate::input`]).
+
+use std::{fmt, net::SocketAddr};
+
+use common::color::Rgb8;
+use serde::Deserialize;
+use tokio::{
+    io::{self, AsyncRead, AsyncReadExt},
+    net::TcpListener,
+};
+
+use crate::{
+    config::{NodeConfig, OpcMapping},
+    input::{self, MapError, Router, Span, StripBuffers},
+    udp::UdpSender,
+};
+
+/// The port OPC clients connect to by default.
+pub const OPC_PORT: u16 = 7890;
+
+/// The channel that addresses every channel at once.
+pub const BROADCAST: u8 = 0;
+
+pub const CMD_SET_PIXELS: u8 = 0;
+pub const CMD_SYSTEM_EXCLUSIVE: u8 = 255;
+
+/// The system id fadecandy uses for its system-exclusive messages.
+pub const SYSTEM_FADECANDY: u16 = 0x0001;
+/// Fadecandy's color correction command, carrying a JSON object.
+pub const SYSEX_COLOR_CORRECTION: u16 = 0x0001;
+
+/// One OPC message, as read off a connection.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OpcMessage {
+    pub channel: u8,
+    pub command: u8,
+    pub data: Vec<u8>,
+}
+
+impl OpcMessage {
+    /// Read the next message, or `None` once the client disconnects between
+    /// messages.
+    pub async fn read(reader: &mut (impl AsyncRead + Unpin)) -> io::Result<Option<Self>> {
+        let mut header = [0u8; 4];
+        match reader.read_exact(&mut header).await {
+            Ok(_) => (),
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+            Err(e) => return Err(e),
+        }
+
+        let len = u16::from_be_bytes([header[2], header[3]]) as usize;
+        let mut data = vec![0u8; len];
+        reader.read_exact(&mut data).await?;
+        Ok(Some(Self {
+            channel: header[0],
+            command: header[1],
+            data,
+        }))
+    }
+
+    /// Encode the message, header first.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = vec![self.channel, self.command];
+        bytes.extend((self.data.len() as u16).to_be_bytes());
+        bytes.extend(&self.data);
+        bytes
+    }
+}
+
+/// Fadecandy-style color correction, applied to a channel's pixels before
+/// they're sent. Nodes apply their own gamma on top, so the default is none.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct ColorCorrection {
+    pub gamma: f32,
+    /// Scales each of red, green and blue.
+    pub whitepoint: [f32; 3],
+}
+
+impl Default for ColorCorrection {
+    fn default() -> Self {
+        Self {
+            gamma: 1.0,
+            whitepoint: [1.0; 3],
+        }
+    }
+}
+
+impl ColorCorrection {
+    pub fn apply(&self, color: Rgb8) -> Rgb8 {
+        let channel = |value: u8, scale: f32| {
+            let linear = (value as f32 / 255.0).powf(self.gamma) * scale;
+            (linear.clamp(0.0, 1.0) * 255.0).round() as u8
+        };
+        Rgb8::new(
+            channel(color.r, self.whitepoint[0]),
+            channel(color.g, self.whitepoint[1]),
+            channel(color.b, self.whitepoint[2]),
+        )
+    }
+}
+
+#[derive(Debug)]
+pub enum OpcError {
+    /// A color correction message whose JSON couldn't be read.
+    BadCorrection(serde_json::Error),
+    /// A system-exclusive message too short to hold its ids.
+    Truncated,
+}
+
+impl fmt::Display for OpcError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::BadCorrection(e) => write!(f, "bad OPC color correction: {e}"),
+            Self::Truncated => write!(f, "truncated OPC system-exclusive message"),
+        }
+    }
+}
+
+impl std::error::Error for OpcError {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OpcRouteError {
+    /// The mapping names a node that isn't configured.
+    UnknownNode(SocketAddr),
+    /// The node has no strip at this index.
+    UnknownStrip(SocketAddr, u8),
+    /// Mappings can't use the broadcast channel.
+    BadChannel(u8),
+    /// The mapped pixels run past the end of the strip.
+    PastStrip(SocketAddr, u8),
+}
+
+impl fmt::Display for OpcRouteError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnknownNode(addr) => write!(f, "OPC mapping for unknown node {addr}"),
+            Self::UnknownStrip(addr, strip) => write!(f, "node {addr} has no strip {strip}"),
+            Self::BadChannel(channel) => write!(f, "OPC channel {channel} is outside 1..=255"),
+            Self::PastStrip(addr, strip) => {
+                write!(f, "OPC mapping runs past the end of {addr} strip {strip}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for OpcRouteError {}
+
+impl From<MapError> for OpcRouteError {
+    fn from(error: MapError) -> Self {
+        match error {
+            MapError::UnknownNode(addr) => Self::UnknownNode(addr),
+            MapError::UnknownStrip(addr, strip) => Self::UnknownStrip(addr, strip),
+            MapError::PastStrip(addr, strip) => Self::PastStrip(addr, strip),
+        }
+    }
+}
+
+struct Route {
+    channel: u8,
+    /// The channel's first pixel in this route.
+    first: usize,
+    span: Span,
+}
+
+/// `OpcRouter` copies OPC channels into strip buffers, following a mapping
+/// table and each channel's color correction.
+pub struct OpcRouter {
+    strips: StripBuffers,
+    routes: Vec<Route>,
+    /// Indexed by channel, so index 0 is unused.
+    corrections: [ColorCorrection; 256],
+}
+
+impl OpcRouter {
+    /// Route each mapping to its node's strip, sending to the node's IP on `udp_port`.
+    pub fn new(
+        mappings: &[OpcMapping],
+        nodes: &[NodeConfig],
+        udp_port: u16,
+    ) -> Result<Self, OpcRouteError> {
+        let mut router = Self {
+            strips: StripBuffers::new(udp_port),
+            routes: Vec::new(),
+            corrections: [ColorCorrection::default(); 256],
+        };
+        for mapping in mappings {
+            if mapping.channel == BROADCAST {
+                return Err(OpcRouteError::BadChannel(mapping.channel));
+            }
+            let span = router.strips.map(
+                nodes,
+                mapping.node,
+                mapping.strip,
+                mapping.offset,
+                mapping.pixels,
+            )?;
+            router.routes.push(Route {
+                channel: mapping.channel,
+                first: mapping.first,
+                span,
+            });
+        }
+        Ok(router)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.routes.is_empty()
+    }
+
+    /// The color correction applied to a channel's pixels.
+    pub fn correction(&self, channel: u8) -> ColorCorrection {
+        self.corrections[channel as usize]
+    }
+
+    /// Apply a message, copying pixels into the strips they're mapped to or
+    /// updating color correction. Returns whether any strip changed.
+    ///
+    /// Commands and system-exclusive messages that aren't understood are ignored.
+    pub fn apply(&mut self, msg: &OpcMessage) -> Result<bool, OpcError> {
+        match msg.command {
+            CMD_SET_PIXELS => Ok(self.set_pixels(msg.channel, &msg.data)),
+            CMD_SYSTEM_EXCLUSIVE => {
+                self.system_exclusive(msg.channel, &msg.data)?;
+                Ok(false)
+            }
+            _ => Ok(false),
+        }
+    }
+
+    fn set_pixels(&mut self, channel: u8, data: &[u8]) -> bool {
+        let mut applied = false;
+        let routes = self
+            .routes
+            .iter()
+            .filter(|r| channel == BROADCAST || r.channel == channel);
+        for route in routes {
+            let correction = self.corrections[route.channel as usize];
+            let data = data.get(route.first * 3..).unwrap_or_default();
+            let pixels = data.chunks_exact(3);
+            for (px, rgb) in self.strips.span_mut(route.span).iter_mut().zip(pixels) {
+                *px = correction.apply(Rgb8::new(rgb[0], rgb[1], rgb[2]));
+            }
+            applied = true;
+        }
+        applied
+    }
+
+    fn system_exclusive(&mut self, channel: u8, data: &[u8]) -> Result<(), OpcError> {
+        let [s0, s1, c0, c1, json @ ..] = data else {
+            return Err(OpcError::Truncated);
+        };
+        let system = u16::from_be_bytes([*s0, *s1]);
+        let command = u16::from_be_bytes([*c0, *c1]);
+        if system != SYSTEM_FADECANDY || command != SYSEX_COLOR_CORRECTION {
+            return Ok(());
+        }
+
+        let correction = serde_json::from_slice(json).map_err(OpcError::BadCorrection)?;
+        if channel == BROADCAST {
+            self.corrections.fill(correction);
+        } else {
+            self.corrections[channel as usize] = correction;
+        }
+        Ok(())
+    }
+
+    /// The current colors of every mapped strip.
+    pub fn buffers(&self) -> impl Iterator<Item = (SocketAddr, u8, &[Rgb8])> {
+        self.strips.buffers()
+    }
+}
+
+impl Router for OpcRouter {
+    type Message = OpcMessage;
+    type Error = OpcError;
+
+    fn route(&mut self, msg: &OpcMessage) -> Result<bool, OpcError> {
+        self.apply(msg)
+    }
+
+    fn strips_mut(&mut self) -> &mut StripBuffers {
+        &mut self.strips
+    }
+}
+
+/// Accept OPC clients and forward their messages through a router until the
+/// listener fails.
+pub async fn serve(listener: TcpListener, router: OpcRouter, sender: UdpSender) {
+    input::forward(router, sender, "OPC", |tx| {
+        tokio::spawn(async move {
+            loop {
+                let (mut stream, addr) = match listener.accept().await {
+                    Ok(accepted) => accepted,
+                    Err(e) => {
+                        eprintln!("warn: OPC listener failed: {e}");
+                        return;
+                    }
+                };
+
+                let tx = tx.clone();
+                tokio::spawn(async move {
+                    loop {
+                        match OpcMessage::read(&mut stream).await {
+                            Ok(Some(msg)) => {
+                                if tx.send(msg).await.is_err() {
+                                    return;
+                                }
+                            }
+                            Ok(None) => return,
+                            Err(e) => {
+                                eprintln!("warn: OPC client {addr} failed: {e}");
+                                return;
+                            }
+                        }
+                    }
+                });
+            }
+        });
+    })
+    .await
+}
@@ -0,0 +1,116 @@
+//! Pushing firmware images to nodes over their control sessions (see
+//! [`common::ota`]).
+
+use std::{fmt, time::Duration};
+
+use common::{
+    net::{NodeMessage, ServerMessage},
+    ota::{self, UpdateError, UpdateHeader, UpdateStatus, WINDOW},
+};
+use tokio::{
+    sync::broadcast::{self, error::RecvError},
+    time,
+};
+
+use crate::node::{NodeEvent, NodeId, NodeRegistry, RegistryError};
+
+/// How long the node can go without reporting progress before the update is
+/// given up on. Writing a sector takes well under this.
+const REPLY_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PushError {
+    Registry(RegistryError),
+    /// There's nothing in the image to send.
+    EmptyImage,
+    /// The image is too large to describe in an [`UpdateHeader`].
+    TooLarge,
+    /// The node abandoned the update.
+    Node(UpdateError),
+    /// The node stopped reporting progress.
+    TimedOut,
+}
+
+impl fmt::Display for PushError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Registry(e) => e.fmt(f),
+            Self::EmptyImage => write!(f, "the image is empty"),
+            Self::TooLarge => write!(f, "the image is too large"),
+            Self::Node(e) => write!(f, "node abandoned the update: {e:?}"),
+            Self::TimedOut => write!(f, "node stopped answering"),
+        }
+    }
+}
+
+impl std::error::Error for PushError {}
+
+impl From<RegistryError> for PushError {
+    fn from(e: RegistryError) -> Self {
+        Self::Registry(e)
+    }
+}
+
+/// Send `image` to a node, which restarts into it once it's verified.
+///
+/// `progress` is called with the number of bytes in the node's flash every
+/// time that grows. Returns once the node has verified the image; whether it
+/// keeps it shows up later as [`UpdateStatus::Confirmed`].
+pub async fn push(
+    registry: &NodeRegistry,
+    id: NodeId,
+    image: &[u8],
+    mut progress: impl FnMut(u32),
+) -> Result<(), PushError> {
+    if image.is_empty() {
+        return Err(PushError::EmptyImage);
+    }
+    let header = UpdateHeader::for_image(image).ok_or(PushError::TooLarge)?;
+
+    let mut events = registry.subscribe();
+    registry.send(id, ServerMessage::BeginUpdate(header))?;
+
+    let mut chunks = ota::chunks(image).peekable();
+    let mut written = None;
+    loop {
+        // nothing goes out until the node is ready, then up to a window
+        // ahead of what it's written
+        if let Some(written) = written {
+            while let Some(chunk) = chunks.next_if(|chunk| chunk.offset < written + WINDOW) {
+                registry.send(id, ServerMessage::UpdateChunk(chunk))?;
+            }
+        }
+
+        match next_status(&mut events, id).await? {
+            UpdateStatus::Ready => written = Some(0),
+            UpdateStatus::Written(n) => {
+                written = Some(n);
+                progress(n);
+            }
+            UpdateStatus::Verified => return Ok(()),
+            UpdateStatus::Failed(e) => return Err(PushError::Node(e)),
+            UpdateStatus::Confirmed => (),
+        }
+    }
+}
+
+/// Wait for the node's next [`NodeMessage::Update`].
+async fn next_status(
+    events: &mut broadcast::Receiver<NodeEvent>,
+    id: NodeId,
+) -> Result<UpdateStatus, PushError> {
+    loop {
+        let event = time::timeout(REPLY_TIMEOUT, events.recv())
+            .await
+            .map_err(|_| PushError::TimedOut)?;
+        match event {
+            Ok(NodeEvent {
+                id: from,
+                msg: NodeMessage::Update(status),
+            }) if from == id => return Ok(status),
+            // `Written` counts are cumulative, so a missed one is made up by the next
+            Ok(_) | Err(RecvError::Lagged(_)) => (),
+            Err(RecvError::Closed) => return Err(PushError::TimedOut),
+        }
+    }
+}
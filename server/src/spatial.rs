@@ -0,0 +1,225 @@
+use std::{
+    net::SocketAddr,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use common::{
+    color::Rgb8,
+    effect::spatial::SpatialEffect,
+    math::Vec3,
+    net::{PRESENTATION_DELAY_MS, STREAM_INTERVAL_MS},
+    preview::{FpsCounter, Preview, PreviewRow, PreviewStatus},
+};
+use serde::Deserialize;
+use tokio::{
+    io::{self, AsyncWriteExt},
+    sync::watch,
+    time::MissedTickBehavior,
+};
+
+use crate::{
+    clock,
+    ddp::DdpSender,
+    layout::{LayoutFile, LayoutFileError},
+    udp::UdpSender,
+};
+
+/// How often a running spatial effect is rendered and sent.
+pub const FRAME_INTERVAL: Duration = Duration::from_millis(STREAM_INTERVAL_MS as u64);
+
+/// A spatial effect that can be handed to the streaming task.
+pub type SharedEffect = Arc<dyn SpatialEffect + Send + Sync>;
+
+/// Which protocol spatial effects are streamed with.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputProtocol {
+    /// Lightspace's own UDP commands, which nodes can blend over effects.
+    #[default]
+    Lightspace,
+    /// DDP, which nodes and other displays like WLED accept.
+    Ddp,
+}
+
+/// How rendered frames reach the nodes.
+pub enum Output {
+    /// A `SetBufferToMany` command per strip.
+    Lightspace(UdpSender),
+    /// One DDP frame per node, pushed so all its strips update together.
+    /// Every node needs its strips laid out from 0 up (see
+    /// [`SpatialRenderer::ddp_gap`]).
+    Ddp(DdpSender),
+}
+
+struct Target {
+    addr: SocketAddr,
+    strip: u8,
+    positions: Vec<Vec3>,
+    buf: Vec<Rgb8>,
+}
+
+/// `SpatialRenderer` samples a [`SpatialEffect`] at every laid-out LED and
+/// streams the result to the nodes through an [`Output`].
+#[derive(Default)]
+pub struct SpatialRenderer {
+    targets: Vec<Target>,
+}
+
+impl SpatialRenderer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Render to every strip in a layout, sending to each node's IP on `port`.
+    pub fn from_layout(layout: &LayoutFile, port: u16) -> Result<Self, LayoutFileError> {
+        let mut renderer = Self::new();
+        for node in &layout.nodes {
+            let addr = SocketAddr::new(node.addr.ip(), port);
+            for (strip, positions) in node.positions()?.into_iter().enumerate() {
+                renderer.add_strip(addr, strip as u8, positions);
+            }
+        }
+        Ok(renderer)
+    }
+
+    /// Render to a strip with LEDs at `positions`, sending to a node's UDP address.
+    pub fn add_strip(&mut self, addr: SocketAddr, strip: u8, positions: Vec<Vec3>) {
+        self.targets.push(Target {
+            addr,
+            strip,
+            buf: vec![Rgb8::zero(); positions.len()],
+            positions,
+        });
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.targets.is_empty()
+    }
+
+    /// The first strip missing from below a node's others, as `(addr, strip)`.
+    ///
+    /// DDP lays a node's strips end to end, so with a gap every strip past it
+    /// would land on the wrong LEDs.
+    pub fn ddp_gap(&self) -> Option<(SocketAddr, u8)> {
+        self.nodes().into_iter().find_map(|addr| {
+            let strips = self.strips(addr);
+            (0..)
+                .zip(&strips)
+                .find(|(i, target)| target.strip != *i)
+                .map(|(i, _)| (addr, i))
+        })
+    }
+
+    /// Every address strips are sent to, once each.
+    fn nodes(&self) -> Vec<SocketAddr> {
+        let mut addrs = self.targets.iter().map(|t| t.addr).collect::<Vec<_>>();
+        addrs.sort_unstable();
+        addrs.dedup();
+        addrs
+    }
+
+    /// A node's strips, in index order.
+    fn strips(&self, addr: SocketAddr) -> Vec<&Target> {
+        let mut strips = self
+            .targets
+            .iter()
+            .filter(|t| t.addr == addr)
+            .collect::<Vec<_>>();
+        strips.sort_by_key(|t| t.strip);
+        strips
+    }
+
+    /// Sample an effect into every strip's buffer.
+    pub fn render(&mut self, effect: &dyn SpatialEffect, time: u64) {
+        for target in &mut self.targets {
+            effect.render(&target.positions, &mut target.buf, time);
+        }
+    }
+
+    /// The last rendered colors of every strip, in the order they were added.
+    pub fn buffers(&self) -> impl Iterator<Item = (SocketAddr, u8, &[Rgb8])> {
+        self.targets
+            .iter()
+            .map(|target| (target.addr, target.strip, &target.buf[..]))
+    }
+
+    /// Send the last rendered frame to every strip.
+    pub async fn send(&self, output: &Output) -> io::Result<()> {
+        match output {
+            Output::Lightspace(sender) => {
+                // nodes queue frames and show them together once this comes around
+                let timestamp = (clock::now() as u32).wrapping_add(PRESENTATION_DELAY_MS);
+                for (addr, strip, colors) in self.buffers() {
+                    sender.set_buffer_at(addr, strip, colors, timestamp).await?;
+                }
+            }
+            Output::Ddp(sender) => {
+                if let Some((addr, strip)) = self.ddp_gap() {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        format!("{addr} has no strip {strip} to lay its others after"),
+                    ));
+                }
+                for addr in self.nodes() {
+                    let strips = self.strips(addr);
+                    sender
+                        .send_frame(addr, strips.iter().map(|t| &t.buf[..]))
+                        .await?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Render and send whichever effect is current at [`FRAME_INTERVAL`], idling
+/// while it's `None`. Returns once the sending half of `effect` is dropped.
+///
+/// With a `preview`, every frame sent is also drawn to stdout.
+pub async fn stream(
+    mut renderer: SpatialRenderer,
+    output: Output,
+    mut effect: watch::Receiver<Option<SharedEffect>>,
+    preview: Option<Preview>,
+) {
+    let start = Instant::now();
+    let mut fps = FpsCounter::new();
+    let mut stdout = io::stdout();
+    let mut interval = tokio::time::interval(FRAME_INTERVAL);
+    interval.set_missed_tick_behavior(MissedTickBehavior::Skip);
+
+    loop {
+        let Some(current) = effect.borrow_and_update().clone() else {
+            if effect.changed().await.is_err() {
+                return;
+            }
+            continue;
+        };
+
+        interval.tick().await;
+        renderer.render(&*current, start.elapsed().as_millis() as u64);
+        if let Err(e) = renderer.send(&output).await {
+            eprintln!("warn: failed to send spatial frame: {e}");
+        }
+
+        if let Some(preview) = preview {
+            let rows = renderer.buffers().map(|(addr, strip, pixels)| PreviewRow {
+                label: format!("{addr} #{strip}"),
+                mode: None,
+                pixels,
+            });
+            let status = PreviewStatus {
+                effect: None,
+                fps: fps.tick(1),
+            };
+            let frame = preview.draw(rows, status);
+            _ = stdout.write_all(frame.as_bytes()).await;
+            _ = stdout.flush().await;
+        }
+
+        if effect.has_changed().is_err() {
+            return;
+        }
+    }
+}
@@ -0,0 +1,199 @@
+use std::{
+    collections::HashMap,
+    net::{IpAddr, SocketAddr},
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicU16, Ordering},
+    },
+};
+
+use common::{
+    color::{Rgb8, Rgba8},
+    net::{
+        auth::{Key, MAC_LEN, sign_packet},
+        udp::{EncodeError, MAX_PACKET_LEN, PacketHeader, UdpEncoder},
+    },
+};
+use tokio::{io, net::UdpSocket};
+
+/// Numbers every unsigned packet the server streams. Shared by every
+/// [`UdpSender`], so nodes see one sequence whether spatial output, DMX or OPC
+/// sent a packet.
+static SEQUENCE: AtomicU16 = AtomicU16::new(0);
+
+/// `StreamKeys` holds the key for each node with an authenticated session
+/// (see [`Key::for_session`]), by the node's IP, and counts the packets
+/// signed with it. It's shared between the [`crate::node::NodeRegistry`]
+/// opening the sessions and the [`UdpSender`]s signing packets with them.
+#[derive(Debug, Clone, Default)]
+pub struct StreamKeys(Arc<Mutex<HashMap<IpAddr, (Key, u64)>>>);
+
+impl StreamKeys {
+    /// Start signing a node's packets with a new session's key, counting from 0.
+    pub fn insert(&self, ip: IpAddr, key: Key) {
+        self.0.lock().unwrap().insert(ip, (key, 0));
+    }
+
+    pub fn remove(&self, ip: IpAddr) {
+        self.0.lock().unwrap().remove(&ip);
+    }
+
+    pub fn get(&self, ip: IpAddr) -> Option<Key> {
+        self.0.lock().unwrap().get(&ip).map(|(key, _)| key.clone())
+    }
+
+    /// The key for a node's session and the counter of the next packet signed
+    /// with it, counting that packet as sent.
+    pub fn next(&self, ip: IpAddr) -> Option<(Key, u64)> {
+        let mut keys = self.0.lock().unwrap();
+        let (key, counter) = keys.get_mut(&ip)?;
+        *counter += 1;
+        Some((key.clone(), *counter - 1))
+    }
+}
+
+/// `UdpSender` streams color data to nodes using the shared [`UdpEncoder`],
+/// numbering every packet it sends and signing it for nodes with an
+/// authenticated session.
+pub struct UdpSender {
+    socket: UdpSocket,
+    keys: StreamKeys,
+}
+
+impl UdpSender {
+    /// Bind a sender to an ephemeral local port.
+    pub async fn bind() -> io::Result<Self> {
+        Ok(Self {
+            socket: UdpSocket::bind("0.0.0.0:0").await?,
+            keys: StreamKeys::default(),
+        })
+    }
+
+    /// Sign packets to nodes with an authenticated session, so nodes holding
+    /// the installation's key take them (see [`common::net::auth`]). Nodes
+    /// without one get their packets unsigned.
+    pub fn with_keys(mut self, keys: StreamKeys) -> Self {
+        self.keys = keys;
+        self
+    }
+
+    /// Build a single packet with an encoder and send it.
+    pub async fn send_with<F>(&self, addr: SocketAddr, f: F) -> io::Result<()>
+    where
+        F: FnOnce(&mut UdpEncoder) -> Result<(), EncodeError>,
+    {
+        self.send_packet(addr, None, f).await
+    }
+
+    /// Build a single packet with an encoder and send it, stamped with the
+    /// server time nodes should show it at (see [`crate::clock`]).
+    pub async fn send_at<F>(&self, addr: SocketAddr, timestamp: u32, f: F) -> io::Result<()>
+    where
+        F: FnOnce(&mut UdpEncoder) -> Result<(), EncodeError>,
+    {
+        self.send_packet(addr, Some(timestamp), f).await
+    }
+
+    async fn send_packet<F>(&self, addr: SocketAddr, timestamp: Option<u32>, f: F) -> io::Result<()>
+    where
+        F: FnOnce(&mut UdpEncoder) -> Result<(), EncodeError>,
+    {
+        let mut buf = [0u8; MAX_PACKET_LEN];
+        // signed packets are numbered by their session instead
+        let signing = self.keys.next(addr.ip());
+        let (sequence, room) = match &signing {
+            Some((_, counter)) => (*counter as u16, MAX_PACKET_LEN - MAC_LEN),
+            None => (SEQUENCE.fetch_add(1, Ordering::Relaxed), MAX_PACKET_LEN),
+        };
+        let header = PacketHeader {
+            sequence,
+            timestamp,
+        };
+        let mut enc = UdpEncoder::new(&mut buf[..room], header);
+        f(&mut enc).map_err(|e| io::Error::other(format!("failed to encode packet: {e:?}")))?;
+
+        let mut len = enc.len();
+        if let Some((key, counter)) = &signing {
+            len = sign_packet(key, *counter, &mut buf, len).expect("room was left for the MAC");
+        }
+        self.socket.send_to(&buf[..len], addr).await?;
+        Ok(())
+    }
+
+    /// Set every pixel of a strip.
+    pub async fn set_buffer(&self, addr: SocketAddr, strip: u8, colors: &[Rgb8]) -> io::Result<()> {
+        self.send_with(addr, |enc| enc.set_buffer_to_many(strip, colors))
+            .await
+    }
+
+    /// Set every pixel of a strip, shown once the nodes' synced clocks reach `timestamp`.
+    pub async fn set_buffer_at(
+        &self,
+        addr: SocketAddr,
+        strip: u8,
+        colors: &[Rgb8],
+        timestamp: u32,
+    ) -> io::Result<()> {
+        self.send_at(addr, timestamp, |enc| enc.set_buffer_to_many(strip, colors))
+            .await
+    }
+
+    /// Set every pixel of a strip to a single color.
+    pub async fn fill(&self, addr: SocketAddr, strip: u8, color: Rgb8) -> io::Result<()> {
+        self.send_with(addr, |enc| enc.set_buffer_to_single(strip, color))
+            .await
+    }
+
+    /// Set every pixel of a strip, with alpha for blending over effects in hybrid mode.
+    pub async fn set_buffer_alpha(
+        &self,
+        addr: SocketAddr,
+        strip: u8,
+        colors: &[Rgba8],
+    ) -> io::Result<()> {
+        self.send_with(addr, |enc| enc.set_buffer_to_many_alpha(strip, colors))
+            .await
+    }
+
+    /// Set a single pixel of a strip.
+    pub async fn set_pixel(
+        &self,
+        addr: SocketAddr,
+        strip: u8,
+        index: u16,
+        color: Rgb8,
+    ) -> io::Result<()> {
+        self.send_with(addr, |enc| enc.set_single_pixel(strip, index, color))
+            .await
+    }
+
+    /// Set every pixel of a strip to a single color, with alpha for blending over effects.
+    pub async fn fill_alpha(&self, addr: SocketAddr, strip: u8, color: Rgba8) -> io::Result<()> {
+        self.send_with(addr, |enc| enc.set_buffer_to_single_alpha(strip, color))
+            .await
+    }
+
+    /// Set the pixels `start..start + colors.len()` of a strip.
+    pub async fn set_range(
+        &self,
+        addr: SocketAddr,
+        strip: u8,
+        start: u16,
+        colors: &[Rgb8],
+    ) -> io::Result<()> {
+        self.send_with(addr, |enc| enc.set_pixel_range(strip, start, colors))
+            .await
+    }
+
+    /// Set the pixels `start..start + colors.len()` of a strip, with alpha for blending over effects.
+    pub async fn set_range_alpha(
+        &self,
+        addr: SocketAddr,
+        strip: u8,
+        start: u16,
+        colors: &[Rgba8],
+    ) -> io::Result<()> {
+        self.send_with(addr, |enc| enc.set_pixel_range_alpha(strip, start, colors))
+            .await
+    }
+}
@@ -0,0 +1,22 @@
+use common::effect::registry;
+use server::{console::command, node::NodeRegistry};
+use tokio::sync::watch;
+
+#[test]
+fn refuses_effect_ids_past_the_registry() {
+    let registry = NodeRegistry::new();
+    let (effect, _) = watch::channel(None);
+
+    let past = registry::COUNT;
+    for line in [
+        format!("param 0 {past} speed 1"),
+        format!("effect 0 {past}"),
+    ] {
+        let e = command(&registry, &effect, &line).unwrap_err();
+        assert_eq!(e.to_string(), format!("unknown effect `{past}`"));
+    }
+
+    // a registered id gets as far as the registry, which has no node 0
+    let e = command(&registry, &effect, "effect 0 0").unwrap_err();
+    assert_eq!(e.to_string(), "unknown node 0");
+}
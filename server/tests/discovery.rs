@@ -0,0 +1,114 @@
+use std::{net::SocketAddr, time::Duration};
+
+use common::net::{
+    DDP_PORT, Mcu, UDP_PORT, Version,
+    discovery::{Beacon, MAX_BEACON_LEN},
+};
+use server::{config::Config, discovery, node::NodeRegistry};
+use tokio::{net::UdpSocket, time::sleep};
+
+/// The beacon of the node on `tcp_port`, one node per port.
+fn beacon(tcp_port: u16) -> Beacon {
+    let [hi, lo] = tcp_port.to_be_bytes();
+    Beacon {
+        id: [0x02, 0, 0, 0, hi, lo],
+        name: "stage-left".into(),
+        version: Version::new(0, 1, 0),
+        mcu: Mcu::Esp32c6,
+        num_strips: 2,
+        tcp_port,
+        udp_port: UDP_PORT,
+        ddp_port: DDP_PORT,
+    }
+}
+
+#[tokio::test]
+async fn registers_each_node_once() {
+    let listener = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let target = listener.local_addr().unwrap();
+    let registry = NodeRegistry::new();
+    // a node from the config file
+    let known: SocketAddr = "127.0.0.1:9".parse().unwrap();
+    registry.add(known, &[300]);
+    tokio::spawn(discovery::listen(listener, registry.clone()));
+
+    let node = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let mut buf = [0u8; MAX_BEACON_LEN];
+    for packet in [
+        beacon(9).encode(&mut buf).unwrap().to_vec(),
+        b"not a beacon".to_vec(),
+        beacon(1338).encode(&mut buf).unwrap().to_vec(),
+        beacon(1338).encode(&mut buf).unwrap().to_vec(),
+    ] {
+        node.send_to(&packet, target).await.unwrap();
+    }
+
+    let discovered: SocketAddr = "127.0.0.1:1338".parse().unwrap();
+    for _ in 0..250 {
+        if registry.find(discovered).is_some() {
+            break;
+        }
+        sleep(Duration::from_millis(20)).await;
+    }
+    // give the duplicate a chance to arrive
+    sleep(Duration::from_millis(100)).await;
+
+    assert_eq!(registry.find(known), Some(0));
+    assert_eq!(registry.find(discovered), Some(1));
+    assert_eq!(registry.len(), 2);
+    // strips arrive with the node's handshake
+    assert!(registry.get(1).unwrap().strips.is_empty());
+}
+
+#[tokio::test]
+async fn follows_a_node_to_its_new_address() {
+    let listener = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let target = listener.local_addr().unwrap();
+    let registry = NodeRegistry::new();
+    // a node from the config file, known by its address until its first beacon
+    let known: SocketAddr = "127.0.0.1:1338".parse().unwrap();
+    registry.add(known, &[300]);
+    tokio::spawn(discovery::listen(listener, registry.clone()));
+
+    let node = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let mut buf = [0u8; MAX_BEACON_LEN];
+    let packet = beacon(1338).encode(&mut buf).unwrap().to_vec();
+    node.send_to(&packet, target).await.unwrap();
+    for _ in 0..250 {
+        if registry.find_beacon(beacon(1338).id).is_some() {
+            break;
+        }
+        sleep(Duration::from_millis(20)).await;
+    }
+    assert_eq!(registry.find_beacon(beacon(1338).id), Some(0));
+
+    // the same node, somewhere else
+    let moved: SocketAddr = "127.0.0.1:1340".parse().unwrap();
+    let packet = Beacon {
+        tcp_port: 1340,
+        ..beacon(1338)
+    }
+    .encode(&mut buf)
+    .unwrap()
+    .to_vec();
+    node.send_to(&packet, target).await.unwrap();
+    for _ in 0..250 {
+        if registry.get(0).unwrap().addr == moved {
+            break;
+        }
+        sleep(Duration::from_millis(20)).await;
+    }
+    assert_eq!(registry.get(0).unwrap().addr, moved);
+    assert_eq!(registry.find(moved), Some(0));
+    assert_eq!(registry.len(), 1);
+}
+
+#[test]
+fn discovery_is_on_by_default() {
+    let config = Config::parse("").unwrap();
+    assert!(config.discovery.enabled);
+    assert_eq!(config.discovery.port, 1339);
+
+    let config = Config::parse("[discovery]\nenabled = false").unwrap();
+    assert!(!config.discovery.enabled);
+}
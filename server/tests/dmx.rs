@@ -0,0 +1,294 @@
+use std::time::Duration;
+
+use common::{
+    color::Rgb8,
+    net::udp::{UdpCommand, UdpDecoder},
+};
+use server::{
+    config::{Config, DmxMapping, NodeConfig},
+    dmx::{self, ColorOrder, DmxError, DmxFrame, DmxRouteError, DmxRouter},
+    udp::UdpSender,
+};
+use tokio::{net::UdpSocket, time::timeout};
+
+/// An ArtDmx packet, laid out as a desk sends it.
+fn artnet(universe: u16, data: &[u8]) -> Vec<u8> {
+    let mut packet = b"Art-Net\0".to_vec();
+    packet.extend([0x00, 0x50]); // OpDmx, little-endian
+    packet.extend([0, 14]); // protocol version
+    packet.extend([1, 0]); // sequence, physical
+    packet.extend(universe.to_le_bytes());
+    packet.extend((data.len() as u16).to_be_bytes());
+    packet.extend(data);
+    packet
+}
+
+/// An E1.31 data packet, laid out as a desk sends it.
+fn sacn(universe: u16, options: u8, data: &[u8]) -> Vec<u8> {
+    let mut packet = Vec::new();
+    // root layer
+    packet.extend([0x00, 0x10, 0x00, 0x00]);
+    packet.extend(b"ASC-E1.17\0\0\0");
+    packet.extend([0x72, 0x6e]);
+    packet.extend(4u32.to_be_bytes());
+    packet.extend([0xab; 16]); // CID
+    // framing layer
+    packet.extend([0x72, 0x58]);
+    packet.extend(2u32.to_be_bytes());
+    let mut name = [0u8; 64];
+    name[..4].copy_from_slice(b"desk");
+    packet.extend(name);
+    packet.extend([100, 0, 0, 7, options]); // priority, sync address, sequence, options
+    packet.extend(universe.to_be_bytes());
+    // DMP layer
+    packet.extend([0x72, 0x0b, 0x02, 0xa1, 0x00, 0x00, 0x00, 0x01]);
+    packet.extend((data.len() as u16 + 1).to_be_bytes());
+    packet.push(0); // start code
+    packet.extend(data);
+    packet
+}
+
+fn node(strips: &[usize]) -> NodeConfig {
+    NodeConfig {
+        addr: "127.0.0.1:1338".parse().unwrap(),
+        strips: strips.to_vec(),
+    }
+}
+
+fn mapping(universe: u16, strip: u8) -> DmxMapping {
+    DmxMapping {
+        universe,
+        start_channel: 1,
+        node: "127.0.0.1:1338".parse().unwrap(),
+        strip,
+        offset: 0,
+        pixels: None,
+        order: ColorOrder::Rgb,
+    }
+}
+
+#[test]
+fn parses_artnet_and_sacn() {
+    let data = [1, 2, 3, 4, 5, 6];
+    assert_eq!(
+        dmx::parse(&artnet(0x0123, &data)),
+        Ok(Some(DmxFrame {
+            universe: 0x0123,
+            data: &data
+        }))
+    );
+    assert_eq!(
+        dmx::parse(&sacn(7, 0, &data)),
+        Ok(Some(DmxFrame {
+            universe: 7,
+            data: &data
+        }))
+    );
+}
+
+#[test]
+fn ignores_packets_without_levels() {
+    let mut poll = artnet(0, &[]);
+    poll[8..10].copy_from_slice(&0x2000u16.to_le_bytes());
+    assert_eq!(dmx::parse(&poll), Ok(None));
+    assert_eq!(dmx::parse(&sacn(1, 0x40, &[255; 3])), Ok(None));
+
+    let mut alt_start = sacn(1, 0, &[255; 3]);
+    alt_start[125] = 0xdd;
+    assert_eq!(dmx::parse(&alt_start), Ok(None));
+}
+
+#[test]
+fn rejects_bad_packets() {
+    assert_eq!(dmx::parse(b"hello"), Err(DmxError::UnknownProtocol));
+
+    let packet = artnet(0, &[1, 2, 3]);
+    assert_eq!(
+        dmx::parse(&packet[..packet.len() - 1]),
+        Err(DmxError::Truncated)
+    );
+    let packet = sacn(1, 0, &[1, 2, 3]);
+    assert_eq!(dmx::parse(&packet[..100]), Err(DmxError::Truncated));
+}
+
+#[test]
+fn color_orders() {
+    assert_eq!(ColorOrder::Rgb.read([1, 2, 3]), Rgb8::new(1, 2, 3));
+    assert_eq!(ColorOrder::Grb.read([1, 2, 3]), Rgb8::new(2, 1, 3));
+    assert_eq!(ColorOrder::Bgr.read([1, 2, 3]), Rgb8::new(3, 2, 1));
+    assert_eq!(ColorOrder::Gbr.read([1, 2, 3]), Rgb8::new(3, 1, 2));
+}
+
+#[test]
+fn routes_universes_onto_strips() {
+    let mappings = [
+        // the first 170 pixels fill a universe
+        mapping(1, 0),
+        DmxMapping {
+            start_channel: 4,
+            offset: 170,
+            pixels: Some(2),
+            order: ColorOrder::Grb,
+            ..mapping(2, 0)
+        },
+    ];
+    let mut router = DmxRouter::new(&mappings, &[node(&[180])], 1337).unwrap();
+    assert_eq!(router.universes(), [1, 2]);
+
+    let data = (0..=255).cycle().take(512).collect::<Vec<u8>>();
+    assert!(router.apply(&DmxFrame {
+        universe: 1,
+        data: &data
+    }));
+    assert!(router.apply(&DmxFrame {
+        universe: 2,
+        data: &[0, 0, 0, 10, 20, 30, 40, 50, 60, 70, 80, 90]
+    }));
+    assert!(!router.apply(&DmxFrame {
+        universe: 3,
+        data: &data
+    }));
+
+    let (addr, strip, colors) = router.buffers().next().unwrap();
+    assert_eq!(addr, "127.0.0.1:1337".parse().unwrap());
+    assert_eq!(strip, 0);
+    assert_eq!(colors[0], Rgb8::new(0, 1, 2));
+    assert_eq!(colors[85], Rgb8::new(255, 0, 1));
+    assert_eq!(colors[169], Rgb8::new(251, 252, 253));
+    assert_eq!(colors[170], Rgb8::new(20, 10, 30));
+    assert_eq!(colors[171], Rgb8::new(50, 40, 60));
+    assert_eq!(colors[172], Rgb8::zero());
+}
+
+#[test]
+fn rejects_bad_mappings() {
+    let nodes = [node(&[100])];
+    let other = "10.0.0.1:1338".parse().unwrap();
+    let cases = [
+        (
+            DmxMapping {
+                node: other,
+                ..mapping(1, 0)
+            },
+            DmxRouteError::UnknownNode(other),
+        ),
+        (mapping(1, 1), DmxRouteError::UnknownStrip(nodes[0].addr, 1)),
+        (
+            DmxMapping {
+                start_channel: 0,
+                ..mapping(1, 0)
+            },
+            DmxRouteError::BadChannel(0),
+        ),
+        (
+            DmxMapping {
+                offset: 90,
+                pixels: Some(20),
+                ..mapping(1, 0)
+            },
+            DmxRouteError::PastStrip(nodes[0].addr, 0),
+        ),
+        (
+            DmxMapping {
+                offset: 1,
+                pixels: Some(usize::MAX),
+                ..mapping(1, 0)
+            },
+            DmxRouteError::PastStrip(nodes[0].addr, 0),
+        ),
+    ];
+    for (mapping, error) in cases {
+        assert_eq!(DmxRouter::new(&[mapping], &nodes, 1337).err(), Some(error));
+    }
+}
+
+#[test]
+fn parses_config_table() {
+    let config = Config::parse(
+        r#"
+        [[nodes]]
+        addr = "127.0.0.1:1338"
+        strips = [300]
+
+        [dmx]
+        artnet = true
+
+        [[dmx.map]]
+        universe = 1
+        node = "127.0.0.1:1338"
+        strip = 0
+
+        [[dmx.map]]
+        universe = 2
+        start_channel = 4
+        node = "127.0.0.1:1338"
+        strip = 0
+        offset = 170
+        order = "grb"
+        "#,
+    )
+    .unwrap();
+
+    assert!(config.dmx.artnet);
+    assert!(!config.dmx.sacn);
+    assert_eq!(config.dmx.map[0], mapping(1, 0));
+    assert_eq!(
+        config.dmx.map[1],
+        DmxMapping {
+            start_channel: 4,
+            offset: 170,
+            order: ColorOrder::Grb,
+            ..mapping(2, 0)
+        }
+    );
+    DmxRouter::new(&config.dmx.map, &config.nodes, 1337).unwrap();
+}
+
+#[tokio::test]
+async fn forwards_packets_to_nodes() {
+    let node_socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let port = node_socket.local_addr().unwrap().port();
+    let router = DmxRouter::new(&[mapping(0, 0), mapping(1, 1)], &[node(&[4, 2])], port).unwrap();
+
+    let input = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let input_addr = input.local_addr().unwrap();
+    tokio::spawn(dmx::forward(
+        router,
+        UdpSender::bind().await.unwrap(),
+        vec![input],
+    ));
+
+    let desk = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    desk.send_to(&artnet(0, &[255, 0, 0, 0, 255, 0]), input_addr)
+        .await
+        .unwrap();
+    desk.send_to(b"not dmx", input_addr).await.unwrap();
+    desk.send_to(&sacn(1, 0, &[0, 0, 255]), input_addr)
+        .await
+        .unwrap();
+
+    let mut buf = [0u8; 2048];
+    for expected in [
+        (
+            0,
+            vec![
+                Rgb8::new(255, 0, 0),
+                Rgb8::new(0, 255, 0),
+                Rgb8::zero(),
+                Rgb8::zero(),
+            ],
+        ),
+        (1, vec![Rgb8::new(0, 0, 255), Rgb8::zero()]),
+    ] {
+        let n = timeout(Duration::from_secs(5), node_socket.recv(&mut buf))
+            .await
+            .expect("timed out waiting for a packet")
+            .unwrap();
+        match UdpDecoder::new(&buf[..n]).unwrap().collect::<Vec<_>>()[..] {
+            [Ok(UdpCommand::SetBufferToMany { strip, colors })] => {
+                assert_eq!((strip, colors.iter().collect::<Vec<_>>()), expected);
+            }
+            ref other => panic!("unexpected packet: {other:?}"),
+        }
+    }
+}
@@ -0,0 +1,91 @@
+use common::{layout::LayoutError, math::Vec3};
+use server::layout::{LayoutFile, LayoutFileError};
+
+fn assert_close(actual: &[Vec3], expected: &[Vec3]) {
+    assert_eq!(actual.len(), expected.len());
+    for (i, (a, e)) in actual.iter().zip(expected).enumerate() {
+        assert!(a.distance(*e) < 1e-4, "LED {i}: {a:?} != {e:?}");
+    }
+}
+
+#[test]
+fn parses_toml() {
+    let layout = LayoutFile::from_toml(
+        r#"
+        [[nodes]]
+        addr = "10.0.0.1:1338"
+
+        [[nodes.strips]]
+        leds = 2
+        density = 1.0
+        segments = [{ start = [0.0, 0.0, 0.0], end = [0.0, 2.0, 0.0] }]
+
+        [[nodes.strips]]
+        leds = 1
+        positions = [[1.0, 2.0, 3.0]]
+        "#,
+    )
+    .unwrap();
+
+    let node = layout.node("10.0.0.1:1338".parse().unwrap()).unwrap();
+    assert_eq!(node.leds(), [2, 1]);
+    let positions = node.positions().unwrap();
+    assert_close(
+        &positions[0],
+        &[Vec3::new(0.0, 0.5, 0.0), Vec3::new(0.0, 1.5, 0.0)],
+    );
+    assert_close(&positions[1], &[Vec3::new(1.0, 2.0, 3.0)]);
+}
+
+#[test]
+fn parses_json() {
+    let layout = LayoutFile::from_json(
+        r#"{
+            "nodes": [{
+                "addr": "10.0.0.1:1338",
+                "strips": [{
+                    "leds": 2,
+                    "rev": true,
+                    "segments": [{ "start": [0, 0, 0], "end": [1, 0, 0], "density": 2 }]
+                }]
+            }]
+        }"#,
+    )
+    .unwrap();
+
+    let positions = layout.nodes[0].positions().unwrap();
+    assert_close(
+        &positions[0],
+        &[Vec3::new(0.75, 0.0, 0.0), Vec3::new(0.25, 0.0, 0.0)],
+    );
+}
+
+#[test]
+fn reports_the_broken_strip() {
+    let layout = LayoutFile::from_toml(
+        r#"
+        [[nodes]]
+        addr = "10.0.0.1:1338"
+        strips = [
+            { leds = 1, positions = [[0.0, 0.0, 0.0]] },
+            { leds = 3, positions = [[0.0, 0.0, 0.0]] },
+        ]
+        "#,
+    )
+    .unwrap();
+
+    match layout.validate() {
+        Err(LayoutFileError::Strip {
+            strip: 1,
+            error: LayoutError::LedCountMismatch { leds: 3, placed: 1 },
+            ..
+        }) => (),
+        other => panic!("unexpected result {other:?}"),
+    }
+}
+
+#[test]
+fn example_layout_is_valid() {
+    let layout = LayoutFile::load(concat!(env!("CARGO_MANIFEST_DIR"), "/layout.example.toml"));
+    layout.unwrap().validate().unwrap();
+}
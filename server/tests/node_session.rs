@@ -0,0 +1,582 @@
+use std::time::Duration;
+
+use common::{
+    color::{BlendMode, ColorOrder, Rgb8},
+    effect::{
+        StripInfo,
+        fx::{MAX_OVERLAYS, OverlayConfig},
+        param::{ParamError, ParamValue},
+        registry,
+        transition::{Transition, TransitionKind},
+    },
+    net::{
+        Chipset, Heartbeat, MAX_FRAME_LEN, Mcu, NodeError, NodeHello, NodeMessage, ServerMessage,
+        StripConfig, StripMode, Version,
+        auth::{Key, Opener, SealedFrame},
+        decode_frame, encode_frame,
+        failsafe::{Fallback, StreamTimeout},
+    },
+};
+use server::{
+    config::Config,
+    node::{NodeEvent, NodeRegistry, RegistryError},
+};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    net::{TcpListener, TcpStream},
+    time::timeout,
+};
+
+/// A fake node that speaks the same framing as the firmware.
+struct MockNode {
+    listener: TcpListener,
+}
+
+impl MockNode {
+    async fn bind() -> Self {
+        Self {
+            listener: TcpListener::bind("127.0.0.1:0").await.unwrap(),
+        }
+    }
+
+    async fn accept(&self) -> BufReader<TcpStream> {
+        let (stream, _) = timeout(Duration::from_secs(5), self.listener.accept())
+            .await
+            .expect("server never connected")
+            .unwrap();
+        BufReader::new(stream)
+    }
+}
+
+/// Read the next non-keepalive message from the server.
+async fn recv(stream: &mut BufReader<TcpStream>) -> ServerMessage {
+    loop {
+        let mut frame = Vec::new();
+        timeout(Duration::from_secs(5), stream.read_until(0, &mut frame))
+            .await
+            .expect("timed out waiting for a frame")
+            .unwrap();
+
+        match decode_frame(&mut frame).unwrap() {
+            ServerMessage::KeepAlive => continue,
+            msg => return msg,
+        }
+    }
+}
+
+/// Read and open the next non-keepalive sealed message from the server.
+async fn recv_sealed(stream: &mut BufReader<TcpStream>, opener: &mut Opener) -> ServerMessage {
+    loop {
+        let mut frame = Vec::new();
+        timeout(Duration::from_secs(5), stream.read_until(0, &mut frame))
+            .await
+            .expect("timed out waiting for a frame")
+            .unwrap();
+
+        let sealed = decode_frame::<SealedFrame>(&mut frame).unwrap();
+        match opener.open(&sealed).unwrap() {
+            ServerMessage::KeepAlive => continue,
+            msg => return msg,
+        }
+    }
+}
+
+/// Send a message to the server as the node.
+async fn send(stream: &mut BufReader<TcpStream>, msg: &NodeMessage) {
+    let mut buf = [0u8; MAX_FRAME_LEN];
+    let frame = encode_frame(msg, &mut buf).unwrap();
+    stream.get_mut().write_all(frame).await.unwrap();
+}
+
+#[tokio::test]
+async fn replays_modes_and_forwards_messages() {
+    let node = MockNode::bind().await;
+    let registry = NodeRegistry::new();
+    let id = registry.add(node.listener.local_addr().unwrap(), &[300, 150]);
+
+    let mut stream = node.accept().await;
+    assert_eq!(
+        recv(&mut stream).await,
+        ServerMessage::SetStripMode(0, StripMode::Effects)
+    );
+    assert_eq!(
+        recv(&mut stream).await,
+        ServerMessage::SetStripMode(1, StripMode::Effects)
+    );
+
+    registry.set_strip_mode(id, 1, StripMode::Dynamic).unwrap();
+    assert_eq!(
+        recv(&mut stream).await,
+        ServerMessage::SetStripMode(1, StripMode::Dynamic)
+    );
+
+    registry.shift_effect_mode(id, -1, None).unwrap();
+    assert_eq!(
+        recv(&mut stream).await,
+        ServerMessage::ShiftEffectMode(-1, None)
+    );
+
+    let wipe = Transition::new(TransitionKind::Wipe, 500);
+    registry.shift_effect_mode(id, 2, Some(wipe)).unwrap();
+    assert_eq!(
+        recv(&mut stream).await,
+        ServerMessage::ShiftEffectMode(2, Some(wipe))
+    );
+
+    let status = registry.get(id).unwrap();
+    assert!(status.connected);
+    assert_eq!(status.strips[1].leds, 150);
+    assert_eq!(status.strips[1].mode, StripMode::Dynamic);
+}
+
+#[tokio::test]
+async fn rejects_unknown_targets() {
+    let node = MockNode::bind().await;
+    let registry = NodeRegistry::new();
+    let id = registry.add(node.listener.local_addr().unwrap(), &[300]);
+
+    assert_eq!(
+        registry.set_strip_mode(id, 1, StripMode::Off),
+        Err(RegistryError::UnknownStrip(id, 1))
+    );
+    assert_eq!(
+        registry.shift_effect_mode(id + 1, 1, None),
+        Err(RegistryError::UnknownNode(id + 1))
+    );
+}
+
+#[tokio::test]
+async fn forwards_and_checks_registry_effects() {
+    let node = MockNode::bind().await;
+    let registry = NodeRegistry::new();
+    let id = registry.add(node.listener.local_addr().unwrap(), &[300]);
+
+    let mut stream = node.accept().await;
+    assert_eq!(
+        recv(&mut stream).await,
+        ServerMessage::SetStripMode(0, StripMode::Effects)
+    );
+
+    let fire = registry::find("fire").unwrap();
+    let black = Transition::new(TransitionKind::FadeThroughBlack, 800);
+    registry.select_effect(id, fire, Some(black)).unwrap();
+    assert_eq!(
+        recv(&mut stream).await,
+        ServerMessage::SelectEffect(fire, Some(black))
+    );
+
+    let meteor = registry::find("meteor").unwrap();
+    let red = ParamValue::Color(Rgb8::new(255, 0, 0));
+    registry.set_effect_param(id, meteor, 0, red).unwrap();
+    assert_eq!(
+        recv(&mut stream).await,
+        ServerMessage::SetEffectParam(meteor, 0, red)
+    );
+
+    assert_eq!(
+        registry.select_effect(id, registry::COUNT as u8, None),
+        Err(RegistryError::UnknownEffect(registry::COUNT as u8))
+    );
+    assert_eq!(
+        registry.set_effect_param(id, meteor, 0, ParamValue::Bool(true)),
+        Err(RegistryError::InvalidParam(meteor, ParamError::WrongType))
+    );
+    assert_eq!(
+        registry.set_effect_param(id, meteor, 99, red),
+        Err(RegistryError::InvalidParam(
+            meteor,
+            ParamError::UnknownParam(99)
+        ))
+    );
+
+    let status = registry.get(id).unwrap();
+    assert_eq!(status.effect, Some(fire));
+    assert_eq!(status.params, [(meteor, 0, red)]);
+
+    // shifting moves the effect the registry replays, wrapping around
+    registry
+        .shift_effect_mode(id, -(fire as i8) - 1, None)
+        .unwrap();
+    assert_eq!(
+        registry.get(id).unwrap().effect,
+        Some(registry::COUNT as u8 - 1)
+    );
+}
+
+#[tokio::test]
+async fn checks_and_replays_overlays() {
+    let node = MockNode::bind().await;
+    let registry = NodeRegistry::new();
+    let id = registry.add(node.listener.local_addr().unwrap(), &[300]);
+
+    let mut stream = node.accept().await;
+    assert_eq!(
+        recv(&mut stream).await,
+        ServerMessage::SetStripMode(0, StripMode::Effects)
+    );
+
+    // nodes are sent the whole stack, so one that missed a change still matches
+    let twinkle = registry::find("twinkle").unwrap();
+    let fire = registry::find("fire").unwrap();
+    let glitter = OverlayConfig {
+        effect: twinkle,
+        mode: BlendMode::Screen,
+        opacity: 0.5,
+    };
+    let flames = OverlayConfig {
+        effect: fire,
+        mode: BlendMode::Add,
+        opacity: 0.25,
+    };
+    registry
+        .add_overlay(id, 0, twinkle, BlendMode::Screen, 0.5)
+        .unwrap();
+    registry
+        .add_overlay(id, 0, fire, BlendMode::Add, 0.25)
+        .unwrap();
+    assert_eq!(
+        recv(&mut stream).await,
+        ServerMessage::SetOverlays(0, vec![glitter])
+    );
+    assert_eq!(
+        recv(&mut stream).await,
+        ServerMessage::SetOverlays(0, vec![glitter, flames])
+    );
+
+    assert_eq!(
+        registry.add_overlay(id, 0, twinkle, BlendMode::Over, 1.5),
+        Err(RegistryError::InvalidOpacity)
+    );
+    assert_eq!(
+        registry.add_overlay(id, 1, twinkle, BlendMode::Over, 1.0),
+        Err(RegistryError::UnknownStrip(id, 1))
+    );
+    assert_eq!(
+        registry.add_overlay(id, 0, registry::COUNT as u8, BlendMode::Over, 1.0),
+        Err(RegistryError::UnknownEffect(registry::COUNT as u8))
+    );
+    for _ in 2..MAX_OVERLAYS {
+        registry
+            .add_overlay(id, 0, twinkle, BlendMode::Max, 1.0)
+            .unwrap();
+        recv(&mut stream).await;
+    }
+    assert_eq!(
+        registry.add_overlay(id, 0, twinkle, BlendMode::Max, 1.0),
+        Err(RegistryError::TooManyOverlays(id, 0))
+    );
+
+    registry.clear_overlays(id, 0).unwrap();
+    assert_eq!(
+        recv(&mut stream).await,
+        ServerMessage::SetOverlays(0, Vec::new())
+    );
+    registry
+        .add_overlay(id, 0, twinkle, BlendMode::Screen, 0.5)
+        .unwrap();
+    recv(&mut stream).await;
+    drop(stream);
+
+    let mut stream = node.accept().await;
+    assert_eq!(
+        recv(&mut stream).await,
+        ServerMessage::SetStripMode(0, StripMode::Effects)
+    );
+    assert_eq!(
+        recv(&mut stream).await,
+        ServerMessage::SetOverlays(0, vec![glitter])
+    );
+}
+
+#[tokio::test]
+async fn reconnects_after_node_drops() {
+    let node = MockNode::bind().await;
+    let registry = NodeRegistry::new();
+    let id = registry.add(node.listener.local_addr().unwrap(), &[300]);
+
+    let mut stream = node.accept().await;
+    assert_eq!(
+        recv(&mut stream).await,
+        ServerMessage::SetStripMode(0, StripMode::Effects)
+    );
+    drop(stream);
+
+    // wait for the server to notice, then change the mode while disconnected
+    for _ in 0..50 {
+        if !registry.get(id).unwrap().connected {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    registry.set_strip_mode(id, 0, StripMode::Hybrid).unwrap();
+    let failsafe = StreamTimeout::new(2000, Fallback::Effects);
+    registry.set_stream_timeout(id, 0, Some(failsafe)).unwrap();
+    let twinkle = registry::find("twinkle").unwrap();
+    registry
+        .set_effect_param(id, twinkle, 1, ParamValue::F32(0.5))
+        .unwrap();
+    registry.select_effect(id, twinkle, None).unwrap();
+
+    let mut stream = node.accept().await;
+    assert_eq!(
+        recv(&mut stream).await,
+        ServerMessage::SetStripMode(0, StripMode::Hybrid)
+    );
+    assert_eq!(
+        recv(&mut stream).await,
+        ServerMessage::SetStreamTimeout(0, Some(failsafe))
+    );
+    assert_eq!(
+        recv(&mut stream).await,
+        ServerMessage::SetEffectParam(twinkle, 1, ParamValue::F32(0.5))
+    );
+    assert_eq!(
+        recv(&mut stream).await,
+        ServerMessage::SelectEffect(twinkle, Some(Transition::new(TransitionKind::Crossfade, 0)))
+    );
+}
+
+#[tokio::test]
+async fn configures_strips_and_replays_them() {
+    let node = MockNode::bind().await;
+    let registry = NodeRegistry::new();
+    let id = registry.add(node.listener.local_addr().unwrap(), &[300]);
+
+    let mut stream = node.accept().await;
+    assert_eq!(
+        recv(&mut stream).await,
+        ServerMessage::SetStripMode(0, StripMode::Effects)
+    );
+
+    let config = StripConfig {
+        leds: 120,
+        rev: true,
+        order: ColorOrder::Rgb,
+        chipset: Chipset::Ws2811,
+    };
+    registry.configure_strip(id, 0, config).unwrap();
+    assert_eq!(
+        recv(&mut stream).await,
+        ServerMessage::ConfigureStrip(0, config)
+    );
+    assert_eq!(
+        registry.configure_strip(id, 1, config),
+        Err(RegistryError::UnknownStrip(id, 1))
+    );
+
+    // the LED count only changes once the node has rewired the strip
+    assert_eq!(registry.get(id).unwrap().strips[0].leds, 300);
+    let mut events = registry.subscribe();
+    send(&mut stream, &NodeMessage::StripConfigured(0, config)).await;
+    timeout(Duration::from_secs(5), events.recv())
+        .await
+        .expect("timed out waiting for the ack")
+        .unwrap();
+    assert_eq!(registry.get(id).unwrap().strips[0].leds, 120);
+    assert_eq!(registry.get(id).unwrap().strips[0].config, Some(config));
+
+    // a config the node refuses is never kept
+    let too_long = StripConfig {
+        leds: 10_000,
+        ..config
+    };
+    registry.configure_strip(id, 0, too_long).unwrap();
+    assert_eq!(
+        recv(&mut stream).await,
+        ServerMessage::ConfigureStrip(0, too_long)
+    );
+    send(&mut stream, &NodeMessage::Error(NodeError::StripTooLong(0))).await;
+    timeout(Duration::from_secs(5), events.recv())
+        .await
+        .expect("timed out waiting for the error")
+        .unwrap();
+    assert_eq!(registry.get(id).unwrap().strips[0].config, Some(config));
+
+    // a reconnecting node is rewired before anything else
+    drop(stream);
+    let mut stream = node.accept().await;
+    assert_eq!(
+        recv(&mut stream).await,
+        ServerMessage::ConfigureStrip(0, config)
+    );
+    assert_eq!(
+        recv(&mut stream).await,
+        ServerMessage::SetStripMode(0, StripMode::Effects)
+    );
+}
+
+#[tokio::test]
+async fn records_node_telemetry() {
+    let node = MockNode::bind().await;
+    let registry = NodeRegistry::new();
+    let mut events = registry.subscribe();
+    let id = registry.add(node.listener.local_addr().unwrap(), &[300]);
+
+    let hello = NodeHello {
+        version: Version::new(0, 1, 0),
+        mcu: Mcu::Esp32c6,
+        num_strips: 2,
+    };
+    let heartbeat = Heartbeat {
+        uptime_ms: 5000,
+        free_heap: 40000,
+        frame_rate: 60.0,
+        dropped_packets: 3,
+        stale_packets: 1,
+        rejected_packets: 0,
+        rssi: Some(-55),
+    };
+    let messages = [
+        NodeMessage::Hello(hello),
+        NodeMessage::Strip(
+            0,
+            StripInfo {
+                leds: 120,
+                rev: false,
+            },
+        ),
+        NodeMessage::Strip(
+            1,
+            StripInfo {
+                leds: 60,
+                rev: true,
+            },
+        ),
+        NodeMessage::Heartbeat(heartbeat),
+        NodeMessage::Error(NodeError::UnknownStrip(4)),
+    ];
+
+    let mut stream = node.accept().await;
+    for msg in &messages {
+        send(&mut stream, msg).await;
+    }
+
+    for msg in messages {
+        let event = timeout(Duration::from_secs(5), events.recv())
+            .await
+            .expect("timed out waiting for an event")
+            .unwrap();
+        assert_eq!(event, NodeEvent { id, msg });
+    }
+
+    let status = registry.get(id).unwrap();
+    assert_eq!(status.hello, Some(hello));
+    assert_eq!(status.heartbeat, Some(heartbeat));
+    assert_eq!(status.last_error, Some(NodeError::UnknownStrip(4)));
+    assert_eq!(
+        status.strips.iter().map(|s| s.leds).collect::<Vec<_>>(),
+        [120, 60]
+    );
+
+    // the strip the config didn't know about gets brought in line
+    assert_eq!(
+        recv(&mut stream).await,
+        ServerMessage::SetStripMode(0, StripMode::Effects)
+    );
+    assert_eq!(
+        recv(&mut stream).await,
+        ServerMessage::SetStripMode(1, StripMode::Effects)
+    );
+}
+
+#[tokio::test]
+async fn answers_time_requests() {
+    let node = MockNode::bind().await;
+    let registry = NodeRegistry::new();
+    registry.add(node.listener.local_addr().unwrap(), &[]);
+
+    let mut stream = node.accept().await;
+    let before = server::clock::now();
+    send(&mut stream, &NodeMessage::TimeRequest(42)).await;
+    let ServerMessage::TimeResponse(sent, time) = recv(&mut stream).await else {
+        panic!("expected a time response");
+    };
+    assert_eq!(sent, 42);
+    assert!((before..=server::clock::now()).contains(&time));
+}
+
+#[tokio::test]
+async fn seals_sessions_to_nodes_holding_the_key() {
+    let key = Key::new([0x42; 32]);
+    let node = MockNode::bind().await;
+    let registry = NodeRegistry::with_key(Some(key.clone()));
+    let id = registry.add(node.listener.local_addr().unwrap(), &[300]);
+
+    let hello = NodeHello {
+        version: Version::new(0, 1, 0),
+        mcu: Mcu::Host,
+        num_strips: 1,
+    };
+    let mut stream = node.accept().await;
+    send(&mut stream, &NodeMessage::Hello(hello)).await;
+    send(&mut stream, &NodeMessage::Challenge(0xfeed)).await;
+
+    // everything from the replay on is sealed with the node's nonce
+    let mut opener = Opener::new(key.clone(), 0xfeed);
+    assert_eq!(
+        recv_sealed(&mut stream, &mut opener).await,
+        ServerMessage::SetStripMode(0, StripMode::Effects)
+    );
+    send(&mut stream, &NodeMessage::TimeRequest(42)).await;
+    assert!(matches!(
+        recv_sealed(&mut stream, &mut opener).await,
+        ServerMessage::TimeResponse(42, _)
+    ));
+    registry.set_strip_mode(id, 0, StripMode::Off).unwrap();
+    assert_eq!(
+        recv_sealed(&mut stream, &mut opener).await,
+        ServerMessage::SetStripMode(0, StripMode::Off)
+    );
+    assert_eq!(registry.get(id).unwrap().hello, Some(hello));
+
+    // packets streamed to the node are signed for this session only
+    let ip = node.listener.local_addr().unwrap().ip();
+    assert_eq!(
+        registry.stream_keys().get(ip),
+        Some(key.for_session(0xfeed))
+    );
+    drop(stream);
+    for _ in 0..250 {
+        if registry.stream_keys().get(ip).is_none() {
+            return;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    panic!("the session's stream key outlived it");
+}
+
+#[tokio::test]
+async fn waits_for_the_challenge_before_replaying() {
+    let node = MockNode::bind().await;
+    let registry = NodeRegistry::with_key(Some(Key::new([0x42; 32])));
+    registry.add(node.listener.local_addr().unwrap(), &[300]);
+
+    let mut stream = node.accept().await;
+    let mut frame = Vec::new();
+    let early = timeout(Duration::from_millis(200), stream.read_until(0, &mut frame)).await;
+    assert!(early.is_err(), "nothing goes out before the handshake");
+
+    // a node without a key ends its handshake without a challenge, and gets
+    // the session in the clear
+    send(&mut stream, &NodeMessage::TimeRequest(42)).await;
+    assert_eq!(
+        recv(&mut stream).await,
+        ServerMessage::SetStripMode(0, StripMode::Effects)
+    );
+    assert!(matches!(
+        recv(&mut stream).await,
+        ServerMessage::TimeResponse(42, _)
+    ));
+}
+
+#[test]
+fn reads_the_key_from_the_config() {
+    assert_eq!(Config::parse("").unwrap().key, None);
+
+    let hex = "42".repeat(32);
+    let config = Config::parse(&format!("key = \"{hex}\"")).unwrap();
+    assert_eq!(config.key, Some(Key::new([0x42; 32])));
+    assert!(!format!("{config:?}").contains(&hex));
+
+    assert!(Config::parse("key = \"4242\"").is_err());
+}
@@ -0,0 +1,241 @@
+use std::time::Duration;
+
+use common::{
+    color::Rgb8,
+    net::udp::{UdpCommand, UdpDecoder},
+};
+use server::{
+    config::{Config, NodeConfig, OpcMapping},
+    opc::{
+        self, CMD_SET_PIXELS, CMD_SYSTEM_EXCLUSIVE, ColorCorrection, OpcMessage, OpcRouteError,
+        OpcRouter,
+    },
+    udp::UdpSender,
+};
+use tokio::{
+    io::AsyncWriteExt,
+    net::{TcpListener, TcpStream, UdpSocket},
+    time::timeout,
+};
+
+fn node(strips: &[usize]) -> NodeConfig {
+    NodeConfig {
+        addr: "127.0.0.1:1338".parse().unwrap(),
+        strips: strips.to_vec(),
+    }
+}
+
+fn mapping(channel: u8, strip: u8) -> OpcMapping {
+    OpcMapping {
+        channel,
+        first: 0,
+        node: "127.0.0.1:1338".parse().unwrap(),
+        strip,
+        offset: 0,
+        pixels: None,
+    }
+}
+
+fn set_pixels(channel: u8, colors: &[Rgb8]) -> OpcMessage {
+    OpcMessage {
+        channel,
+        command: CMD_SET_PIXELS,
+        data: colors.iter().flat_map(|c| [c.r, c.g, c.b]).collect(),
+    }
+}
+
+/// Fadecandy's color correction message, as its Python client sends it.
+fn correction(channel: u8, json: &str) -> OpcMessage {
+    let mut data = vec![0x00, 0x01, 0x00, 0x01];
+    data.extend(json.as_bytes());
+    OpcMessage {
+        channel,
+        command: CMD_SYSTEM_EXCLUSIVE,
+        data,
+    }
+}
+
+#[tokio::test]
+async fn reads_messages_off_a_stream() {
+    let msg = set_pixels(3, &[Rgb8::new(1, 2, 3)]);
+    let mut bytes = msg.to_bytes();
+    assert_eq!(bytes[..4], [3, 0, 0, 3]);
+    bytes.extend(correction(0, "{}").to_bytes());
+
+    let mut reader = &bytes[..];
+    assert_eq!(OpcMessage::read(&mut reader).await.unwrap(), Some(msg));
+    assert_eq!(
+        OpcMessage::read(&mut reader).await.unwrap(),
+        Some(correction(0, "{}"))
+    );
+    assert_eq!(OpcMessage::read(&mut reader).await.unwrap(), None);
+
+    // a message cut off mid-data is an error, not a clean disconnect
+    let bytes = [1, 0, 0, 6, 255, 0, 0];
+    assert!(OpcMessage::read(&mut &bytes[..]).await.is_err());
+}
+
+#[test]
+fn routes_channels_onto_strips() {
+    let mappings = [
+        mapping(1, 0),
+        OpcMapping {
+            first: 1,
+            offset: 2,
+            pixels: Some(1),
+            ..mapping(2, 1)
+        },
+    ];
+    let mut router = OpcRouter::new(&mappings, &[node(&[3, 4])], 1337).unwrap();
+
+    let colors = [Rgb8::new(1, 1, 1), Rgb8::new(2, 2, 2), Rgb8::new(3, 3, 3)];
+    assert!(router.apply(&set_pixels(1, &colors)).unwrap());
+    assert!(router.apply(&set_pixels(2, &colors)).unwrap());
+    assert!(!router.apply(&set_pixels(9, &colors)).unwrap());
+
+    let buffers = router.buffers().collect::<Vec<_>>();
+    assert_eq!(buffers[0].0, "127.0.0.1:1337".parse().unwrap());
+    assert_eq!(buffers[0].2, colors);
+    assert_eq!(
+        buffers[1].2,
+        [Rgb8::zero(), Rgb8::zero(), colors[1], Rgb8::zero()]
+    );
+
+    // channel 0 goes to every channel
+    assert!(router.apply(&set_pixels(0, &[Rgb8::new(9, 9, 9)])).unwrap());
+    let buffers = router.buffers().collect::<Vec<_>>();
+    assert_eq!(buffers[0].2[0], Rgb8::new(9, 9, 9));
+    assert_eq!(buffers[1].2[2], colors[1]);
+}
+
+#[test]
+fn corrects_colors_per_channel() {
+    let mut router =
+        OpcRouter::new(&[mapping(1, 0), mapping(2, 1)], &[node(&[1, 1])], 1337).unwrap();
+
+    router
+        .apply(&correction(
+            2,
+            r#"{"gamma": 2.0, "whitepoint": [1.0, 0.5, 0.0]}"#,
+        ))
+        .unwrap();
+    assert_eq!(router.correction(1), ColorCorrection::default());
+    assert_eq!(router.correction(2).gamma, 2.0);
+
+    let gray = Rgb8::new(128, 128, 128);
+    router.apply(&set_pixels(1, &[gray])).unwrap();
+    router.apply(&set_pixels(2, &[gray])).unwrap();
+    let buffers = router.buffers().collect::<Vec<_>>();
+    assert_eq!(buffers[0].2, [gray]);
+    assert_eq!(buffers[1].2, [Rgb8::new(64, 32, 0)]);
+
+    // channel 0 resets every channel, and missing fields take their defaults
+    router.apply(&correction(0, r#"{"gamma": 1.0}"#)).unwrap();
+    assert_eq!(router.correction(2), ColorCorrection::default());
+
+    assert!(router.apply(&correction(1, "not json")).is_err());
+    assert!(router.apply(&correction(1, "")).is_err());
+    // other systems' messages are ignored
+    let mut other = correction(1, "whatever");
+    other.data[1] = 0x02;
+    assert!(!router.apply(&other).unwrap());
+}
+
+#[test]
+fn rejects_bad_mappings() {
+    let nodes = [node(&[100])];
+    let other = "10.0.0.1:1338".parse().unwrap();
+    let cases = [
+        (
+            OpcMapping {
+                node: other,
+                ..mapping(1, 0)
+            },
+            OpcRouteError::UnknownNode(other),
+        ),
+        (mapping(1, 1), OpcRouteError::UnknownStrip(nodes[0].addr, 1)),
+        (mapping(0, 0), OpcRouteError::BadChannel(0)),
+        (
+            OpcMapping {
+                offset: 90,
+                pixels: Some(20),
+                ..mapping(1, 0)
+            },
+            OpcRouteError::PastStrip(nodes[0].addr, 0),
+        ),
+        (
+            OpcMapping {
+                offset: 1,
+                pixels: Some(usize::MAX),
+                ..mapping(1, 0)
+            },
+            OpcRouteError::PastStrip(nodes[0].addr, 0),
+        ),
+    ];
+    for (mapping, error) in cases {
+        assert_eq!(OpcRouter::new(&[mapping], &nodes, 1337).err(), Some(error));
+    }
+}
+
+#[test]
+fn parses_config_table() {
+    let config = Config::parse(
+        r#"
+        [[nodes]]
+        addr = "127.0.0.1:1338"
+        strips = [300]
+
+        [opc]
+        listen = "0.0.0.0:7890"
+
+        [[opc.map]]
+        channel = 1
+        node = "127.0.0.1:1338"
+        strip = 0
+        "#,
+    )
+    .unwrap();
+
+    assert_eq!(config.opc.listen, Some("0.0.0.0:7890".parse().unwrap()));
+    assert_eq!(config.opc.map, [mapping(1, 0)]);
+    OpcRouter::new(&config.opc.map, &config.nodes, 1337).unwrap();
+}
+
+#[tokio::test]
+async fn relays_a_client_to_nodes() {
+    let node_socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let port = node_socket.local_addr().unwrap().port();
+    let router = OpcRouter::new(&[mapping(1, 0)], &[node(&[2])], port).unwrap();
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(opc::serve(
+        listener,
+        router,
+        UdpSender::bind().await.unwrap(),
+    ));
+
+    let mut client = TcpStream::connect(addr).await.unwrap();
+    let red = Rgb8::new(255, 0, 0);
+    client
+        .write_all(&correction(1, r#"{"whitepoint": [0.5, 1.0, 1.0]}"#).to_bytes())
+        .await
+        .unwrap();
+    client
+        .write_all(&set_pixels(1, &[red, red]).to_bytes())
+        .await
+        .unwrap();
+
+    let mut buf = [0u8; 2048];
+    let n = timeout(Duration::from_secs(5), node_socket.recv(&mut buf))
+        .await
+        .expect("timed out waiting for a packet")
+        .unwrap();
+    match UdpDecoder::new(&buf[..n]).unwrap().collect::<Vec<_>>()[..] {
+        [Ok(UdpCommand::SetBufferToMany { strip, colors })] => {
+            assert_eq!(strip, 0);
+            assert_eq!(colors.iter().collect::<Vec<_>>(), [Rgb8::new(128, 0, 0); 2]);
+        }
+        ref other => panic!("unexpected packet: {other:?}"),
+    }
+}
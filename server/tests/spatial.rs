@@ -0,0 +1,158 @@
+use std::{sync::Arc, time::Duration};
+
+use common::{
+    color::Rgb8,
+    effect::spatial::{PlaneSweep, SpatialEffect},
+    math::Vec3,
+    net::{
+        ddp::DdpPacket,
+        udp::{UdpCommand, UdpDecoder},
+    },
+};
+use server::{
+    ddp::DdpSender,
+    layout::LayoutFile,
+    spatial::{Output, SharedEffect, SpatialRenderer, stream},
+    udp::UdpSender,
+};
+use tokio::{net::UdpSocket, sync::watch, time::timeout};
+
+async fn recv(node: &UdpSocket) -> (u8, Vec<Rgb8>) {
+    let mut buf = [0u8; 2048];
+    let n = timeout(Duration::from_secs(5), node.recv(&mut buf))
+        .await
+        .expect("timed out waiting for a packet")
+        .unwrap();
+    match UdpDecoder::new(&buf[..n]).unwrap().collect::<Vec<_>>()[..] {
+        [Ok(UdpCommand::SetBufferToMany { strip, colors })] => (strip, colors.iter().collect()),
+        ref other => panic!("unexpected packet {other:?}"),
+    }
+}
+
+fn line(leds: usize, y: f32) -> Vec<Vec3> {
+    (0..leds).map(|i| Vec3::new(i as f32, y, 0.0)).collect()
+}
+
+#[test]
+fn renders_across_strips() {
+    let mut renderer = SpatialRenderer::new();
+    let addr = "127.0.0.1:1337".parse().unwrap();
+    renderer.add_strip(addr, 0, line(8, 0.0));
+    renderer.add_strip(addr, 1, line(8, 5.0));
+
+    let sweep = PlaneSweep::default();
+    renderer.render(&sweep, 2500);
+
+    let buffers = renderer.buffers().collect::<Vec<_>>();
+    assert_eq!(buffers.len(), 2);
+    // a sweep along x lights both strips identically
+    assert_eq!(buffers[0].2, buffers[1].2);
+    for (i, px) in buffers[0].2.iter().enumerate() {
+        assert_eq!(*px, sweep.sample(Vec3::new(i as f32, 0.0, 0.0), 2500));
+    }
+}
+
+#[test]
+fn targets_every_laid_out_strip() {
+    let layout = LayoutFile::from_toml(
+        r#"
+        [[nodes]]
+        addr = "10.0.0.1:1338"
+        strips = [
+            { leds = 1, positions = [[0.0, 0.0, 0.0]] },
+            { leds = 2, positions = [[0.0, 0.0, 0.0], [1.0, 0.0, 0.0]] },
+        ]
+        "#,
+    )
+    .unwrap();
+
+    let renderer = SpatialRenderer::from_layout(&layout, 1337).unwrap();
+    let targets = renderer
+        .buffers()
+        .map(|(addr, strip, buf)| (addr.to_string(), strip, buf.len()))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        targets,
+        [
+            ("10.0.0.1:1337".to_string(), 0, 1),
+            ("10.0.0.1:1337".to_string(), 1, 2)
+        ]
+    );
+}
+
+#[tokio::test]
+async fn streams_the_current_effect() {
+    let node = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let mut renderer = SpatialRenderer::new();
+    renderer.add_strip(node.local_addr().unwrap(), 3, line(16, 0.0));
+
+    let (effect, rx) = watch::channel::<Option<SharedEffect>>(None);
+    let output = Output::Lightspace(UdpSender::bind().await.unwrap());
+    let task = tokio::spawn(stream(renderer, output, rx, None));
+
+    effect.send_replace(Some(Arc::new(PlaneSweep::default())));
+    let (strip, colors) = recv(&node).await;
+    assert_eq!(strip, 3);
+    assert_eq!(colors.len(), 16);
+
+    drop(effect);
+    timeout(Duration::from_secs(5), task)
+        .await
+        .expect("stream did not stop")
+        .unwrap();
+}
+
+#[tokio::test]
+async fn sends_a_ddp_frame_per_node() {
+    let node = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let sender = DdpSender::bind().await.unwrap();
+
+    // 500 LEDs take two packets, only the last of which pushes
+    let mut renderer = SpatialRenderer::new();
+    let addr = node.local_addr().unwrap();
+    renderer.add_strip(addr, 1, line(200, 5.0));
+    renderer.add_strip(addr, 0, line(300, 0.0));
+    renderer.render(&PlaneSweep::default(), 2500);
+    renderer.send(&Output::Ddp(sender)).await.unwrap();
+
+    let mut strips = [vec![Rgb8::zero(); 300], vec![Rgb8::zero(); 200]];
+    let mut buf = [0u8; 2048];
+    for push in [false, true] {
+        let n = timeout(Duration::from_secs(5), node.recv(&mut buf))
+            .await
+            .expect("timed out waiting for a packet")
+            .unwrap();
+        let packet = DdpPacket::parse(&buf[..n]).unwrap();
+        assert_eq!(packet.push, push);
+        packet.write(strips.iter_mut().map(|s| &mut s[..]));
+    }
+
+    // strips are laid out in index order, whatever order they were added in
+    let buffers = renderer.buffers().collect::<Vec<_>>();
+    assert_eq!(strips[0], buffers[1].2);
+    assert_eq!(strips[1], buffers[0].2);
+}
+
+#[tokio::test]
+async fn refuses_ddp_for_a_node_missing_a_strip() {
+    let node = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let addr = node.local_addr().unwrap();
+    let mut renderer = SpatialRenderer::new();
+    renderer.add_strip(addr, 1, line(8, 0.0));
+    renderer.add_strip(addr, 2, line(8, 5.0));
+    assert_eq!(renderer.ddp_gap(), Some((addr, 0)));
+
+    // strip 1 would land where strip 0 goes, so nothing is sent
+    let sender = DdpSender::bind().await.unwrap();
+    let result = renderer.send(&Output::Ddp(sender)).await;
+    assert_eq!(result.unwrap_err().kind(), std::io::ErrorKind::InvalidInput);
+    let mut buf = [0u8; 64];
+    assert!(
+        timeout(Duration::from_millis(100), node.recv(&mut buf))
+            .await
+            .is_err()
+    );
+
+    renderer.add_strip(addr, 0, line(8, 10.0));
+    assert_eq!(renderer.ddp_gap(), None);
+}
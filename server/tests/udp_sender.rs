@@ -0,0 +1,215 @@
+use std::time::Duration;
+
+use common::{
+    color::{Rgb8, Rgba8},
+    net::{
+        auth::{AuthError, Key, PacketOpener, check_packet},
+        udp::{SequenceFilter, UdpCommand, UdpDecoder},
+    },
+};
+use server::udp::{StreamKeys, UdpSender};
+use tokio::{net::UdpSocket, time::timeout};
+
+async fn recv(node: &UdpSocket) -> Vec<u8> {
+    let mut buf = [0u8; 2048];
+    let n = timeout(Duration::from_secs(5), node.recv(&mut buf))
+        .await
+        .expect("timed out waiting for a packet")
+        .unwrap();
+    buf[..n].to_vec()
+}
+
+#[tokio::test]
+async fn sends_decodable_packets() {
+    let node = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let addr = node.local_addr().unwrap();
+    let sender = UdpSender::bind().await.unwrap();
+
+    let colors = (0..=255).map(Rgb8::gray).collect::<Vec<_>>();
+    sender.set_buffer(addr, 1, &colors).await.unwrap();
+    let packet = recv(&node).await;
+    match UdpDecoder::new(&packet).unwrap().collect::<Vec<_>>()[..] {
+        [
+            Ok(UdpCommand::SetBufferToMany {
+                strip: 1,
+                colors: c,
+            }),
+        ] => {
+            assert_eq!(c.iter().collect::<Vec<_>>(), colors);
+        }
+        ref other => panic!("unexpected packet: {other:?}"),
+    }
+
+    sender.fill(addr, 0, Rgb8::new(1, 2, 3)).await.unwrap();
+    let packet = recv(&node).await;
+    assert_eq!(
+        UdpDecoder::new(&packet).unwrap().collect::<Vec<_>>(),
+        [Ok(UdpCommand::SetBufferToSingle {
+            strip: 0,
+            color: Rgb8::new(1, 2, 3)
+        })]
+    );
+
+    let colors = [Rgba8::new(10, 20, 30, 40); 4];
+    sender.set_buffer_alpha(addr, 0, &colors).await.unwrap();
+    let packet = recv(&node).await;
+    match UdpDecoder::new(&packet).unwrap().collect::<Vec<_>>()[..] {
+        [
+            Ok(UdpCommand::SetBufferToManyAlpha {
+                strip: 0,
+                colors: c,
+            }),
+        ] => {
+            assert_eq!(c.iter().collect::<Vec<_>>(), colors);
+        }
+        ref other => panic!("unexpected packet: {other:?}"),
+    }
+}
+
+#[tokio::test]
+async fn sends_partial_updates() {
+    let node = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let addr = node.local_addr().unwrap();
+    let sender = UdpSender::bind().await.unwrap();
+
+    sender.set_pixel(addr, 1, 299, Rgb8::gray(9)).await.unwrap();
+    let packet = recv(&node).await;
+    assert_eq!(
+        UdpDecoder::new(&packet).unwrap().collect::<Vec<_>>(),
+        [Ok(UdpCommand::SetSinglePixel {
+            strip: 1,
+            index: 299,
+            color: Rgb8::gray(9)
+        })]
+    );
+
+    sender
+        .fill_alpha(addr, 0, Rgba8::new(1, 2, 3, 4))
+        .await
+        .unwrap();
+    let packet = recv(&node).await;
+    assert_eq!(
+        UdpDecoder::new(&packet).unwrap().collect::<Vec<_>>(),
+        [Ok(UdpCommand::SetBufferToSingleAlpha {
+            strip: 0,
+            color: Rgba8::new(1, 2, 3, 4)
+        })]
+    );
+
+    let colors = [Rgb8::new(5, 6, 7); 3];
+    sender.set_range(addr, 0, 10, &colors).await.unwrap();
+    let packet = recv(&node).await;
+    match UdpDecoder::new(&packet).unwrap().collect::<Vec<_>>()[..] {
+        [
+            Ok(UdpCommand::SetPixelRange {
+                strip: 0,
+                start: 10,
+                colors: c,
+            }),
+        ] => assert_eq!(c.iter().collect::<Vec<_>>(), colors),
+        ref other => panic!("unexpected packet: {other:?}"),
+    }
+
+    let colors = [Rgba8::new(5, 6, 7, 8); 2];
+    sender.set_range_alpha(addr, 1, 20, &colors).await.unwrap();
+    let packet = recv(&node).await;
+    match UdpDecoder::new(&packet).unwrap().collect::<Vec<_>>()[..] {
+        [
+            Ok(UdpCommand::SetPixelRangeAlpha {
+                strip: 1,
+                start: 20,
+                colors: c,
+            }),
+        ] => assert_eq!(c.iter().collect::<Vec<_>>(), colors),
+        ref other => panic!("unexpected packet: {other:?}"),
+    }
+}
+
+#[tokio::test]
+async fn senders_share_one_sequence() {
+    let node = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let addr = node.local_addr().unwrap();
+    // like spatial output and DMX streaming to the same strip
+    let (first, second) = (
+        UdpSender::bind().await.unwrap(),
+        UdpSender::bind().await.unwrap(),
+    );
+
+    let mut filter = SequenceFilter::new();
+    for sender in [&first, &second, &first] {
+        sender.fill(addr, 0, Rgb8::gray(1)).await.unwrap();
+        let packet = recv(&node).await;
+        let sequence = UdpDecoder::new(&packet).unwrap().header().sequence;
+        assert!(
+            filter.accept(sequence),
+            "packet {sequence} dropped as stale"
+        );
+    }
+}
+
+#[tokio::test]
+async fn refuses_packets_that_do_not_fit() {
+    let node = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let sender = UdpSender::bind().await.unwrap();
+
+    let colors = vec![Rgb8::zero(); 1000];
+    assert!(
+        sender
+            .set_buffer(node.local_addr().unwrap(), 0, &colors)
+            .await
+            .is_err()
+    );
+}
+
+#[tokio::test]
+async fn signs_packets_for_nodes_with_a_session() {
+    let node = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let addr = node.local_addr().unwrap();
+    let key = Key::new([0x42; 32]).for_session(7);
+    let keys = StreamKeys::default();
+    let sender = UdpSender::bind().await.unwrap().with_keys(keys.clone());
+
+    // no session yet, so nothing to sign with
+    sender.fill(addr, 0, Rgb8::new(1, 2, 3)).await.unwrap();
+    let packet = recv(&node).await;
+    let mut opener = PacketOpener::new(key.clone());
+    assert_eq!(
+        check_packet(Some(&mut opener), &packet),
+        Err(AuthError::Unsigned)
+    );
+
+    keys.insert(addr.ip(), key.clone());
+    sender.fill(addr, 0, Rgb8::new(1, 2, 3)).await.unwrap();
+    let packet = recv(&node).await;
+    let checked = check_packet(Some(&mut opener), &packet).unwrap();
+    // the session numbers its own packets
+    assert_eq!(UdpDecoder::new(checked).unwrap().header().sequence, 0);
+    assert_eq!(
+        UdpDecoder::new(checked).unwrap().collect::<Vec<_>>(),
+        [Ok(UdpCommand::SetBufferToSingle {
+            strip: 0,
+            color: Rgb8::new(1, 2, 3)
+        })]
+    );
+    let other = Key::new([0x42; 32]).for_session(8);
+    assert_eq!(
+        check_packet(Some(&mut PacketOpener::new(other)), &packet),
+        Err(AuthError::BadMac)
+    );
+
+    // a packet filled to the brim still has room for its MAC, and another
+    // sender carries on the session's count
+    let colors = vec![Rgb8::gray(7); 485];
+    let other_sender = UdpSender::bind().await.unwrap().with_keys(keys.clone());
+    other_sender.set_buffer(addr, 0, &colors).await.unwrap();
+    let packet = recv(&node).await;
+    let checked = check_packet(Some(&mut opener), &packet).unwrap();
+    assert_eq!(UdpDecoder::new(checked).unwrap().header().sequence, 1);
+
+    // a new session counts from the start again
+    keys.insert(addr.ip(), key.clone());
+    sender.fill(addr, 0, Rgb8::new(1, 2, 3)).await.unwrap();
+    let packet = recv(&node).await;
+    let checked = check_packet(Some(&mut PacketOpener::new(key)), &packet).unwrap();
+    assert_eq!(UdpDecoder::new(checked).unwrap().header().sequence, 0);
+}
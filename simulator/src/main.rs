@@ -0,0 +1,127 @@
+use common::{
+    effect::StripInfo,
+    net::auth::Key,
+    preview::{FpsCounter, Preview, PreviewRow, PreviewStatus},
+};
+use simulator::node::{FRAME_INTERVAL, NodeConfig, VirtualNode};
+use tokio::{
+    io::{self, AsyncWriteExt},
+    time::{self, MissedTickBehavior},
+};
+
+const USAGE: &str = "usage: simulator [--ip <addr>] [--tcp <port>] [--udp <port>] [--ddp <port>] [--strips <leds>[r],...] [--seed <n>] [--name <name>] [--beacon <addr>|off] [--key <hex>] [--preview]";
+
+#[tokio::main]
+async fn main() {
+    let (config, preview) = match parse_args(std::env::args().skip(1)) {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("error: {e}\n{USAGE}");
+            std::process::exit(2);
+        }
+    };
+
+    let node = match VirtualNode::bind(config).await {
+        Ok(node) => node,
+        Err(e) => {
+            eprintln!("error: failed to bind virtual node: {e}");
+            std::process::exit(1);
+        }
+    };
+    println!(
+        "virtual node listening on {} (tcp), {} (udp) and {} (ddp) with {} strip(s)",
+        node.tcp_addr(),
+        node.udp_addr(),
+        node.ddp_addr(),
+        node.num_strips()
+    );
+
+    match preview {
+        Some(preview) => {
+            tokio::select! {
+                _ = draw(&node, preview) => (),
+                _ = tokio::signal::ctrl_c() => (),
+            }
+        }
+        None => _ = tokio::signal::ctrl_c().await,
+    }
+}
+
+/// Draw the node's strips to the terminal every frame.
+async fn draw(node: &VirtualNode, preview: Preview) {
+    let mut stdout = io::stdout();
+    let mut fps = FpsCounter::new();
+    let mut frames = node.frames();
+
+    let mut interval = time::interval(FRAME_INTERVAL);
+    interval.set_missed_tick_behavior(MissedTickBehavior::Skip);
+    loop {
+        interval.tick().await;
+
+        let pixels = (0..node.num_strips())
+            .map(|i| node.pixels(i).unwrap_or_default())
+            .collect::<Vec<_>>();
+        let rows = pixels.iter().enumerate().map(|(i, pixels)| PreviewRow {
+            label: format!("strip {i}"),
+            mode: node.mode(i),
+            pixels,
+        });
+
+        let rendered = node.frames();
+        let status = PreviewStatus {
+            effect: Some(node.effect()),
+            fps: fps.tick(rendered.wrapping_sub(frames)),
+        };
+        frames = rendered;
+
+        let frame = preview.draw(rows, status);
+        _ = stdout.write_all(frame.as_bytes()).await;
+        _ = stdout.flush().await;
+    }
+}
+
+fn parse_args(
+    mut args: impl Iterator<Item = String>,
+) -> Result<(NodeConfig, Option<Preview>), Box<dyn std::error::Error>> {
+    let mut config = NodeConfig::default();
+    let mut preview = None;
+    while let Some(flag) = args.next() {
+        if flag == "--preview" {
+            preview = Some(Preview::default());
+            continue;
+        }
+
+        let value = args
+            .next()
+            .ok_or_else(|| format!("missing value for `{flag}`"))?;
+        match flag.as_str() {
+            "--ip" => config.ip = value.parse()?,
+            "--tcp" => config.tcp_port = value.parse()?,
+            "--udp" => config.udp_port = value.parse()?,
+            "--ddp" => config.ddp_port = value.parse()?,
+            "--seed" => config.seed = value.parse()?,
+            "--name" => config.name = value,
+            "--beacon" => {
+                config.beacon = match value.as_str() {
+                    "off" => None,
+                    addr => Some(addr.parse()?),
+                }
+            }
+            "--key" => {
+                config.key = Some(Key::parse(&value).ok_or("key must be 64 hex digits")?);
+            }
+            // a trailing `r` marks a reversed strip, e.g. `300r,150`
+            "--strips" => {
+                config.strips = value
+                    .split(',')
+                    .map(|strip| match strip.strip_suffix('r') {
+                        Some(leds) => leds.parse().map(|leds| StripInfo { leds, rev: true }),
+                        None => strip.parse().map(|leds| StripInfo { leds, rev: false }),
+                    })
+                    .collect::<Result<_, _>>()?;
+            }
+            _ => return Err(format!("unknown flag `{flag}`").into()),
+        }
+    }
+    Ok((config, preview))
+}
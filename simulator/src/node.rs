@@ -0,0 +1,853 @@
+use std::{
+    hash::{BuildHasher, Hasher, RandomState},
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use common::{
+    clock::ClockSync,
+    color::{BlendMode, ColorOrder, Rgb8, RgbaF32},
+    effect::{
+        StripInfo,
+        fx::{Effects, MAX_OVERLAYS},
+        layer::{blend_layer, flatten},
+        param::check,
+        registry,
+        transition::{Transition, TransitionKind},
+    },
+    math::Rng,
+    net::{
+        Chipset, DDP_PORT, HEARTBEAT_INTERVAL_MS, Heartbeat, MAX_FRAME_LEN, Mcu, NodeError,
+        NodeHello, NodeMessage, QUEUED_FRAMES, SESSION_TIMEOUT_MS, STREAM_INTERVAL_MS,
+        ServerMessage, StripConfig, StripMode, TCP_PORT, UDP_PORT, Version,
+        auth::{self, AuthError, Key, Opener, PacketOpener, SealedFrame},
+        ddp::DdpPacket,
+        discovery::{self, BEACON_INTERVAL_MS, Beacon, MAX_BEACON_LEN},
+        encode_frame,
+        failsafe::StreamWatchdog,
+        jitter::JitterBuffer,
+        udp::{MAX_PACKET_LEN, SequenceFilter, UdpDecoder},
+    },
+    ota::{RamFlash, UpdateError, UpdateStatus, UpdateWriter},
+};
+use postcard::accumulator::{CobsAccumulator, FeedResult};
+use tokio::{
+    io::{self, AsyncReadExt, AsyncWrite, AsyncWriteExt},
+    net::{TcpListener, TcpStream, UdpSocket},
+    task::JoinHandle,
+    time::{self, MissedTickBehavior},
+};
+
+const VERSION: Version = Version::parse(env!("CARGO_PKG_VERSION"));
+
+/// How often a virtual node renders a frame.
+pub const FRAME_INTERVAL: Duration = Duration::from_millis(16);
+
+/// The longest strip a virtual node renders, matching the firmware.
+pub const MAX_STRIP_LEN: usize = 300;
+
+/// The size of the partition a firmware update goes in, matching the
+/// firmware's OTA slots.
+pub const UPDATE_PARTITION_LEN: u32 = 0x1f_0000;
+
+/// Global brightness, applied once after compositing.
+pub const BRIGHTNESS: f32 = 0.5;
+
+/// How effects switch when the server doesn't ask for a particular transition.
+pub const DEFAULT_TRANSITION: Transition = Transition::new(TransitionKind::Crossfade, 1000);
+
+/// How a [`VirtualNode`] is set up.
+#[derive(Debug, Clone, PartialEq)]
+pub struct NodeConfig {
+    /// The address to bind every socket to.
+    pub ip: IpAddr,
+    /// The port streamed colors arrive on, or 0 for any free port.
+    pub udp_port: u16,
+    /// The port DDP pixels arrive on, or 0 for any free port.
+    pub ddp_port: u16,
+    /// The port the server's control session connects to, or 0 for any free port.
+    pub tcp_port: u16,
+    /// Each strip's LED count, up to [`MAX_STRIP_LEN`], and direction.
+    pub strips: Vec<StripInfo>,
+    /// Seeds the random effects, so nodes can differ or match.
+    pub seed: u32,
+    /// Announced in the node's beacon.
+    pub name: String,
+    /// Where to send the node's beacon, or `None` to stay quiet.
+    pub beacon: Option<SocketAddr>,
+    /// The installation's key, or `None` to take the server's traffic
+    /// unauthenticated.
+    pub key: Option<Key>,
+}
+
+impl Default for NodeConfig {
+    /// The firmware's defaults, on every interface.
+    fn default() -> Self {
+        Self {
+            ip: IpAddr::from([0, 0, 0, 0]),
+            udp_port: UDP_PORT,
+            ddp_port: DDP_PORT,
+            tcp_port: TCP_PORT,
+            strips: vec![
+                StripInfo {
+                    leds: 300,
+                    rev: true,
+                },
+                StripInfo {
+                    leds: 300,
+                    rev: false,
+                },
+            ],
+            seed: 1,
+            name: String::new(),
+            beacon: Some(SocketAddr::from((
+                Ipv4Addr::BROADCAST,
+                discovery::DISCOVERY_PORT,
+            ))),
+            key: None,
+        }
+    }
+}
+
+struct StripState {
+    info: StripInfo,
+    order: ColorOrder,
+    chipset: Chipset,
+    mode: StripMode,
+    /// The layer streamed from the server.
+    colors: Vec<RgbaF32>,
+    /// The last frame's output colors.
+    pixels: Vec<Rgb8>,
+    /// DDP pixels waiting for a push before they're shown.
+    pending: Vec<Rgb8>,
+    /// Drops streamed packets older than the last one applied.
+    sequence: SequenceFilter,
+    /// Timestamped frames waiting to be shown.
+    jitter: JitterBuffer<Vec<RgbaF32>, QUEUED_FRAMES>,
+    /// Falls back when the stream goes quiet.
+    watchdog: StreamWatchdog,
+}
+
+impl StripState {
+    fn config(&self) -> StripConfig {
+        StripConfig {
+            leds: self.info.leds,
+            rev: self.info.rev,
+            order: self.order,
+            chipset: self.chipset,
+        }
+    }
+
+    /// Rewire the strip, like the firmware's `StripState::configure`.
+    fn configure(&mut self, config: StripConfig) {
+        self.info = config.info();
+        self.order = config.order;
+        self.chipset = config.chipset;
+        self.colors = vec![RgbaF32::zero(); config.leds];
+        self.pixels = vec![Rgb8::zero(); config.leds];
+        self.pending = vec![Rgb8::zero(); config.leds];
+        self.jitter.reset();
+    }
+}
+
+struct State {
+    strips: Vec<StripState>,
+    fx: Effects<{ registry::COUNT }>,
+    /// Seeds the random effects added as overlays.
+    rng: Rng,
+    /// When the node started, the local clock synced against the server's.
+    started: Instant,
+    /// The server's clock, as estimated from time sync round trips.
+    clock: ClockSync,
+    frames: u32,
+    dropped_packets: u32,
+    stale_packets: u32,
+    /// UDP packets and TCP frames that failed authentication.
+    rejected_packets: u32,
+    key: Option<Key>,
+    /// The server holding an authenticated session, the only one DDP is
+    /// taken from when there's a key.
+    trusted: Option<IpAddr>,
+    /// What streamed packets are checked against during the open session,
+    /// when there's a key.
+    stream_opener: Option<PacketOpener>,
+    /// The open firmware update and the partition it's going into.
+    update: Option<(UpdateWriter, RamFlash)>,
+    /// The last image an update verified.
+    installed: Option<Vec<u8>>,
+}
+
+impl State {
+    /// Milliseconds since the node started.
+    fn uptime_ms(&self) -> u64 {
+        self.started.elapsed().as_millis() as u64
+    }
+}
+
+/// `VirtualNode` emulates a node on the host, speaking the same protocol and
+/// running the same effect pipeline as the firmware.
+///
+/// It listens for a control session and streamed colors until dropped. Bind
+/// several to different ports to simulate a full installation.
+pub struct VirtualNode {
+    state: Arc<Mutex<State>>,
+    udp_addr: SocketAddr,
+    ddp_addr: SocketAddr,
+    tcp_addr: SocketAddr,
+    tasks: Vec<JoinHandle<()>>,
+}
+
+impl VirtualNode {
+    /// Bind the node's sockets and start running it.
+    ///
+    /// Must be called from within a Tokio runtime.
+    pub async fn bind(config: NodeConfig) -> io::Result<Self> {
+        let udp = UdpSocket::bind((config.ip, config.udp_port)).await?;
+        let ddp = UdpSocket::bind((config.ip, config.ddp_port)).await?;
+        let tcp = TcpListener::bind((config.ip, config.tcp_port)).await?;
+
+        let strips = config
+            .strips
+            .iter()
+            .map(|&info| {
+                let info = StripInfo {
+                    leds: info.leds.min(MAX_STRIP_LEN),
+                    ..info
+                };
+                let config = StripConfig::new(info);
+                StripState {
+                    info,
+                    order: config.order,
+                    chipset: config.chipset,
+                    mode: StripMode::Hybrid,
+                    colors: vec![RgbaF32::zero(); info.leds],
+                    pixels: vec![Rgb8::zero(); info.leds],
+                    pending: vec![Rgb8::zero(); info.leds],
+                    sequence: SequenceFilter::new(),
+                    jitter: JitterBuffer::new(Some(STREAM_INTERVAL_MS)),
+                    watchdog: StreamWatchdog::new(),
+                }
+            })
+            .collect::<Vec<_>>();
+
+        let mut rng = Rng::new(config.seed);
+        let mut fx = Effects::from_registry::<MAX_STRIP_LEN, _>(strips.len(), || rng.next_u32());
+        fx.set_transition(Some(DEFAULT_TRANSITION));
+
+        let state = Arc::new(Mutex::new(State {
+            strips,
+            fx,
+            rng,
+            started: Instant::now(),
+            clock: ClockSync::new(),
+            frames: 0,
+            dropped_packets: 0,
+            stale_packets: 0,
+            rejected_packets: 0,
+            key: config.key,
+            trusted: None,
+            stream_opener: None,
+            update: None,
+            installed: None,
+        }));
+
+        let udp_addr = udp.local_addr()?;
+        let ddp_addr = ddp.local_addr()?;
+        let tcp_addr = tcp.local_addr()?;
+        let mut tasks = vec![
+            tokio::spawn(render(state.clone())),
+            tokio::spawn(udp_socket(state.clone(), udp)),
+            tokio::spawn(ddp_socket(state.clone(), ddp)),
+            tokio::spawn(tcp_socket(state.clone(), tcp)),
+        ];
+
+        if let Some(target) = config.beacon {
+            let socket = UdpSocket::bind((config.ip, 0)).await?;
+            socket.set_broadcast(true)?;
+            // a locally administered MAC address, unique per port on the host
+            let [hi, lo] = tcp_addr.port().to_be_bytes();
+            let beacon = Beacon {
+                id: [0x02, 0, 0, 0, hi, lo],
+                name: config.name,
+                version: VERSION,
+                mcu: Mcu::Host,
+                num_strips: config.strips.len() as u8,
+                tcp_port: tcp_addr.port(),
+                udp_port: udp_addr.port(),
+                ddp_port: ddp_addr.port(),
+            };
+            tasks.push(tokio::spawn(announce(socket, target, beacon)));
+        }
+
+        Ok(Self {
+            state,
+            udp_addr,
+            ddp_addr,
+            tcp_addr,
+            tasks,
+        })
+    }
+
+    /// Where streamed colors should be sent.
+    pub fn udp_addr(&self) -> SocketAddr {
+        self.udp_addr
+    }
+
+    /// Where DDP pixels should be sent.
+    pub fn ddp_addr(&self) -> SocketAddr {
+        self.ddp_addr
+    }
+
+    /// Where the server's control session should connect.
+    pub fn tcp_addr(&self) -> SocketAddr {
+        self.tcp_addr
+    }
+
+    pub fn num_strips(&self) -> usize {
+        self.state.lock().unwrap().strips.len()
+    }
+
+    /// The colors a strip showed on the last frame, after gamma and brightness.
+    pub fn pixels(&self, strip: usize) -> Option<Vec<Rgb8>> {
+        let state = self.state.lock().unwrap();
+        state.strips.get(strip).map(|s| s.pixels.clone())
+    }
+
+    /// How a strip is wired, as last set by the server.
+    pub fn config(&self, strip: usize) -> Option<StripConfig> {
+        let state = self.state.lock().unwrap();
+        state.strips.get(strip).map(StripState::config)
+    }
+
+    pub fn mode(&self, strip: usize) -> Option<StripMode> {
+        let state = self.state.lock().unwrap();
+        state.strips.get(strip).map(|s| s.mode)
+    }
+
+    /// The index of the effect currently running.
+    pub fn effect(&self) -> usize {
+        self.state.lock().unwrap().fx.index()
+    }
+
+    /// The number of overlays on a strip.
+    pub fn overlays(&self, strip: usize) -> usize {
+        self.state.lock().unwrap().fx.overlays(strip)
+    }
+
+    /// Server time minus the node's time, in milliseconds, once synced.
+    pub fn clock_offset(&self) -> Option<i64> {
+        self.state.lock().unwrap().clock.offset()
+    }
+
+    /// The server-aligned time effects are running at, in milliseconds.
+    pub fn time(&self) -> u64 {
+        let state = self.state.lock().unwrap();
+        state.clock.server_time(state.uptime_ms())
+    }
+
+    /// The number of frames rendered since the node started.
+    pub fn frames(&self) -> u32 {
+        self.state.lock().unwrap().frames
+    }
+
+    /// UDP commands dropped since the node started.
+    pub fn dropped_packets(&self) -> u32 {
+        self.state.lock().unwrap().dropped_packets
+    }
+
+    /// The last firmware image pushed to the node and verified. A virtual node
+    /// keeps running as it is rather than restarting into it.
+    pub fn installed_image(&self) -> Option<Vec<u8>> {
+        self.state.lock().unwrap().installed.clone()
+    }
+
+    /// UDP commands dropped since the node started for arriving after a newer one.
+    pub fn stale_packets(&self) -> u32 {
+        self.state.lock().unwrap().stale_packets
+    }
+
+    /// UDP packets and TCP frames dropped since the node started for failing
+    /// authentication.
+    pub fn rejected_packets(&self) -> u32 {
+        self.state.lock().unwrap().rejected_packets
+    }
+}
+
+impl Drop for VirtualNode {
+    fn drop(&mut self) {
+        for task in &self.tasks {
+            task.abort();
+        }
+    }
+}
+
+/// Composite each strip's effects and streamed colors, like the firmware's `data_tx`.
+async fn render(state: Arc<Mutex<State>>) {
+    let mut buf = vec![RgbaF32::zero(); MAX_STRIP_LEN];
+    let mut scratch = vec![RgbaF32::zero(); MAX_STRIP_LEN];
+
+    let mut ticker = time::interval(FRAME_INTERVAL);
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Skip);
+    loop {
+        ticker.tick().await;
+        let mut state = state.lock().unwrap();
+        // effects run on the server's time, so they line up across nodes
+        let local = state.uptime_ms();
+        let now = state.clock.server_time(local);
+        let State { strips, fx, .. } = &mut *state;
+        for (i, strip) in strips.iter_mut().enumerate() {
+            let leds = strip.info.leds;
+            if leds == 0 {
+                continue;
+            }
+
+            if let Some(frame) = strip.jitter.present(now as u32) {
+                frame.write(&mut strip.colors);
+            }
+
+            let buf = &mut buf[..leds];
+            buf.fill(RgbaF32::zero());
+            let (mode, opacity) = strip.watchdog.apply(strip.mode, local);
+            if mode.runs_effects() {
+                fx.update(i, &strip.info, buf, &mut scratch[..leds], now);
+            }
+            if mode.streams() {
+                blend_layer(buf, &strip.colors, BlendMode::Over, opacity);
+            }
+
+            for (px, rgb) in strip.pixels.iter_mut().zip(flatten(buf, BRIGHTNESS)) {
+                *px = rgb;
+            }
+        }
+        state.frames = state.frames.wrapping_add(1);
+    }
+}
+
+/// Apply streamed colors, like the firmware's `udp_socket`.
+async fn udp_socket(state: Arc<Mutex<State>>, socket: UdpSocket) {
+    let mut buf = [0u8; MAX_PACKET_LEN];
+    loop {
+        let Ok((n, _)) = socket.recv_from(&mut buf).await else {
+            continue;
+        };
+
+        let mut state = state.lock().unwrap();
+        // with a key, packets are only taken during a session
+        let keyed = state.key.is_some();
+        let checked = match (keyed, state.stream_opener.as_mut()) {
+            (true, None) => Err(AuthError::Unsigned),
+            (_, opener) => auth::check_packet(opener, &buf[..n]),
+        };
+        let Ok(packet) = checked else {
+            state.rejected_packets += 1;
+            continue;
+        };
+        let Ok(decoder) = UdpDecoder::new(packet) else {
+            state.dropped_packets += 1;
+            continue;
+        };
+        let header = decoder.header();
+        // timestamps are only meaningful against the server's clock
+        let timestamp = header.timestamp.filter(|_| state.clock.is_synced());
+        let now = state.uptime_ms();
+
+        for cmd in decoder {
+            let Ok(cmd) = cmd else {
+                state.dropped_packets += 1;
+                break;
+            };
+
+            let Some(strip) = state.strips.get_mut(cmd.strip() as usize) else {
+                state.dropped_packets += 1;
+                break;
+            };
+
+            if !strip.mode.streams() {
+                state.dropped_packets += 1;
+                break;
+            }
+
+            if !strip.sequence.accept(header.sequence) {
+                state.stale_packets += 1;
+                break;
+            }
+
+            strip.watchdog.feed(now);
+            let Some(timestamp) = timestamp else {
+                cmd.apply(&mut strip.colors);
+                continue;
+            };
+            match strip.jitter.stage(timestamp, &strip.colors) {
+                Some(frame) => cmd.apply(frame),
+                None => {
+                    // its time already came and went
+                    state.stale_packets += 1;
+                    break;
+                }
+            }
+        }
+    }
+}
+
+/// Apply DDP pixels on each push, like the firmware's `ddp_socket`.
+async fn ddp_socket(state: Arc<Mutex<State>>, socket: UdpSocket) {
+    let mut buf = [0u8; 1500];
+    loop {
+        let Ok((n, from)) = socket.recv_from(&mut buf).await else {
+            continue;
+        };
+
+        let mut state = state.lock().unwrap();
+        // DDP can't be signed, so with a key it's only taken from the server
+        if state.key.is_some() && state.trusted != Some(from.ip()) {
+            state.rejected_packets += 1;
+            continue;
+        }
+        let Ok(packet) = DdpPacket::parse(&buf[..n]) else {
+            state.dropped_packets += 1;
+            continue;
+        };
+
+        packet.write(state.strips.iter_mut().map(|s| &mut s.pending[..]));
+        if packet.push {
+            let now = state.uptime_ms();
+            for strip in state.strips.iter_mut().filter(|s| s.mode.streams()) {
+                strip.watchdog.feed(now);
+                for (dst, &src) in strip.colors.iter_mut().zip(&strip.pending) {
+                    *dst = src.into();
+                }
+            }
+        }
+    }
+}
+
+/// Send the node's beacon every [`BEACON_INTERVAL_MS`], like the firmware's `beacon`.
+async fn announce(socket: UdpSocket, target: SocketAddr, beacon: Beacon) {
+    let mut buf = [0u8; MAX_BEACON_LEN];
+    let Ok(packet) = beacon.encode(&mut buf) else {
+        println!("warn: beacon doesn't fit in {MAX_BEACON_LEN} bytes");
+        return;
+    };
+
+    let mut ticker = time::interval(Duration::from_millis(BEACON_INTERVAL_MS));
+    loop {
+        ticker.tick().await;
+        if let Err(e) = socket.send_to(packet, target).await {
+            println!("warn: failed to send beacon to {target}: {e}");
+        }
+    }
+}
+
+/// Serve one control session at a time, like the firmware's `tcp_socket`.
+async fn tcp_socket(state: Arc<Mutex<State>>, listener: TcpListener) {
+    loop {
+        let (stream, addr) = match listener.accept().await {
+            Ok(accepted) => accepted,
+            Err(e) => {
+                println!("warn: failed to accept TCP connection: {e}");
+                time::sleep(Duration::from_secs(1)).await;
+                continue;
+            }
+        };
+
+        println!("server connected from {addr}");
+        if let Err(e) = session(&state, stream).await {
+            println!("warn: TCP session failed: {e}");
+        }
+        {
+            let mut state = state.lock().unwrap();
+            state.trusted = None;
+            state.stream_opener = None;
+        }
+        println!("server disconnected");
+    }
+}
+
+/// Drive a control session until the server disconnects or goes quiet.
+async fn session(state: &Mutex<State>, mut stream: TcpStream) -> io::Result<()> {
+    stream.set_nodelay(true)?;
+    // the server may have restarted, along with its clock, the
+    // timestamps queued frames were stamped with and its packet numbering
+    {
+        let mut state = state.lock().unwrap();
+        state.clock.reset();
+        for strip in &mut state.strips {
+            strip.jitter.reset();
+            strip.sequence.reset();
+        }
+    }
+    let peer = stream.peer_addr()?.ip();
+    let key = state.lock().unwrap().key.clone();
+    // with a key, the rest of the session is sealed with a fresh nonce; std
+    // seeds every `RandomState` from the OS, which is plenty for a virtual node
+    let nonce = key
+        .is_some()
+        .then(|| RandomState::new().build_hasher().finish());
+    // a new opener per session, so packets from the last one can't be replayed
+    state.lock().unwrap().stream_opener = key
+        .as_ref()
+        .zip(nonce)
+        .map(|(key, nonce)| PacketOpener::new(key.for_session(nonce)));
+    let mut opener = key.zip(nonce).map(|(key, nonce)| Opener::new(key, nonce));
+    handshake(state, &mut stream, nonce).await?;
+
+    let mut buf = [0u8; 512];
+    let mut frames = CobsAccumulator::<MAX_FRAME_LEN>::new();
+    let mut heartbeat = time::interval(Duration::from_millis(HEARTBEAT_INTERVAL_MS));
+    heartbeat.reset();
+    let mut last_heartbeat = (Instant::now(), state.lock().unwrap().frames);
+    loop {
+        let n = tokio::select! {
+            n = time::timeout(Duration::from_millis(SESSION_TIMEOUT_MS), stream.read(&mut buf)) => {
+                match n.map_err(|_| io::ErrorKind::TimedOut)?? {
+                    0 => return Ok(()),
+                    n => n,
+                }
+            }
+            _ = heartbeat.tick() => {
+                let msg = NodeMessage::Heartbeat(next_heartbeat(state, &mut last_heartbeat));
+                send(&mut stream, &msg).await?;
+                let sent = state.lock().unwrap().uptime_ms();
+                send(&mut stream, &NodeMessage::TimeRequest(sent)).await?;
+                continue;
+            }
+        };
+
+        let mut window = &buf[..n];
+        while !window.is_empty() {
+            let Some((msg, rest)) = next_message(&mut frames, opener.as_mut(), window) else {
+                break;
+            };
+            let reply = msg.and_then(|msg| {
+                if opener.is_some() {
+                    state.lock().unwrap().trusted = Some(peer);
+                }
+                handle_server_message(state, msg)
+            });
+
+            match reply {
+                Ok(Some(reply)) => send(&mut stream, &reply).await?,
+                Ok(None) => (),
+                Err(error @ NodeError::Unauthenticated(_)) => {
+                    println!("warn: {error:?}");
+                    state.lock().unwrap().rejected_packets += 1;
+                    send(&mut stream, &NodeMessage::Error(error)).await?;
+                    return Err(io::Error::new(
+                        io::ErrorKind::PermissionDenied,
+                        "unauthenticated frame",
+                    ));
+                }
+                Err(error) => {
+                    println!("warn: {error:?}");
+                    send(&mut stream, &NodeMessage::Error(error)).await?;
+                }
+            }
+            window = rest;
+        }
+    }
+}
+
+/// Take the next message out of what's been read from the server, opening
+/// it if the session is sealed, like the firmware's `next_message`. Returns
+/// `None` once `window` is used up.
+fn next_message<'a>(
+    frames: &mut CobsAccumulator<MAX_FRAME_LEN>,
+    opener: Option<&mut Opener>,
+    window: &'a [u8],
+) -> Option<(Result<ServerMessage, NodeError>, &'a [u8])> {
+    let Some(opener) = opener else {
+        return match frames.feed::<ServerMessage>(window) {
+            FeedResult::Consumed => None,
+            FeedResult::OverFull(rest) | FeedResult::DeserError(rest) => {
+                Some((Err(NodeError::MalformedFrame), rest))
+            }
+            FeedResult::Success { data, remaining } => Some((Ok(data), remaining)),
+        };
+    };
+
+    match frames.feed_ref::<SealedFrame>(window) {
+        FeedResult::Consumed => None,
+        FeedResult::OverFull(rest) | FeedResult::DeserError(rest) => {
+            Some((Err(NodeError::Unauthenticated(AuthError::Malformed)), rest))
+        }
+        FeedResult::Success { data, remaining } => {
+            let msg = opener.open(&data).map_err(|e| match e {
+                // it checked out, the server just sent something odd
+                AuthError::Malformed => NodeError::MalformedFrame,
+                e => NodeError::Unauthenticated(e),
+            });
+            Some((msg, remaining))
+        }
+    }
+}
+
+/// Introduce this node to the server, with the nonce to seal the session with
+/// if there's a key.
+async fn handshake<W: AsyncWrite + Unpin>(
+    state: &Mutex<State>,
+    writer: &mut W,
+    nonce: Option<u64>,
+) -> io::Result<()> {
+    let infos = {
+        let state = state.lock().unwrap();
+        state.strips.iter().map(|s| s.info).collect::<Vec<_>>()
+    };
+
+    let hello = NodeHello {
+        version: VERSION,
+        mcu: Mcu::Host,
+        num_strips: infos.len() as u8,
+    };
+    send(writer, &NodeMessage::Hello(hello)).await?;
+    if let Some(nonce) = nonce {
+        send(writer, &NodeMessage::Challenge(nonce)).await?;
+    }
+    for (i, info) in infos.into_iter().enumerate() {
+        send(writer, &NodeMessage::Strip(i as u8, info)).await?;
+    }
+
+    // sync clocks now, rather than a heartbeat from now
+    let sent = state.lock().unwrap().uptime_ms();
+    send(writer, &NodeMessage::TimeRequest(sent)).await
+}
+
+/// Gather telemetry since the last heartbeat.
+fn next_heartbeat(state: &Mutex<State>, last: &mut (Instant, u32)) -> Heartbeat {
+    let state = state.lock().unwrap();
+    let now = Instant::now();
+    let elapsed = (now - last.0).as_millis().max(1);
+    let frame_rate = state.frames.wrapping_sub(last.1) as f32 * 1000.0 / elapsed as f32;
+    *last = (now, state.frames);
+
+    Heartbeat {
+        uptime_ms: state.uptime_ms(),
+        free_heap: 0,
+        frame_rate,
+        dropped_packets: state.dropped_packets,
+        stale_packets: state.stale_packets,
+        rejected_packets: state.rejected_packets,
+        rssi: None,
+    }
+}
+
+/// Write a single framed [`NodeMessage`] to the server.
+async fn send<W: AsyncWrite + Unpin>(writer: &mut W, msg: &NodeMessage) -> io::Result<()> {
+    let mut buf = [0u8; MAX_FRAME_LEN];
+    let frame = encode_frame(msg, &mut buf).map_err(io::Error::other)?;
+    writer.write_all(frame).await
+}
+
+/// Apply a decoded [`ServerMessage`], like the firmware's `handle_server_message`,
+/// returning the reply to send back, if any.
+fn handle_server_message(
+    state: &Mutex<State>,
+    msg: ServerMessage,
+) -> Result<Option<NodeMessage>, NodeError> {
+    let mut state = state.lock().unwrap();
+    match msg {
+        ServerMessage::SetStripMode(strip, mode) => match state.strips.get_mut(strip as usize) {
+            Some(s) => s.mode = mode,
+            None => return Err(NodeError::UnknownStrip(strip)),
+        },
+        ServerMessage::ShiftEffectMode(delta, transition) => {
+            state.fx.shift_effect(delta, transition);
+        }
+        ServerMessage::SelectEffect(id, transition) => {
+            if id as usize >= registry::COUNT {
+                return Err(NodeError::UnknownEffect(id));
+            }
+            state.fx.set_effect(id as usize, transition);
+        }
+        ServerMessage::SetEffectParam(effect, param, value) => {
+            let Some(info) = registry::EFFECTS.get(effect as usize) else {
+                return Err(NodeError::UnknownEffect(effect));
+            };
+            if let Err(e) = check(info.params, param, value) {
+                return Err(NodeError::InvalidParam(effect, e));
+            }
+            // already checked against the registry
+            _ = state.fx.set_param(effect as usize, param, value);
+        }
+        ServerMessage::KeepAlive => (),
+        ServerMessage::SetStreamTimeout(strip, timeout) => {
+            match state.strips.get_mut(strip as usize) {
+                Some(s) => s.watchdog.set_timeout(timeout),
+                None => return Err(NodeError::UnknownStrip(strip)),
+            }
+        }
+        ServerMessage::TimeResponse(sent, server) => {
+            let received = state.uptime_ms();
+            state.clock.add_sample(sent, server, received);
+        }
+        ServerMessage::SetOverlays(strip, overlays) => {
+            if strip as usize >= state.strips.len() {
+                return Err(NodeError::UnknownStrip(strip));
+            }
+            if let Some(overlay) = overlays
+                .iter()
+                .find(|overlay| overlay.effect as usize >= registry::COUNT)
+            {
+                return Err(NodeError::UnknownEffect(overlay.effect));
+            }
+            if overlays.len() > MAX_OVERLAYS
+                || overlays
+                    .iter()
+                    .any(|overlay| !(0.0..=1.0).contains(&overlay.opacity))
+            {
+                return Err(NodeError::InvalidOverlay(strip));
+            }
+
+            let State { fx, rng, .. } = &mut *state;
+            fx.clear_overlays(strip as usize);
+            for overlay in overlays {
+                let effect = registry::build::<MAX_STRIP_LEN>(overlay.effect, rng.next_u32())
+                    .expect("ids below COUNT are registered");
+                fx.add_overlay(strip as usize, effect, overlay.mode, overlay.opacity);
+            }
+        }
+        ServerMessage::ConfigureStrip(strip, config) => {
+            let Some(s) = state.strips.get_mut(strip as usize) else {
+                return Err(NodeError::UnknownStrip(strip));
+            };
+            if config.leds > MAX_STRIP_LEN {
+                return Err(NodeError::StripTooLong(strip));
+            }
+            s.configure(config);
+            return Ok(Some(NodeMessage::StripConfigured(strip, config)));
+        }
+        ServerMessage::BeginUpdate(header) => {
+            let flash = RamFlash::new(UPDATE_PARTITION_LEN);
+            let status = match UpdateWriter::begin(&flash, header) {
+                Ok(writer) => {
+                    state.update = Some((writer, flash));
+                    UpdateStatus::Ready
+                }
+                Err(e) => {
+                    state.update = None;
+                    UpdateStatus::Failed(e)
+                }
+            };
+            return Ok(Some(NodeMessage::Update(status)));
+        }
+        ServerMessage::UpdateChunk(chunk) => {
+            let State {
+                update, installed, ..
+            } = &mut *state;
+            let Some((writer, flash)) = update else {
+                let status = UpdateStatus::Failed(UpdateError::NotStarted);
+                return Ok(Some(NodeMessage::Update(status)));
+            };
+            let status = writer.apply(flash, &chunk);
+            if status == Some(UpdateStatus::Verified) {
+                let len = writer.header().size as usize;
+                *installed = Some(flash.bytes()[..len].to_vec());
+            }
+            if !matches!(status, None | Some(UpdateStatus::Written(_))) {
+                *update = None;
+            }
+            return Ok(status.map(NodeMessage::Update));
+        }
+    }
+    Ok(None)
+}
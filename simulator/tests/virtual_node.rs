@@ -0,0 +1,702 @@
+use std::time::Duration;
+
+use common::{
+    color::{BlendMode, ColorOrder, Rgb8, RgbaF32},
+    effect::{StripInfo, fx::OverlayConfig, layer::flatten, registry},
+    net::{
+        Chipset, Mcu, NodeError, NodeMessage, ServerMessage, StripConfig, StripMode,
+        auth::{AuthError, KEY_LEN, Key, MAC_LEN, sign_packet},
+        ddp, decode_frame,
+        failsafe::{Fallback, StreamTimeout},
+        udp::{PacketHeader, UdpEncoder},
+    },
+    ota::{SECTOR_LEN, UpdateError, UpdateStatus},
+};
+use server::{
+    discovery,
+    node::{NodeEvent, NodeRegistry},
+    ota::{self, PushError},
+    udp::UdpSender,
+};
+use simulator::node::{BRIGHTNESS, MAX_STRIP_LEN, NodeConfig, UPDATE_PARTITION_LEN, VirtualNode};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncReadExt, BufReader},
+    net::{TcpStream, UdpSocket},
+    time::{sleep, timeout},
+};
+
+async fn bind(strips: &[usize]) -> VirtualNode {
+    VirtualNode::bind(local(strips)).await.unwrap()
+}
+
+/// A node on localhost with the given strips.
+fn local(strips: &[usize]) -> NodeConfig {
+    NodeConfig {
+        ip: [127, 0, 0, 1].into(),
+        udp_port: 0,
+        ddp_port: 0,
+        tcp_port: 0,
+        strips: strips
+            .iter()
+            .map(|&leds| StripInfo { leds, rev: false })
+            .collect(),
+        beacon: None,
+        ..NodeConfig::default()
+    }
+}
+
+/// Poll until a condition holds, failing the test after a few seconds.
+async fn wait_until(what: &str, mut f: impl FnMut() -> bool) {
+    for _ in 0..250 {
+        if f() {
+            return;
+        }
+        sleep(Duration::from_millis(20)).await;
+    }
+    panic!("timed out waiting until {what}");
+}
+
+/// What a node shows for an opaque streamed color.
+fn shown(color: Rgb8) -> Rgb8 {
+    flatten(&[RgbaF32::from(color)], BRIGHTNESS).next().unwrap()
+}
+
+#[tokio::test]
+async fn introduces_itself_to_the_server() {
+    let node = bind(&[120, 60]).await;
+    let registry = NodeRegistry::new();
+    let mut events = registry.subscribe();
+    let id = registry.add(node.tcp_addr(), &[120, 60]);
+
+    let event = timeout(Duration::from_secs(5), events.recv())
+        .await
+        .expect("timed out waiting for hello")
+        .unwrap();
+    let NodeEvent {
+        msg: NodeMessage::Hello(hello),
+        ..
+    } = event
+    else {
+        panic!("expected hello, got {event:?}");
+    };
+    assert_eq!(hello.mcu, Mcu::Host);
+    assert_eq!(hello.num_strips, 2);
+
+    wait_until("the server replays modes", || {
+        node.mode(0) == Some(StripMode::Effects)
+    })
+    .await;
+    wait_until("a heartbeat arrives", || {
+        registry.get(id).unwrap().heartbeat.is_some()
+    })
+    .await;
+    assert!(node.pixels(0).unwrap().iter().any(|&px| px != Rgb8::zero()));
+}
+
+#[tokio::test]
+async fn is_discovered_from_its_beacon() {
+    let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let target = socket.local_addr().unwrap();
+    let registry = NodeRegistry::new();
+    tokio::spawn(discovery::listen(socket, registry.clone()));
+
+    let config = |name: &str| NodeConfig {
+        ip: [127, 0, 0, 1].into(),
+        udp_port: 0,
+        ddp_port: 0,
+        tcp_port: 0,
+        strips: vec![StripInfo {
+            leds: 90,
+            rev: false,
+        }],
+        name: name.into(),
+        beacon: Some(target),
+        ..NodeConfig::default()
+    };
+    let a = VirtualNode::bind(config("a")).await.unwrap();
+    let b = VirtualNode::bind(config("b")).await.unwrap();
+
+    // each node is registered once, then picks its strips up from the handshake
+    wait_until("both nodes are registered", || registry.len() == 2).await;
+    for node in [&a, &b] {
+        let id = registry.find(node.tcp_addr()).unwrap();
+        wait_until("the node connects", || {
+            registry
+                .get(id)
+                .unwrap()
+                .strips
+                .iter()
+                .map(|s| s.leds)
+                .eq([90])
+        })
+        .await;
+        let hello = registry.get(id).unwrap().hello.unwrap();
+        assert_eq!(hello.mcu, Mcu::Host);
+    }
+    assert_eq!(registry.len(), 2);
+}
+
+#[tokio::test]
+async fn takes_a_pushed_update() {
+    let node = bind(&[30]).await;
+    let registry = NodeRegistry::new();
+    let id = registry.add(node.tcp_addr(), &[30]);
+
+    let image = (0..3 * SECTOR_LEN + 500)
+        .map(|i| (i % 251) as u8)
+        .collect::<Vec<_>>();
+    let mut progress = Vec::new();
+    timeout(
+        Duration::from_secs(10),
+        ota::push(&registry, id, &image, |written| progress.push(written)),
+    )
+    .await
+    .expect("timed out pushing the update")
+    .unwrap();
+
+    assert_eq!(node.installed_image(), Some(image));
+    assert_eq!(
+        progress,
+        [
+            SECTOR_LEN as u32,
+            2 * SECTOR_LEN as u32,
+            3 * SECTOR_LEN as u32
+        ]
+    );
+    assert_eq!(
+        registry.get(id).unwrap().update,
+        Some(UpdateStatus::Verified)
+    );
+
+    // an image too big for the partition is turned down up front
+    let image = vec![0; UPDATE_PARTITION_LEN as usize + 1];
+    let result = ota::push(&registry, id, &image, |_| ()).await;
+    assert_eq!(result, Err(PushError::Node(UpdateError::TooLarge)));
+
+    // and an empty one never goes out, since there'd be nothing to write
+    let result = ota::push(&registry, id, &[], |_| ()).await;
+    assert_eq!(result, Err(PushError::EmptyImage));
+}
+
+#[tokio::test]
+async fn simulates_several_nodes() {
+    let nodes = [bind(&[30]).await, bind(&[30, 10]).await];
+    let registry = NodeRegistry::new();
+    let sender = UdpSender::bind().await.unwrap();
+    let colors = [Rgb8::new(255, 0, 0), Rgb8::new(0, 0, 255)];
+
+    for node in &nodes {
+        let id = registry.add(node.tcp_addr(), &vec![30; node.num_strips()]);
+        registry.set_strip_mode(id, 0, StripMode::Dynamic).unwrap();
+    }
+    for node in &nodes {
+        wait_until("strips switch to dynamic", || {
+            node.mode(0) == Some(StripMode::Dynamic)
+        })
+        .await;
+    }
+
+    for (node, &color) in nodes.iter().zip(&colors) {
+        sender.fill(node.udp_addr(), 0, color).await.unwrap();
+    }
+    for (node, &color) in nodes.iter().zip(&colors) {
+        wait_until("streamed colors show", || {
+            node.pixels(0).unwrap() == [shown(color); 30]
+        })
+        .await;
+    }
+    // the second strip was left running effects
+    assert_eq!(nodes[1].mode(1), Some(StripMode::Effects));
+}
+
+#[tokio::test]
+async fn drops_colors_for_strips_not_streaming() {
+    let node = bind(&[10]).await;
+    let registry = NodeRegistry::new();
+    let sender = UdpSender::bind().await.unwrap();
+    let id = registry.add(node.tcp_addr(), &[10]);
+
+    registry.set_strip_mode(id, 0, StripMode::Off).unwrap();
+    wait_until("the strip turns off", || {
+        node.mode(0) == Some(StripMode::Off)
+    })
+    .await;
+
+    sender
+        .fill(node.udp_addr(), 0, Rgb8::new(0, 255, 0))
+        .await
+        .unwrap();
+    sender
+        .fill(node.udp_addr(), 3, Rgb8::new(0, 255, 0))
+        .await
+        .unwrap();
+    wait_until("both packets are dropped", || node.dropped_packets() == 2).await;
+    assert_eq!(node.pixels(0).unwrap(), [Rgb8::zero(); 10]);
+}
+
+#[tokio::test]
+async fn follows_effect_commands() {
+    let node = bind(&[10]).await;
+    let registry = NodeRegistry::new();
+    let mut events = registry.subscribe();
+    let id = registry.add(node.tcp_addr(), &[10]);
+
+    let fire = registry::find("fire").unwrap();
+    registry.select_effect(id, fire, None).unwrap();
+    wait_until("fire is selected", || node.effect() == fire as usize).await;
+
+    registry.shift_effect_mode(id, 1, None).unwrap();
+    wait_until("the effect shifts", || node.effect() == fire as usize + 1).await;
+
+    // sent raw, since `select_effect` refuses ids past the registry's
+    registry
+        .send(id, ServerMessage::SelectEffect(200, None))
+        .unwrap();
+    loop {
+        let event = timeout(Duration::from_secs(5), events.recv())
+            .await
+            .expect("timed out waiting for an error")
+            .unwrap();
+        if let NodeMessage::Error(error) = event.msg {
+            assert_eq!(error, NodeError::UnknownEffect(200));
+            break;
+        }
+    }
+    assert_eq!(node.effect(), fire as usize + 1);
+}
+
+#[tokio::test]
+async fn layers_overlays_on_request() {
+    let node = bind(&[10]).await;
+    let registry = NodeRegistry::new();
+    let mut events = registry.subscribe();
+    let id = registry.add(node.tcp_addr(), &[10]);
+
+    let twinkle = registry::find("twinkle").unwrap();
+    registry
+        .add_overlay(id, 0, twinkle, BlendMode::Screen, 0.5)
+        .unwrap();
+    wait_until("the overlay is added", || node.overlays(0) == 1).await;
+
+    // sent raw, since `add_overlay` refuses opacities past 1
+    let too_bright = OverlayConfig {
+        effect: twinkle,
+        mode: BlendMode::Add,
+        opacity: 2.0,
+    };
+    registry
+        .send(id, ServerMessage::SetOverlays(0, vec![too_bright]))
+        .unwrap();
+    loop {
+        let event = timeout(Duration::from_secs(5), events.recv())
+            .await
+            .expect("timed out waiting for an error")
+            .unwrap();
+        if let NodeMessage::Error(error) = event.msg {
+            assert_eq!(error, NodeError::InvalidOverlay(0));
+            break;
+        }
+    }
+    assert_eq!(node.overlays(0), 1);
+
+    registry.clear_overlays(id, 0).unwrap();
+    wait_until("the overlays are cleared", || node.overlays(0) == 0).await;
+}
+
+#[tokio::test]
+async fn rewires_strips_on_request() {
+    let node = bind(&[10]).await;
+    let registry = NodeRegistry::new();
+    let mut events = registry.subscribe();
+    let id = registry.add(node.tcp_addr(), &[10]);
+
+    let config = StripConfig {
+        leds: 24,
+        rev: true,
+        order: ColorOrder::Brg,
+        chipset: Chipset::Sk6812,
+    };
+    registry.configure_strip(id, 0, config).unwrap();
+    wait_until("the strip is rewired", || node.config(0) == Some(config)).await;
+    assert_eq!(node.pixels(0).unwrap().len(), 24);
+    wait_until("the registry hears back", || {
+        registry.get(id).unwrap().strips[0].leds == 24
+    })
+    .await;
+
+    // the node refuses a strip it can't hold, and the registry keeps the one it took
+    let too_long = StripConfig {
+        leds: MAX_STRIP_LEN + 1,
+        ..config
+    };
+    registry.configure_strip(id, 0, too_long).unwrap();
+    loop {
+        let event = timeout(Duration::from_secs(5), events.recv())
+            .await
+            .expect("timed out waiting for an error")
+            .unwrap();
+        if let NodeMessage::Error(error) = event.msg {
+            assert_eq!(error, NodeError::StripTooLong(0));
+            break;
+        }
+    }
+    assert_eq!(node.config(0), Some(config));
+    assert_eq!(registry.get(id).unwrap().strips[0].config, Some(config));
+}
+
+#[tokio::test]
+async fn shows_ddp_frames_on_push() {
+    let node = bind(&[2, 2]).await;
+    let socket = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let (red, blue) = (Rgb8::new(255, 0, 0), Rgb8::new(0, 0, 255));
+
+    let mut buf = [0u8; 64];
+    let first = ddp::encode(&mut buf, 1, false, 0, &[red; 2]).unwrap();
+    socket.send_to(first, node.ddp_addr()).await.unwrap();
+    let second = ddp::encode(&mut buf, 1, true, 2, &[blue; 2]).unwrap();
+    socket.send_to(second, node.ddp_addr()).await.unwrap();
+
+    // both strips update together, on the push
+    wait_until("the pushed frame shows", || {
+        node.pixels(0).unwrap() == [shown(red); 2] && node.pixels(1).unwrap() == [shown(blue); 2]
+    })
+    .await;
+
+    socket.send_to(b"not ddp", node.ddp_addr()).await.unwrap();
+    wait_until("the bad packet is dropped", || node.dropped_packets() == 1).await;
+}
+
+#[tokio::test]
+async fn drops_packets_older_than_the_last_applied() {
+    let node = bind(&[4]).await;
+    let socket = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let (red, blue) = (Rgb8::new(255, 0, 0), Rgb8::new(0, 0, 255));
+
+    for (sequence, color) in [(7, red), (6, blue)] {
+        let mut buf = [0u8; 64];
+        let mut enc = UdpEncoder::new(&mut buf, PacketHeader::new(sequence));
+        enc.set_buffer_to_single(0, color).unwrap();
+        socket.send_to(enc.packet(), node.udp_addr()).await.unwrap();
+    }
+
+    wait_until("the late packet is dropped", || node.stale_packets() == 1).await;
+    wait_until("the newer frame shows", || {
+        node.pixels(0).unwrap() == [shown(red); 4]
+    })
+    .await;
+    assert_eq!(node.dropped_packets(), 0);
+}
+
+#[tokio::test]
+async fn takes_packets_from_a_restarted_server() {
+    let node = bind(&[4]).await;
+    let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let (red, blue) = (Rgb8::new(255, 0, 0), Rgb8::new(0, 0, 255));
+    let fill = |sequence, color| {
+        let mut buf = [0u8; 64];
+        let mut enc = UdpEncoder::new(&mut buf, PacketHeader::new(sequence));
+        enc.set_buffer_to_single(0, color).unwrap();
+        enc.packet().to_vec()
+    };
+
+    socket
+        .send_to(&fill(1000, red), node.udp_addr())
+        .await
+        .unwrap();
+    wait_until("the first frame shows", || {
+        node.pixels(0).unwrap() == [shown(red); 4]
+    })
+    .await;
+
+    // the restarted server connects and numbers its packets from scratch
+    let mut session = TcpStream::connect(node.tcp_addr()).await.unwrap();
+    let mut hello = [0u8; 1];
+    timeout(Duration::from_secs(5), session.read_exact(&mut hello))
+        .await
+        .expect("timed out waiting for hello")
+        .unwrap();
+    socket
+        .send_to(&fill(900, blue), node.udp_addr())
+        .await
+        .unwrap();
+    wait_until("the restarted server's frame shows", || {
+        node.pixels(0).unwrap() == [shown(blue); 4]
+    })
+    .await;
+    assert_eq!(node.stale_packets(), 0);
+}
+
+#[tokio::test]
+async fn nodes_share_the_server_clock() {
+    let first = bind(&[1]).await;
+    sleep(Duration::from_millis(300)).await;
+    let second = bind(&[1]).await;
+
+    let registry = NodeRegistry::new();
+    for node in [&first, &second] {
+        registry.add(node.tcp_addr(), &[1]);
+    }
+    for node in [&first, &second] {
+        wait_until("the clock syncs", || node.clock_offset().is_some()).await;
+    }
+
+    // started apart, but running effects on the same time
+    let drift = first.time().abs_diff(second.time());
+    assert!(drift <= 20, "nodes drifted {drift}ms apart");
+    assert!(server::clock::now().abs_diff(first.time()) <= 20);
+}
+
+#[tokio::test]
+async fn holds_timestamped_frames_until_due() {
+    let node = bind(&[4]).await;
+    let registry = NodeRegistry::new();
+    let sender = UdpSender::bind().await.unwrap();
+    let id = registry.add(node.tcp_addr(), &[4]);
+    registry.set_strip_mode(id, 0, StripMode::Dynamic).unwrap();
+    wait_until("the clock syncs", || node.clock_offset().is_some()).await;
+    wait_until("the strip streams", || {
+        node.mode(0) == Some(StripMode::Dynamic)
+    })
+    .await;
+
+    let red = Rgb8::new(255, 0, 0);
+    let due = server::clock::now() + 300;
+    sender
+        .set_buffer_at(node.udp_addr(), 0, &[red; 4], due as u32)
+        .await
+        .unwrap();
+
+    sleep(Duration::from_millis(150)).await;
+    assert_eq!(node.pixels(0).unwrap(), [Rgb8::zero(); 4]);
+    wait_until("the frame is due", || {
+        node.pixels(0).unwrap() == [shown(red); 4]
+    })
+    .await;
+    assert!(node.time() + 20 >= due, "shown early, at {}", node.time());
+
+    // a frame whose time has passed is too late to show
+    sender
+        .set_buffer_at(node.udp_addr(), 0, &[Rgb8::zero(); 4], due as u32 - 10)
+        .await
+        .unwrap();
+    wait_until("the late frame is dropped", || node.stale_packets() == 1).await;
+    assert_eq!(node.pixels(0).unwrap(), [shown(red); 4]);
+}
+
+#[tokio::test]
+async fn falls_back_when_the_stream_stops() {
+    let node = bind(&[4]).await;
+    let registry = NodeRegistry::new();
+    let sender = UdpSender::bind().await.unwrap();
+    let id = registry.add(node.tcp_addr(), &[4]);
+    registry.set_strip_mode(id, 0, StripMode::Dynamic).unwrap();
+    let failsafe = StreamTimeout::new(100, Fallback::Fade);
+    registry.set_stream_timeout(id, 0, Some(failsafe)).unwrap();
+    wait_until("the strip streams", || {
+        node.mode(0) == Some(StripMode::Dynamic)
+    })
+    .await;
+
+    let red = Rgb8::new(255, 0, 0);
+    sender.fill(node.udp_addr(), 0, red).await.unwrap();
+    wait_until("the frame shows", || {
+        node.pixels(0).unwrap() == [shown(red); 4]
+    })
+    .await;
+
+    // the server goes quiet, so the frame fades away
+    wait_until("the stream fades out", || {
+        node.pixels(0).unwrap() == [Rgb8::zero(); 4]
+    })
+    .await;
+    assert_eq!(node.mode(0), Some(StripMode::Dynamic));
+
+    sender.fill(node.udp_addr(), 0, red).await.unwrap();
+    wait_until("the stream resumes", || {
+        node.pixels(0).unwrap() == [shown(red); 4]
+    })
+    .await;
+}
+
+fn key() -> Key {
+    Key::new([0x42; KEY_LEN])
+}
+
+#[tokio::test]
+async fn takes_traffic_from_a_server_with_the_key() {
+    let node = VirtualNode::bind(NodeConfig {
+        key: Some(key()),
+        ..local(&[4])
+    })
+    .await
+    .unwrap();
+    let socket = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let (red, blue) = (Rgb8::new(255, 0, 0), Rgb8::new(0, 0, 255));
+
+    // DDP is only taken from the server once it has a session
+    let mut buf = [0u8; 64];
+    let frame = ddp::encode(&mut buf, 1, true, 0, &[blue; 4]).unwrap();
+    socket.send_to(frame, node.ddp_addr()).await.unwrap();
+    wait_until("the DDP frame is rejected", || node.rejected_packets() == 1).await;
+
+    let registry = NodeRegistry::with_key(Some(key()));
+    let id = registry.add(node.tcp_addr(), &[4]);
+    registry.set_strip_mode(id, 0, StripMode::Dynamic).unwrap();
+    wait_until("the strip switches to dynamic", || {
+        node.mode(0) == Some(StripMode::Dynamic)
+    })
+    .await;
+    wait_until("a heartbeat arrives", || {
+        registry.get(id).unwrap().heartbeat.is_some()
+    })
+    .await;
+
+    let sender = UdpSender::bind()
+        .await
+        .unwrap()
+        .with_keys(registry.stream_keys());
+    sender.fill(node.udp_addr(), 0, red).await.unwrap();
+    wait_until("signed colors show", || {
+        node.pixels(0).unwrap() == [shown(red); 4]
+    })
+    .await;
+
+    socket.send_to(frame, node.ddp_addr()).await.unwrap();
+    wait_until("the DDP frame shows", || {
+        node.pixels(0).unwrap() == [shown(blue); 4]
+    })
+    .await;
+    assert_eq!(node.rejected_packets(), 1);
+}
+
+/// Stand in for the server on a keyed node's session, returning the key its
+/// packets have to be signed with.
+async fn open_session(node: &VirtualNode) -> (BufReader<TcpStream>, Key) {
+    let mut stream = BufReader::new(TcpStream::connect(node.tcp_addr()).await.unwrap());
+    loop {
+        let mut frame = Vec::new();
+        timeout(Duration::from_secs(5), stream.read_until(0, &mut frame))
+            .await
+            .expect("timed out waiting for the challenge")
+            .unwrap();
+        if let Ok(NodeMessage::Challenge(nonce)) = decode_frame(&mut frame) {
+            return (stream, key().for_session(nonce));
+        }
+    }
+}
+
+/// A packet filling strip 0 with one color, signed if there's a key.
+fn signed_fill(sequence: u16, color: Rgb8, key: Option<&Key>) -> Vec<u8> {
+    let mut buf = [0u8; 64];
+    let mut enc = UdpEncoder::new(&mut buf[..64 - MAC_LEN], PacketHeader::new(sequence));
+    enc.set_buffer_to_single(0, color).unwrap();
+    let len = enc.len();
+    let len = match key {
+        Some(key) => sign_packet(key, sequence as u64, &mut buf, len).unwrap(),
+        None => len,
+    };
+    buf[..len].to_vec()
+}
+
+#[tokio::test]
+async fn rejects_unauthenticated_and_replayed_packets() {
+    let node = VirtualNode::bind(NodeConfig {
+        key: Some(key()),
+        ..local(&[4])
+    })
+    .await
+    .unwrap();
+    let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let (red, green, blue) = (
+        Rgb8::new(255, 0, 0),
+        Rgb8::new(0, 255, 0),
+        Rgb8::new(0, 0, 255),
+    );
+
+    // nothing is taken before a session, even signed with the key itself
+    let early = signed_fill(1, green, Some(&key()));
+    socket.send_to(&early, node.udp_addr()).await.unwrap();
+    wait_until("the early packet is rejected", || {
+        node.rejected_packets() == 1
+    })
+    .await;
+
+    let (first, stream_key) = open_session(&node).await;
+    let forged = Key::new([0x43; KEY_LEN]).for_session(7);
+    for packet in [
+        signed_fill(1, green, None),
+        signed_fill(1, green, Some(&forged)),
+    ] {
+        socket.send_to(&packet, node.udp_addr()).await.unwrap();
+    }
+    wait_until("both packets are rejected", || node.rejected_packets() == 3).await;
+
+    let captured = signed_fill(1, green, Some(&stream_key));
+    socket.send_to(&captured, node.udp_addr()).await.unwrap();
+    let latest = signed_fill(2, red, Some(&stream_key));
+    socket.send_to(&latest, node.udp_addr()).await.unwrap();
+    wait_until("signed colors show", || {
+        node.pixels(0).unwrap() == [shown(red); 4]
+    })
+    .await;
+
+    // an older packet replayed during the session
+    socket.send_to(&captured, node.udp_addr()).await.unwrap();
+    wait_until("the replayed packet is rejected", || {
+        node.rejected_packets() == 4
+    })
+    .await;
+    assert_eq!(node.pixels(0).unwrap(), [shown(red); 4]);
+
+    // a packet from the last session is no good in the next
+    drop(first);
+    let (_second, stream_key) = open_session(&node).await;
+    socket.send_to(&captured, node.udp_addr()).await.unwrap();
+    wait_until("the replayed packet is rejected", || {
+        node.rejected_packets() == 5
+    })
+    .await;
+    socket
+        .send_to(&signed_fill(1, blue, Some(&stream_key)), node.udp_addr())
+        .await
+        .unwrap();
+    wait_until("the new session's colors show", || {
+        node.pixels(0).unwrap() == [shown(blue); 4]
+    })
+    .await;
+    assert_eq!(node.rejected_packets(), 5);
+    assert_eq!(node.dropped_packets(), 0);
+}
+
+#[tokio::test]
+async fn drops_sessions_from_servers_without_the_key() {
+    for server_key in [None, Some(Key::new([0x43; KEY_LEN]))] {
+        let node = VirtualNode::bind(NodeConfig {
+            key: Some(key()),
+            ..local(&[4])
+        })
+        .await
+        .unwrap();
+        let registry = NodeRegistry::with_key(server_key);
+        let mut events = registry.subscribe();
+        registry.add(node.tcp_addr(), &[4]);
+
+        loop {
+            let event = timeout(Duration::from_secs(5), events.recv())
+                .await
+                .expect("timed out waiting for an error")
+                .unwrap();
+            if let NodeMessage::Error(error) = event.msg {
+                assert!(
+                    matches!(
+                        error,
+                        NodeError::Unauthenticated(AuthError::BadMac | AuthError::Malformed)
+                    ),
+                    "{error:?}"
+                );
+                break;
+            }
+        }
+        assert!(node.rejected_packets() >= 1);
+    }
+}